@@ -35,6 +35,24 @@ class BtcLedgerConstants {
   static const int swOk = 0x9000;
   static const int swInterrupt = 0xE000;
 
+  /// Network name for a Bitcoin chain's first chain id.
+  static String network(int chainId) {
+    switch (chainId) {
+      case 1:
+        return 'testnet';
+      case 2:
+        return 'regtest';
+      case 3:
+        return 'signet';
+      default:
+        return 'bitcoin';
+    }
+  }
+
+  /// BIP-44 coin type of Ledger account paths: 0 on mainnet, 1 on every
+  /// test network.
+  static int coinType(String network) => network == 'bitcoin' ? 0 : 1;
+
   /// Map BIP purpose to Ledger wallet descriptor template.
   static String? descriptorTemplateForBip(int bip) {
     switch (bip) {
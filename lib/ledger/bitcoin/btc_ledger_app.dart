@@ -114,11 +114,13 @@ class BtcLedgerApp {
     required List<int> indices,
     required int bipPurpose,
     int accountIndex = 0,
+    String network = 'bitcoin',
   }) async {
     final fingerprint = await getMasterFingerprint();
 
-    // Get the account-level xpub: m/purpose'/0'/account'
-    final accountPath = "m/$bipPurpose'/0'/$accountIndex'";
+    // Get the account-level xpub: m/purpose'/coin'/account'
+    final coinType = BtcLedgerConstants.coinType(network);
+    final accountPath = "m/$bipPurpose'/$coinType'/$accountIndex'";
     final xpub = await getExtendedPubkey(path: accountPath);
 
     // Build wallet policy
@@ -127,6 +129,7 @@ class BtcLedgerApp {
       masterFingerprint: fingerprint,
       bipPurpose: bipPurpose,
       accountIndex: accountIndex,
+      network: network,
     );
 
     final List<LedgerAccount> accounts = [];
@@ -158,11 +161,13 @@ class BtcLedgerApp {
     required Uint8List psbtBytes,
     required int bipPurpose,
     required int accountIndex,
+    String network = 'bitcoin',
   }) async {
     final fingerprint = await getMasterFingerprint();
 
     // Get account xpub
-    final accountPath = "m/$bipPurpose'/0'/$accountIndex'";
+    final coinType = BtcLedgerConstants.coinType(network);
+    final accountPath = "m/$bipPurpose'/$coinType'/$accountIndex'";
     final xpub = await getExtendedPubkey(path: accountPath);
 
     // Build wallet policy
@@ -171,6 +176,7 @@ class BtcLedgerApp {
       masterFingerprint: fingerprint,
       bipPurpose: bipPurpose,
       accountIndex: accountIndex,
+      network: network,
     );
 
     // Populate BIP32 derivation info (tap_key_origins, tap_internal_key, etc.)
@@ -319,6 +325,7 @@ class BtcLedgerApp {
     required int bipPurpose,
     required int index,
     int accountIndex = 0,
+    String network = 'bitcoin',
   }) async {
     final msgBytes = Uint8List.fromList(message.codeUnits);
 
@@ -343,7 +350,8 @@ class BtcLedgerApp {
         await btc_ffi.btcLedgerComputeMerkleRoot(leafHashes: leafHashes);
 
     // Build the payload: path || varint(msgLen) || merkleRoot(32)
-    final fullPath = "m/$bipPurpose'/0'/$accountIndex'/0/$index";
+    final coinType = BtcLedgerConstants.coinType(network);
+    final fullPath = "m/$bipPurpose'/$coinType'/$accountIndex'/0/$index";
     final pathBytes = await btc_ffi.btcLedgerEncodePath(path: fullPath);
     final msgLenVarint = _encodeVarint(msgBytes.length);
 
@@ -6,6 +6,7 @@ import 'package:flutter/services.dart';
 import 'package:permission_handler/permission_handler.dart';
 import 'package:bearby/config/bip_purposes.dart';
 import 'package:bearby/config/web3_constants.dart';
+import 'package:bearby/ledger/bitcoin/btc_constants.dart';
 import 'package:bearby/ledger/bitcoin/btc_ledger_app.dart';
 import 'package:bearby/ledger/common.dart';
 import 'package:bearby/ledger/ethereum/eth_ledger_app.dart';
@@ -257,6 +258,7 @@ class LedgerViewController extends ChangeNotifier {
     required int accountIndex,
     required AccountInfo account,
     int bipPurpose = kBip86Purpose,
+    String btcNetwork = 'bitcoin',
   }) async {
     if (transaction.scilla != null) {
       final zilliqaApp = ZilliqaLedgerApp(_connectedTransport!);
@@ -297,7 +299,8 @@ class LedgerViewController extends ChangeNotifier {
 
       // Get fingerprint & xpub to prepare the PSBT with bip32_derivation
       final fingerprint = await btcApp.getMasterFingerprint();
-      final accountPath = "m/$bipPurpose'/0'/$accountIndex'";
+      final coinType = BtcLedgerConstants.coinType(btcNetwork);
+      final accountPath = "m/$bipPurpose'/$coinType'/$accountIndex'";
       final xpub = await btcApp.getExtendedPubkey(path: accountPath);
 
       // Prepare PSBT: populates bip32_derivation (pubkeys) for non-Taproot
@@ -313,6 +316,7 @@ class LedgerViewController extends ChangeNotifier {
         psbtBytes: preparedPsbt,
         bipPurpose: bipPurpose,
         accountIndex: accountIndex,
+        network: btcNetwork,
       );
 
       final ledgerSigs = <btc_ffi.LedgerInputSignature>[];
@@ -342,6 +346,7 @@ class LedgerViewController extends ChangeNotifier {
     required BigInt walletIndex,
     required int slip44,
     int bipPurpose = kBip86Purpose,
+    String btcNetwork = 'bitcoin',
   }) async {
     String? sig;
 
@@ -380,6 +385,7 @@ class LedgerViewController extends ChangeNotifier {
         message: message,
         bipPurpose: bipPurpose,
         index: account.index.toInt(),
+        network: btcNetwork,
       );
       sig = sigBytes.map((b) => b.toRadixString(16).padLeft(2, '0')).join();
     } else {
@@ -447,6 +453,7 @@ class LedgerViewController extends ChangeNotifier {
     required int count,
     required int chainId,
     int bipPurpose = kBip86Purpose,
+    String btcNetwork = 'bitcoin',
   }) async {
     if (_connectedTransport == null) {
       await open(device);
@@ -472,6 +479,7 @@ class LedgerViewController extends ChangeNotifier {
       accounts = await btcApp.getAccounts(
         indices: List<int>.generate(count, (i) => i),
         bipPurpose: bipPurpose,
+        network: btcNetwork,
       );
     } else if (slip44 == kEthereumSlip44 || slip44 == kZilliqaSlip44) {
       final evmApp = EthLedgerApp(_connectedTransport!);
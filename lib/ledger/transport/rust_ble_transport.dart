@@ -42,6 +42,10 @@ class RustBleTransport extends Transport {
           apdu: apdu.toList(),
         );
         return Uint8List.fromList(result);
+      } on RustLedgerError_Status catch (e) {
+        // Hand the status word back so Transport.send and the app's
+        // statusCodeChecker handle it like any other device answer.
+        return Uint8List.fromList([e.statusWord >> 8, e.statusWord & 0xff]);
       } catch (e) {
         throw DisconnectedDeviceDuringOperationException(e.toString());
       }
@@ -4,4 +4,12 @@ enum WalletType {
   SecretPhrase,
   // ignore: constant_identifier_names
   SecretKey,
+  watchOnly,
+  multisig,
 }
+
+/// Watch-only and multisig wallets hold no keys: they unlock without a
+/// password and cannot sign on their own.
+bool isKeylessWalletType(String walletType) =>
+    walletType.contains(WalletType.watchOnly.name) ||
+    walletType.contains(WalletType.multisig.name);
@@ -204,6 +204,7 @@ class _ChainInfoModalContentState extends State<_ChainInfoModalContent> {
                                 fallbackEnabled: value,
                                 testnet: _config.testnet,
                                 ftokens: _config.ftokens,
+                                btcBackend: _config.btcBackend,
                               );
                             });
                             await createOrUpdateChain(providerConfig: _config);
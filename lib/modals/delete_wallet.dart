@@ -199,8 +199,10 @@ class _DeleteWalletModalState extends State<DeleteWalletModal> {
                           ),
                           const SizedBox(height: 24),
                           if (appState.wallet?.walletType
-                                  .contains(WalletType.ledger.name) ==
-                              false)
+                                      .contains(WalletType.ledger.name) ==
+                                  false &&
+                              !isKeylessWalletType(
+                                  appState.wallet?.walletType ?? ''))
                             SmartInput(
                               key: _passwordInputKey,
                               controller: _passwordController,
@@ -5,6 +5,7 @@ import 'package:flutter_svg/svg.dart';
 import 'package:provider/provider.dart';
 import 'package:bearby/components/smart_input.dart';
 import 'package:bearby/components/swipe_button.dart';
+import 'package:bearby/ledger/bitcoin/btc_constants.dart';
 import 'package:bearby/ledger/ledger_connector.dart';
 import 'package:bearby/ledger/models/discovered_device.dart';
 import 'package:bearby/mixins/adaptive_size.dart';
@@ -174,6 +175,8 @@ class _SignMessageModalContentState extends State<_SignMessageModalContent> {
             walletIndex: appState.selectedWalletIndex,
             slip44: wallet.slip44,
             bipPurpose: wallet.bip,
+            btcNetwork: BtcLedgerConstants.network(
+                appState.chain?.chainIds.first ?? 0),
           );
           widget.onMessageSigned(account.pubKey ?? account.addr, sig);
         } else if (widget.typedData != null) {
@@ -9,6 +9,7 @@ import 'package:bearby/components/smart_input.dart';
 import 'package:bearby/components/swipe_button.dart';
 import 'package:bearby/components/token_transfer_amount.dart';
 import 'package:bearby/components/transaction_amount_display.dart';
+import 'package:bearby/ledger/bitcoin/btc_constants.dart';
 import 'package:bearby/ledger/ledger_connector.dart';
 import 'package:bearby/ledger/models/discovered_device.dart';
 import 'package:bearby/mixins/adaptive_size.dart';
@@ -91,6 +92,7 @@ class _ConfirmTransactionContent extends StatefulWidget {
 class _ConfirmTransactionContentState
     extends State<_ConfirmTransactionContent> {
   late final bool _isLedgerWallet;
+  late final bool _isKeylessWallet;
   final _passwordController = TextEditingController();
   final _passwordInputKey = GlobalKey<SmartInputState>();
 
@@ -153,6 +155,7 @@ class _ConfirmTransactionContentState
         : null;
     _isLedgerWallet =
         wallet?.walletType.contains(WalletType.ledger.name) ?? false;
+    _isKeylessWallet = isKeylessWalletType(wallet?.walletType ?? '');
 
     if (_isLedgerWallet) {
       appState.ledgerViewController.scanAndAutoConnect().then((_) {
@@ -280,6 +283,8 @@ class _ConfirmTransactionContentState
       accountIndex: accountIndex,
       account: appState.account!,
       bipPurpose: appState.wallet!.bip,
+      btcNetwork:
+          BtcLedgerConstants.network(appState.chain?.chainIds.first ?? 0),
     );
 
     return await sendSignedTransactions(
@@ -510,7 +515,9 @@ class _ConfirmTransactionContentState
                         ],
                       ),
                     ),
-                    if (appState.wallet!.authType == "none" && !_isLedgerWallet)
+                    if (appState.wallet!.authType == "none" &&
+                        !_isLedgerWallet &&
+                        !_isKeylessWallet)
                       Padding(
                         padding: const EdgeInsets.all(12),
                         child: SmartInput(
@@ -540,6 +547,7 @@ class _ConfirmTransactionContentState
                             ? l10n.confirmTransactionContentUnableToConfirm
                             : l10n.confirmTransactionContentConfirm,
                         disabled: _isDisabled ||
+                            _isKeylessWallet ||
                             (_isLedgerWallet &&
                                 appState.ledgerViewController
                                         .connectedTransport ==
@@ -13,6 +13,7 @@ import 'package:bearby/config/bip_purposes.dart';
 import 'package:bearby/config/cipher.dart';
 import 'package:bearby/config/web3_constants.dart';
 import 'package:bearby/utils/utils.dart';
+import 'package:bearby/ledger/bitcoin/btc_constants.dart';
 import 'package:bearby/ledger/common.dart';
 import 'package:bearby/ledger/ledger_connector.dart';
 import 'package:bearby/ledger/ledger_view_controller.dart';
@@ -362,6 +363,8 @@ class _AddLedgerAccountPageState extends State<AddLedgerAccountPage>
                       count: _accountCount,
                       chainId: _network!.chainId.toInt(),
                       bipPurpose: bipPurpose,
+                      btcNetwork: BtcLedgerConstants.network(
+                          _network!.chainIds.first),
                     );
 
                     final newSelectedAccounts = {
@@ -9,6 +9,7 @@ import 'package:bearby/config/web3_constants.dart';
 import 'package:bearby/mixins/adaptive_size.dart';
 import 'package:bearby/mixins/status_bar.dart';
 import 'package:bearby/src/rust/api/provider.dart';
+import 'package:bearby/src/rust/models/btc.dart';
 import 'package:bearby/src/rust/models/ftoken.dart';
 import 'package:bearby/src/rust/models/provider.dart';
 import 'package:bearby/state/app_state.dart';
@@ -112,6 +113,7 @@ class _AddNetworkPageState extends State<AddNetworkPage> with StatusBarMixin {
         explorers: explorers,
         fallbackEnabled: true,
         testnet: _isTestnet,
+        btcBackend: BtcBackend.http,
         ftokens: [
           FTokenInfo(
             name: _nameController.text.trim(),
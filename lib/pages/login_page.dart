@@ -158,7 +158,8 @@ class _LoginPageState extends State<LoginPage> with StatusBarMixin {
     try {
       bool isAuthenticated = false;
 
-      if (wallet.walletType.contains(WalletType.ledger.name)) {
+      if (wallet.walletType.contains(WalletType.ledger.name) ||
+          isKeylessWalletType(wallet.walletType)) {
         await _completeAuthentication(_selectedWallet);
         isAuthenticated = true;
       } else if (wallet.authType != "none" &&
@@ -314,6 +315,10 @@ class _LoginPageState extends State<LoginPage> with StatusBarMixin {
     return [
       if (wallet.walletType.contains(WalletType.ledger.name))
         'assets/icons/ledger.svg',
+      if (wallet.walletType.contains(WalletType.watchOnly.name))
+        'assets/icons/open_eye.svg',
+      if (wallet.walletType.contains(WalletType.multisig.name))
+        'assets/icons/shield.svg',
       if (wallet.walletType.contains(WalletType.SecretPhrase.name))
         'assets/icons/document.svg',
       if (wallet.walletType.contains(WalletType.SecretKey.name))
@@ -343,7 +348,8 @@ class _LoginPageState extends State<LoginPage> with StatusBarMixin {
         ? _appState.wallets.elementAtOrNull(_selectedWallet)
         : null;
     final isLedgerWallet =
-        wallet?.walletType.contains(WalletType.ledger.name) ?? false;
+        (wallet?.walletType.contains(WalletType.ledger.name) ?? false) ||
+            isKeylessWalletType(wallet?.walletType ?? '');
     final l10n = AppLocalizations.of(context)!;
 
     return Padding(
@@ -207,7 +207,8 @@ class _WalletPageState extends State<WalletPage> {
 
     final walletType = appState.wallet!.walletType;
 
-    if (!walletType.contains(WalletType.ledger.name)) {
+    if (!walletType.contains(WalletType.ledger.name) &&
+        !isKeylessWalletType(walletType)) {
       items.add(
         WalletPreferenceItem(
           title: l10n.walletPageBackup,
@@ -21,6 +21,7 @@ import 'package:bearby/src/rust/api/provider.dart';
 import 'package:bearby/src/rust/api/token.dart';
 import 'package:bearby/src/rust/api/utils.dart';
 import 'package:bearby/src/rust/api/wallet.dart';
+import 'package:bearby/src/rust/models/btc.dart';
 import 'package:bearby/src/rust/models/connection.dart';
 import 'package:bearby/src/rust/models/ftoken.dart';
 import 'package:bearby/src/rust/models/provider.dart';
@@ -52,6 +53,7 @@ extension NetworkConfigInfoExtension on NetworkConfigInfo {
     bool? fallbackEnabled,
     bool? testnet,
     List<FTokenInfo>? ftokens,
+    BtcBackend? btcBackend,
   }) {
     return NetworkConfigInfo(
       ftokens: ftokens ?? this.ftokens,
@@ -70,6 +72,7 @@ extension NetworkConfigInfoExtension on NetworkConfigInfo {
       explorers: explorers ?? this.explorers,
       fallbackEnabled: fallbackEnabled ?? this.fallbackEnabled,
       testnet: testnet ?? this.testnet,
+      btcBackend: btcBackend ?? this.btcBackend,
     );
   }
 }
@@ -1518,6 +1521,7 @@ class Web3EIP1193Handler {
         explorers: explorers,
         fallbackEnabled: true,
         testnet: name.toLowerCase().contains(kTestnetIdentifier),
+        btcBackend: BtcBackend.http,
       );
 
       foundChain = foundChain.copyWith(
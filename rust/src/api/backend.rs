@@ -14,7 +14,10 @@ pub use zilpay::{
 use crate::{
     frb_generated::StreamSink,
     models::background::BackgroundState,
-    service::service::{ServiceBackground, BACKGROUND_SERVICE},
+    service::{
+        app_storage,
+        service::{ServiceBackground, BACKGROUND_SERVICE},
+    },
     utils::{
         errors::ServiceError,
         utils::{get_background_state, with_service},
@@ -26,6 +29,7 @@ pub async fn load_service(path: &str) -> Result<BackgroundState, String> {
     let mut guard = BACKGROUND_SERVICE.write().await;
     if guard.is_none() {
         let bg = ServiceBackground::from_path(path)?;
+        app_storage::open(path)?;
         let state = get_background_state(&bg.core)?;
         *guard = Some(bg);
         Ok(state)
@@ -0,0 +1,555 @@
+use std::str::FromStr;
+
+use bitcoin::secp256k1::{Secp256k1, SecretKey};
+use bitcoin::{
+    Address, AddressType, Amount, FeeRate, Network, OutPoint, Sequence, Transaction, TxOut, Weight,
+};
+
+use crate::api::btc_coin_control::load_frozen;
+use crate::api::local_storage::{KeyValueStorage, LocalStorageImpl};
+use crate::btc::coin_control::unfrozen;
+use crate::btc::coin_selection::{
+    select_coins, ChangePolicy, CoinSelectionStrategy, Selection, Utxo,
+};
+use crate::btc::cpfp::{build_cpfp, Parent};
+use crate::btc::message::key_address;
+use crate::btc::rbf::{bump_fee, PendingTx};
+use crate::btc::silent_payments::{
+    placeholder_script, sender_outputs, InputKey, SilentPaymentAddress,
+};
+pub use crate::models::btc::{
+    BtcCoinSelectionInfo, BtcCoinSelectionStrategy, BtcCpfpInfo, BtcCpfpParamsInfo, BtcFeeBumpInfo,
+    BtcRecipientInfo, BtcTxParamsInfo, BtcUtxoInfo,
+};
+use crate::models::transactions::history::{HistoricalTransactionInfo, TransactionStatusInfo};
+use crate::models::transactions::request::TransactionRequestInfo;
+use crate::models::transactions::transaction_metadata::TransactionMetadataInfo;
+use zilpay::history::status::TransactionStatus;
+use zilpay::history::transaction::HistoricalTransaction;
+
+pub(crate) fn parse_address(address: &str, network: Network) -> Result<Address, String> {
+    Address::from_str(address)
+        .map_err(|e| format!("Invalid address {}: {}", address, e))?
+        .require_network(network)
+        .map_err(|e| format!("Invalid address {}: {}", address, e))
+}
+
+pub(crate) fn parse_fee_rate(sat_per_vb: f64) -> Result<FeeRate, String> {
+    if !sat_per_vb.is_finite() || sat_per_vb <= 0.0 {
+        return Err(format!("Invalid fee rate {}", sat_per_vb));
+    }
+    // 1 sat/vB = 250 sat/kwu
+    Ok(FeeRate::from_sat_per_kwu((sat_per_vb * 250.0).ceil() as u64))
+}
+
+/// Unsigned transaction as a request for `sign_send_transactions`, and as
+/// a PSBT for the Ledger path.
+pub(crate) fn build_request(
+    unsigned_tx: Transaction,
+    witness_utxos: Vec<TxOut>,
+    mut metadata: TransactionMetadataInfo,
+) -> Result<(TransactionRequestInfo, Vec<u8>), String> {
+    let raw_tx = bitcoin::consensus::encode::serialize(&unsigned_tx);
+    let psbt = zilpay::proto::btc_tx::build_psbt(unsigned_tx, &witness_utxos)
+        .map_err(|e| format!("Failed to build PSBT: {:?}", e))?;
+
+    metadata.btc_witness_utxos =
+        Some(serde_json::to_string(&witness_utxos).map_err(|e| e.to_string())?);
+    let tx = TransactionRequestInfo {
+        metadata,
+        scilla: None,
+        evm: None,
+        btc: Some(hex::encode(raw_tx)),
+        tron: None,
+        solana: None,
+    };
+
+    Ok((tx, psbt.serialize()))
+}
+
+/// Select inputs for `params` and build the unsigned transaction. The
+/// result signs through `sign_send_transactions` for seed wallets, or goes
+/// through `btc_ledger_prepare_psbt` and `btc_ledger_sign_psbt` for Ledger.
+/// Silent payment recipients go through `btc_create_silent_payment_transaction`.
+///
+/// UTXOs frozen for `wallet_address` are never spent. With the `Manual`
+/// strategy the given UTXOs are the inputs, and a frozen one among them is
+/// an error.
+pub fn btc_create_transaction(
+    storage: &LocalStorageImpl,
+    wallet_address: String,
+    params: BtcTxParamsInfo,
+    metadata: TransactionMetadataInfo,
+) -> Result<BtcCoinSelectionInfo, String> {
+    create_transaction(storage, &wallet_address, params, metadata, None)
+}
+
+/// `btc_create_transaction` with the account key in `sender`, which paying
+/// silent payment addresses needs once the inputs are selected.
+pub(crate) fn create_transaction(
+    storage: &dyn KeyValueStorage,
+    wallet_address: &str,
+    params: BtcTxParamsInfo,
+    metadata: TransactionMetadataInfo,
+    sender: Option<(SecretKey, AddressType)>,
+) -> Result<BtcCoinSelectionInfo, String> {
+    let network = Network::from_str(&params.network)
+        .map_err(|e| format!("Invalid network {}: {}", params.network, e))?;
+    let fee_rate = parse_fee_rate(params.fee_rate)?;
+    let change = ChangePolicy::new(parse_address(&params.change_address, network)?.script_pubkey());
+
+    let mut silent_payments = Vec::new();
+    let recipients = params
+        .recipients
+        .iter()
+        .enumerate()
+        .map(|(i, r)| {
+            let script_pubkey = match SilentPaymentAddress::from_str(&r.address) {
+                Ok(address) => {
+                    let address = address
+                        .require_network(network.into())
+                        .map_err(|e| format!("Invalid address {}: {}", r.address, e))?;
+                    silent_payments.push((i, address));
+                    placeholder_script(&address)
+                }
+                Err(_) => parse_address(&r.address, network)?.script_pubkey(),
+            };
+            Ok(TxOut {
+                value: Amount::from_sat(r.value),
+                script_pubkey,
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    let utxos = params
+        .utxos
+        .into_iter()
+        .map(Utxo::try_from)
+        .collect::<Result<Vec<_>, String>>()?;
+    let manual = CoinSelectionStrategy::from(params.strategy) == CoinSelectionStrategy::Manual;
+    let utxos = unfrozen(utxos, &load_frozen(storage, wallet_address)?, manual)
+        .map_err(|e| e.to_string())?;
+
+    let mut selection = select_coins(
+        &utxos,
+        &recipients,
+        fee_rate,
+        &change,
+        params.strategy.into(),
+    )
+    .map_err(|e| e.to_string())?;
+
+    if !silent_payments.is_empty() {
+        let (sk, address_type) =
+            sender.ok_or("Silent payments need the account key to build outputs")?;
+        pay_silent_payments(&mut selection, &silent_payments, &sk, address_type, network)?;
+    }
+
+    selection_info(selection, metadata)
+}
+
+/// Request and PSBT spending `selection`, replaceable by fee.
+pub(crate) fn selection_info(
+    selection: Selection,
+    metadata: TransactionMetadataInfo,
+) -> Result<BtcCoinSelectionInfo, String> {
+    let unsigned_tx = selection.to_unsigned_tx(Sequence::ENABLE_RBF_NO_LOCKTIME);
+    let (tx, psbt) = build_request(unsigned_tx, selection.witness_utxos(), metadata)?;
+
+    Ok(BtcCoinSelectionInfo {
+        tx,
+        psbt,
+        fee: selection.fee.to_sat(),
+        vsize: selection.vsize(),
+        change: selection.change.as_ref().map(|c| c.value.to_sat()),
+        selected: selection.selected.into_iter().map(Into::into).collect(),
+    })
+}
+
+/// Replace the placeholder outputs of `silent_payments` with the outputs
+/// derived from the selected inputs. They have the same size, so the fee
+/// holds.
+fn pay_silent_payments(
+    selection: &mut Selection,
+    silent_payments: &[(usize, SilentPaymentAddress)],
+    sk: &SecretKey,
+    address_type: AddressType,
+    network: Network,
+) -> Result<(), String> {
+    let secp = Secp256k1::new();
+    let key = match address_type {
+        AddressType::P2tr => InputKey::Taproot(*sk),
+        AddressType::P2pkh | AddressType::P2wpkh => InputKey::Ecdsa(*sk),
+        other => {
+            return Err(format!(
+                "Silent payments are not supported from {} accounts",
+                other
+            ))
+        }
+    };
+    let script_pubkey = key_address(&secp, sk, network, address_type)
+        .map_err(|e| e.to_string())?
+        .script_pubkey();
+    if let Some(utxo) = selection
+        .selected
+        .iter()
+        .find(|u| u.txout.script_pubkey != script_pubkey)
+    {
+        return Err(format!(
+            "Input {} is not spent by the account key",
+            utxo.outpoint
+        ));
+    }
+
+    let outpoints: Vec<OutPoint> = selection.selected.iter().map(|u| u.outpoint).collect();
+    let addresses: Vec<SilentPaymentAddress> = silent_payments
+        .iter()
+        .map(|(_, address)| *address)
+        .collect();
+    let scripts = sender_outputs(&secp, &outpoints, &vec![key; outpoints.len()], &addresses)
+        .map_err(|e| e.to_string())?;
+    for ((i, _), script_pubkey) in silent_payments.iter().zip(scripts) {
+        selection.recipients[*i].script_pubkey = script_pubkey;
+    }
+
+    Ok(())
+}
+
+/// Build a replacement of a pending send paying `fee_rate` sat/vB. It
+/// spends the same inputs and takes the extra fee from the output paying
+/// `change_address`. Sign it like any other BTC request, broadcasting it
+/// marks the original as replaced in history.
+pub fn btc_bump_fee(
+    original: HistoricalTransactionInfo,
+    fee_rate: f64,
+    change_address: String,
+    network: String,
+) -> Result<BtcFeeBumpInfo, String> {
+    if !matches!(original.status, TransactionStatusInfo::Pending) {
+        return Err("Only pending transactions can be replaced".into());
+    }
+    let network =
+        Network::from_str(&network).map_err(|e| format!("Invalid network {}: {}", network, e))?;
+    let fee_rate = parse_fee_rate(fee_rate)?;
+    let change_script = parse_address(&change_address, network)?.script_pubkey();
+
+    let pending = PendingTx::parse(original.btc.as_deref().ok_or("Not a Bitcoin transaction")?)
+        .map_err(|e| e.to_string())?;
+    let witness_utxos: Vec<TxOut> = original
+        .metadata
+        .btc_witness_utxos
+        .as_deref()
+        .map(serde_json::from_str)
+        .transpose()
+        .map_err(|e| format!("Failed to parse witness UTXOs: {}", e))?
+        .ok_or("Missing witness UTXOs")?;
+    let original_txid = pending
+        .txid
+        .map(|txid| txid.to_string())
+        .or(original.metadata.hash.clone())
+        .ok_or("Missing original txid")?;
+
+    let bump =
+        bump_fee(&pending, &witness_utxos, &change_script, fee_rate).map_err(|e| e.to_string())?;
+
+    let mut metadata = original.metadata;
+    metadata.hash = None;
+    let (tx, psbt) = build_request(bump.tx.clone(), bump.witness_utxos(), metadata)?;
+
+    Ok(BtcFeeBumpInfo {
+        tx,
+        psbt,
+        original_txid,
+        original_signals_rbf: pending.signals_rbf(),
+        fee: bump.fee.to_sat(),
+        vsize: bump.weight.to_vbytes_ceil(),
+    })
+}
+
+/// Build a child spending the wallet's outputs of a stuck parent so that
+/// both confirm at `params.fee_rate`. The child pays to
+/// `params.destination_address`, usually a fresh change address.
+pub fn btc_create_cpfp(
+    params: BtcCpfpParamsInfo,
+    metadata: TransactionMetadataInfo,
+) -> Result<BtcCpfpInfo, String> {
+    let network = Network::from_str(&params.network)
+        .map_err(|e| format!("Invalid network {}: {}", params.network, e))?;
+    let fee_rate = parse_fee_rate(params.fee_rate)?;
+    let destination = parse_address(&params.destination_address, network)?.script_pubkey();
+
+    let pending = PendingTx::parse(&params.parent).map_err(|e| e.to_string())?;
+    let parent = Parent {
+        txid: pending.txid.ok_or("Missing parent txid")?,
+        fee: params
+            .parent_fee
+            .map(Amount::from_sat)
+            .or(pending.fee)
+            .ok_or("Missing parent fee")?,
+        weight: params
+            .parent_vsize
+            .map(Weight::from_vb_unchecked)
+            .or(pending.weight)
+            .ok_or("Missing parent vsize")?,
+    };
+    let outputs = params
+        .vouts
+        .iter()
+        .map(|&vout| {
+            pending
+                .outputs
+                .get(vout as usize)
+                .map(|out| (vout, out.clone()))
+                .ok_or(format!("Parent has no output {}", vout))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let cpfp = build_cpfp(&parent, &outputs, &destination, fee_rate).map_err(|e| e.to_string())?;
+    let package_fee_rate = cpfp.package_fee_rate(&parent);
+    let (tx, psbt) = build_request(cpfp.tx.clone(), cpfp.witness_utxos(), metadata)?;
+
+    Ok(BtcCpfpInfo {
+        tx,
+        psbt,
+        fee: cpfp.fee.to_sat(),
+        vsize: cpfp.weight.to_vbytes_ceil(),
+        package_fee_rate,
+    })
+}
+
+/// Mark pending entries of `history` that `sent` replaced (BIP-125) as
+/// failed, with the replacement txid in `info`. Returns whether any entry
+/// changed.
+pub(crate) fn record_replacement(
+    history: &mut [HistoricalTransaction],
+    sent: &HistoricalTransaction,
+) -> bool {
+    let Some(replacement) = sent
+        .btc
+        .as_deref()
+        .and_then(|btc| PendingTx::parse(btc).ok())
+    else {
+        return false;
+    };
+    let Some(replacement_txid) = replacement
+        .txid
+        .map(|txid| txid.to_string())
+        .or(sent.metadata.hash.clone())
+    else {
+        return false;
+    };
+
+    let mut changed = false;
+    for entry in history
+        .iter_mut()
+        .filter(|entry| matches!(entry.status, TransactionStatus::Pending))
+    {
+        let replaced = entry
+            .btc
+            .as_deref()
+            .and_then(|btc| PendingTx::parse(btc).ok())
+            .is_some_and(|original| original.is_replaced_by(&replacement));
+        if replaced {
+            entry.status = TransactionStatus::Failed;
+            entry.metadata.info = Some(format!("Replaced by {}", replacement_txid));
+            changed = true;
+        }
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::local_storage::MemoryStorage;
+    use bitcoin::hashes::Hash;
+    use bitcoin::psbt::Psbt;
+    use bitcoin::{ScriptBuf, WPubkeyHash};
+
+    fn address(tag: u8) -> String {
+        let script = ScriptBuf::new_p2wpkh(&WPubkeyHash::from_byte_array([tag; 20]));
+        Address::from_script(&script, Network::Bitcoin)
+            .unwrap()
+            .to_string()
+    }
+
+    fn metadata() -> TransactionMetadataInfo {
+        TransactionMetadataInfo {
+            chain_hash: 0,
+            hash: None,
+            info: None,
+            icon: None,
+            title: None,
+            signer: None,
+            token_info: None,
+            btc_witness_utxos: None,
+            broadcast: true,
+        }
+    }
+
+    fn params(fee_rate: f64) -> BtcTxParamsInfo {
+        let script = ScriptBuf::new_p2wpkh(&WPubkeyHash::from_byte_array([0xaa; 20]));
+        BtcTxParamsInfo {
+            utxos: (0..3)
+                .map(|vout| BtcUtxoInfo {
+                    txid: "11".repeat(32),
+                    vout,
+                    value: 40_000,
+                    script_pubkey: script.to_hex_string(),
+                    satisfaction_weight: None,
+                })
+                .collect(),
+            recipients: vec![BtcRecipientInfo {
+                address: address(0xbb),
+                value: 70_000,
+            }],
+            change_address: address(0xcc),
+            network: "bitcoin".to_string(),
+            fee_rate,
+            strategy: BtcCoinSelectionStrategy::BranchAndBound,
+        }
+    }
+
+    /// `create_transaction` for a wallet without frozen UTXOs.
+    fn create(params: BtcTxParamsInfo) -> Result<BtcCoinSelectionInfo, String> {
+        create_transaction(&MemoryStorage::default(), "w", params, metadata(), None)
+    }
+
+    #[test]
+    fn test_create_transaction() {
+        let result = create(params(2.0)).unwrap();
+
+        assert_eq!(result.selected.len(), 2);
+        assert_eq!(result.change, Some(80_000 - 70_000 - result.fee));
+        assert!(result.fee >= 2 * result.vsize);
+
+        let raw = hex::decode(result.tx.btc.unwrap()).unwrap();
+        let tx: Transaction = bitcoin::consensus::encode::deserialize(&raw).unwrap();
+        assert_eq!(tx.input.len(), 2);
+        assert_eq!(tx.output.len(), 2);
+
+        let witness_utxos: Vec<TxOut> =
+            serde_json::from_str(&result.tx.metadata.btc_witness_utxos.unwrap()).unwrap();
+        assert_eq!(witness_utxos.len(), 2);
+
+        let psbt = Psbt::deserialize(&result.psbt).unwrap();
+        assert_eq!(psbt.unsigned_tx, tx);
+        assert_eq!(
+            psbt.inputs[0].witness_utxo.as_ref(),
+            Some(&witness_utxos[0])
+        );
+    }
+
+    #[test]
+    fn test_create_transaction_invalid_params() {
+        assert!(create(params(0.0)).is_err());
+
+        let mut wrong_network = params(1.0);
+        wrong_network.network = "testnet".to_string();
+        assert!(create(wrong_network).is_err());
+
+        let mut too_much = params(1.0);
+        too_much.recipients[0].value = 200_000;
+        assert!(create(too_much).unwrap_err().contains("Insufficient funds"));
+    }
+
+    #[test]
+    fn test_bump_fee_from_history() {
+        let created = create(params(1.0)).unwrap();
+        let raw = created.tx.btc.clone().unwrap();
+        let original_txid = {
+            let bytes = hex::decode(&raw).unwrap();
+            let tx: Transaction = bitcoin::consensus::encode::deserialize(&bytes).unwrap();
+            tx.compute_txid().to_string()
+        };
+        let history = |status| HistoricalTransactionInfo {
+            status,
+            metadata: created.tx.metadata.clone(),
+            evm: None,
+            scilla: None,
+            btc: Some(raw.clone()),
+            tron: None,
+            signed_message: None,
+            timestamp: 0,
+        };
+
+        let bump = btc_bump_fee(
+            history(TransactionStatusInfo::Pending),
+            5.0,
+            address(0xcc),
+            "bitcoin".to_string(),
+        )
+        .unwrap();
+        assert_eq!(bump.original_txid, original_txid);
+        assert!(bump.original_signals_rbf);
+        assert!(bump.fee >= 5 * bump.vsize);
+        assert!(bump.fee > created.fee);
+        assert!(bump.tx.metadata.hash.is_none());
+
+        let psbt = Psbt::deserialize(&bump.psbt).unwrap();
+        let original_inputs: Vec<_> = psbt
+            .unsigned_tx
+            .input
+            .iter()
+            .map(|i| i.previous_output)
+            .collect();
+        let created_psbt = Psbt::deserialize(&created.psbt).unwrap();
+        assert!(created_psbt
+            .unsigned_tx
+            .input
+            .iter()
+            .all(|i| original_inputs.contains(&i.previous_output)));
+
+        assert!(btc_bump_fee(
+            history(TransactionStatusInfo::Success),
+            5.0,
+            address(0xcc),
+            "bitcoin".to_string(),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_create_cpfp() {
+        let parent = format!(
+            r#"{{
+                "txid": "{}",
+                "vsize": 141,
+                "fee": 141,
+                "vin": [{{"txid": "{}", "vout": 0, "sequence": 4294967293}}],
+                "vout": [
+                    {{"value": 0.0005, "scriptPubKey": {{"hex": "0014bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb"}}}},
+                    {{"value": 20000, "scriptPubKey": {{"hex": "0014aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"}}}}
+                ]
+            }}"#,
+            "22".repeat(32),
+            "33".repeat(32)
+        );
+        let cpfp_params = |vouts: Vec<u32>, parent_fee: Option<u64>| BtcCpfpParamsInfo {
+            parent: parent.clone(),
+            parent_fee,
+            parent_vsize: None,
+            vouts,
+            destination_address: address(0xcc),
+            network: "bitcoin".to_string(),
+            fee_rate: 10.0,
+        };
+
+        let cpfp = btc_create_cpfp(cpfp_params(vec![1], None), metadata()).unwrap();
+        assert_eq!(cpfp.vsize, 110);
+        assert_eq!(cpfp.fee, 2510 - 141);
+        assert!(cpfp.package_fee_rate >= 10.0);
+
+        let psbt = Psbt::deserialize(&cpfp.psbt).unwrap();
+        assert_eq!(psbt.unsigned_tx.input[0].previous_output.vout, 1);
+        assert_eq!(
+            psbt.inputs[0].witness_utxo.as_ref().unwrap().value,
+            Amount::from_sat(20_000)
+        );
+
+        // An explicit parent fee wins over the one in JSON
+        let cheaper = btc_create_cpfp(cpfp_params(vec![1], Some(500)), metadata()).unwrap();
+        assert_eq!(cheaper.fee, 2510 - 500);
+
+        assert!(btc_create_cpfp(cpfp_params(vec![5], None), metadata()).is_err());
+    }
+}
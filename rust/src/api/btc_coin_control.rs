@@ -0,0 +1,274 @@
+use std::collections::HashSet;
+use std::str::FromStr;
+
+use bitcoin::{Amount, Network, OutPoint};
+
+use crate::api::btc::{parse_address, parse_fee_rate, selection_info};
+use crate::api::btc_electrum::electrum_wallet;
+use crate::api::btc_labels::{load_labels, store_labels};
+use crate::api::local_storage::{KeyValueStorage, LocalStorageImpl};
+use crate::btc::coin_control::{confirmations, consolidate, frozen_outpoints, unfrozen};
+use crate::btc::coin_selection::Utxo;
+use crate::btc::labels::{Label, LabelType};
+pub use crate::models::btc::{
+    BtcCoinSelectionInfo, BtcConsolidateParamsInfo, BtcUtxoInfo, BtcWalletUtxoInfo,
+};
+use crate::models::transactions::transaction_metadata::TransactionMetadataInfo;
+
+fn set_frozen(
+    storage: &dyn KeyValueStorage,
+    wallet_address: &str,
+    outpoints: &[String],
+    frozen: bool,
+) -> Result<(), String> {
+    let mut labels = load_labels(storage, wallet_address)?;
+    for outpoint in outpoints {
+        OutPoint::from_str(outpoint)
+            .map_err(|e| format!("Invalid outpoint {}: {}", outpoint, e))?;
+        let position = labels
+            .iter()
+            .position(|l| l.label_type == LabelType::Output && &l.reference == outpoint);
+
+        match (position, frozen) {
+            (Some(position), true) => labels[position].spendable = Some(false),
+            (Some(position), false) => {
+                labels[position].spendable = None;
+                if labels[position].label.is_none() {
+                    labels.remove(position);
+                }
+            }
+            (None, true) => {
+                let mut label = Label::new(LabelType::Output, outpoint.clone(), None);
+                label.spendable = Some(false);
+                labels.push(label);
+            }
+            (None, false) => {}
+        }
+    }
+    store_labels(storage, wallet_address, &labels)
+}
+
+pub(crate) fn load_frozen(
+    storage: &dyn KeyValueStorage,
+    wallet_address: &str,
+) -> Result<HashSet<OutPoint>, String> {
+    Ok(frozen_outpoints(&load_labels(storage, wallet_address)?))
+}
+
+fn parse_utxos(utxos: Vec<BtcUtxoInfo>) -> Result<Vec<Utxo>, String> {
+    utxos.into_iter().map(Utxo::try_from).collect()
+}
+
+/// Every UTXO of the wallet's accounts, looked up on the chain's Electrum
+/// servers, with labels and freeze state from the BIP-329 labels.
+pub async fn btc_list_utxos(
+    storage: &LocalStorageImpl,
+    wallet_index: usize,
+) -> Result<Vec<BtcWalletUtxoInfo>, String> {
+    let wallet = electrum_wallet(wallet_index).await?;
+    let (tip, unspent) = wallet
+        .with_client(|client, scripts| Ok((client.tip_height()?, client.scripts_unspent(scripts)?)))
+        .await?;
+
+    let labels = load_labels(storage, &wallet.wallet_address)?;
+    let frozen = frozen_outpoints(&labels);
+    let label_of = |label_type: LabelType, reference: &str| {
+        labels
+            .iter()
+            .find(|l| l.label_type == label_type && l.reference == reference)
+            .and_then(|l| l.label.clone())
+    };
+
+    let mut utxos = Vec::new();
+    for (watched, items) in wallet.watched.iter().zip(unspent) {
+        let address = &watched.address;
+        for item in items {
+            let outpoint = format!("{}:{}", item.tx_hash, item.tx_pos);
+            let is_frozen = OutPoint::from_str(&outpoint)
+                .map(|o| frozen.contains(&o))
+                .unwrap_or_default();
+            utxos.push(BtcWalletUtxoInfo {
+                label: label_of(LabelType::Output, &outpoint)
+                    .or_else(|| label_of(LabelType::Addr, address)),
+                utxo: BtcUtxoInfo {
+                    txid: item.tx_hash,
+                    vout: item.tx_pos,
+                    value: item.value,
+                    script_pubkey: watched.script.to_hex_string(),
+                    satisfaction_weight: None,
+                },
+                account_index: watched.account_index,
+                address: address.clone(),
+                confirmations: confirmations(item.height, tip),
+                frozen: is_frozen,
+            });
+        }
+    }
+
+    Ok(utxos)
+}
+
+/// Freeze or unfreeze outputs (`txid:vout`). Frozen outputs are stored as
+/// `spendable: false` output labels, so they travel with label exports.
+pub fn btc_freeze_utxos(
+    storage: &LocalStorageImpl,
+    wallet_address: String,
+    outpoints: Vec<String>,
+    frozen: bool,
+) -> Result<(), String> {
+    set_frozen(storage, &wallet_address, &outpoints, frozen)
+}
+
+fn consolidate_utxos(
+    storage: &dyn KeyValueStorage,
+    wallet_address: &str,
+    params: BtcConsolidateParamsInfo,
+    metadata: TransactionMetadataInfo,
+) -> Result<BtcCoinSelectionInfo, String> {
+    let network = Network::from_str(&params.network)
+        .map_err(|e| format!("Invalid network {}: {}", params.network, e))?;
+    let fee_rate = parse_fee_rate(params.fee_rate)?;
+    let destination = parse_address(&params.destination_address, network)?;
+    let frozen = load_frozen(storage, wallet_address)?;
+    let utxos = unfrozen(parse_utxos(params.utxos)?, &frozen, false).map_err(|e| e.to_string())?;
+
+    let selection = consolidate(
+        &utxos,
+        destination.script_pubkey(),
+        fee_rate,
+        params.max_value.map(Amount::from_sat),
+    )
+    .map_err(|e| e.to_string())?;
+
+    selection_info(selection, metadata)
+}
+
+/// Merge small unfrozen UTXOs into one output, usually a fresh address of
+/// the wallet, at a low fee rate. Signs like any other BTC request.
+pub fn btc_consolidate_utxos(
+    storage: &LocalStorageImpl,
+    wallet_address: String,
+    params: BtcConsolidateParamsInfo,
+    metadata: TransactionMetadataInfo,
+) -> Result<BtcCoinSelectionInfo, String> {
+    consolidate_utxos(storage, &wallet_address, params, metadata)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::btc::{create_transaction, BtcRecipientInfo, BtcTxParamsInfo};
+    use crate::api::local_storage::MemoryStorage;
+    use crate::btc::testing::{address, metadata, p2wpkh};
+    use crate::models::btc::BtcCoinSelectionStrategy;
+
+    const WALLET: &str = "w";
+
+    fn utxos() -> Vec<BtcUtxoInfo> {
+        let script = p2wpkh(0xaa);
+        [(0, 100_000), (1, 3_000), (2, 4_000)]
+            .into_iter()
+            .map(|(vout, value)| BtcUtxoInfo {
+                txid: "11".repeat(32),
+                vout,
+                value,
+                script_pubkey: script.to_hex_string(),
+                satisfaction_weight: None,
+            })
+            .collect()
+    }
+
+    fn outpoint(vout: u32) -> String {
+        format!("{}:{}", "11".repeat(32), vout)
+    }
+
+    fn params(strategy: BtcCoinSelectionStrategy) -> BtcTxParamsInfo {
+        BtcTxParamsInfo {
+            utxos: utxos(),
+            recipients: vec![BtcRecipientInfo {
+                address: address(0xbb),
+                value: 50_000,
+            }],
+            change_address: address(0xcc),
+            network: "bitcoin".to_string(),
+            fee_rate: 1.0,
+            strategy,
+        }
+    }
+
+    #[test]
+    fn test_freeze_keeps_label_text() {
+        let storage = MemoryStorage::default();
+        let mut labeled = Label::new(LabelType::Output, outpoint(0), Some("salary".into()));
+        labeled.origin = Some("wpkh(...)".into());
+        store_labels(&storage, WALLET, &[labeled]).unwrap();
+
+        set_frozen(&storage, WALLET, &[outpoint(0), outpoint(1)], true).unwrap();
+        assert_eq!(load_frozen(&storage, WALLET).unwrap().len(), 2);
+
+        set_frozen(&storage, WALLET, &[outpoint(0), outpoint(1)], false).unwrap();
+        let labels = load_labels(&storage, WALLET).unwrap();
+        assert_eq!(labels.len(), 1);
+        assert_eq!(labels[0].label.as_deref(), Some("salary"));
+        assert_eq!(labels[0].spendable, None);
+
+        assert!(set_frozen(&storage, WALLET, &["nope".into()], true).is_err());
+    }
+
+    #[test]
+    fn test_frozen_utxos_are_not_selected() {
+        let storage = MemoryStorage::default();
+        set_frozen(&storage, WALLET, &[outpoint(0)], true).unwrap();
+
+        // Only the frozen one covers the payment
+        assert!(create_transaction(
+            &storage,
+            WALLET,
+            params(BtcCoinSelectionStrategy::LargestFirst),
+            metadata(),
+            None,
+        )
+        .unwrap_err()
+        .contains("Insufficient funds"));
+        assert!(create_transaction(
+            &storage,
+            WALLET,
+            params(BtcCoinSelectionStrategy::Manual),
+            metadata(),
+            None,
+        )
+        .unwrap_err()
+        .contains("frozen"));
+
+        set_frozen(&storage, WALLET, &[outpoint(0)], false).unwrap();
+        let created = create_transaction(
+            &storage,
+            WALLET,
+            params(BtcCoinSelectionStrategy::Manual),
+            metadata(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(created.selected.len(), 3);
+    }
+
+    #[test]
+    fn test_consolidate_skips_frozen() {
+        let storage = MemoryStorage::default();
+        let consolidate_params = || BtcConsolidateParamsInfo {
+            utxos: utxos(),
+            destination_address: address(0xdd),
+            network: "bitcoin".to_string(),
+            fee_rate: 1.0,
+            max_value: Some(10_000),
+        };
+
+        let merged = consolidate_utxos(&storage, WALLET, consolidate_params(), metadata()).unwrap();
+        assert_eq!(merged.selected.len(), 2);
+        assert_eq!(merged.change, None);
+        assert!(merged.tx.metadata.btc_witness_utxos.is_some());
+
+        set_frozen(&storage, WALLET, &[outpoint(1)], true).unwrap();
+        assert!(consolidate_utxos(&storage, WALLET, consolidate_params(), metadata()).is_err());
+    }
+}
@@ -0,0 +1,257 @@
+use std::collections::{BTreeMap, HashSet};
+
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::Network;
+use secrecy::zeroize::Zeroize;
+use secrecy::SecretString;
+use zilpay::background::bg_provider::ProvidersManagement;
+use zilpay::background::bg_wallet::WalletManagement;
+use zilpay::crypto::slip44;
+use zilpay::wallet::wallet_account::AccountManagement;
+use zilpay::wallet::wallet_crypto::WalletCrypto;
+use zilpay::wallet::wallet_storage::StorageOperations;
+
+use crate::btc::discovery::{
+    account_xpub, discover_accounts, AccountChains, AccountScan, ChainScan, DiscoveryError,
+};
+use crate::btc::electrum::ElectrumClient;
+use crate::btc::watch_only::SingleSigType;
+pub use crate::models::btc::BtcDiscoveredAccountInfo;
+use crate::models::wallet::WalletInfo;
+use crate::service::app_storage::with_app_storage;
+use crate::service::service::BACKGROUND_SERVICE;
+use crate::utils::errors::ServiceError;
+use crate::utils::utils::bitcoin_network;
+
+pub const DEFAULT_GAP_LIMIT: u32 = 20;
+/// Wallet account indices are a byte.
+const MAX_ACCOUNTS: u32 = 256;
+
+fn used_addresses(
+    scan: &AccountScan,
+    network: Network,
+    change: bool,
+    chain: &ChainScan,
+) -> Result<Vec<String>, String> {
+    let secp = Secp256k1::verification_only();
+    chain
+        .used
+        .iter()
+        .map(|&index| {
+            scan.chains
+                .address(&secp, network, change, index)
+                .map(|a| a.to_string())
+                .map_err(|e| e.to_string())
+        })
+        .collect()
+}
+
+fn discovered_key(wallet_address: &str) -> String {
+    format!("btc_discovered:{}", wallet_address)
+}
+
+/// Used addresses discovery found besides each account's own one, the
+/// first receive address, by account index. Wallet accounts hold one
+/// address, so these live in the app storage.
+pub(crate) fn discovered_addresses(
+    wallet_address: &str,
+) -> Result<BTreeMap<usize, Vec<String>>, String> {
+    with_app_storage(|storage| {
+        storage
+            .get(discovered_key(wallet_address))
+            .map(|json| serde_json::from_str(&json).map_err(|e| e.to_string()))
+            .unwrap_or(Ok(BTreeMap::new()))
+    })
+}
+
+pub(crate) fn remove_discovered_addresses(wallet_address: &str) -> Result<(), String> {
+    with_app_storage(|storage| storage.rm(discovered_key(wallet_address)))
+}
+
+/// Add the used addresses of `scans` past index 0 of the receive chain
+/// to the stored ones.
+fn save_discovered(
+    wallet_address: &str,
+    scans: &[AccountScan],
+    network: Network,
+) -> Result<(), String> {
+    let mut discovered = discovered_addresses(wallet_address)?;
+    for scan in scans {
+        let receive = ChainScan {
+            used: scan
+                .receive
+                .used
+                .iter()
+                .copied()
+                .filter(|&i| i > 0)
+                .collect(),
+            ..Default::default()
+        };
+        let found = used_addresses(scan, network, false, &receive)?
+            .into_iter()
+            .chain(used_addresses(scan, network, true, &scan.change)?);
+        let addresses = discovered.entry(scan.account as usize).or_default();
+        for address in found {
+            if !addresses.contains(&address) {
+                addresses.push(address);
+            }
+        }
+    }
+    discovered.retain(|_, addresses| !addresses.is_empty());
+
+    let json = serde_json::to_string(&discovered).map_err(|e| e.to_string())?;
+    with_app_storage(|storage| storage.set(discovered_key(wallet_address), json))
+}
+
+fn account_info(
+    scan: &AccountScan,
+    network: Network,
+    added: bool,
+) -> Result<BtcDiscoveredAccountInfo, String> {
+    let secp = Secp256k1::verification_only();
+    let next = |change: bool, chain: &ChainScan| -> Result<String, String> {
+        scan.chains
+            .address(&secp, network, change, chain.next_index())
+            .map(|a| a.to_string())
+            .map_err(|e| e.to_string())
+    };
+
+    Ok(BtcDiscoveredAccountInfo {
+        account: scan.account,
+        used_receive: used_addresses(scan, network, false, &scan.receive)?,
+        used_change: used_addresses(scan, network, true, &scan.change)?,
+        next_receive: next(false, &scan.receive)?,
+        next_change: next(true, &scan.change)?,
+        added,
+    })
+}
+
+/// Restore a BIP-39 BTC wallet from a seed used elsewhere: look up the
+/// receive and change chains of each account on the chain's Electrum
+/// servers, stopping `gap_limit` unused addresses after the last used one
+/// and at the first account without history. Used accounts missing from
+/// the wallet are added. Used addresses past each account's first one are
+/// kept for the Electrum backend, which syncs their balances and UTXOs
+/// into the account; the HTTP backend only sees account addresses.
+pub async fn btc_bip39_discover(
+    wallet_index: usize,
+    password: String,
+    passphrase: Option<String>,
+    gap_limit: Option<u32>,
+) -> Result<Vec<BtcDiscoveredAccountInfo>, String> {
+    let gap_limit = gap_limit.unwrap_or(DEFAULT_GAP_LIMIT).max(1);
+    let passphrase = passphrase.unwrap_or_default();
+
+    let (seed, mut bip39_seed, network, script_type, rpc, known) = {
+        let guard = BACKGROUND_SERVICE.read().await;
+        let service = guard.as_ref().ok_or(ServiceError::NotRunning)?;
+
+        let mut password = SecretString::new(password.into());
+        let seed = service
+            .core
+            .unlock_wallet_with_password(&password, None, wallet_index)
+            .await
+            .map_err(ServiceError::BackgroundError)?;
+        password.zeroize();
+
+        let wallet = service
+            .core
+            .get_wallet_by_index(wallet_index)
+            .map_err(ServiceError::BackgroundError)?;
+        let wallet_data = wallet
+            .get_wallet_data()
+            .map_err(|e| ServiceError::WalletError(wallet_index, e))?;
+        let chain = service
+            .core
+            .get_provider(wallet_data.chain_hash)
+            .map_err(ServiceError::BackgroundError)?;
+        if chain.config.slip_44 != slip44::BITCOIN {
+            return Err("Address discovery is only supported for Bitcoin".into());
+        }
+        let network = bitcoin_network(&chain.config).ok_or(ServiceError::AccountTypeNotValid)?;
+        let script_type = SingleSigType::from_bip_purpose(wallet_data.bip)
+            .ok_or_else(|| format!("Unsupported BIP purpose {}", wallet_data.bip))?;
+
+        let info =
+            WalletInfo::try_from(wallet).map_err(|e| ServiceError::WalletError(wallet_index, e))?;
+        let known: HashSet<usize> = info
+            .accounts
+            .get(&info.slip44)
+            .and_then(|bips| bips.get(&info.bip))
+            .map(|accounts| accounts.iter().map(|a| a.index).collect())
+            .unwrap_or_default();
+
+        let mnemonic = wallet
+            .reveal_mnemonic(&seed)
+            .map_err(|e| ServiceError::WalletError(wallet_index, e))?;
+        let bip39_seed = mnemonic.to_seed(&passphrase).map_err(|e| e.to_string())?;
+
+        (
+            seed,
+            bip39_seed,
+            network,
+            script_type,
+            chain.config.rpc.clone(),
+            known,
+        )
+    };
+
+    // Electrum I/O blocks, keep it off the runtime workers
+    let task = tokio::task::spawn_blocking(move || {
+        let secp = Secp256k1::new();
+        let scans = ElectrumClient::connect_any(&rpc)
+            .map_err(|e| DiscoveryError::Lookup(e.to_string()))
+            .and_then(|client| {
+                discover_accounts(
+                    &secp,
+                    gap_limit,
+                    MAX_ACCOUNTS,
+                    |account| {
+                        Ok(AccountChains {
+                            xpub: account_xpub(&secp, &bip39_seed, network, script_type, account)?,
+                            script_type,
+                        })
+                    },
+                    client,
+                )
+            });
+        bip39_seed.zeroize();
+        scans
+    })
+    .await;
+    // The task wiped its own copy
+    bip39_seed.zeroize();
+    let scans = task
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())?;
+
+    let guard = BACKGROUND_SERVICE.read().await;
+    let service = guard.as_ref().ok_or(ServiceError::NotRunning)?;
+    let wallet = service
+        .core
+        .get_wallet_by_index(wallet_index)
+        .map_err(ServiceError::BackgroundError)?;
+
+    let wallet_address = hex::encode(wallet.wallet_address);
+    save_discovered(&wallet_address, &scans, network)?;
+
+    scans
+        .iter()
+        .map(|scan| {
+            let index = scan.account as usize;
+            let added = scan.is_used() && !known.contains(&index);
+            if added {
+                wallet
+                    .add_next_bip39_account(
+                        format!("Account {}", index + 1),
+                        index,
+                        Some(network),
+                        &passphrase,
+                        &seed,
+                    )
+                    .map_err(|e| ServiceError::WalletError(wallet_index, e))?;
+            }
+            account_info(scan, network, added)
+        })
+        .collect()
+}
@@ -0,0 +1,510 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use bitcoin::{Script, ScriptBuf, Transaction, Txid};
+use tokio::sync::mpsc;
+use zilpay::background::bg_provider::ProvidersManagement;
+use zilpay::background::bg_wallet::WalletManagement;
+use zilpay::crypto::slip44;
+use zilpay::proto::U256;
+use zilpay::wallet::wallet_storage::StorageOperations;
+use zilpay::wallet::wallet_token::TokenManagement;
+
+use crate::api::btc::parse_address;
+use crate::api::btc_discovery::discovered_addresses;
+use crate::btc::coin_control::confirmations;
+use crate::btc::electrum::{electrum_servers, Balance, ElectrumClient, ElectrumError};
+use crate::btc::history::{merge_histories, net_amount};
+use crate::frb_generated::StreamSink;
+pub use crate::models::btc::{BtcBackend, BtcBalanceEvent, BtcBalanceInfo, BtcHistoryItemInfo};
+use crate::models::provider::{chain_btc_backend, ChainConfig};
+use crate::models::wallet::WalletInfo;
+use crate::service::service::BACKGROUND_SERVICE;
+use crate::utils::errors::ServiceError;
+use crate::utils::utils::{bitcoin_network, with_service};
+
+const RECONNECT_SECS: u64 = 10;
+
+/// A Bitcoin chain syncs over the Electrum protocol when its config's
+/// `btc_backend` says so, through the `tcp://` or `ssl://` servers of its
+/// `rpc` list, e.g. `tcp://127.0.0.1:50001` for a local electrs.
+fn backend(config: &ChainConfig) -> BtcBackend {
+    if config.slip_44 == slip44::BITCOIN {
+        chain_btc_backend(config.hash())
+    } else {
+        BtcBackend::Http
+    }
+}
+
+/// An address the wallet syncs, of the account at `account_index`.
+pub(crate) struct WatchedAddress {
+    pub account_index: usize,
+    pub address: String,
+    pub script: ScriptBuf,
+}
+
+/// A Bitcoin wallet with the Electrum servers of its chain.
+pub(crate) struct ElectrumWallet {
+    pub wallet_address: String,
+    pub chain_hash: u64,
+    pub servers: Vec<String>,
+    /// Account addresses, by account index
+    pub addresses: Vec<String>,
+    /// The account addresses, then the used ones `btc_bip39_discover` found
+    pub watched: Vec<WatchedAddress>,
+}
+
+impl ElectrumWallet {
+    pub(crate) fn scripts(&self) -> Vec<ScriptBuf> {
+        self.watched.iter().map(|w| w.script.clone()).collect()
+    }
+
+    /// Sum balances of `watched`, in its order, into one per account.
+    pub(crate) fn account_balances(&self, balances: &[Balance]) -> Vec<Balance> {
+        let mut totals = vec![
+            Balance {
+                confirmed: 0,
+                unconfirmed: 0,
+            };
+            self.addresses.len()
+        ];
+        for (watched, balance) in self.watched.iter().zip(balances) {
+            let total = &mut totals[watched.account_index];
+            total.confirmed = total.confirmed.saturating_add(balance.confirmed);
+            total.unconfirmed = total.unconfirmed.saturating_add(balance.unconfirmed);
+        }
+        totals
+    }
+
+    /// `with_electrum` over the wallet's servers, `f` also gets the
+    /// watched scripts.
+    pub(crate) async fn with_client<T, F>(&self, f: F) -> Result<T, String>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut ElectrumClient, &[&Script]) -> Result<T, ElectrumError> + Send + 'static,
+    {
+        let scripts = self.scripts();
+        with_electrum(self.servers.clone(), move |client| {
+            let scripts: Vec<&Script> = scripts.iter().map(|s| s.as_script()).collect();
+            f(client, &scripts)
+        })
+        .await
+    }
+}
+
+/// Connect to one of `servers` and run `f` on the blocking pool, the
+/// Electrum socket I/O would stall a runtime worker otherwise.
+pub(crate) async fn with_electrum<T, F>(servers: Vec<String>, f: F) -> Result<T, String>
+where
+    T: Send + 'static,
+    F: FnOnce(&mut ElectrumClient) -> Result<T, ElectrumError> + Send + 'static,
+{
+    tokio::task::spawn_blocking(move || {
+        let mut client = ElectrumClient::connect_any(&servers)?;
+        f(&mut client)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| e.to_string())
+}
+
+pub(crate) async fn electrum_wallet(wallet_index: usize) -> Result<ElectrumWallet, String> {
+    let (wallet_address, chain_hash, servers, network, addresses) = with_service(|core| {
+        let wallet = core.get_wallet_by_index(wallet_index)?;
+        let data = wallet
+            .get_wallet_data()
+            .map_err(|e| ServiceError::WalletError(wallet_index, e))?;
+        let chain = core.get_provider(data.chain_hash)?;
+        let network = bitcoin_network(&chain.config).ok_or(ServiceError::AccountTypeNotValid)?;
+        let info =
+            WalletInfo::try_from(wallet).map_err(|e| ServiceError::WalletError(wallet_index, e))?;
+        let accounts: Vec<(usize, String)> = info
+            .accounts
+            .get(&info.slip44)
+            .and_then(|bips| bips.get(&info.bip))
+            .map(|accounts| accounts.iter().map(|a| (a.index, a.addr.clone())).collect())
+            .unwrap_or_default();
+
+        Ok((
+            hex::encode(wallet.wallet_address),
+            chain.config.hash(),
+            electrum_servers(&chain.config.rpc),
+            network,
+            accounts,
+        ))
+    })
+    .await?;
+
+    let mut discovered = discovered_addresses(&wallet_address)?;
+    let mut extra = Vec::new();
+    for (account_index, (index, _)) in accounts.iter().enumerate() {
+        let found = discovered.remove(index).unwrap_or_default();
+        extra.extend(found.into_iter().map(|address| (account_index, address)));
+    }
+    let addresses: Vec<String> = accounts.into_iter().map(|(_, address)| address).collect();
+    let watched = addresses
+        .iter()
+        .cloned()
+        .enumerate()
+        .chain(extra)
+        .map(|(account_index, address)| {
+            Ok(WatchedAddress {
+                account_index,
+                script: parse_address(&address, network)?.script_pubkey(),
+                address,
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    Ok(ElectrumWallet {
+        wallet_address,
+        chain_hash,
+        servers,
+        addresses,
+        watched,
+    })
+}
+
+pub(crate) async fn uses_electrum(wallet_index: usize) -> Result<bool, String> {
+    with_service(|core| {
+        let wallet = core.get_wallet_by_index(wallet_index)?;
+        let data = wallet
+            .get_wallet_data()
+            .map_err(|e| ServiceError::WalletError(wallet_index, e))?;
+        let chain = core.get_provider(data.chain_hash)?;
+
+        Ok(backend(&chain.config) == BtcBackend::Electrum)
+    })
+    .await
+    .map_err(Into::into)
+}
+
+fn balance_infos(addresses: &[String], balances: &[Balance]) -> Vec<BtcBalanceInfo> {
+    addresses
+        .iter()
+        .zip(balances)
+        .enumerate()
+        .map(|(account_index, (address, balance))| BtcBalanceInfo {
+            account_index,
+            address: address.clone(),
+            confirmed: balance.confirmed,
+            unconfirmed: balance.unconfirmed,
+        })
+        .collect()
+}
+
+/// Spendable once the mempool settles: confirmed plus the mempool change.
+fn total(balance: &Balance) -> u64 {
+    balance.confirmed.saturating_add_signed(balance.unconfirmed)
+}
+
+/// Write account balances into the chain's native token, where
+/// `sync_balances` puts them for the other backends.
+async fn store_balances(
+    wallet_index: usize,
+    chain_hash: u64,
+    balances: &[Balance],
+) -> Result<(), String> {
+    with_service(|core| {
+        let wallet = core.get_wallet_by_index(wallet_index)?;
+        let mut ftokens = wallet
+            .get_ftokens()
+            .map_err(|e| ServiceError::WalletError(wallet_index, e))?;
+        for token in ftokens
+            .iter_mut()
+            .filter(|t| t.native && t.chain_hash == chain_hash)
+        {
+            token.balances = balances
+                .iter()
+                .enumerate()
+                .map(|(account_index, balance)| (account_index, U256::from(total(balance))))
+                .collect();
+        }
+        wallet
+            .save_ftokens(&ftokens)
+            .map_err(|e| ServiceError::WalletError(wallet_index, e))?;
+
+        Ok(())
+    })
+    .await
+    .map_err(Into::into)
+}
+
+/// Balances of the wallet's accounts from its chain's Electrum servers,
+/// also stored on the native token. `sync_balances` calls this for
+/// Electrum backed chains.
+pub async fn btc_electrum_sync_balances(
+    wallet_index: usize,
+) -> Result<Vec<BtcBalanceInfo>, String> {
+    let wallet = electrum_wallet(wallet_index).await?;
+    let balances = wallet
+        .with_client(|client, scripts| client.scripts_balance(scripts))
+        .await?;
+    let balances = wallet.account_balances(&balances);
+    store_balances(wallet_index, wallet.chain_hash, &balances).await?;
+
+    Ok(balance_infos(&wallet.addresses, &balances))
+}
+
+/// Transactions of all the wallet's accounts, mempool ones first, with
+/// what each moved in or out of the wallet.
+pub async fn btc_electrum_history(wallet_index: usize) -> Result<Vec<BtcHistoryItemInfo>, String> {
+    let wallet = electrum_wallet(wallet_index).await?;
+    let (tip, history, wallet_txs) = wallet
+        .with_client(|client, scripts| {
+            let tip = client.tip_height()?;
+            let history = merge_histories(&client.scripts_history(scripts)?)
+                .map_err(|e| ElectrumError::Response(e.to_string()))?;
+            let txids: Vec<Txid> = history.iter().map(|(txid, _)| *txid).collect();
+            let wallet_txs: HashMap<Txid, Transaction> = txids
+                .iter()
+                .copied()
+                .zip(client.transactions(&txids)?)
+                .collect();
+
+            Ok((tip, history, wallet_txs))
+        })
+        .await?;
+    let scripts: HashSet<ScriptBuf> = wallet.scripts().into_iter().collect();
+
+    Ok(history
+        .into_iter()
+        .map(|(txid, height)| BtcHistoryItemInfo {
+            txid: txid.to_string(),
+            height: height.max(0) as u32,
+            confirmations: confirmations(height, tip),
+            amount: net_amount(&wallet_txs[&txid], &wallet_txs, &scripts),
+        })
+        .collect())
+}
+
+/// Broadcast a signed transaction through the chain's Electrum servers.
+pub async fn btc_electrum_broadcast(chain_hash: u64, tx_hex: String) -> Result<String, String> {
+    let servers = with_service(|core| {
+        let chain = core.get_provider(chain_hash)?;
+        bitcoin_network(&chain.config).ok_or(ServiceError::AccountTypeNotValid)?;
+
+        Ok(electrum_servers(&chain.config.rpc))
+    })
+    .await?;
+    let bytes = hex::decode(&tx_hex).map_err(|e| format!("Invalid transaction hex: {}", e))?;
+    let tx: Transaction = bitcoin::consensus::encode::deserialize(&bytes)
+        .map_err(|e| format!("Invalid transaction: {}", e))?;
+
+    let txid = with_electrum(servers, move |client| client.broadcast(&tx)).await?;
+
+    Ok(txid.to_string())
+}
+
+/// One connection: balances right after subscribing and again on every
+/// status change, until stopped or the receiver is gone.
+fn watch_session(
+    servers: &[String],
+    scripts: &[&Script],
+    stop: &AtomicBool,
+    events: &mpsc::Sender<Result<Vec<Balance>, String>>,
+) -> Result<(), ElectrumError> {
+    let mut client = ElectrumClient::connect_any(servers)?;
+    client.scripts_subscribe(scripts)?;
+    let mut changed = true;
+
+    while !stop.load(Ordering::Relaxed) && !events.is_closed() {
+        if changed {
+            let balances = client.scripts_balance(scripts)?;
+            if events.blocking_send(Ok(balances)).is_err() {
+                break;
+            }
+        }
+        changed = match client.next_notification()? {
+            Some(_) => true,
+            None => {
+                client.ping()?;
+                false
+            }
+        };
+    }
+
+    Ok(())
+}
+
+fn watch_balances(
+    servers: Vec<String>,
+    scripts: Vec<ScriptBuf>,
+    stop: Arc<AtomicBool>,
+    events: mpsc::Sender<Result<Vec<Balance>, String>>,
+) {
+    let scripts: Vec<&Script> = scripts.iter().map(|s| s.as_script()).collect();
+    while !stop.load(Ordering::Relaxed) && !events.is_closed() {
+        if let Err(e) = watch_session(&servers, &scripts, &stop, &events) {
+            if events.blocking_send(Err(e.to_string())).is_err() {
+                break;
+            }
+            // A timed out session is reopened right away, the server is up
+            if e != ElectrumError::Timeout {
+                thread::sleep(Duration::from_secs(RECONNECT_SECS));
+            }
+        }
+    }
+}
+
+/// Live balances of the wallet's accounts through Electrum scripthash
+/// subscriptions, stored on the native token as they change. Replaces a
+/// running subscription and reconnects after connection errors, which
+/// are reported as events.
+pub async fn btc_electrum_subscribe_balances(
+    wallet_index: usize,
+    sink: StreamSink<BtcBalanceEvent>,
+) -> Result<(), String> {
+    let wallet = electrum_wallet(wallet_index).await?;
+    let stop = Arc::new(AtomicBool::new(false));
+    {
+        let mut guard = BACKGROUND_SERVICE.write().await;
+        let service = guard.as_mut().ok_or(ServiceError::NotRunning)?;
+
+        if let Some(previous) = service.electrum_stop.replace(Arc::clone(&stop)) {
+            previous.store(true, Ordering::Relaxed);
+        }
+    }
+
+    let (tx, mut rx) = mpsc::channel(10);
+    let (servers, scripts) = (wallet.servers.clone(), wallet.scripts());
+    thread::spawn(move || watch_balances(servers, scripts, stop, tx));
+
+    while let Some(update) = rx.recv().await {
+        let stored = match update {
+            Ok(balances) => {
+                let balances = wallet.account_balances(&balances);
+                store_balances(wallet_index, wallet.chain_hash, &balances)
+                    .await
+                    .map(|_| balances)
+            }
+            Err(e) => Err(e),
+        };
+        let event = match stored {
+            Ok(balances) => BtcBalanceEvent {
+                balances: balance_infos(&wallet.addresses, &balances),
+                error: None,
+            },
+            Err(e) => BtcBalanceEvent {
+                balances: Vec::new(),
+                error: Some(e),
+            },
+        };
+        if sink.add(event).is_err() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn btc_electrum_unsubscribe_balances() -> Result<(), String> {
+    let mut guard = BACKGROUND_SERVICE.write().await;
+    let service = guard.as_mut().ok_or(ServiceError::NotRunning)?;
+
+    if let Some(stop) = service.electrum_stop.take() {
+        stop.store(true, Ordering::Relaxed);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::provider::{
+        init_chain_btc_backend, set_chain_btc_backend, NetworkConfigInfo,
+    };
+    use crate::service::app_storage::open_in_memory;
+
+    #[test]
+    fn test_backend() {
+        let config = |slip44: u32, backend: Option<&str>| {
+            let mut json = serde_json::json!({
+                "name": "Bitcoin",
+                "chain": "BTC",
+                "rpc": ["tcp://127.0.0.1:50001"],
+                "features": ["EIP155"],
+                "chainIds": [2, 0],
+                "slip44": slip44,
+            });
+            if let Some(backend) = backend {
+                json["backend"] = backend.into();
+            }
+            NetworkConfigInfo::from_json_value(&json).unwrap()
+        };
+
+        open_in_memory();
+
+        // Electrum server urls alone keep the default path
+        let info = config(slip44::BITCOIN, None);
+        assert_eq!(info.btc_backend, BtcBackend::Http);
+        let chain = ChainConfig::try_from(info).unwrap();
+        assert_eq!(backend(&chain), BtcBackend::Http);
+        assert_eq!(chain.features, vec![155]);
+
+        assert_eq!(
+            config(slip44::BITCOIN, Some("electrum")).btc_backend,
+            BtcBackend::Electrum
+        );
+        set_chain_btc_backend(chain.hash(), BtcBackend::Electrum).unwrap();
+        assert_eq!(backend(&chain), BtcBackend::Electrum);
+        // Bundled chain lists leave a picked backend alone
+        init_chain_btc_backend(chain.hash(), BtcBackend::Http).unwrap();
+        let info = NetworkConfigInfo::from(chain);
+        assert_eq!(info.btc_backend, BtcBackend::Electrum);
+        assert_eq!(info.features, vec![155]);
+
+        let chain = ChainConfig::try_from(config(slip44::ETHEREUM, Some("electrum"))).unwrap();
+        set_chain_btc_backend(chain.hash(), BtcBackend::Electrum).unwrap();
+        assert_eq!(backend(&chain), BtcBackend::Http);
+    }
+
+    #[test]
+    fn test_balances() {
+        let balances = [
+            Balance {
+                confirmed: 5_000,
+                unconfirmed: -2_000,
+            },
+            Balance {
+                confirmed: 0,
+                unconfirmed: 700,
+            },
+        ];
+        assert_eq!(total(&balances[0]), 3_000);
+        assert_eq!(total(&balances[1]), 700);
+
+        let infos = balance_infos(&["a".to_string(), "b".to_string()], &balances);
+        assert_eq!(infos[1].account_index, 1);
+        assert_eq!(infos[1].address, "b");
+        assert_eq!(infos[0].unconfirmed, -2_000);
+    }
+
+    #[test]
+    fn test_account_balances() {
+        let watched = |account_index: usize, address: &str| WatchedAddress {
+            account_index,
+            address: address.to_string(),
+            script: ScriptBuf::new(),
+        };
+        let wallet = ElectrumWallet {
+            wallet_address: String::new(),
+            chain_hash: 0,
+            servers: Vec::new(),
+            addresses: vec!["a".to_string(), "b".to_string()],
+            watched: vec![watched(0, "a"), watched(1, "b"), watched(0, "a2")],
+        };
+        let balance = |confirmed: u64, unconfirmed: i64| Balance {
+            confirmed,
+            unconfirmed,
+        };
+
+        let totals =
+            wallet.account_balances(&[balance(1_000, 0), balance(5, 0), balance(300, -50)]);
+        assert_eq!(totals, vec![balance(1_300, -50), balance(5, 0)]);
+    }
+}
@@ -0,0 +1,250 @@
+use std::time::Duration;
+
+use bitcoin::{Amount, Transaction, TxOut};
+use serde_json::Value;
+use zilpay::background::bg_provider::ProvidersManagement;
+use zilpay::crypto::slip44;
+
+use crate::api::btc_electrum::with_electrum;
+use crate::btc::fee_estimation::{
+    input_vsize, signed_vsize, FeeTiers, FAST_TARGET, MARKET_TARGET, SLOW_TARGET,
+};
+pub use crate::models::btc::BtcFeeEstimateInfo;
+use crate::models::gas::RequiredTxParamsInfo;
+use crate::models::transactions::request::TransactionRequestInfo;
+use crate::service::service::BACKGROUND_SERVICE;
+use crate::utils::errors::ServiceError;
+
+/// Per explorer, so a dead one cannot hold up the estimate.
+const EXPLORER_TIMEOUT_SECS: u64 = 10;
+
+async fn electrum_tiers(rpc: Vec<String>) -> Result<FeeTiers, String> {
+    let rates = with_electrum(rpc, |client| {
+        client.fee_rates(&[SLOW_TARGET, MARKET_TARGET, FAST_TARGET])
+    })
+    .await?;
+    match rates[..] {
+        [slow, market, fast, relay_fee] => {
+            Ok(FeeTiers::from_electrum(slow, market, fast, relay_fee))
+        }
+        _ => Err("Invalid Electrum fee estimates".into()),
+    }
+}
+
+/// Tries every explorer of the chain, those without the mempool.space
+/// fee endpoint fail or answer something else and are skipped.
+async fn mempool_tiers(explorers: &[String]) -> Option<FeeTiers> {
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(EXPLORER_TIMEOUT_SECS))
+        .build()
+        .ok()?;
+    for explorer in explorers {
+        let url = format!("{}/api/v1/fees/recommended", explorer.trim_end_matches('/'));
+        let Ok(response) = client
+            .get(&url)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+        else {
+            continue;
+        };
+        let Ok(recommended) = response.json::<Value>().await else {
+            continue;
+        };
+        if let Some(tiers) = FeeTiers::from_mempool(&recommended) {
+            return Some(tiers);
+        }
+    }
+    None
+}
+
+/// `estimatesmartfee` through the chain's Electrum servers, falling back
+/// to the fee endpoint of the chain's mempool.space style explorers.
+async fn fee_tiers(chain_hash: u64) -> Result<FeeTiers, String> {
+    let (rpc, explorers) = {
+        let guard = BACKGROUND_SERVICE.read().await;
+        let service = guard.as_ref().ok_or(ServiceError::NotRunning)?;
+        let chain = service
+            .core
+            .get_provider(chain_hash)
+            .map_err(ServiceError::BackgroundError)?;
+        if chain.config.slip_44 != slip44::BITCOIN {
+            return Err("Fee rate estimation is only supported for Bitcoin".into());
+        }
+        let explorers: Vec<String> = chain
+            .config
+            .explorers
+            .iter()
+            .map(|e| e.url.clone())
+            .collect();
+
+        (chain.config.rpc.clone(), explorers)
+    };
+
+    match electrum_tiers(rpc).await {
+        Ok(tiers) => Ok(tiers),
+        Err(e) => mempool_tiers(&explorers).await.ok_or(e),
+    }
+}
+
+fn pending_tx(tx: &TransactionRequestInfo) -> Result<(Transaction, Vec<TxOut>), String> {
+    let tx_hex = tx.btc.as_deref().ok_or("Not a Bitcoin transaction")?;
+    let bytes = hex::decode(tx_hex).map_err(|e| format!("Invalid transaction hex: {}", e))?;
+    let unsigned_tx: Transaction = bitcoin::consensus::encode::deserialize(&bytes)
+        .map_err(|e| format!("Invalid transaction: {}", e))?;
+    let witness_utxos: Vec<TxOut> = tx
+        .metadata
+        .btc_witness_utxos
+        .as_deref()
+        .map(serde_json::from_str)
+        .transpose()
+        .map_err(|e| format!("Failed to parse witness UTXOs: {}", e))?
+        .ok_or("Missing witness UTXOs")?;
+
+    Ok((unsigned_tx, witness_utxos))
+}
+
+fn estimate_info(
+    tiers: FeeTiers,
+    pending: Option<&(Transaction, Vec<TxOut>)>,
+) -> Result<BtcFeeEstimateInfo, String> {
+    let (vsize, input_vsizes) = match pending {
+        Some((tx, prevouts)) => (
+            Some(signed_vsize(tx, prevouts).map_err(|e| e.to_string())?),
+            prevouts
+                .iter()
+                .map(input_vsize)
+                .collect::<Result<_, _>>()
+                .map_err(|e| e.to_string())?,
+        ),
+        None => (None, Vec::new()),
+    };
+
+    Ok(BtcFeeEstimateInfo {
+        slow: tiers.slow,
+        market: tiers.market,
+        fast: tiers.fast,
+        vsize,
+        input_vsizes,
+    })
+}
+
+/// What `cacl_gas_fee` returns for Bitcoin. `slow`, `market` and `fast`
+/// are total fees in satoshis for the signed size in `tx_estimate_gas`,
+/// `current` is the fee the transaction pays as built and `gas_price` the
+/// market rate rounded up.
+fn required_params(
+    tiers: FeeTiers,
+    tx: &Transaction,
+    prevouts: &[TxOut],
+) -> Result<RequiredTxParamsInfo, String> {
+    let vsize = signed_vsize(tx, prevouts).map_err(|e| e.to_string())?;
+    let fee = |rate: f64| (rate * vsize as f64).ceil() as u64;
+    let input_value: Amount = prevouts.iter().map(|o| o.value).sum();
+    let output_value: Amount = tx.output.iter().map(|o| o.value).sum();
+    let current = input_value
+        .checked_sub(output_value)
+        .ok_or("Outputs are worth more than the inputs")?;
+
+    Ok(RequiredTxParamsInfo {
+        gas_price: tiers.market.ceil() as u128,
+        tx_estimate_gas: vsize,
+        slow: fee(tiers.slow).to_string(),
+        market: fee(tiers.market).to_string(),
+        fast: fee(tiers.fast).to_string(),
+        current: current.to_sat().to_string(),
+        ..Default::default()
+    })
+}
+
+/// Slow, market and fast fee rates in sat/vB. With `tx`, an unsigned
+/// transaction from `btc_create_transaction` or the like, also its virtual
+/// size once signed, counted for the script type of each input.
+pub async fn btc_estimate_fees(
+    chain_hash: u64,
+    tx: Option<TransactionRequestInfo>,
+) -> Result<BtcFeeEstimateInfo, String> {
+    let pending = tx.as_ref().map(pending_tx).transpose()?;
+    let tiers = fee_tiers(chain_hash).await?;
+
+    estimate_info(tiers, pending.as_ref())
+}
+
+pub(crate) async fn btc_required_tx_params(
+    tx: &TransactionRequestInfo,
+) -> Result<RequiredTxParamsInfo, String> {
+    let (unsigned_tx, prevouts) = pending_tx(tx)?;
+    let tiers = fee_tiers(tx.metadata.chain_hash).await?;
+
+    required_params(tiers, &unsigned_tx, &prevouts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::hashes::Hash;
+    use bitcoin::{
+        absolute, transaction, OutPoint, ScriptBuf, Sequence, TxIn, Txid, WPubkeyHash, Witness,
+    };
+
+    fn p2wpkh(tag: u8) -> ScriptBuf {
+        ScriptBuf::new_p2wpkh(&WPubkeyHash::from_byte_array([tag; 20]))
+    }
+
+    fn pending() -> (Transaction, Vec<TxOut>) {
+        let prevouts = vec![TxOut {
+            value: Amount::from_sat(100_000),
+            script_pubkey: p2wpkh(0xaa),
+        }];
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::from_byte_array([0x11; 32]), 0),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: Witness::new(),
+            }],
+            output: vec![
+                TxOut {
+                    value: Amount::from_sat(60_000),
+                    script_pubkey: p2wpkh(0xbb),
+                },
+                TxOut {
+                    value: Amount::from_sat(39_718),
+                    script_pubkey: p2wpkh(0xaa),
+                },
+            ],
+        };
+        (tx, prevouts)
+    }
+
+    fn tiers() -> FeeTiers {
+        FeeTiers::new(Some(1.5), Some(2.0), Some(10.0), 1.0)
+    }
+
+    #[test]
+    fn test_required_params() {
+        let (tx, prevouts) = pending();
+        let params = required_params(tiers(), &tx, &prevouts).unwrap();
+
+        assert_eq!(params.tx_estimate_gas, 141);
+        assert_eq!(params.gas_price, 2);
+        assert_eq!(params.slow, "212");
+        assert_eq!(params.market, "282");
+        assert_eq!(params.fast, "1410");
+        assert_eq!(params.current, "282");
+    }
+
+    #[test]
+    fn test_estimate_info() {
+        let info = estimate_info(tiers(), Some(&pending())).unwrap();
+        assert_eq!(info.vsize, Some(141));
+        assert_eq!(info.input_vsizes, vec![68]);
+        assert_eq!((info.slow, info.market, info.fast), (1.5, 2.0, 10.0));
+
+        let info = estimate_info(tiers(), None).unwrap();
+        assert_eq!(info.vsize, None);
+        assert!(info.input_vsizes.is_empty());
+    }
+}
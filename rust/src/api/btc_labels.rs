@@ -0,0 +1,184 @@
+use std::str::FromStr;
+
+use crate::api::local_storage::{KeyValueStorage, LocalStorageImpl};
+use crate::btc::labels::{merge, parse_jsonl, to_jsonl, Label, LabelType};
+pub use crate::models::btc::{BtcLabelImportInfo, BtcLabelInfo};
+
+const LABELS_STORAGE_PREFIX: &str = "btc_labels";
+
+fn labels_storage_key(wallet_address: &str) -> String {
+    format!("{}:{}", LABELS_STORAGE_PREFIX, wallet_address)
+}
+
+pub(crate) fn load_labels(
+    storage: &dyn KeyValueStorage,
+    wallet_address: &str,
+) -> Result<Vec<Label>, String> {
+    storage
+        .get(labels_storage_key(wallet_address))
+        .map(|json| serde_json::from_str(&json).map_err(|e| e.to_string()))
+        .unwrap_or(Ok(Vec::new()))
+}
+
+pub(crate) fn store_labels(
+    storage: &dyn KeyValueStorage,
+    wallet_address: &str,
+    labels: &[Label],
+) -> Result<(), String> {
+    let json = serde_json::to_string(labels).map_err(|e| e.to_string())?;
+    storage.set(labels_storage_key(wallet_address), json)
+}
+
+fn import_labels(
+    storage: &dyn KeyValueStorage,
+    wallet_address: &str,
+    jsonl: &str,
+) -> Result<BtcLabelImportInfo, String> {
+    let (incoming, errors) = parse_jsonl(jsonl);
+    let mut labels = load_labels(storage, wallet_address)?;
+    let imported = merge(&mut labels, incoming);
+    store_labels(storage, wallet_address, &labels)?;
+
+    Ok(BtcLabelImportInfo {
+        imported: imported as u32,
+        errors: errors.iter().map(ToString::to_string).collect(),
+    })
+}
+
+fn set_label(
+    storage: &dyn KeyValueStorage,
+    wallet_address: &str,
+    info: BtcLabelInfo,
+) -> Result<(), String> {
+    let label_type = LabelType::from_str(&info.label_type)?;
+    let mut labels = load_labels(storage, wallet_address)?;
+    let position = labels
+        .iter()
+        .position(|l| l.label_type == label_type && l.reference == info.reference);
+
+    if info.label.is_none() && info.spendable.is_none() {
+        if let Some(position) = position {
+            labels.remove(position);
+        }
+        return store_labels(storage, wallet_address, &labels);
+    }
+
+    // Keep fields other wallets exported along with the record
+    let mut label = position
+        .map(|p| labels.remove(p))
+        .unwrap_or_else(|| Label::new(label_type, info.reference, None));
+    label.label = info.label;
+    label.origin = info.origin.or(label.origin);
+    label.spendable = info.spendable;
+    label.validate().map_err(|e| e.to_string())?;
+
+    match position {
+        Some(position) => labels.insert(position, label),
+        None => labels.push(label),
+    }
+    store_labels(storage, wallet_address, &labels)
+}
+
+/// Merge a BIP-329 JSONL export (Sparrow, Electrum, ...) into the wallet's
+/// labels. Records replace existing ones with the same type and ref; lines
+/// that fail to parse are skipped and reported.
+pub fn btc_import_labels(
+    storage: &LocalStorageImpl,
+    wallet_address: String,
+    jsonl: String,
+) -> Result<BtcLabelImportInfo, String> {
+    import_labels(storage, &wallet_address, &jsonl)
+}
+
+/// All labels of the wallet as a BIP-329 JSONL file.
+pub fn btc_export_labels(
+    storage: &LocalStorageImpl,
+    wallet_address: String,
+) -> Result<String, String> {
+    to_jsonl(&load_labels(storage, &wallet_address)?).map_err(|e| e.to_string())
+}
+
+pub fn btc_get_labels(
+    storage: &LocalStorageImpl,
+    wallet_address: String,
+) -> Result<Vec<BtcLabelInfo>, String> {
+    Ok(load_labels(storage, &wallet_address)?
+        .into_iter()
+        .map(|l| BtcLabelInfo {
+            label_type: l.label_type.to_string(),
+            reference: l.reference,
+            label: l.label,
+            origin: l.origin,
+            spendable: l.spendable,
+        })
+        .collect())
+}
+
+/// Add or update one label. A label with neither text nor `spendable` is
+/// removed.
+pub fn btc_set_label(
+    storage: &LocalStorageImpl,
+    wallet_address: String,
+    label: BtcLabelInfo,
+) -> Result<(), String> {
+    set_label(storage, &wallet_address, label)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::local_storage::MemoryStorage;
+
+    const WALLET: &str = "0xwallet";
+    const TXID: &str = "f91d0a8a78462bc59398f2c5d7a84fcff491c26ba54c4833478b202796c8aafd";
+
+    fn info(label_type: &str, reference: String, label: Option<&str>) -> BtcLabelInfo {
+        BtcLabelInfo {
+            label_type: label_type.to_string(),
+            reference,
+            label: label.map(str::to_string),
+            origin: None,
+            spendable: None,
+        }
+    }
+
+    #[test]
+    fn test_import_set_export() {
+        let storage = MemoryStorage::default();
+        let jsonl = format!(
+            "{{\"type\":\"tx\",\"ref\":\"{TXID}\",\"label\":\"Salary\",\"height\":800000}}\n\
+             {{\"type\":\"output\",\"ref\":\"{TXID}:0\",\"label\":\"Change\"}}\n\
+             {{\"type\":\"addr\",\"ref\":\"nope\",\"label\":\"Bad\"}}\n"
+        );
+
+        let report = import_labels(&storage, WALLET, &jsonl).unwrap();
+        assert_eq!(report.imported, 2);
+        assert_eq!(report.errors.len(), 1);
+
+        set_label(&storage, WALLET, info("tx", TXID.into(), Some("Bonus"))).unwrap();
+        let mut frozen = info("output", format!("{TXID}:0"), None);
+        frozen.spendable = Some(false);
+        set_label(&storage, WALLET, frozen).unwrap();
+        assert!(set_label(&storage, WALLET, info("addr", "nope".into(), Some("x"))).is_err());
+        assert!(set_label(&storage, WALLET, info("wallet", TXID.into(), Some("x"))).is_err());
+
+        let labels = load_labels(&storage, WALLET).unwrap();
+        assert_eq!(labels.len(), 2);
+        assert_eq!(labels[0].label.as_deref(), Some("Bonus"));
+        assert_eq!(labels[0].extra["height"], 800000);
+        assert_eq!(labels[1].label, None);
+        assert_eq!(labels[1].spendable, Some(false));
+
+        let exported = to_jsonl(&labels).unwrap();
+        let other = MemoryStorage::default();
+        assert_eq!(
+            import_labels(&other, WALLET, &exported).unwrap().imported,
+            2
+        );
+        assert_eq!(load_labels(&other, WALLET).unwrap(), labels);
+
+        set_label(&storage, WALLET, info("tx", TXID.into(), None)).unwrap();
+        assert_eq!(load_labels(&storage, WALLET).unwrap().len(), 1);
+        assert!(load_labels(&storage, "0xother").unwrap().is_empty());
+    }
+}
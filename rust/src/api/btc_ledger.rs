@@ -5,10 +5,17 @@ use bitcoin::psbt::Psbt;
 use bitcoin::secp256k1::Secp256k1;
 use bitcoin::Transaction as BitcoinTransaction;
 use bitcoin::Witness;
+use bitcoin::{Network, NetworkKind};
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use std::str::FromStr;
 use zilpay::crypto::bip49::DerivationPath;
 
+use crate::api::ledger_transport::RustLedgerError;
+use crate::api::local_storage::{KeyValueStorage, LocalStorageImpl};
+use crate::btc::network::coin_type;
+use crate::btc::psbt::decode as decode_psbt;
+
 // --- Merkle Tree ---
 
 /// Leaf hash: SHA256(0x00 || data)
@@ -114,7 +121,7 @@ pub fn btc_ledger_get_merkle_leaf_index(leaf_hashes: Vec<Vec<u8>>, target_hash:
 // --- Varint Encoding ---
 
 /// Encode a value as a Bitcoin-style varint.
-fn encode_varint(value: u64) -> Vec<u8> {
+pub(crate) fn encode_varint(value: u64) -> Vec<u8> {
     if value < 0xFD {
         vec![value as u8]
     } else if value <= 0xFFFF {
@@ -191,6 +198,8 @@ pub struct MerkleProof {
 
 /// Wallet policy for Ledger BTC app
 pub struct WalletPolicy {
+    /// Policy name shown on the device, empty for default wallets
+    pub name: String,
     /// Descriptor template string, e.g. "wpkh(@0)"
     pub descriptor_template: String,
     /// Keys info strings, e.g. ["[fingerprint/84'/0'/0']xpub..."]
@@ -444,8 +453,7 @@ fn build_v2_output_maps(psbt: &Psbt) -> Vec<(Vec<Vec<u8>>, Vec<Vec<u8>>)> {
 }
 
 /// Decode a Bitcoin-style varint. Returns (value, bytes_consumed).
-#[cfg(test)]
-fn decode_varint(data: &[u8]) -> (u64, usize) {
+pub(crate) fn decode_varint(data: &[u8]) -> (u64, usize) {
     if data.is_empty() {
         return (0, 0);
     }
@@ -469,8 +477,7 @@ fn decode_varint(data: &[u8]) -> (u64, usize) {
 /// Decompose a PSBT into merkle structures for Ledger signing.
 /// Converts PSBTv0 to PSBTv2 key-value maps as required by the Ledger Bitcoin app v2.
 pub fn btc_ledger_merkelise_psbt(psbt_bytes: Vec<u8>) -> Result<MerkelizedPsbt, String> {
-    let psbt: Psbt =
-        Psbt::deserialize(&psbt_bytes).map_err(|e| format!("Failed to parse PSBT: {}", e))?;
+    let psbt: Psbt = decode_psbt(&psbt_bytes).map_err(|e| e.to_string())?;
 
     let input_count = psbt.unsigned_tx.input.len();
     let output_count = psbt.unsigned_tx.output.len();
@@ -564,6 +571,17 @@ pub fn btc_ledger_merkelise_psbt(psbt_bytes: Vec<u8>) -> Result<MerkelizedPsbt,
 
 // --- Wallet Policy ---
 
+const POLICY_VERSION_V2: u8 = 0x02;
+const MAX_POLICY_NAME_LEN: usize = 64;
+const POLICIES_STORAGE_PREFIX: &str = "btc_ledger_policies";
+
+/// BIP-44 coin type of Ledger account paths on `network`.
+fn network_coin_type(network: &str) -> Result<u32, String> {
+    let network =
+        Network::from_str(network).map_err(|e| format!("Invalid network {}: {}", network, e))?;
+    Ok(coin_type(network))
+}
+
 /// Map BIP purpose to Ledger wallet descriptor template.
 fn descriptor_template_for_bip(bip: u32) -> Option<String> {
     match bip {
@@ -580,25 +598,39 @@ fn descriptor_template_for_bip(bip: u32) -> Option<String> {
 /// `master_fingerprint`: 4-byte master key fingerprint
 /// `bip_purpose`: BIP purpose number (44, 49, 84, 86)
 /// `account_index`: the account index in the derivation path
+/// `network`: the chain's network, which `xpub` must belong to
 pub fn btc_ledger_build_wallet_policy(
     xpub: String,
     master_fingerprint: Vec<u8>,
     bip_purpose: u32,
     account_index: u32,
+    network: String,
 ) -> Result<WalletPolicy, String> {
     if master_fingerprint.len() != 4 {
         return Err("Master fingerprint must be 4 bytes".into());
     }
+    let network =
+        Network::from_str(&network).map_err(|e| format!("Invalid network {}: {}", network, e))?;
+    let prefix = match NetworkKind::from(network) {
+        NetworkKind::Main => "xpub",
+        NetworkKind::Test => "tpub",
+    };
+    if !xpub.starts_with(prefix) {
+        return Err(format!("Expected a {} key on {}", prefix, network));
+    }
 
     let descriptor_template = descriptor_template_for_bip(bip_purpose)
         .ok_or_else(|| format!("Unknown BIP purpose: {}", bip_purpose))?;
 
     // Key info format: [fingerprint/purpose'/cointype'/account']xpub
-    // Bitcoin mainnet coin type = 0, testnet = 1
     let fp_hex = hex::encode(&master_fingerprint);
     let key_info = format!(
-        "[{}/{}'/0'/{}']{}/**",
-        fp_hex, bip_purpose, account_index, xpub
+        "[{}/{}'/{}'/{}']{}/**",
+        fp_hex,
+        bip_purpose,
+        coin_type(network),
+        account_index,
+        xpub
     );
     let keys_info = vec![key_info.clone()];
 
@@ -622,6 +654,7 @@ pub fn btc_ledger_build_wallet_policy(
     let policy_id = btc_ledger_sha256(serialized.clone());
 
     Ok(WalletPolicy {
+        name: String::new(),
         descriptor_template,
         keys_info,
         policy_id,
@@ -630,6 +663,180 @@ pub fn btc_ledger_build_wallet_policy(
     })
 }
 
+/// Key placeholder indices (`@N`) of a descriptor template, in order of
+/// appearance.
+fn template_key_indices(descriptor_template: &str) -> Result<Vec<usize>, String> {
+    let mut indices = Vec::new();
+    let mut rest = descriptor_template;
+    while let Some(pos) = rest.find('@') {
+        rest = &rest[pos + 1..];
+        let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        let index = rest[..digits]
+            .parse::<usize>()
+            .map_err(|_| format!("Invalid key placeholder in {}", descriptor_template))?;
+        indices.push(index);
+        rest = &rest[digits..];
+    }
+    Ok(indices)
+}
+
+/// Build a named wallet policy (version 2) for REGISTER_WALLET from any
+/// descriptor template the BTC app accepts, e.g.
+/// `wsh(sortedmulti(2,@0/**,@1/**))` or `tr(@0/**,{pk(@1/**),pk(@2/**)})`.
+/// `keys_info` are `[fingerprint/path]xpub` strings, derivation steps such
+/// as `/**` belong in the template.
+pub fn btc_ledger_build_registered_policy(
+    name: String,
+    descriptor_template: String,
+    keys_info: Vec<String>,
+) -> Result<WalletPolicy, String> {
+    if name.is_empty() || name.len() > MAX_POLICY_NAME_LEN || !name.is_ascii() {
+        return Err(format!(
+            "Policy name must be 1-{} ASCII characters",
+            MAX_POLICY_NAME_LEN
+        ));
+    }
+    if keys_info.is_empty() {
+        return Err("Policy needs at least one key".into());
+    }
+    if let Some(key) = keys_info.iter().find(|k| k.ends_with("/**")) {
+        return Err(format!("Key {} must not carry the /** suffix", key));
+    }
+
+    let indices = template_key_indices(&descriptor_template)?;
+    if let Some(index) = indices.iter().find(|i| **i >= keys_info.len()) {
+        return Err(format!(
+            "Template uses @{} but has {} keys",
+            index,
+            keys_info.len()
+        ));
+    }
+    if let Some(unused) = (0..keys_info.len()).find(|i| !indices.contains(i)) {
+        return Err(format!("Key @{} is not used by the template", unused));
+    }
+
+    // version(1) || name_len(1) || name || varint(desc_len) || SHA256(descriptor)
+    // || varint(key_count) || keys_merkle_root(32)
+    let key_leaves: Vec<Vec<u8>> = keys_info
+        .iter()
+        .map(|k| btc_ledger_hash_leaf(k.as_bytes().to_vec()))
+        .collect();
+    let keys_root = build_merkle_tree(&key_leaves);
+
+    let mut serialized = vec![POLICY_VERSION_V2, name.len() as u8];
+    serialized.extend_from_slice(name.as_bytes());
+    serialized.extend_from_slice(&encode_varint(descriptor_template.len() as u64));
+    serialized.extend_from_slice(&btc_ledger_sha256(descriptor_template.as_bytes().to_vec()));
+    serialized.extend_from_slice(&encode_varint(keys_info.len() as u64));
+    serialized.extend_from_slice(&keys_root);
+
+    let policy_id = btc_ledger_sha256(serialized.clone());
+
+    Ok(WalletPolicy {
+        name,
+        descriptor_template,
+        keys_info,
+        policy_id,
+        policy_hmac: vec![0u8; 32], // set by btc_ledger_register_wallet
+        serialized,
+    })
+}
+
+// --- Registered Policies ---
+
+/// What is kept in storage for a registered policy, everything else is
+/// derived again on load.
+#[derive(Serialize, Deserialize)]
+struct StoredPolicy {
+    name: String,
+    descriptor_template: String,
+    keys_info: Vec<String>,
+    policy_hmac: String,
+}
+
+fn policies_storage_key(wallet_address: &str) -> String {
+    format!("{}:{}", POLICIES_STORAGE_PREFIX, wallet_address)
+}
+
+fn load_policies(
+    storage: &dyn KeyValueStorage,
+    wallet_address: &str,
+) -> Result<Vec<WalletPolicy>, String> {
+    let Some(json) = storage.get(policies_storage_key(wallet_address)) else {
+        return Ok(Vec::new());
+    };
+    let stored: Vec<StoredPolicy> = serde_json::from_str(&json).map_err(|e| e.to_string())?;
+
+    stored
+        .into_iter()
+        .map(|p| {
+            let mut policy =
+                btc_ledger_build_registered_policy(p.name, p.descriptor_template, p.keys_info)?;
+            policy.policy_hmac = hex::decode(p.policy_hmac).map_err(|e| e.to_string())?;
+            Ok(policy)
+        })
+        .collect()
+}
+
+fn store_policies(
+    storage: &dyn KeyValueStorage,
+    wallet_address: &str,
+    policies: &[WalletPolicy],
+) -> Result<(), String> {
+    let stored: Vec<StoredPolicy> = policies
+        .iter()
+        .map(|p| StoredPolicy {
+            name: p.name.clone(),
+            descriptor_template: p.descriptor_template.clone(),
+            keys_info: p.keys_info.clone(),
+            policy_hmac: hex::encode(&p.policy_hmac),
+        })
+        .collect();
+    let json = serde_json::to_string(&stored).map_err(|e| e.to_string())?;
+    storage.set(policies_storage_key(wallet_address), json)
+}
+
+fn save_policy(
+    storage: &dyn KeyValueStorage,
+    wallet_address: &str,
+    policy: WalletPolicy,
+) -> Result<(), String> {
+    if policy.policy_hmac.len() != 32 || policy.policy_hmac.iter().all(|b| *b == 0) {
+        return Err("Policy is not registered on the device".into());
+    }
+    let mut policies = load_policies(storage, wallet_address)?;
+    policies.retain(|p| p.policy_id != policy.policy_id);
+    policies.push(policy);
+    store_policies(storage, wallet_address, &policies)
+}
+
+/// Keep a registered policy and its HMAC for the wallet identified by
+/// `wallet_address` (as returned when the wallet was added).
+pub fn btc_ledger_save_registered_policy(
+    storage: &LocalStorageImpl,
+    wallet_address: String,
+    policy: WalletPolicy,
+) -> Result<(), String> {
+    save_policy(storage, &wallet_address, policy)
+}
+
+pub fn btc_ledger_get_registered_policies(
+    storage: &LocalStorageImpl,
+    wallet_address: String,
+) -> Result<Vec<WalletPolicy>, String> {
+    load_policies(storage, &wallet_address)
+}
+
+pub fn btc_ledger_remove_registered_policy(
+    storage: &LocalStorageImpl,
+    wallet_address: String,
+    policy_id: Vec<u8>,
+) -> Result<(), String> {
+    let mut policies = load_policies(storage, &wallet_address)?;
+    policies.retain(|p| p.policy_id != policy_id);
+    store_policies(storage, &wallet_address, &policies)
+}
+
 // --- PSBT Finalization with Ledger Signatures ---
 
 /// Finalize a PSBT with signatures collected from Ledger device.
@@ -641,8 +848,7 @@ pub fn btc_ledger_finalize_psbt_with_sigs(
     sigs: Vec<LedgerInputSignature>,
     addr_type: u32,
 ) -> Result<FinalizedBtcTx, String> {
-    let mut psbt: Psbt =
-        Psbt::deserialize(&psbt_bytes).map_err(|e| format!("Failed to parse PSBT: {}", e))?;
+    let mut psbt: Psbt = decode_psbt(&psbt_bytes).map_err(|e| e.to_string())?;
 
     let btc_addr_type = match addr_type {
         DerivationPath::BIP44_PURPOSE => bitcoin::AddressType::P2pkh,
@@ -819,8 +1025,7 @@ pub fn btc_ledger_prepare_psbt(
         return Err("Master fingerprint must be 4 bytes".into());
     }
 
-    let mut psbt: Psbt =
-        Psbt::deserialize(&psbt_bytes).map_err(|e| format!("Failed to parse PSBT: {}", e))?;
+    let mut psbt: Psbt = decode_psbt(&psbt_bytes).map_err(|e| e.to_string())?;
 
     let secp = Secp256k1::verification_only();
     let account_xpub = Xpub::from_str(&xpub).map_err(|e| format!("Failed to parse xpub: {}", e))?;
@@ -833,7 +1038,8 @@ pub fn btc_ledger_prepare_psbt(
     // Build the hardened portion of the derivation path: purpose'/cointype'/account'
     let account_path = vec![
         ChildNumber::from_hardened_idx(bip_purpose).map_err(|e| e.to_string())?,
-        ChildNumber::from_hardened_idx(0).map_err(|e| e.to_string())?, // cointype 0 = mainnet
+        ChildNumber::from_hardened_idx(coin_type(account_xpub.network))
+            .map_err(|e| e.to_string())?,
         ChildNumber::from_hardened_idx(account_index).map_err(|e| e.to_string())?,
     ];
 
@@ -1067,9 +1273,324 @@ pub fn btc_ledger_encode_path(path: String) -> Result<Vec<u8>, String> {
     Ok(buf)
 }
 
+// --- Device ---
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+use crate::api::btc_message::{
+    btc_message_prepare_psbt, btc_message_signature_from_psbt, parse_address, BtcMessageFormat,
+};
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+use crate::btc::message::signature_from_compact;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+use crate::ledger::btc as ledger_btc;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+use crate::ledger::registry;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+use crate::utils::utils::ensure_wallet_can_sign;
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub async fn btc_ledger_get_master_fingerprint(
+    connection_id: String,
+) -> Result<Vec<u8>, RustLedgerError> {
+    let mut session = registry::session(&connection_id).await?;
+    ledger_btc::get_master_fingerprint(&mut session)
+        .await
+        .map_err(Into::into)
+}
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub async fn btc_ledger_get_extended_pubkey(
+    connection_id: String,
+    path: String,
+    display: bool,
+) -> Result<String, RustLedgerError> {
+    let encoded_path = btc_ledger_encode_path(path).map_err(RustLedgerError::Service)?;
+    let mut session = registry::session(&connection_id).await?;
+    ledger_btc::get_extended_pubkey(&mut session, &encoded_path, display)
+        .await
+        .map_err(Into::into)
+}
+
+/// Fetch fingerprint and account xpub and build the default wallet policy.
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+async fn device_wallet_policy(
+    session: &mut registry::Session,
+    bip_purpose: u32,
+    account_index: u32,
+    network: &str,
+) -> Result<(Vec<u8>, String, WalletPolicy), RustLedgerError> {
+    let coin_type = network_coin_type(network).map_err(RustLedgerError::Service)?;
+    let fingerprint = ledger_btc::get_master_fingerprint(session).await?;
+    let account_path = format!("m/{}'/{}'/{}'", bip_purpose, coin_type, account_index);
+    let encoded_path = btc_ledger_encode_path(account_path).map_err(RustLedgerError::Service)?;
+    let xpub = ledger_btc::get_extended_pubkey(session, &encoded_path, false).await?;
+    let policy = btc_ledger_build_wallet_policy(
+        xpub.clone(),
+        fingerprint.clone(),
+        bip_purpose,
+        account_index,
+        network.to_string(),
+    )
+    .map_err(RustLedgerError::Service)?;
+
+    Ok((fingerprint, xpub, policy))
+}
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub async fn btc_ledger_get_wallet_address(
+    connection_id: String,
+    bip_purpose: u32,
+    account_index: u32,
+    change: bool,
+    address_index: u32,
+    display: bool,
+    network: String,
+) -> Result<String, RustLedgerError> {
+    let mut session = registry::session(&connection_id).await?;
+    let (_, _, policy) =
+        device_wallet_policy(&mut session, bip_purpose, account_index, &network).await?;
+    ledger_btc::get_wallet_address(&mut session, &policy, change, address_index, display)
+        .await
+        .map_err(Into::into)
+}
+
+/// Sign `psbt_bytes` on the device and finalize it. The whole SIGN_PSBT
+/// client-command exchange runs in Rust. Refused for key-less wallets.
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub async fn btc_ledger_sign_psbt(
+    connection_id: String,
+    wallet_index: usize,
+    psbt_bytes: Vec<u8>,
+    bip_purpose: u32,
+    account_index: u32,
+    network: String,
+) -> Result<FinalizedBtcTx, RustLedgerError> {
+    ensure_wallet_can_sign(wallet_index)
+        .await
+        .map_err(|e| RustLedgerError::Service(e.to_string()))?;
+    let mut session = registry::session(&connection_id).await?;
+    let (fingerprint, xpub, policy) =
+        device_wallet_policy(&mut session, bip_purpose, account_index, &network).await?;
+    let prepared =
+        btc_ledger_prepare_psbt(psbt_bytes, fingerprint, bip_purpose, account_index, xpub)
+            .map_err(RustLedgerError::Service)?;
+    let merkelized =
+        btc_ledger_merkelise_psbt(prepared.clone()).map_err(RustLedgerError::Service)?;
+
+    let sigs = ledger_btc::sign_psbt(&mut session, &merkelized, &policy)
+        .await?
+        .into_iter()
+        .map(|(input_index, pubkey, signature)| LedgerInputSignature {
+            input_index,
+            signature,
+            pubkey,
+        })
+        .collect();
+
+    btc_ledger_finalize_psbt_with_sigs(prepared, sigs, bip_purpose)
+        .map_err(RustLedgerError::Service)
+}
+
+/// Sign `message` for `address`, the key at `change`/`address_index` of
+/// the account. Legacy signatures go through SIGN_MESSAGE, BIP-322 ones
+/// sign the `to_sign` PSBT. The signature is verified before it is
+/// returned.
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+#[allow(clippy::too_many_arguments)]
+pub async fn btc_ledger_sign_message(
+    connection_id: String,
+    wallet_index: usize,
+    address: String,
+    network: String,
+    message: String,
+    format: BtcMessageFormat,
+    bip_purpose: u32,
+    account_index: u32,
+    change: bool,
+    address_index: u32,
+) -> Result<String, RustLedgerError> {
+    ensure_wallet_can_sign(wallet_index)
+        .await
+        .map_err(|e| RustLedgerError::Service(e.to_string()))?;
+
+    if !matches!(format, BtcMessageFormat::Legacy) {
+        let psbt_bytes =
+            btc_message_prepare_psbt(address.clone(), network.clone(), message.clone())
+                .map_err(RustLedgerError::Service)?;
+        let signed = btc_ledger_sign_psbt(
+            connection_id,
+            wallet_index,
+            psbt_bytes,
+            bip_purpose,
+            account_index,
+            network.clone(),
+        )
+        .await?;
+        return btc_message_signature_from_psbt(
+            address,
+            network,
+            message,
+            signed.psbt_bytes,
+            format,
+        )
+        .map_err(RustLedgerError::Service);
+    }
+
+    let parsed = parse_address(&address, &network).map_err(RustLedgerError::Service)?;
+    let coin_type = network_coin_type(&network).map_err(RustLedgerError::Service)?;
+    let path = format!(
+        "m/{}'/{}'/{}'/{}/{}",
+        bip_purpose, coin_type, account_index, change as u32, address_index
+    );
+    let encoded_path = btc_ledger_encode_path(path).map_err(RustLedgerError::Service)?;
+    let mut session = registry::session(&connection_id).await?;
+    let compact = ledger_btc::sign_message(&mut session, &encoded_path, message.as_bytes()).await?;
+    signature_from_compact(&Secp256k1::verification_only(), &parsed, &message, &compact)
+        .map_err(|e| RustLedgerError::Service(e.to_string()))
+}
+
+/// Register a named policy on the device (the user confirms every key) and
+/// return it with the HMAC filled in.
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub async fn btc_ledger_register_wallet(
+    connection_id: String,
+    mut policy: WalletPolicy,
+) -> Result<WalletPolicy, RustLedgerError> {
+    let mut session = registry::session(&connection_id).await?;
+    policy.policy_hmac = ledger_btc::register_wallet(&mut session, &policy).await?;
+    Ok(policy)
+}
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub async fn btc_ledger_get_policy_address(
+    connection_id: String,
+    policy: WalletPolicy,
+    change: bool,
+    address_index: u32,
+    display: bool,
+) -> Result<String, RustLedgerError> {
+    let mut session = registry::session(&connection_id).await?;
+    ledger_btc::get_wallet_address(&mut session, &policy, change, address_index, display)
+        .await
+        .map_err(Into::into)
+}
+
+/// Sign a PSBT that already carries the key origins of `policy`. Returns
+/// the raw signatures, finalizing is left to the caller since it depends on
+/// the script.
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub async fn btc_ledger_sign_psbt_with_policy(
+    connection_id: String,
+    psbt_bytes: Vec<u8>,
+    policy: WalletPolicy,
+) -> Result<Vec<LedgerInputSignature>, RustLedgerError> {
+    let merkelized = btc_ledger_merkelise_psbt(psbt_bytes).map_err(RustLedgerError::Service)?;
+    let mut session = registry::session(&connection_id).await?;
+    let sigs = ledger_btc::sign_psbt(&mut session, &merkelized, &policy).await?;
+
+    Ok(sigs
+        .into_iter()
+        .map(|(input_index, pubkey, signature)| LedgerInputSignature {
+            input_index,
+            signature,
+            pubkey,
+        })
+        .collect())
+}
+
+#[cfg(any(target_os = "android", target_os = "ios"))]
+const NOT_SUPPORTED: &str = "Ledger Bitcoin app not supported on mobile";
+
+#[cfg(any(target_os = "android", target_os = "ios"))]
+pub async fn btc_ledger_get_master_fingerprint(
+    _connection_id: String,
+) -> Result<Vec<u8>, RustLedgerError> {
+    Err(RustLedgerError::Io(NOT_SUPPORTED.to_string()))
+}
+
+#[cfg(any(target_os = "android", target_os = "ios"))]
+pub async fn btc_ledger_get_extended_pubkey(
+    _connection_id: String,
+    _path: String,
+    _display: bool,
+) -> Result<String, RustLedgerError> {
+    Err(RustLedgerError::Io(NOT_SUPPORTED.to_string()))
+}
+
+#[cfg(any(target_os = "android", target_os = "ios"))]
+pub async fn btc_ledger_get_wallet_address(
+    _connection_id: String,
+    _bip_purpose: u32,
+    _account_index: u32,
+    _change: bool,
+    _address_index: u32,
+    _display: bool,
+    _network: String,
+) -> Result<String, RustLedgerError> {
+    Err(RustLedgerError::Io(NOT_SUPPORTED.to_string()))
+}
+
+#[cfg(any(target_os = "android", target_os = "ios"))]
+pub async fn btc_ledger_sign_psbt(
+    _connection_id: String,
+    _wallet_index: usize,
+    _psbt_bytes: Vec<u8>,
+    _bip_purpose: u32,
+    _account_index: u32,
+    _network: String,
+) -> Result<FinalizedBtcTx, RustLedgerError> {
+    Err(RustLedgerError::Io(NOT_SUPPORTED.to_string()))
+}
+
+#[cfg(any(target_os = "android", target_os = "ios"))]
+#[allow(clippy::too_many_arguments)]
+pub async fn btc_ledger_sign_message(
+    _connection_id: String,
+    _wallet_index: usize,
+    _address: String,
+    _network: String,
+    _message: String,
+    _format: crate::api::btc_message::BtcMessageFormat,
+    _bip_purpose: u32,
+    _account_index: u32,
+    _change: bool,
+    _address_index: u32,
+) -> Result<String, RustLedgerError> {
+    Err(RustLedgerError::Io(NOT_SUPPORTED.to_string()))
+}
+
+#[cfg(any(target_os = "android", target_os = "ios"))]
+pub async fn btc_ledger_register_wallet(
+    _connection_id: String,
+    _policy: WalletPolicy,
+) -> Result<WalletPolicy, RustLedgerError> {
+    Err(RustLedgerError::Io(NOT_SUPPORTED.to_string()))
+}
+
+#[cfg(any(target_os = "android", target_os = "ios"))]
+pub async fn btc_ledger_get_policy_address(
+    _connection_id: String,
+    _policy: WalletPolicy,
+    _change: bool,
+    _address_index: u32,
+    _display: bool,
+) -> Result<String, RustLedgerError> {
+    Err(RustLedgerError::Io(NOT_SUPPORTED.to_string()))
+}
+
+#[cfg(any(target_os = "android", target_os = "ios"))]
+pub async fn btc_ledger_sign_psbt_with_policy(
+    _connection_id: String,
+    _psbt_bytes: Vec<u8>,
+    _policy: WalletPolicy,
+) -> Result<Vec<LedgerInputSignature>, RustLedgerError> {
+    Err(RustLedgerError::Io(NOT_SUPPORTED.to_string()))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::api::local_storage::MemoryStorage;
 
     fn test_hasher(data: &[u8]) -> Vec<u8> {
         data.to_vec()
@@ -1341,8 +1862,14 @@ mod tests {
         let fp = vec![0xc5, 0x5d, 0x68, 0x95];
         let xpub = "xpub6CatWdiZiodmUeTDp8LT5or8nmbKNcuyvz7WyksVFkKB4RHwCD3XYuvFvS3w9TF1joB3Nq5LKFpCGRb5k5Jcc9L4CUmXA4wC9gPgL3ep6D1".to_string();
 
-        let policy =
-            btc_ledger_build_wallet_policy(xpub, fp, DerivationPath::BIP84_PURPOSE, 0).unwrap();
+        let policy = btc_ledger_build_wallet_policy(
+            xpub,
+            fp,
+            DerivationPath::BIP84_PURPOSE,
+            0,
+            "bitcoin".into(),
+        )
+        .unwrap();
 
         assert_eq!(
             policy.serialized[0], 0x01,
@@ -1355,9 +1882,14 @@ mod tests {
         let fp = vec![0xc5, 0x5d, 0x68, 0x95];
         let xpub = "xpub6CatWdiZiodmUeTDp8LT5or8nmbKNcuyvz7WyksVFkKB4RHwCD3XYuvFvS3w9TF1joB3Nq5LKFpCGRb5k5Jcc9L4CUmXA4wC9gPgL3ep6D1".to_string();
 
-        let policy =
-            btc_ledger_build_wallet_policy(xpub.clone(), fp, DerivationPath::BIP84_PURPOSE, 0)
-                .unwrap();
+        let policy = btc_ledger_build_wallet_policy(
+            xpub.clone(),
+            fp,
+            DerivationPath::BIP84_PURPOSE,
+            0,
+            "bitcoin".into(),
+        )
+        .unwrap();
 
         assert!(
             policy.keys_info[0].ends_with("/**"),
@@ -1374,8 +1906,14 @@ mod tests {
         let fp = vec![0xc5, 0x5d, 0x68, 0x95];
         let xpub = "xpub6CatWdiZiodmUeTDp8LT5or8nmbKNcuyvz7WyksVFkKB4RHwCD3XYuvFvS3w9TF1joB3Nq5LKFpCGRb5k5Jcc9L4CUmXA4wC9gPgL3ep6D1".to_string();
 
-        let policy =
-            btc_ledger_build_wallet_policy(xpub, fp, DerivationPath::BIP84_PURPOSE, 0).unwrap();
+        let policy = btc_ledger_build_wallet_policy(
+            xpub,
+            fp,
+            DerivationPath::BIP84_PURPOSE,
+            0,
+            "bitcoin".into(),
+        )
+        .unwrap();
 
         assert_eq!(policy.descriptor_template, "wpkh(@0)");
         assert_eq!(policy.policy_id.len(), 32);
@@ -1389,8 +1927,14 @@ mod tests {
         let fp = vec![0xab, 0xcd, 0xef, 0x12];
         let xpub = "xpub6CatWdiZiodmUeTDp8LT5or8nmbKNcuyvz7WyksVFkKB4RHwCD3XYuvFvS3w9TF1joB3Nq5LKFpCGRb5k5Jcc9L4CUmXA4wC9gPgL3ep6D1".to_string();
 
-        let policy =
-            btc_ledger_build_wallet_policy(xpub, fp, DerivationPath::BIP44_PURPOSE, 0).unwrap();
+        let policy = btc_ledger_build_wallet_policy(
+            xpub,
+            fp,
+            DerivationPath::BIP44_PURPOSE,
+            0,
+            "bitcoin".into(),
+        )
+        .unwrap();
 
         assert_eq!(policy.descriptor_template, "pkh(@0)");
         assert!(policy.keys_info[0].starts_with("[abcdef12/44'/0'/0']"));
@@ -1401,8 +1945,14 @@ mod tests {
         let fp = vec![0x11, 0x22, 0x33, 0x44];
         let xpub = "xpub6CatWdiZiodmUeTDp8LT5or8nmbKNcuyvz7WyksVFkKB4RHwCD3XYuvFvS3w9TF1joB3Nq5LKFpCGRb5k5Jcc9L4CUmXA4wC9gPgL3ep6D1".to_string();
 
-        let policy =
-            btc_ledger_build_wallet_policy(xpub, fp, DerivationPath::BIP49_PURPOSE, 0).unwrap();
+        let policy = btc_ledger_build_wallet_policy(
+            xpub,
+            fp,
+            DerivationPath::BIP49_PURPOSE,
+            0,
+            "bitcoin".into(),
+        )
+        .unwrap();
 
         assert_eq!(policy.descriptor_template, "sh(wpkh(@0))");
         assert!(policy.keys_info[0].starts_with("[11223344/49'/0'/0']"));
@@ -1413,20 +1963,60 @@ mod tests {
         let fp = vec![0xaa, 0xbb, 0xcc, 0xdd];
         let xpub = "xpub6CatWdiZiodmUeTDp8LT5or8nmbKNcuyvz7WyksVFkKB4RHwCD3XYuvFvS3w9TF1joB3Nq5LKFpCGRb5k5Jcc9L4CUmXA4wC9gPgL3ep6D1".to_string();
 
-        let policy =
-            btc_ledger_build_wallet_policy(xpub, fp, DerivationPath::BIP86_PURPOSE, 0).unwrap();
+        let policy = btc_ledger_build_wallet_policy(
+            xpub,
+            fp,
+            DerivationPath::BIP86_PURPOSE,
+            0,
+            "bitcoin".into(),
+        )
+        .unwrap();
 
         assert_eq!(policy.descriptor_template, "tr(@0)");
         assert!(policy.keys_info[0].starts_with("[aabbccdd/86'/0'/0']"));
     }
 
+    #[test]
+    fn test_wallet_policy_test_network() {
+        let fp = vec![0xaa, 0xbb, 0xcc, 0xdd];
+        let tpub = "tpubDE7NQymr4AFtewpAsWtnreyq9ghkzQBXpCZjWLFVRAvnbf7vya2eMTvT2fPapNqL8SuVvLQdbUbMfWLVDCZKnsEBqp6UK93QEzL8Ck23AwF".to_string();
+
+        let policy = btc_ledger_build_wallet_policy(
+            tpub,
+            fp,
+            DerivationPath::BIP84_PURPOSE,
+            2,
+            "testnet".into(),
+        )
+        .unwrap();
+
+        assert!(policy.keys_info[0].starts_with("[aabbccdd/84'/1'/2']tpub"));
+
+        let fp = vec![0xaa, 0xbb, 0xcc, 0xdd];
+        let xpub = "xpub6CatWdiZiodmUeTDp8LT5or8nmbKNcuyvz7WyksVFkKB4RHwCD3XYuvFvS3w9TF1joB3Nq5LKFpCGRb5k5Jcc9L4CUmXA4wC9gPgL3ep6D1".to_string();
+        assert!(btc_ledger_build_wallet_policy(
+            xpub,
+            fp,
+            DerivationPath::BIP84_PURPOSE,
+            0,
+            "regtest".into()
+        )
+        .is_err());
+    }
+
     #[test]
     fn test_wallet_policy_serialization_format() {
         let fp = vec![0xc5, 0x5d, 0x68, 0x95];
         let xpub = "xpub6CatWdiZiodmUeTDp8LT5or8nmbKNcuyvz7WyksVFkKB4RHwCD3XYuvFvS3w9TF1joB3Nq5LKFpCGRb5k5Jcc9L4CUmXA4wC9gPgL3ep6D1".to_string();
 
-        let policy =
-            btc_ledger_build_wallet_policy(xpub, fp, DerivationPath::BIP84_PURPOSE, 0).unwrap();
+        let policy = btc_ledger_build_wallet_policy(
+            xpub,
+            fp,
+            DerivationPath::BIP84_PURPOSE,
+            0,
+            "bitcoin".into(),
+        )
+        .unwrap();
 
         assert_eq!(policy.serialized[0], 0x01, "version byte");
         assert_eq!(policy.serialized[1], 0x00, "empty wallet name");
@@ -1466,11 +2056,18 @@ mod tests {
             fp.clone(),
             DerivationPath::BIP84_PURPOSE,
             0,
+            "bitcoin".into(),
         )
         .unwrap();
 
-        let policy1 =
-            btc_ledger_build_wallet_policy(xpub, fp, DerivationPath::BIP84_PURPOSE, 1).unwrap();
+        let policy1 = btc_ledger_build_wallet_policy(
+            xpub,
+            fp,
+            DerivationPath::BIP84_PURPOSE,
+            1,
+            "bitcoin".into(),
+        )
+        .unwrap();
 
         assert_ne!(
             policy0.policy_id, policy1.policy_id,
@@ -1623,8 +2220,14 @@ mod tests {
         let fp = vec![0xc5, 0x5d, 0x68, 0x95];
         let xpub = "xpub6CatWdiZiodmUeTDp8LT5or8nmbKNcuyvz7WyksVFkKB4RHwCD3XYuvFvS3w9TF1joB3Nq5LKFpCGRb5k5Jcc9L4CUmXA4wC9gPgL3ep6D1".to_string();
 
-        let policy =
-            btc_ledger_build_wallet_policy(xpub, fp, DerivationPath::BIP84_PURPOSE, 0).unwrap();
+        let policy = btc_ledger_build_wallet_policy(
+            xpub,
+            fp,
+            DerivationPath::BIP84_PURPOSE,
+            0,
+            "bitcoin".into(),
+        )
+        .unwrap();
 
         assert_eq!(
             policy.serialized[1], 0x00,
@@ -1637,8 +2240,14 @@ mod tests {
         let fp = vec![0xc5, 0x5d, 0x68, 0x95];
         let xpub = "xpub6CatWdiZiodmUeTDp8LT5or8nmbKNcuyvz7WyksVFkKB4RHwCD3XYuvFvS3w9TF1joB3Nq5LKFpCGRb5k5Jcc9L4CUmXA4wC9gPgL3ep6D1".to_string();
 
-        let policy =
-            btc_ledger_build_wallet_policy(xpub, fp, DerivationPath::BIP84_PURPOSE, 0).unwrap();
+        let policy = btc_ledger_build_wallet_policy(
+            xpub,
+            fp,
+            DerivationPath::BIP84_PURPOSE,
+            0,
+            "bitcoin".into(),
+        )
+        .unwrap();
 
         let desc = "wpkh(@0)".as_bytes();
         assert_eq!(
@@ -1656,8 +2265,14 @@ mod tests {
         let fp = vec![0xc5, 0x5d, 0x68, 0x95];
         let xpub = "xpub6CatWdiZiodmUeTDp8LT5or8nmbKNcuyvz7WyksVFkKB4RHwCD3XYuvFvS3w9TF1joB3Nq5LKFpCGRb5k5Jcc9L4CUmXA4wC9gPgL3ep6D1".to_string();
 
-        let policy =
-            btc_ledger_build_wallet_policy(xpub, fp, DerivationPath::BIP84_PURPOSE, 0).unwrap();
+        let policy = btc_ledger_build_wallet_policy(
+            xpub,
+            fp,
+            DerivationPath::BIP84_PURPOSE,
+            0,
+            "bitcoin".into(),
+        )
+        .unwrap();
 
         let desc = "wpkh(@0)".as_bytes();
         let key_count_offset = 3 + desc.len();
@@ -1672,8 +2287,14 @@ mod tests {
         let fp = vec![0xc5, 0x5d, 0x68, 0x95];
         let xpub = "xpub6CatWdiZiodmUeTDp8LT5or8nmbKNcuyvz7WyksVFkKB4RHwCD3XYuvFvS3w9TF1joB3Nq5LKFpCGRb5k5Jcc9L4CUmXA4wC9gPgL3ep6D1".to_string();
 
-        let policy =
-            btc_ledger_build_wallet_policy(xpub, fp, DerivationPath::BIP84_PURPOSE, 0).unwrap();
+        let policy = btc_ledger_build_wallet_policy(
+            xpub,
+            fp,
+            DerivationPath::BIP84_PURPOSE,
+            0,
+            "bitcoin".into(),
+        )
+        .unwrap();
 
         let expected_id = btc_ledger_sha256(policy.serialized.clone());
         assert_eq!(
@@ -1713,7 +2334,13 @@ mod tests {
         let fp = vec![0x01, 0x02];
         let xpub = "xpub6CatWdiZiodmUeTDp8LT5or8nmbKNcuyvz7WyksVFkKB4RHwCD3XYuvFvS3w9TF1joB3Nq5LKFpCGRb5k5Jcc9L4CUmXA4wC9gPgL3ep6D1".to_string();
 
-        let result = btc_ledger_build_wallet_policy(xpub, fp, DerivationPath::BIP84_PURPOSE, 0);
+        let result = btc_ledger_build_wallet_policy(
+            xpub,
+            fp,
+            DerivationPath::BIP84_PURPOSE,
+            0,
+            "bitcoin".into(),
+        );
 
         assert!(result.is_err());
     }
@@ -1723,7 +2350,7 @@ mod tests {
         let fp = vec![0xc5, 0x5d, 0x68, 0x95];
         let xpub = "xpub6CatWdiZiodmUeTDp8LT5or8nmbKNcuyvz7WyksVFkKB4RHwCD3XYuvFvS3w9TF1joB3Nq5LKFpCGRb5k5Jcc9L4CUmXA4wC9gPgL3ep6D1".to_string();
 
-        let result = btc_ledger_build_wallet_policy(xpub, fp, 12345, 0);
+        let result = btc_ledger_build_wallet_policy(xpub, fp, 12345, 0, "bitcoin".into());
 
         assert!(result.is_err());
     }
@@ -1758,4 +2385,113 @@ mod tests {
         let elem2 = u32::from_be_bytes([encoded[9], encoded[10], encoded[11], encoded[12]]);
         assert_eq!(elem2, 2);
     }
+
+    const TPUB_A: &str = "[76223a6e/48'/1'/0'/2']tpubDE7NQymr4AFtewpAsWtnreyq9ghkzQBXpCZjWLFVRAvnbf7vya2eMTvT2fPapNqL8SuVvLQdbUbMfWLVDCZKnsEBqp6UK93QEzL8Ck23AwF";
+    const TPUB_B: &str = "[f5acc2fd/48'/1'/0'/2']tpubDFAqEGNyad35aBCKUAXbQGDjdVhNueno5ZZVEn3sQbW5ci457gLR7HyTmHBg93oourBssgUxuWz1jX5uhc1qaqFo9VsybY1J5FuedLfm4dK";
+
+    fn multisig_policy() -> WalletPolicy {
+        btc_ledger_build_registered_policy(
+            "Cold 2of2".to_string(),
+            "wsh(sortedmulti(2,@0/**,@1/**))".to_string(),
+            vec![TPUB_A.to_string(), TPUB_B.to_string()],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_registered_policy_serialization() {
+        let policy = multisig_policy();
+        let template = "wsh(sortedmulti(2,@0/**,@1/**))";
+
+        assert_eq!(policy.serialized[0], 0x02);
+        assert_eq!(policy.serialized[1], 9);
+        assert_eq!(&policy.serialized[2..11], b"Cold 2of2");
+        assert_eq!(policy.serialized[11] as usize, template.len());
+        assert_eq!(
+            &policy.serialized[12..44],
+            &btc_ledger_sha256(template.as_bytes().to_vec())[..]
+        );
+        assert_eq!(policy.serialized[44], 2);
+
+        let leaves = vec![
+            btc_ledger_hash_leaf(TPUB_A.as_bytes().to_vec()),
+            btc_ledger_hash_leaf(TPUB_B.as_bytes().to_vec()),
+        ];
+        assert_eq!(
+            &policy.serialized[45..],
+            &btc_ledger_compute_merkle_root(leaves)[..]
+        );
+        assert_eq!(
+            policy.policy_id,
+            btc_ledger_sha256(policy.serialized.clone())
+        );
+    }
+
+    #[test]
+    fn test_registered_policy_validation() {
+        let build = |name: &str, template: &str, keys: &[&str]| {
+            btc_ledger_build_registered_policy(
+                name.to_string(),
+                template.to_string(),
+                keys.iter().map(|k| k.to_string()).collect(),
+            )
+        };
+
+        assert!(build("", "wsh(multi(1,@0/**,@1/**))", &[TPUB_A, TPUB_B]).is_err());
+        assert!(build(
+            &"x".repeat(65),
+            "wsh(multi(1,@0/**,@1/**))",
+            &[TPUB_A, TPUB_B]
+        )
+        .is_err());
+        assert!(build("Multi", "wsh(multi(1,@0/**,@1/**))", &[]).is_err());
+        assert!(build("Multi", "wsh(multi(1,@0/**,@2/**))", &[TPUB_A, TPUB_B]).is_err());
+        assert!(build("Multi", "wsh(multi(1,@0/**,@0/<2;3>/*))", &[TPUB_A, TPUB_B]).is_err());
+        let with_suffix = format!("{}/**", TPUB_B);
+        assert!(build(
+            "Multi",
+            "wsh(multi(1,@0/**,@1/**))",
+            &[TPUB_A, &with_suffix]
+        )
+        .is_err());
+
+        let tapscript = build(
+            "Tap",
+            "tr(@0/**,{pk(@1/**),pk(@0/<2;3>/*)})",
+            &[TPUB_A, TPUB_B],
+        )
+        .unwrap();
+        assert_eq!(tapscript.name, "Tap");
+    }
+
+    #[test]
+    fn test_registered_policy_storage() {
+        let storage = MemoryStorage::default();
+        let wallet = "wallet_a";
+
+        assert!(save_policy(&storage, wallet, multisig_policy()).is_err());
+
+        let mut policy = multisig_policy();
+        policy.policy_hmac = vec![0x5a; 32];
+        save_policy(&storage, wallet, policy).unwrap();
+
+        // Registering the same policy again replaces the HMAC
+        let mut policy = multisig_policy();
+        policy.policy_hmac = vec![0x6b; 32];
+        let policy_id = policy.policy_id.clone();
+        let serialized = policy.serialized.clone();
+        save_policy(&storage, wallet, policy).unwrap();
+
+        let loaded = load_policies(&storage, wallet).unwrap();
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(loaded[0].policy_id, policy_id);
+        assert_eq!(loaded[0].serialized, serialized);
+        assert_eq!(loaded[0].policy_hmac, vec![0x6b; 32]);
+        assert!(load_policies(&storage, "wallet_b").unwrap().is_empty());
+
+        let mut remaining = loaded;
+        remaining.retain(|p| p.policy_id != policy_id);
+        store_policies(&storage, wallet, &remaining).unwrap();
+        assert!(load_policies(&storage, wallet).unwrap().is_empty());
+    }
 }
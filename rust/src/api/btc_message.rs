@@ -0,0 +1,71 @@
+use std::str::FromStr;
+
+use bitcoin::psbt::Psbt;
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::{Address, Network};
+
+use crate::btc::message::{signature_from_psbt, to_sign_psbt, verify};
+use crate::btc::psbt::decode as decode_psbt;
+pub use crate::models::btc::BtcMessageFormat;
+
+pub(crate) fn parse_address(address: &str, network: &str) -> Result<Address, String> {
+    let network =
+        Network::from_str(network).map_err(|e| format!("Invalid network {}: {}", network, e))?;
+    Address::from_str(address)
+        .map_err(|e| format!("Invalid address {}: {}", address, e))?
+        .require_network(network)
+        .map_err(|e| format!("Invalid address {}: {}", address, e))
+}
+
+/// Check a signature made by `btc_sign_message` or another wallet: legacy
+/// `signmessage`, BIP-137 or BIP-322 simple/full.
+pub fn btc_verify_message(
+    address: String,
+    network: String,
+    message: String,
+    signature: String,
+) -> Result<bool, String> {
+    let address = parse_address(&address, &network)?;
+    verify(
+        &Secp256k1::verification_only(),
+        &address,
+        &message,
+        &signature,
+    )
+    .map_err(|e| e.to_string())
+}
+
+/// BIP-322 `to_sign` PSBT for devices that only sign PSBTs. On Ledger
+/// `btc_ledger_sign_message` signs it like a transaction, and signs legacy
+/// messages with SIGN_MESSAGE instead.
+pub fn btc_message_prepare_psbt(
+    address: String,
+    network: String,
+    message: String,
+) -> Result<Vec<u8>, String> {
+    let address = parse_address(&address, &network)?;
+    to_sign_psbt(&address, &message)
+        .map(|psbt| psbt.serialize())
+        .map_err(|e| e.to_string())
+}
+
+/// BIP-322 signature from a PSBT of `btc_message_prepare_psbt` signed
+/// elsewhere. The signature is verified before it is returned.
+pub fn btc_message_signature_from_psbt(
+    address: String,
+    network: String,
+    message: String,
+    psbt_bytes: Vec<u8>,
+    format: BtcMessageFormat,
+) -> Result<String, String> {
+    let address = parse_address(&address, &network)?;
+    let psbt: Psbt = decode_psbt(&psbt_bytes).map_err(|e| e.to_string())?;
+    signature_from_psbt(
+        &Secp256k1::verification_only(),
+        psbt,
+        &address,
+        &message,
+        format.into(),
+    )
+    .map_err(|e| e.to_string())
+}
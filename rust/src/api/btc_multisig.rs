@@ -0,0 +1,291 @@
+use std::str::FromStr;
+
+use bitcoin::consensus::encode as btc_encode;
+use bitcoin::psbt::Psbt;
+use bitcoin::Network;
+
+use crate::api::btc_ledger::{
+    btc_ledger_build_registered_policy, FinalizedBtcTx, LedgerInputSignature, WalletPolicy,
+};
+use crate::api::btc_watch_only::{
+    add_keyless_wallet, check_address_count, wallet_record, DEFAULT_GAP_LIMIT,
+};
+use crate::btc::multisig::{finalize_psbt, MultisigDescriptor, MultisigScriptType};
+use crate::btc::psbt::{decode as decode_psbt, verify as verify_psbt};
+pub use crate::models::btc::BtcMultisigInfo;
+use crate::models::ftoken::FTokenInfo;
+use crate::models::settings::WalletSettingsInfo;
+use crate::utils::utils::KeylessWallet;
+
+/// Addresses of each chain matched against PSBT inputs and outputs.
+const MULTISIG_LOOKAHEAD: u32 = 30;
+
+fn parse_descriptor(
+    descriptor: &str,
+    network: &str,
+) -> Result<(MultisigDescriptor, Network), String> {
+    let network =
+        Network::from_str(network).map_err(|e| format!("Invalid network {}: {}", network, e))?;
+    let descriptor = MultisigDescriptor::from_str(descriptor).map_err(|e| e.to_string())?;
+    descriptor
+        .check_network(network)
+        .map_err(|e| e.to_string())?;
+    Ok((descriptor, network))
+}
+
+fn parse_psbt(psbt_bytes: &[u8]) -> Result<Psbt, String> {
+    decode_psbt(psbt_bytes).map_err(|e| e.to_string())
+}
+
+pub fn btc_multisig_parse_descriptor(
+    descriptor: String,
+    network: String,
+) -> Result<BtcMultisigInfo, String> {
+    let (parsed, _) = parse_descriptor(&descriptor, &network)?;
+
+    Ok(BtcMultisigInfo {
+        descriptor: parsed.to_string(),
+        script_type: match parsed.script_type {
+            MultisigScriptType::Wsh => "wsh",
+            MultisigScriptType::ShWsh => "sh-wsh",
+        }
+        .to_string(),
+        threshold: parsed.threshold as u32,
+        sorted: parsed.sorted,
+        keys: parsed.keys.iter().map(|k| k.origin_string()).collect(),
+        satisfaction_weight: parsed.satisfaction_weight().to_wu(),
+    })
+}
+
+pub fn btc_multisig_get_addresses(
+    descriptor: String,
+    network: String,
+    change: bool,
+    start: u32,
+    count: u32,
+) -> Result<Vec<String>, String> {
+    let (parsed, network) = parse_descriptor(&descriptor, &network)?;
+
+    (start..start.saturating_add(count))
+        .map(|index| {
+            parsed
+                .address(network, change, index)
+                .map(|a| a.to_string())
+                .map_err(|e| e.to_string())
+        })
+        .collect()
+}
+
+/// Add witness scripts and every cosigner's key origin to a PSBT from
+/// `btc_create_transaction`, so each signer (software or Ledger) can find
+/// its key. Each cosigner signs their own copy of the result.
+pub fn btc_multisig_prepare_psbt(
+    descriptor: String,
+    network: String,
+    psbt_bytes: Vec<u8>,
+) -> Result<Vec<u8>, String> {
+    let (parsed, _) = parse_descriptor(&descriptor, &network)?;
+    let mut psbt = parse_psbt(&psbt_bytes)?;
+    parsed
+        .update_psbt(&mut psbt, MULTISIG_LOOKAHEAD)
+        .map_err(|e| e.to_string())?;
+    Ok(psbt.serialize())
+}
+
+/// Store signatures returned by `btc_ledger_sign_psbt_with_policy` as
+/// partial signatures.
+pub fn btc_multisig_add_signatures(
+    psbt_bytes: Vec<u8>,
+    sigs: Vec<LedgerInputSignature>,
+) -> Result<Vec<u8>, String> {
+    let mut psbt = parse_psbt(&psbt_bytes)?;
+
+    for sig in sigs {
+        let input = psbt
+            .inputs
+            .get_mut(sig.input_index as usize)
+            .ok_or_else(|| format!("Input index {} out of bounds", sig.input_index))?;
+        if sig.pubkey.is_empty() {
+            return Err(format!(
+                "Signature for input {} has no pubkey, Ledger Bitcoin app 2.1.0 or newer is required",
+                sig.input_index
+            ));
+        }
+        let pubkey = bitcoin::PublicKey::from_slice(&sig.pubkey)
+            .map_err(|e| format!("Invalid public key: {}", e))?;
+        if !input.bip32_derivation.contains_key(&pubkey.inner) {
+            return Err(format!(
+                "Key {} is not a cosigner of input {}",
+                pubkey, sig.input_index
+            ));
+        }
+        let signature = bitcoin::ecdsa::Signature::from_slice(&sig.signature)
+            .map_err(|e| format!("Invalid ECDSA signature: {}", e))?;
+        input.partial_sigs.insert(pubkey, signature);
+    }
+
+    Ok(psbt.serialize())
+}
+
+/// Merge the partially signed copies returned by the cosigners.
+pub fn btc_multisig_combine_psbts(psbts: Vec<Vec<u8>>) -> Result<Vec<u8>, String> {
+    let mut psbts = psbts.iter().map(|p| parse_psbt(p));
+    let mut combined = psbts.next().ok_or("No PSBTs to combine")??;
+    for psbt in psbts {
+        combined
+            .combine(psbt?)
+            .map_err(|e| format!("Failed to combine PSBTs: {}", e))?;
+    }
+    Ok(combined.serialize())
+}
+
+/// Build the final witnesses once `threshold` cosigners have signed, check
+/// their signatures and extract the transaction for broadcasting.
+pub fn btc_multisig_finalize_psbt(psbt_bytes: Vec<u8>) -> Result<FinalizedBtcTx, String> {
+    let mut psbt = parse_psbt(&psbt_bytes)?;
+    finalize_psbt(&mut psbt).map_err(|e| e.to_string())?;
+    verify_psbt(&psbt).map_err(|e| e.to_string())?;
+
+    let psbt_bytes = psbt.serialize();
+    let signed_tx = psbt.extract_tx_unchecked_fee_rate();
+
+    Ok(FinalizedBtcTx {
+        raw_tx_hex: hex::encode(btc_encode::serialize(&signed_tx)),
+        tx_hash: signed_tx.compute_txid().to_string(),
+        psbt_bytes,
+    })
+}
+
+/// Ledger policy for the wallet, to pass to `btc_ledger_register_wallet`
+/// before the device can cosign.
+pub fn btc_multisig_ledger_policy(
+    name: String,
+    descriptor: String,
+    network: String,
+) -> Result<WalletPolicy, String> {
+    let (parsed, _) = parse_descriptor(&descriptor, &network)?;
+    let (template, keys_info) = parsed.ledger_policy().map_err(|e| e.to_string())?;
+    btc_ledger_build_registered_policy(name, template, keys_info)
+}
+
+// --- Wallets ---
+
+pub struct BtcMultisigWalletParamsInput {
+    pub descriptor: String,
+    pub network: String,
+    pub wallet_name: String,
+    pub chain_hash: u64,
+    /// Addresses derived on each chain, `DEFAULT_GAP_LIMIT` when unset
+    pub address_count: Option<u32>,
+}
+
+/// `(address, account name)` for the first `count` receive and change
+/// addresses of the descriptor.
+fn multisig_addresses(
+    descriptor: &MultisigDescriptor,
+    network: Network,
+    count: u32,
+) -> Result<Vec<(String, String)>, String> {
+    check_address_count(count)?;
+    let mut addresses = Vec::new();
+    for (change, label) in [(false, "Receive"), (true, "Change")] {
+        for index in 0..count {
+            let address = descriptor
+                .address(network, change, index)
+                .map_err(|e| e.to_string())?;
+            addresses.push((address.to_string(), format!("{} {}", label, index + 1)));
+        }
+    }
+    Ok(addresses)
+}
+
+/// Add a wallet for the descriptor's addresses, next to seed and Ledger
+/// wallets. It holds no keys: spends go through `btc_multisig_prepare_psbt`
+/// and each cosigner, and regular signing is refused.
+pub async fn add_btc_multisig_wallet(
+    params: BtcMultisigWalletParamsInput,
+    wallet_settings: WalletSettingsInfo,
+    ftokens: Vec<FTokenInfo>,
+) -> Result<String, String> {
+    let (descriptor, network) = parse_descriptor(&params.descriptor, &params.network)?;
+    let count = params.address_count.unwrap_or(DEFAULT_GAP_LIMIT);
+    let addresses = multisig_addresses(&descriptor, network, count)?;
+
+    add_keyless_wallet(
+        KeylessWallet::Multisig {
+            descriptor: descriptor.to_string(),
+        },
+        params.wallet_name,
+        params.chain_hash,
+        addresses,
+        wallet_settings,
+        ftokens,
+    )
+    .await
+}
+
+/// Descriptor of a wallet added with `add_btc_multisig_wallet`.
+pub async fn btc_multisig_wallet_descriptor(wallet_index: usize) -> Result<String, String> {
+    match wallet_record(wallet_index).await? {
+        Some(KeylessWallet::Multisig { descriptor }) => Ok(descriptor),
+        _ => Err(format!("Wallet at index {} is not multisig", wallet_index)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::btc_watch_only::MAX_ADDRESSES_PER_CHAIN;
+    use bitcoin::bip32::{DerivationPath, Xpriv, Xpub};
+    use bitcoin::secp256k1::Secp256k1;
+    use bitcoin::NetworkKind;
+
+    fn descriptor() -> String {
+        let secp = Secp256k1::new();
+        let path = DerivationPath::from_str("48h/1h/0h/2h").unwrap();
+        let keys: Vec<String> = (1..=2u8)
+            .map(|i| {
+                let master = Xpriv::new_master(NetworkKind::Test, &[i; 32]).unwrap();
+                let account = master.derive_priv(&secp, &path).unwrap();
+                format!(
+                    "[{}/48h/1h/0h/2h]{}/<0;1>/*",
+                    master.fingerprint(&secp),
+                    Xpub::from_priv(&secp, &account)
+                )
+            })
+            .collect();
+        format!("wsh(sortedmulti(2,{}))", keys.join(","))
+    }
+
+    #[test]
+    fn test_parse_descriptor() {
+        let info = btc_multisig_parse_descriptor(descriptor(), "testnet".into()).unwrap();
+        assert_eq!(info.script_type, "wsh");
+        assert_eq!(info.threshold, 2);
+        assert_eq!(info.keys.len(), 2);
+        assert!(info.descriptor.contains("48'/1'/0'/2'"));
+        assert!(info.descriptor.contains('#'));
+        // 2-of-2 p2wsh: 1 + 1 + 2 * 73 + 1 + 71
+        assert_eq!(info.satisfaction_weight, 220);
+
+        assert!(btc_multisig_parse_descriptor(descriptor(), "bitcoin".into()).is_err());
+
+        let receive =
+            btc_multisig_get_addresses(descriptor(), "testnet".into(), false, 0, 3).unwrap();
+        assert_eq!(receive.len(), 3);
+        assert!(receive.iter().all(|a| a.starts_with("tb1q")));
+    }
+
+    #[test]
+    fn test_multisig_addresses() {
+        let (parsed, network) = parse_descriptor(&descriptor(), "testnet").unwrap();
+        let addresses = multisig_addresses(&parsed, network, 2).unwrap();
+        let change =
+            btc_multisig_get_addresses(descriptor(), "testnet".into(), true, 0, 2).unwrap();
+
+        assert_eq!(addresses.len(), 4);
+        assert_eq!(addresses[1].1, "Receive 2");
+        assert_eq!(addresses[2], (change[0].clone(), "Change 1".to_string()));
+        assert!(multisig_addresses(&parsed, network, MAX_ADDRESSES_PER_CHAIN + 1).is_err());
+    }
+}
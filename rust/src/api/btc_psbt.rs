@@ -0,0 +1,169 @@
+use bitcoin::consensus::encode as btc_encode;
+use bitcoin::psbt::Psbt;
+
+use crate::api::btc_ledger::{btc_ledger_build_psbt_from_tx, FinalizedBtcTx};
+use crate::api::transaction::send_signed_transactions;
+use crate::btc::psbt::{check_same_tx, decode, encode_base64, extract, finalize, verify};
+pub use crate::models::btc::BtcPsbtInfo;
+use crate::models::transactions::history::HistoricalTransactionInfo;
+use crate::models::transactions::request::TransactionRequestInfo;
+
+fn psbt_info(psbt: &Psbt) -> BtcPsbtInfo {
+    BtcPsbtInfo {
+        psbt: psbt.serialize(),
+        base64: encode_base64(psbt),
+        txid: psbt.unsigned_tx.compute_txid().to_string(),
+        fee: psbt.fee().ok().map(|fee| fee.to_sat()),
+    }
+}
+
+fn unsigned_psbt(tx: &TransactionRequestInfo) -> Result<Psbt, String> {
+    let tx_hex = tx.btc.clone().ok_or("Not a Bitcoin transaction")?;
+    let witness_utxos = tx
+        .metadata
+        .btc_witness_utxos
+        .clone()
+        .unwrap_or_else(|| "[]".to_string());
+    let psbt_bytes = btc_ledger_build_psbt_from_tx(tx_hex, witness_utxos)?;
+    decode(&psbt_bytes).map_err(|e| e.to_string())
+}
+
+fn import_signed(original: &Psbt, signed: &[u8]) -> Result<FinalizedBtcTx, String> {
+    let signed = decode(signed).map_err(|e| e.to_string())?;
+    check_same_tx(original, &signed).map_err(|e| e.to_string())?;
+
+    // Signers may strip the UTXOs and origins they were sent
+    let mut psbt = original.clone();
+    psbt.combine(signed)
+        .map_err(|e| format!("Failed to combine PSBTs: {}", e))?;
+    finalize(&mut psbt).map_err(|e| e.to_string())?;
+    verify(&psbt).map_err(|e| e.to_string())?;
+
+    let psbt_bytes = psbt.serialize();
+    let signed_tx = extract(psbt).map_err(|e| e.to_string())?;
+
+    Ok(FinalizedBtcTx {
+        raw_tx_hex: hex::encode(btc_encode::serialize(&signed_tx)),
+        tx_hash: signed_tx.compute_txid().to_string(),
+        psbt_bytes,
+    })
+}
+
+/// Export an unsigned transaction from `btc_create_transaction` for an
+/// air-gapped signer.
+pub fn btc_export_psbt(tx: TransactionRequestInfo) -> Result<BtcPsbtInfo, String> {
+    Ok(psbt_info(&unsigned_psbt(&tx)?))
+}
+
+/// Re-encode a PSBT given as binary, base64 or hex, e.g. after
+/// `btc_watch_only_export_psbt` added key origins to it.
+pub fn btc_encode_psbt(psbt: Vec<u8>) -> Result<BtcPsbtInfo, String> {
+    Ok(psbt_info(&decode(&psbt).map_err(|e| e.to_string())?))
+}
+
+/// Check that a PSBT signed elsewhere is for the same transaction as
+/// `original`, finalize it, verify every signature against the spent
+/// outputs and extract the transaction.
+pub fn btc_import_signed_psbt(
+    original: Vec<u8>,
+    signed: Vec<u8>,
+) -> Result<FinalizedBtcTx, String> {
+    let original = decode(&original).map_err(|e| e.to_string())?;
+    import_signed(&original, &signed)
+}
+
+/// Import a PSBT signed elsewhere for `tx` and broadcast it, recording it
+/// in the wallet history like any other signed transaction.
+pub async fn btc_broadcast_signed_psbt(
+    wallet_index: u8,
+    account_index: u8,
+    tx: TransactionRequestInfo,
+    signed: Vec<u8>,
+) -> Result<HistoricalTransactionInfo, String> {
+    let original = unsigned_psbt(&tx)?;
+    let finalized = import_signed(&original, &signed)?;
+    send_signed_transactions(wallet_index, account_index, tx, finalized.psbt_bytes).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::hashes::Hash;
+    use bitcoin::secp256k1::{Message, Secp256k1, SecretKey};
+    use bitcoin::sighash::{EcdsaSighashType, SighashCache};
+    use bitcoin::transaction::Version;
+    use bitcoin::{
+        absolute, ecdsa, Amount, OutPoint, PublicKey, ScriptBuf, Sequence, Transaction, TxIn,
+        TxOut, Txid,
+    };
+
+    fn original(pk: &PublicKey) -> Psbt {
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::from_byte_array([1; 32]), 0),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(90_000),
+                script_pubkey: ScriptBuf::new_p2wpkh(&pk.wpubkey_hash().unwrap()),
+            }],
+        };
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        psbt.inputs[0].witness_utxo = Some(TxOut {
+            value: Amount::from_sat(100_000),
+            script_pubkey: ScriptBuf::new_p2wpkh(&pk.wpubkey_hash().unwrap()),
+        });
+        psbt
+    }
+
+    #[test]
+    fn test_import_signed_psbt() {
+        let secp = Secp256k1::new();
+        let sk = SecretKey::from_slice(&[9u8; 32]).unwrap();
+        let pk = PublicKey::new(sk.public_key(&secp));
+        let original = original(&pk);
+
+        let exported = btc_encode_psbt(original.serialize()).unwrap();
+        assert_eq!(exported.fee, Some(10_000));
+        assert_eq!(
+            exported.txid,
+            original.unsigned_tx.compute_txid().to_string()
+        );
+
+        // The signer returns only the signature, in base64
+        let sign = |digest: [u8; 32]| {
+            let mut signed = Psbt::from_unsigned_tx(original.unsigned_tx.clone()).unwrap();
+            let sig = secp.sign_ecdsa(&Message::from_digest(digest), &sk);
+            signed.inputs[0]
+                .partial_sigs
+                .insert(pk, ecdsa::Signature::sighash_all(sig));
+            signed
+        };
+        let utxo = original.inputs[0].witness_utxo.clone().unwrap();
+        let sighash = SighashCache::new(&original.unsigned_tx)
+            .p2wpkh_signature_hash(0, &utxo.script_pubkey, utxo.value, EcdsaSighashType::All)
+            .unwrap();
+        let signed = sign(sighash.to_byte_array());
+        let signed_base64 = encode_base64(&signed).into_bytes();
+
+        let forged = btc_import_signed_psbt(original.serialize(), sign([3; 32]).serialize());
+        assert!(matches!(forged, Err(e) if e.contains("invalid or incomplete signature")));
+
+        let unsigned = btc_import_signed_psbt(exported.base64.clone().into_bytes(), exported.psbt);
+        assert!(matches!(unsigned, Err(e) if e.contains("not signed")));
+
+        let finalized =
+            btc_import_signed_psbt(exported.base64.into_bytes(), signed_base64).unwrap();
+        assert_eq!(finalized.tx_hash, exported.txid);
+        let final_psbt = Psbt::deserialize(&finalized.psbt_bytes).unwrap();
+        assert!(final_psbt.inputs[0].final_script_witness.is_some());
+
+        let mut other = signed.clone();
+        other.unsigned_tx.output[0].value = Amount::from_sat(1_000);
+        let mismatch = btc_import_signed_psbt(original.serialize(), other.serialize());
+        assert!(matches!(mismatch, Err(e) if e.contains("expected")));
+    }
+}
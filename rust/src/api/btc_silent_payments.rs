@@ -0,0 +1,213 @@
+use std::str::FromStr;
+
+use bitcoin::consensus::encode as btc_encode;
+use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey, XOnlyPublicKey};
+use bitcoin::{Amount, ScriptBuf, Transaction, TxOut};
+
+use crate::btc::silent_payments::{public_tweak, scan};
+pub use crate::models::btc::{BtcSilentPaymentOutputInfo, BtcUtxoInfo};
+
+fn parse_keys(scan_secret: &str, spend_pubkey: &str) -> Result<(SecretKey, PublicKey), String> {
+    let scan_secret =
+        SecretKey::from_str(scan_secret).map_err(|e| format!("Invalid scan key: {}", e))?;
+    let spend_pubkey =
+        PublicKey::from_str(spend_pubkey).map_err(|e| format!("Invalid spend key: {}", e))?;
+    Ok((scan_secret, spend_pubkey))
+}
+
+fn taproot_key(utxo: &BtcUtxoInfo) -> Option<XOnlyPublicKey> {
+    let script = ScriptBuf::from_hex(&utxo.script_pubkey).ok()?;
+    if !script.is_p2tr() {
+        return None;
+    }
+    XOnlyPublicKey::from_slice(&script.as_bytes()[2..]).ok()
+}
+
+fn scan_outputs(
+    scan_secret: &SecretKey,
+    spend_pubkey: &PublicKey,
+    tweak: &PublicKey,
+    outputs: Vec<BtcUtxoInfo>,
+) -> Result<Vec<BtcSilentPaymentOutputInfo>, String> {
+    let (outputs, keys): (Vec<_>, Vec<_>) = outputs
+        .into_iter()
+        .filter_map(|utxo| taproot_key(&utxo).map(|key| (utxo, key)))
+        .unzip();
+    let found = scan(
+        &Secp256k1::verification_only(),
+        scan_secret,
+        spend_pubkey,
+        tweak,
+        &keys,
+    )
+    .map_err(|e| e.to_string())?;
+
+    Ok(found
+        .into_iter()
+        .map(|f| BtcSilentPaymentOutputInfo {
+            utxo: outputs[f.index].clone(),
+            tweak: hex::encode(f.tweak.secret_bytes()),
+        })
+        .collect())
+}
+
+fn tx_tweak(tx: &Transaction, prevout_scripts: &[String]) -> Result<Option<PublicKey>, String> {
+    let prevouts = prevout_scripts
+        .iter()
+        .map(|script| {
+            Ok(TxOut {
+                value: Amount::ZERO,
+                script_pubkey: ScriptBuf::from_hex(script)
+                    .map_err(|e| format!("Invalid script_pubkey: {}", e))?,
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    public_tweak(&Secp256k1::verification_only(), tx, &prevouts).map_err(|e| e.to_string())
+}
+
+fn parse_tx(tx_hex: &str) -> Result<Transaction, String> {
+    let raw = hex::decode(tx_hex).map_err(|e| format!("Invalid transaction hex: {}", e))?;
+    btc_encode::deserialize(&raw).map_err(|e| format!("Invalid transaction: {}", e))
+}
+
+/// BIP-352 tweak data of a transaction given the scripts its inputs spend,
+/// in input order. `None` when it cannot pay a silent payment address.
+pub fn btc_silent_payment_tweak(
+    tx_hex: String,
+    prevout_scripts: Vec<String>,
+) -> Result<Option<String>, String> {
+    let tx = parse_tx(&tx_hex)?;
+    Ok(tx_tweak(&tx, &prevout_scripts)?.map(|tweak| tweak.to_string()))
+}
+
+/// Find the outputs paying `btc_silent_payment_keys` among the taproot
+/// outputs of one transaction, given its tweak from an index server or
+/// `btc_silent_payment_tweak`.
+pub fn btc_silent_payment_scan(
+    scan_secret: String,
+    spend_pubkey: String,
+    tweak: String,
+    outputs: Vec<BtcUtxoInfo>,
+) -> Result<Vec<BtcSilentPaymentOutputInfo>, String> {
+    let (scan_secret, spend_pubkey) = parse_keys(&scan_secret, &spend_pubkey)?;
+    let tweak = PublicKey::from_str(&tweak).map_err(|e| format!("Invalid tweak: {}", e))?;
+    scan_outputs(&scan_secret, &spend_pubkey, &tweak, outputs)
+}
+
+/// `btc_silent_payment_scan` of a full transaction, for blocks fetched
+/// from a node.
+pub fn btc_silent_payment_scan_tx(
+    scan_secret: String,
+    spend_pubkey: String,
+    tx_hex: String,
+    prevout_scripts: Vec<String>,
+) -> Result<Vec<BtcSilentPaymentOutputInfo>, String> {
+    let (scan_secret, spend_pubkey) = parse_keys(&scan_secret, &spend_pubkey)?;
+    let tx = parse_tx(&tx_hex)?;
+    let Some(tweak) = tx_tweak(&tx, &prevout_scripts)? else {
+        return Ok(Vec::new());
+    };
+
+    let txid = tx.compute_txid().to_string();
+    let outputs = tx
+        .output
+        .iter()
+        .enumerate()
+        .map(|(vout, out)| BtcUtxoInfo {
+            txid: txid.clone(),
+            vout: vout as u32,
+            value: out.value.to_sat(),
+            script_pubkey: out.script_pubkey.to_hex_string(),
+            satisfaction_weight: None,
+        })
+        .collect();
+    scan_outputs(&scan_secret, &spend_pubkey, &tweak, outputs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::btc::{create_transaction, BtcCoinSelectionStrategy, BtcRecipientInfo};
+    use crate::api::local_storage::MemoryStorage;
+    use crate::btc::silent_payments::SilentPaymentKeys;
+    use crate::models::btc::BtcTxParamsInfo;
+    use crate::models::transactions::transaction_metadata::TransactionMetadataInfo;
+    use bitcoin::{Address, AddressType, CompressedPublicKey, Network, NetworkKind, Witness};
+
+    #[test]
+    fn test_send_and_scan_tx() {
+        let secp = Secp256k1::new();
+        let receiver = SilentPaymentKeys::from_seed(&secp, &[1; 64], NetworkKind::Test, 0).unwrap();
+        let sender = SecretKey::from_slice(&[2; 32]).unwrap();
+        let sender_pk = CompressedPublicKey(sender.public_key(&secp));
+        let sender_address = Address::p2wpkh(&sender_pk, Network::Regtest);
+
+        let params = BtcTxParamsInfo {
+            utxos: vec![BtcUtxoInfo {
+                txid: "22".repeat(32),
+                vout: 0,
+                value: 100_000,
+                script_pubkey: sender_address.script_pubkey().to_hex_string(),
+                satisfaction_weight: None,
+            }],
+            recipients: vec![BtcRecipientInfo {
+                address: receiver.address(&secp).to_string(),
+                value: 30_000,
+            }],
+            change_address: sender_address.to_string(),
+            network: "regtest".to_string(),
+            fee_rate: 1.0,
+            strategy: BtcCoinSelectionStrategy::LargestFirst,
+        };
+        let metadata = TransactionMetadataInfo {
+            chain_hash: 0,
+            hash: None,
+            info: None,
+            icon: None,
+            title: None,
+            signer: None,
+            token_info: None,
+            btc_witness_utxos: None,
+            broadcast: true,
+        };
+
+        let storage = MemoryStorage::default();
+        assert!(create_transaction(&storage, "w", params.clone(), metadata.clone(), None).is_err());
+        let created = create_transaction(
+            &storage,
+            "w",
+            params,
+            metadata,
+            Some((sender, AddressType::P2wpkh)),
+        )
+        .unwrap();
+
+        // Signed the way the wallet would, the input reveals the sender key
+        let mut tx = parse_tx(&created.tx.btc.unwrap()).unwrap();
+        tx.input[0].witness = Witness::from_slice(&[vec![1u8; 72], sender_pk.to_bytes().to_vec()]);
+        let tx_hex = hex::encode(btc_encode::serialize(&tx));
+        let prevouts = vec![sender_address.script_pubkey().to_hex_string()];
+
+        let found = btc_silent_payment_scan_tx(
+            hex::encode(receiver.scan.secret_bytes()),
+            receiver.spend.public_key(&secp).to_string(),
+            tx_hex.clone(),
+            prevouts.clone(),
+        )
+        .unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].utxo.vout, 0);
+        assert_eq!(found[0].utxo.value, 30_000);
+
+        let tweak = btc_silent_payment_tweak(tx_hex, prevouts).unwrap().unwrap();
+        let other = SilentPaymentKeys::from_seed(&secp, &[3; 64], NetworkKind::Test, 0).unwrap();
+        let missed = btc_silent_payment_scan(
+            hex::encode(other.scan.secret_bytes()),
+            other.spend.public_key(&secp).to_string(),
+            tweak,
+            vec![found[0].utxo.clone()],
+        )
+        .unwrap();
+        assert!(missed.is_empty());
+    }
+}
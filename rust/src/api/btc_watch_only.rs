@@ -0,0 +1,319 @@
+use std::collections::HashSet;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use bitcoin::Network;
+use sha2::{Digest, Sha256};
+use zilpay::background::bg_provider::ProvidersManagement;
+use zilpay::background::bg_wallet::WalletManagement;
+use zilpay::background::BackgroundLedgerParams;
+use zilpay::crypto::slip44;
+use zilpay::errors::token::TokenError;
+use zilpay::proto::address::Address;
+use zilpay::settings::wallet_settings::WalletSettings;
+use zilpay::token::ft::FToken;
+
+use crate::api::ledger::update_ledger_accounts;
+use crate::api::token::sync_balances;
+use crate::btc::network::coin_type;
+use crate::btc::psbt::decode as decode_psbt;
+use crate::btc::watch_only::{scan_target, WatchSource};
+use crate::models::ftoken::FTokenInfo;
+use crate::models::settings::WalletSettingsInfo;
+use crate::models::wallet::WalletInfo;
+use crate::service::service::BACKGROUND_SERVICE;
+use crate::utils::errors::ServiceError;
+use crate::utils::utils::{
+    get_last_wallet, keyless_wallet, save_keyless_wallet, with_wallet, KeylessWallet,
+};
+
+pub const DEFAULT_GAP_LIMIT: u32 = 20;
+/// Every watched address is an account and account indices are a byte,
+/// so each chain gets half of them.
+pub(crate) const MAX_ADDRESSES_PER_CHAIN: u32 = 128;
+
+pub struct BtcWatchOnlyParamsInput {
+    /// xpub/ypub/zpub, output descriptor, or addresses separated by
+    /// commas or whitespace
+    pub source: String,
+    pub network: String,
+    pub wallet_name: String,
+    pub chain_hash: u64,
+    pub gap_limit: Option<u32>,
+}
+
+fn parse_source(source: &str, network: &str) -> Result<(WatchSource, Network), String> {
+    let network =
+        Network::from_str(network).map_err(|e| format!("Invalid network {}: {}", network, e))?;
+    let source = WatchSource::from_str(source).map_err(|e| e.to_string())?;
+    source.check_network(network).map_err(|e| e.to_string())?;
+    Ok((source, network))
+}
+
+/// Every watched address is an account, refuse more than fit.
+pub(crate) fn check_address_count(count: u32) -> Result<(), String> {
+    if count > MAX_ADDRESSES_PER_CHAIN {
+        return Err(format!(
+            "At most {} addresses per chain, got {}",
+            MAX_ADDRESSES_PER_CHAIN, count
+        ));
+    }
+    Ok(())
+}
+
+/// `(address, account name)` for the first `receive` and `change`
+/// addresses of the source.
+fn watched_addresses(
+    source: &WatchSource,
+    network: Network,
+    receive: u32,
+    change: u32,
+) -> Result<Vec<(String, String)>, String> {
+    let mut watched = Vec::new();
+    for (is_change, count, label) in [(false, receive, "Receive"), (true, change, "Change")] {
+        check_address_count(count)?;
+        for index in 0..count {
+            let Some(address) = source
+                .address(network, is_change, index)
+                .map_err(|e| e.to_string())?
+            else {
+                break;
+            };
+            watched.push((address.to_string(), format!("{} {}", label, index + 1)));
+        }
+    }
+    Ok(watched)
+}
+
+fn derive_path(source: &WatchSource, network: Network) -> String {
+    format!("m/{}'/{}'/0'", source.bip_purpose(), coin_type(network))
+}
+
+/// Add a wallet that tracks balances without any keys. Ranged sources
+/// start with `gap_limit` addresses on each chain, see
+/// `btc_watch_only_discover` to follow them further.
+pub async fn add_btc_watch_only_wallet(
+    params: BtcWatchOnlyParamsInput,
+    wallet_settings: WalletSettingsInfo,
+    ftokens: Vec<FTokenInfo>,
+) -> Result<String, String> {
+    let (source, network) = parse_source(&params.source, &params.network)?;
+    let gap_limit = params.gap_limit.unwrap_or(DEFAULT_GAP_LIMIT);
+    let watched = watched_addresses(&source, network, gap_limit, gap_limit)?;
+
+    add_keyless_wallet(
+        KeylessWallet::WatchOnly {
+            source: params.source.trim().to_string(),
+        },
+        params.wallet_name,
+        params.chain_hash,
+        watched,
+        wallet_settings,
+        ftokens,
+    )
+    .await
+}
+
+/// Add a Bitcoin wallet holding `watched` `(address, account name)` pairs
+/// and no keys, and keep `record` for it.
+pub(crate) async fn add_keyless_wallet(
+    record: KeylessWallet,
+    wallet_name: String,
+    chain_hash: u64,
+    watched: Vec<(String, String)>,
+    wallet_settings: WalletSettingsInfo,
+    ftokens: Vec<FTokenInfo>,
+) -> Result<String, String> {
+    let mut guard = BACKGROUND_SERVICE.write().await;
+    let service = guard.as_mut().ok_or(ServiceError::NotRunning)?;
+
+    let provider = service
+        .core
+        .get_provider(chain_hash)
+        .map_err(ServiceError::BackgroundError)?;
+    if provider.config.slip_44 != slip44::BITCOIN {
+        return Err("Key-less wallets are only supported for Bitcoin".into());
+    }
+
+    let mut accounts = Vec::with_capacity(watched.len());
+    let mut account_names = Vec::with_capacity(watched.len());
+    for (index, (address, name)) in watched.into_iter().enumerate() {
+        let addr = Address::from_bitcoin_address(&address).map_err(ServiceError::AddressError)?;
+        accounts.push((index as u8, None, addr));
+        account_names.push(name);
+    }
+    let ftokens = ftokens
+        .into_iter()
+        .map(TryFrom::try_from)
+        .collect::<Result<Vec<FToken>, TokenError>>()
+        .map_err(ServiceError::TokenError)?;
+    let wallet_settings = wallet_settings
+        .try_into()
+        .map_err(ServiceError::SettingsError)?;
+    // Core adds seedless wallets as Ledger ones. The id names no device, it
+    // only keeps wallets of different sources apart.
+    let ledger_id = Sha256::digest(serde_json::to_vec(&record).map_err(|e| e.to_string())?);
+    let wallet_index = service.core.wallets.len();
+    let params = BackgroundLedgerParams {
+        ftokens,
+        accounts,
+        wallet_settings,
+        chain_hash,
+        account_names,
+        wallet_index,
+        wallet_name,
+        ledger_id: ledger_id.to_vec(),
+        biometric_type: "none".to_string().into(),
+    };
+
+    Arc::get_mut(&mut service.core)
+        .ok_or(ServiceError::CoreAccess)?
+        .add_ledger_wallet(params, WalletSettings::default())
+        .await
+        .map_err(ServiceError::BackgroundError)?;
+    let wallet_address = hex::encode(get_last_wallet(&service.core)?.wallet_address);
+
+    if let Err(e) = save_keyless_wallet(&wallet_address, &record) {
+        // Without its record the wallet would pass for a Ledger one
+        Arc::get_mut(&mut service.core)
+            .ok_or(ServiceError::CoreAccess)?
+            .delete_wallet(wallet_index)
+            .ok();
+        return Err(e);
+    }
+
+    Ok(wallet_address)
+}
+
+pub async fn is_watch_only_wallet(wallet_index: usize) -> Result<bool, String> {
+    Ok(matches!(
+        wallet_record(wallet_index).await?,
+        Some(KeylessWallet::WatchOnly { .. })
+    ))
+}
+
+pub(crate) async fn wallet_record(wallet_index: usize) -> Result<Option<KeylessWallet>, String> {
+    with_wallet(wallet_index, |wallet| {
+        keyless_wallet(wallet).map_err(ServiceError::AppStorage)
+    })
+    .await
+    .map_err(Into::into)
+}
+
+async fn wallet_source(wallet_index: usize) -> Result<String, String> {
+    match wallet_record(wallet_index).await? {
+        Some(KeylessWallet::WatchOnly { source }) => Ok(source),
+        _ => Err(format!(
+            "Wallet at index {} is not watch-only",
+            wallet_index
+        )),
+    }
+}
+
+/// Addresses the wallet watches, and those of them with a non-zero native
+/// balance after the last sync.
+async fn watched_and_used(
+    wallet_index: usize,
+) -> Result<(HashSet<String>, HashSet<String>), String> {
+    with_wallet(wallet_index, |wallet| {
+        let info =
+            WalletInfo::try_from(wallet).map_err(|e| ServiceError::WalletError(wallet_index, e))?;
+        let accounts = info
+            .accounts
+            .get(&info.slip44)
+            .and_then(|bips| bips.get(&info.bip))
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        let native = info.tokens.iter().find(|t| t.native);
+
+        let watched = accounts.iter().map(|a| a.addr.clone()).collect();
+        let used = accounts
+            .iter()
+            .enumerate()
+            .filter(|(position, _)| {
+                native
+                    .and_then(|t| t.balances.get(position))
+                    .is_some_and(|b| b != "0")
+            })
+            .map(|(_, account)| account.addr.clone())
+            .collect();
+        Ok((watched, used))
+    })
+    .await
+    .map_err(Into::into)
+}
+
+/// Sync balances and keep extending each chain until `gap_limit` addresses
+/// after the last funded one are watched. Returns the number of watched
+/// addresses.
+pub async fn btc_watch_only_discover(
+    wallet_index: usize,
+    network: String,
+    gap_limit: Option<u32>,
+) -> Result<u32, String> {
+    let source = wallet_source(wallet_index).await?;
+    let (source, network) = parse_source(&source, &network)?;
+    let gap_limit = gap_limit.unwrap_or(DEFAULT_GAP_LIMIT);
+
+    loop {
+        sync_balances(wallet_index).await?;
+        let (watched, used) = watched_and_used(wallet_index).await?;
+        if !source.is_ranged() {
+            return Ok(watched.len() as u32);
+        }
+
+        let mut counts = [0u32; 2];
+        let mut targets = [0u32; 2];
+        for (chain, change) in [false, true].into_iter().enumerate() {
+            let mut last_used = None;
+            for index in 0..MAX_ADDRESSES_PER_CHAIN {
+                let address = match source.address(network, change, index) {
+                    Ok(Some(address)) => address.to_string(),
+                    Ok(None) => break,
+                    Err(e) => return Err(e.to_string()),
+                };
+                if !watched.contains(&address) {
+                    break;
+                }
+                counts[chain] = index + 1;
+                if used.contains(&address) {
+                    last_used = Some(index);
+                }
+            }
+            targets[chain] = scan_target(last_used, gap_limit);
+        }
+
+        if targets[0] <= counts[0] && targets[1] <= counts[1] {
+            return Ok(watched.len() as u32);
+        }
+
+        let accounts = watched_addresses(
+            &source,
+            network,
+            targets[0].max(counts[0]),
+            targets[1].max(counts[1]),
+        )?
+        .into_iter()
+        .enumerate()
+        .map(|(index, (address, name))| (index as u8, address, name))
+        .collect();
+        update_ledger_accounts(wallet_index, accounts, false, derive_path(&source, network))
+            .await?;
+    }
+}
+
+/// Fill key origins into an unsigned PSBT (from `btc_create_transaction`)
+/// so it can be signed elsewhere, e.g. on an air-gapped device.
+pub async fn btc_watch_only_export_psbt(
+    wallet_index: usize,
+    network: String,
+    psbt_bytes: Vec<u8>,
+) -> Result<Vec<u8>, String> {
+    let source = wallet_source(wallet_index).await?;
+    let (source, _) = parse_source(&source, &network)?;
+    let mut psbt = decode_psbt(&psbt_bytes).map_err(|e| e.to_string())?;
+    source
+        .update_psbt(&mut psbt, MAX_ADDRESSES_PER_CHAIN)
+        .map_err(|e| e.to_string())?;
+    Ok(psbt.serialize())
+}
@@ -0,0 +1,208 @@
+use crate::api::ledger_transport::RustLedgerError;
+use crate::models::transactions::request::TransactionRequestInfo;
+
+pub struct RustEthLedgerAccount {
+    pub index: u32,
+    pub public_key: String,
+    pub address: String,
+    pub chain_code: Option<String>,
+}
+
+// ---- Desktop implementation ----
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+use crate::api::transaction::{encode_tx_rlp, prepare_eip712_message};
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+use crate::ledger::eth;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+use crate::ledger::registry;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+use crate::ledger::transport::{bip44_path, LedgerError};
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+use crate::utils::utils::ensure_wallet_can_sign;
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub async fn eth_ledger_get_app_version(connection_id: String) -> Result<String, RustLedgerError> {
+    let mut session = registry::session(&connection_id).await?;
+    eth::get_app_version(&mut session).await.map_err(Into::into)
+}
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub async fn eth_ledger_get_address(
+    connection_id: String,
+    slip44: u32,
+    index: u32,
+    display: bool,
+    with_chain_code: bool,
+    chain_id: Option<u64>,
+) -> Result<RustEthLedgerAccount, RustLedgerError> {
+    let mut session = registry::session(&connection_id).await?;
+    get_account(
+        &mut session,
+        slip44,
+        index,
+        display,
+        with_chain_code,
+        chain_id,
+    )
+    .await
+    .map_err(Into::into)
+}
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+async fn get_account(
+    session: &mut registry::Session,
+    slip44: u32,
+    index: u32,
+    display: bool,
+    with_chain_code: bool,
+    chain_id: Option<u64>,
+) -> Result<RustEthLedgerAccount, LedgerError> {
+    let path = bip44_path(slip44, 0, 0, index);
+    let addr = eth::get_address(session, &path, display, with_chain_code, chain_id).await?;
+
+    Ok(RustEthLedgerAccount {
+        index,
+        public_key: hex::encode(addr.public_key),
+        address: addr.address,
+        chain_code: addr.chain_code.map(hex::encode),
+    })
+}
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub async fn eth_ledger_get_accounts(
+    connection_id: String,
+    slip44: u32,
+    indices: Vec<u32>,
+    chain_id: Option<u64>,
+) -> Result<Vec<RustEthLedgerAccount>, RustLedgerError> {
+    let mut session = registry::session(&connection_id).await?;
+    let mut accounts = Vec::with_capacity(indices.len());
+    for index in indices {
+        accounts.push(get_account(&mut session, slip44, index, false, false, chain_id).await?);
+    }
+    Ok(accounts)
+}
+
+/// Sign `tx` on the device. The returned `r || s || v` signature can be
+/// passed as is to `send_signed_transactions`.
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub async fn eth_ledger_sign_transaction(
+    connection_id: String,
+    wallet_index: usize,
+    account_index: usize,
+    tx: TransactionRequestInfo,
+    slip44: u32,
+) -> Result<Vec<u8>, RustLedgerError> {
+    ensure_wallet_can_sign(wallet_index)
+        .await
+        .map_err(|e| RustLedgerError::Service(e.to_string()))?;
+    let encoded = encode_tx_rlp(wallet_index, account_index, tx, slip44)
+        .await
+        .map_err(RustLedgerError::Service)?;
+
+    let mut session = registry::session(&connection_id).await?;
+    eth::sign_transaction(&mut session, &encoded.chunks_bytes)
+        .await
+        .map_err(Into::into)
+}
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub async fn eth_ledger_sign_personal_message(
+    connection_id: String,
+    slip44: u32,
+    index: u32,
+    message: Vec<u8>,
+) -> Result<Vec<u8>, RustLedgerError> {
+    let path = bip44_path(slip44, 0, 0, index);
+    let mut session = registry::session(&connection_id).await?;
+    eth::sign_personal_message(&mut session, &path, &message)
+        .await
+        .map_err(Into::into)
+}
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub async fn eth_ledger_sign_typed_data(
+    connection_id: String,
+    slip44: u32,
+    index: u32,
+    typed_data_json: String,
+) -> Result<Vec<u8>, RustLedgerError> {
+    let hashes = prepare_eip712_message(typed_data_json)
+        .await
+        .map_err(RustLedgerError::Service)?;
+    let path = bip44_path(slip44, 0, 0, index);
+
+    let mut session = registry::session(&connection_id).await?;
+    eth::sign_eip712_hashed(
+        &mut session,
+        &path,
+        &hashes.domain_separator,
+        &hashes.hash_struct_message,
+    )
+    .await
+    .map_err(Into::into)
+}
+
+// ---- Mobile stubs ----
+
+#[cfg(any(target_os = "android", target_os = "ios"))]
+const NOT_SUPPORTED: &str = "Ledger Ethereum app not supported on mobile";
+
+#[cfg(any(target_os = "android", target_os = "ios"))]
+pub async fn eth_ledger_get_app_version(_connection_id: String) -> Result<String, RustLedgerError> {
+    Err(RustLedgerError::Io(NOT_SUPPORTED.to_string()))
+}
+
+#[cfg(any(target_os = "android", target_os = "ios"))]
+pub async fn eth_ledger_get_address(
+    _connection_id: String,
+    _slip44: u32,
+    _index: u32,
+    _display: bool,
+    _with_chain_code: bool,
+    _chain_id: Option<u64>,
+) -> Result<RustEthLedgerAccount, RustLedgerError> {
+    Err(RustLedgerError::Io(NOT_SUPPORTED.to_string()))
+}
+
+#[cfg(any(target_os = "android", target_os = "ios"))]
+pub async fn eth_ledger_get_accounts(
+    _connection_id: String,
+    _slip44: u32,
+    _indices: Vec<u32>,
+    _chain_id: Option<u64>,
+) -> Result<Vec<RustEthLedgerAccount>, RustLedgerError> {
+    Err(RustLedgerError::Io(NOT_SUPPORTED.to_string()))
+}
+
+#[cfg(any(target_os = "android", target_os = "ios"))]
+pub async fn eth_ledger_sign_transaction(
+    _connection_id: String,
+    _wallet_index: usize,
+    _account_index: usize,
+    _tx: TransactionRequestInfo,
+    _slip44: u32,
+) -> Result<Vec<u8>, RustLedgerError> {
+    Err(RustLedgerError::Io(NOT_SUPPORTED.to_string()))
+}
+
+#[cfg(any(target_os = "android", target_os = "ios"))]
+pub async fn eth_ledger_sign_personal_message(
+    _connection_id: String,
+    _slip44: u32,
+    _index: u32,
+    _message: Vec<u8>,
+) -> Result<Vec<u8>, RustLedgerError> {
+    Err(RustLedgerError::Io(NOT_SUPPORTED.to_string()))
+}
+
+#[cfg(any(target_os = "android", target_os = "ios"))]
+pub async fn eth_ledger_sign_typed_data(
+    _connection_id: String,
+    _slip44: u32,
+    _index: u32,
+    _typed_data_json: String,
+) -> Result<Vec<u8>, RustLedgerError> {
+    Err(RustLedgerError::Io(NOT_SUPPORTED.to_string()))
+}
@@ -0,0 +1,101 @@
+use crate::api::ledger_transport::RustLedgerError;
+
+pub struct RustLedgerAppInfo {
+    pub name: String,
+    pub version: String,
+    pub flags: Vec<u8>,
+}
+
+pub struct RustLedgerDeviceVersion {
+    pub target_id: u32,
+    pub se_version: String,
+    pub mcu_version: Option<String>,
+    pub flags: Vec<u8>,
+    pub model_id: Option<String>,
+    pub product_name: Option<String>,
+}
+
+// ---- Desktop implementation ----
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+use crate::ledger::dashboard;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+use crate::ledger::registry;
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub async fn ledger_get_app_and_version(
+    connection_id: String,
+) -> Result<RustLedgerAppInfo, RustLedgerError> {
+    let mut session = registry::session(&connection_id).await?;
+    let info = dashboard::get_app_and_version(&mut session).await?;
+    Ok(RustLedgerAppInfo {
+        name: info.name,
+        version: info.version,
+        flags: info.flags,
+    })
+}
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub async fn ledger_get_version(
+    connection_id: String,
+) -> Result<RustLedgerDeviceVersion, RustLedgerError> {
+    let mut session = registry::session(&connection_id).await?;
+    let version = dashboard::get_version(&mut session).await?;
+    Ok(RustLedgerDeviceVersion {
+        target_id: version.target_id,
+        se_version: version.se_version,
+        mcu_version: version.mcu_version,
+        flags: version.flags,
+        model_id: version.model.map(|m| m.id.to_string()),
+        product_name: version.model.map(|m| m.product_name.to_string()),
+    })
+}
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub async fn ledger_open_app(
+    connection_id: String,
+    app_name: String,
+) -> Result<(), RustLedgerError> {
+    let mut session = registry::session(&connection_id).await?;
+    dashboard::open_app(&mut session, &app_name)
+        .await
+        .map_err(Into::into)
+}
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub async fn ledger_quit_app(connection_id: String) -> Result<(), RustLedgerError> {
+    let mut session = registry::session(&connection_id).await?;
+    dashboard::quit_app(&mut session).await.map_err(Into::into)
+}
+
+// ---- Mobile stubs ----
+
+#[cfg(any(target_os = "android", target_os = "ios"))]
+const NOT_SUPPORTED: &str = "Ledger dashboard commands not supported on mobile";
+
+#[cfg(any(target_os = "android", target_os = "ios"))]
+pub async fn ledger_get_app_and_version(
+    _connection_id: String,
+) -> Result<RustLedgerAppInfo, RustLedgerError> {
+    Err(RustLedgerError::Io(NOT_SUPPORTED.to_string()))
+}
+
+#[cfg(any(target_os = "android", target_os = "ios"))]
+pub async fn ledger_get_version(
+    _connection_id: String,
+) -> Result<RustLedgerDeviceVersion, RustLedgerError> {
+    Err(RustLedgerError::Io(NOT_SUPPORTED.to_string()))
+}
+
+#[cfg(any(target_os = "android", target_os = "ios"))]
+pub async fn ledger_open_app(
+    _connection_id: String,
+    _app_name: String,
+) -> Result<(), RustLedgerError> {
+    Err(RustLedgerError::Io(NOT_SUPPORTED.to_string()))
+}
+
+#[cfg(any(target_os = "android", target_os = "ios"))]
+pub async fn ledger_quit_app(_connection_id: String) -> Result<(), RustLedgerError> {
+    Err(RustLedgerError::Io(NOT_SUPPORTED.to_string()))
+}
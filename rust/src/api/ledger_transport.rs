@@ -1,3 +1,5 @@
+use crate::frb_generated::StreamSink;
+
 pub struct RustLedgerHidDevice {
     pub device_id: String,
     pub vendor_id: u16,
@@ -14,48 +16,165 @@ pub struct RustLedgerBleDevice {
     pub product_name: String,
 }
 
+pub struct RustLedgerBleConfig {
+    pub exchange_timeout_ms: Option<u64>,
+    pub reconnect_attempts: Option<u32>,
+}
+
+pub enum RustLedgerBleState {
+    Connecting,
+    Connected,
+    MtuNegotiated { mtu: u32 },
+    Reconnecting { attempt: u32 },
+    Disconnected,
+}
+
+pub struct RustLedgerBleEvent {
+    pub device_id: String,
+    pub state: RustLedgerBleState,
+}
+
+pub struct SpeculosConnectParams {
+    pub host: Option<String>,
+    pub port: Option<u16>,
+}
+
+/// Account read from a Ledger app, `public_key` is hex encoded.
+pub struct RustLedgerAccount {
+    pub index: u32,
+    pub public_key: String,
+    pub address: String,
+}
+
+/// Ledger failures as seen by Dart. The status-word variants let the UI
+/// react (ask to unlock, open the right app, ...) without decoding raw codes.
+#[derive(Debug)]
+pub enum RustLedgerError {
+    Disconnected,
+    Timeout,
+    Io(String),
+    Framing(String),
+    ConnectionNotFound(String),
+    UserRejected,
+    DeviceLocked,
+    WrongApp,
+    UnknownInstruction,
+    InvalidData,
+    AppNotOpen,
+    UnexpectedStatus(u16),
+    /// Well-known error status word answered to a raw `ledger_exchange`
+    Status {
+        status_word: u16,
+        message: String,
+    },
+    Cancelled,
+    /// Failure outside the device, e.g. wallet lookup or tx encoding
+    Service(String),
+}
+
+impl std::fmt::Display for RustLedgerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RustLedgerError::Disconnected => write!(f, "Device disconnected"),
+            RustLedgerError::Timeout => write!(f, "Device timeout"),
+            RustLedgerError::Io(msg) => write!(f, "I/O error: {}", msg),
+            RustLedgerError::Framing(msg) => write!(f, "Framing error: {}", msg),
+            RustLedgerError::ConnectionNotFound(id) => write!(f, "Connection {} not found", id),
+            RustLedgerError::UserRejected => write!(f, "Action rejected by user"),
+            RustLedgerError::DeviceLocked => write!(f, "Device is locked"),
+            RustLedgerError::WrongApp => write!(f, "Wrong app open on device"),
+            RustLedgerError::UnknownInstruction => write!(f, "Instruction not supported by app"),
+            RustLedgerError::InvalidData => write!(f, "Invalid data sent to device"),
+            RustLedgerError::AppNotOpen => write!(f, "App is not open on device"),
+            RustLedgerError::UnexpectedStatus(sw) => {
+                write!(f, "Unexpected status word: 0x{:04X}", sw)
+            }
+            RustLedgerError::Status {
+                status_word,
+                message,
+            } => write!(f, "{} (0x{:04X})", message, status_word),
+            RustLedgerError::Cancelled => write!(f, "Exchange cancelled"),
+            RustLedgerError::Service(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
 // ---- Desktop implementation ----
 
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
-use std::collections::HashMap;
+use crate::ledger::ble::{self, BleConfig, BleConnectionState, BleLedgerTransport};
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
-use std::sync::{Arc, Mutex, RwLock};
-
+use crate::ledger::hid::{self, HidLedgerTransport};
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
-use lazy_static::lazy_static;
-
+use crate::ledger::registry;
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
-use crate::ledger::ble::BleLedgerTransport;
+use crate::ledger::replay::{RecordingTransport, ReplayTransport};
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
-use crate::ledger::hid::{self, HidLedgerTransport};
+use crate::ledger::speculos::{
+    SpeculosLedgerTransport, DEFAULT_SPECULOS_APDU_PORT, DEFAULT_SPECULOS_HOST,
+};
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+use crate::ledger::transport::{blocking_io, status_word, LedgerError};
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+use futures::StreamExt;
 
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
-enum TransportEntry {
-    Hid(Mutex<HidLedgerTransport>),
-    Ble(tokio::sync::Mutex<BleLedgerTransport>),
+impl From<LedgerError> for RustLedgerError {
+    fn from(error: LedgerError) -> Self {
+        match error {
+            LedgerError::Disconnected => RustLedgerError::Disconnected,
+            LedgerError::Timeout => RustLedgerError::Timeout,
+            LedgerError::Io(msg) => RustLedgerError::Io(msg),
+            LedgerError::Framing(msg) => RustLedgerError::Framing(msg),
+            LedgerError::ConnectionNotFound(id) => RustLedgerError::ConnectionNotFound(id),
+            LedgerError::UserRejected => RustLedgerError::UserRejected,
+            LedgerError::DeviceLocked => RustLedgerError::DeviceLocked,
+            LedgerError::WrongApp => RustLedgerError::WrongApp,
+            LedgerError::UnknownInstruction => RustLedgerError::UnknownInstruction,
+            LedgerError::InvalidData => RustLedgerError::InvalidData,
+            LedgerError::AppNotOpen => RustLedgerError::AppNotOpen,
+            LedgerError::UnexpectedStatus(sw) => RustLedgerError::UnexpectedStatus(sw),
+            LedgerError::Cancelled => RustLedgerError::Cancelled,
+        }
+    }
 }
 
+/// Send a raw APDU and return the raw response, status word included.
+/// Well-known error status words (user rejection, locked device, ...) fail
+/// with `RustLedgerError::Status`; success and app-specific codes such as
+/// the BTC app's 0xE000 interrupt are returned for the caller to interpret.
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
-unsafe impl Sync for TransportEntry {}
-#[cfg(not(any(target_os = "android", target_os = "ios")))]
-unsafe impl Send for TransportEntry {}
+pub async fn ledger_exchange(
+    connection_id: String,
+    apdu: Vec<u8>,
+) -> Result<Vec<u8>, RustLedgerError> {
+    let response = registry::exchange(&connection_id, &apdu).await?;
+    let sw = status_word(&response)?;
+    match LedgerError::from_status_word(sw) {
+        Some(error) => Err(RustLedgerError::Status {
+            status_word: sw,
+            message: error.to_string(),
+        }),
+        None => Ok(response),
+    }
+}
 
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
-lazy_static! {
-    static ref TRANSPORT_REGISTRY: RwLock<HashMap<String, Arc<TransportEntry>>> =
-        RwLock::new(HashMap::new());
+pub async fn ledger_close(connection_id: String) -> Result<(), RustLedgerError> {
+    registry::close(&connection_id).await.map_err(Into::into)
 }
 
+/// Abort the exchange queued or running on `connection_id`, e.g. when the
+/// user closes the signing dialog. If an APDU was already on the device the
+/// connection is closed too and has to be reopened.
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
-fn next_connection_id() -> String {
-    use std::sync::atomic::{AtomicU64, Ordering};
-    static COUNTER: AtomicU64 = AtomicU64::new(1);
-    format!("conn_{}", COUNTER.fetch_add(1, Ordering::Relaxed))
+pub fn ledger_cancel(connection_id: String) -> Result<(), RustLedgerError> {
+    registry::cancel(&connection_id).map_err(Into::into)
 }
 
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
-pub fn ledger_hid_list() -> Result<Vec<RustLedgerHidDevice>, String> {
-    let devices = hid::list_devices().map_err(|e| e.to_string())?;
+pub fn ledger_hid_list() -> Result<Vec<RustLedgerHidDevice>, RustLedgerError> {
+    let devices = hid::list_devices()?;
     Ok(devices
         .into_iter()
         .map(|d| RustLedgerHidDevice {
@@ -69,54 +188,27 @@ pub fn ledger_hid_list() -> Result<Vec<RustLedgerHidDevice>, String> {
 }
 
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
-pub fn ledger_hid_open(device_id: String) -> Result<String, String> {
-    let transport = HidLedgerTransport::open(&device_id).map_err(|e| e.to_string())?;
-    let conn_id = next_connection_id();
-    let mut registry = TRANSPORT_REGISTRY
-        .write()
-        .map_err(|e| e.to_string())?;
-    registry.insert(
-        conn_id.clone(),
-        Arc::new(TransportEntry::Hid(Mutex::new(transport))),
-    );
-    Ok(conn_id)
+pub fn ledger_hid_open(device_id: String) -> Result<String, RustLedgerError> {
+    let transport = HidLedgerTransport::open(&device_id)?;
+    registry::register(Box::new(transport)).map_err(Into::into)
 }
 
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
-pub fn ledger_hid_exchange(connection_id: String, apdu: Vec<u8>) -> Result<Vec<u8>, String> {
-    let entry = {
-        let registry = TRANSPORT_REGISTRY
-            .read()
-            .map_err(|e| e.to_string())?;
-        registry
-            .get(&connection_id)
-            .cloned()
-            .ok_or_else(|| format!("Connection {} not found", connection_id))?
-    };
-    match entry.as_ref() {
-        TransportEntry::Hid(mutex) => {
-            let transport = mutex.lock().map_err(|e| e.to_string())?;
-            let result = transport.exchange(&apdu).map_err(|e| e.to_string())?;
-            Ok(result)
-        }
-        _ => Err("Connection is not HID".to_string()),
-    }
+pub async fn ledger_hid_exchange(
+    connection_id: String,
+    apdu: Vec<u8>,
+) -> Result<Vec<u8>, RustLedgerError> {
+    ledger_exchange(connection_id, apdu).await
 }
 
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
-pub fn ledger_hid_close(connection_id: String) -> Result<(), String> {
-    let mut registry = TRANSPORT_REGISTRY
-        .write()
-        .map_err(|e| e.to_string())?;
-    registry.remove(&connection_id);
-    Ok(())
+pub async fn ledger_hid_close(connection_id: String) -> Result<(), RustLedgerError> {
+    ledger_close(connection_id).await
 }
 
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
-pub async fn ledger_ble_scan() -> Result<Vec<RustLedgerBleDevice>, String> {
-    let devices = crate::ledger::ble::scan_devices()
-        .await
-        .map_err(|e| e.to_string())?;
+pub async fn ledger_ble_scan() -> Result<Vec<RustLedgerBleDevice>, RustLedgerError> {
+    let devices = crate::ledger::ble::scan_devices().await?;
     Ok(devices
         .into_iter()
         .map(|d| RustLedgerBleDevice {
@@ -130,100 +222,255 @@ pub async fn ledger_ble_scan() -> Result<Vec<RustLedgerBleDevice>, String> {
 }
 
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
-pub async fn ledger_ble_open(device_id: String) -> Result<String, String> {
-    let transport = BleLedgerTransport::open(&device_id)
-        .await
-        .map_err(|e| e.to_string())?;
-    let conn_id = next_connection_id();
-    let mut registry = TRANSPORT_REGISTRY
-        .write()
-        .map_err(|e| e.to_string())?;
-    registry.insert(
-        conn_id.clone(),
-        Arc::new(TransportEntry::Ble(tokio::sync::Mutex::new(transport))),
-    );
-    Ok(conn_id)
-}
-
-#[cfg(not(any(target_os = "android", target_os = "ios")))]
-pub async fn ledger_ble_exchange(connection_id: String, apdu: Vec<u8>) -> Result<Vec<u8>, String> {
-    let entry = {
-        let registry = TRANSPORT_REGISTRY
-            .read()
-            .map_err(|e| e.to_string())?;
-        registry
-            .get(&connection_id)
-            .cloned()
-            .ok_or_else(|| format!("Connection {} not found", connection_id))?
-    };
-    match entry.as_ref() {
-        TransportEntry::Ble(mutex) => {
-            let transport = mutex.lock().await;
-            let result = transport.exchange(&apdu).await.map_err(|e| e.to_string())?;
-            Ok(result)
-        }
-        _ => Err("Connection is not BLE".to_string()),
-    }
+pub async fn ledger_ble_open(device_id: String) -> Result<String, RustLedgerError> {
+    let transport = BleLedgerTransport::open(&device_id).await?;
+    registry::register(Box::new(transport)).map_err(Into::into)
 }
 
+/// Open a BLE connection with a custom exchange timeout and reconnect
+/// policy, unset fields keep the defaults.
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
-pub async fn ledger_ble_close(connection_id: String) -> Result<(), String> {
-    let entry = {
-        let mut registry = TRANSPORT_REGISTRY
-            .write()
-            .map_err(|e| e.to_string())?;
-        registry.remove(&connection_id)
+pub async fn ledger_ble_open_with_config(
+    device_id: String,
+    config: RustLedgerBleConfig,
+) -> Result<String, RustLedgerError> {
+    let defaults = BleConfig::default();
+    let config = BleConfig {
+        exchange_timeout: config
+            .exchange_timeout_ms
+            .map(std::time::Duration::from_millis)
+            .unwrap_or(defaults.exchange_timeout),
+        reconnect_attempts: config
+            .reconnect_attempts
+            .unwrap_or(defaults.reconnect_attempts),
     };
-    if let Some(entry) = entry {
-        if let TransportEntry::Ble(mutex) = entry.as_ref() {
-            let transport = mutex.lock().await;
-            transport.close().await.map_err(|e| e.to_string())?;
+    let transport = BleLedgerTransport::open_with_config(&device_id, config).await?;
+    registry::register(Box::new(transport)).map_err(Into::into)
+}
+
+/// Forward BLE connection state changes to Dart until the stream is closed.
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub async fn ledger_ble_connection_events(
+    sink: StreamSink<RustLedgerBleEvent>,
+) -> Result<(), RustLedgerError> {
+    let events = ble::connection_events().await?;
+    futures::pin_mut!(events);
+
+    while let Some(event) = events.next().await {
+        let state = match event.state {
+            BleConnectionState::Connecting => RustLedgerBleState::Connecting,
+            BleConnectionState::Connected => RustLedgerBleState::Connected,
+            BleConnectionState::MtuNegotiated(mtu) => {
+                RustLedgerBleState::MtuNegotiated { mtu: mtu as u32 }
+            }
+            BleConnectionState::Reconnecting(attempt) => {
+                RustLedgerBleState::Reconnecting { attempt }
+            }
+            BleConnectionState::Disconnected => RustLedgerBleState::Disconnected,
+        };
+        let added = sink.add(RustLedgerBleEvent {
+            device_id: event.device_id,
+            state,
+        });
+        if added.is_err() {
+            break;
         }
     }
+
     Ok(())
 }
 
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub async fn ledger_ble_exchange(
+    connection_id: String,
+    apdu: Vec<u8>,
+) -> Result<Vec<u8>, RustLedgerError> {
+    ledger_exchange(connection_id, apdu).await
+}
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub async fn ledger_ble_close(connection_id: String) -> Result<(), RustLedgerError> {
+    ledger_close(connection_id).await
+}
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub fn ledger_speculos_open(params: SpeculosConnectParams) -> Result<String, RustLedgerError> {
+    let host = params
+        .host
+        .unwrap_or_else(|| DEFAULT_SPECULOS_HOST.to_string());
+    let port = params.port.unwrap_or(DEFAULT_SPECULOS_APDU_PORT);
+    let transport = SpeculosLedgerTransport::open(&host, port)?;
+    registry::register(Box::new(transport)).map_err(Into::into)
+}
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub async fn ledger_speculos_exchange(
+    connection_id: String,
+    apdu: Vec<u8>,
+) -> Result<Vec<u8>, RustLedgerError> {
+    ledger_exchange(connection_id, apdu).await
+}
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub async fn ledger_speculos_close(connection_id: String) -> Result<(), RustLedgerError> {
+    ledger_close(connection_id).await
+}
+
+/// Record every following exchange on `connection_id` to `path`, in the
+/// `=> apdu` / `<= response` session format, until `ledger_record_stop`.
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub async fn ledger_record_start(
+    connection_id: String,
+    path: String,
+) -> Result<(), RustLedgerError> {
+    let file = blocking_io(move |_| {
+        std::fs::File::create(&path)
+            .map_err(|e| LedgerError::Io(format!("Cannot create session file: {}", e)))
+    })
+    .await?;
+    registry::wrap(&connection_id, |inner| {
+        Box::new(RecordingTransport::new(inner, file))
+    })
+    .await
+    .map_err(Into::into)
+}
+
+/// Stop recording on `connection_id` and close the session file. The
+/// connection stays open.
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub async fn ledger_record_stop(connection_id: String) -> Result<(), RustLedgerError> {
+    let recorder = registry::unwrap(&connection_id).await?;
+    blocking_io(move |_| {
+        drop(recorder);
+        Ok(())
+    })
+    .await
+    .map_err(Into::into)
+}
+
+/// Open a connection that serves a recorded session back.
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub fn ledger_replay_open(path: String) -> Result<String, RustLedgerError> {
+    let transport = ReplayTransport::open(std::path::Path::new(&path))?;
+    registry::register(Box::new(transport)).map_err(Into::into)
+}
+
 // ---- Mobile stubs ----
 
 #[cfg(any(target_os = "android", target_os = "ios"))]
 const NOT_SUPPORTED: &str = "Ledger HID/BLE transport not supported on mobile";
 
 #[cfg(any(target_os = "android", target_os = "ios"))]
-pub fn ledger_hid_list() -> Result<Vec<RustLedgerHidDevice>, String> {
-    Err(NOT_SUPPORTED.to_string())
+pub async fn ledger_exchange(
+    _connection_id: String,
+    _apdu: Vec<u8>,
+) -> Result<Vec<u8>, RustLedgerError> {
+    Err(RustLedgerError::Io(NOT_SUPPORTED.to_string()))
+}
+
+#[cfg(any(target_os = "android", target_os = "ios"))]
+pub async fn ledger_close(_connection_id: String) -> Result<(), RustLedgerError> {
+    Err(RustLedgerError::Io(NOT_SUPPORTED.to_string()))
+}
+
+#[cfg(any(target_os = "android", target_os = "ios"))]
+pub fn ledger_cancel(_connection_id: String) -> Result<(), RustLedgerError> {
+    Err(RustLedgerError::Io(NOT_SUPPORTED.to_string()))
+}
+
+#[cfg(any(target_os = "android", target_os = "ios"))]
+pub fn ledger_hid_list() -> Result<Vec<RustLedgerHidDevice>, RustLedgerError> {
+    Err(RustLedgerError::Io(NOT_SUPPORTED.to_string()))
+}
+
+#[cfg(any(target_os = "android", target_os = "ios"))]
+pub fn ledger_hid_open(_device_id: String) -> Result<String, RustLedgerError> {
+    Err(RustLedgerError::Io(NOT_SUPPORTED.to_string()))
+}
+
+#[cfg(any(target_os = "android", target_os = "ios"))]
+pub async fn ledger_hid_exchange(
+    _connection_id: String,
+    _apdu: Vec<u8>,
+) -> Result<Vec<u8>, RustLedgerError> {
+    Err(RustLedgerError::Io(NOT_SUPPORTED.to_string()))
+}
+
+#[cfg(any(target_os = "android", target_os = "ios"))]
+pub async fn ledger_hid_close(_connection_id: String) -> Result<(), RustLedgerError> {
+    Err(RustLedgerError::Io(NOT_SUPPORTED.to_string()))
+}
+
+#[cfg(any(target_os = "android", target_os = "ios"))]
+pub async fn ledger_ble_scan() -> Result<Vec<RustLedgerBleDevice>, RustLedgerError> {
+    Err(RustLedgerError::Io(NOT_SUPPORTED.to_string()))
+}
+
+#[cfg(any(target_os = "android", target_os = "ios"))]
+pub async fn ledger_ble_open(_device_id: String) -> Result<String, RustLedgerError> {
+    Err(RustLedgerError::Io(NOT_SUPPORTED.to_string()))
+}
+
+#[cfg(any(target_os = "android", target_os = "ios"))]
+pub async fn ledger_ble_open_with_config(
+    _device_id: String,
+    _config: RustLedgerBleConfig,
+) -> Result<String, RustLedgerError> {
+    Err(RustLedgerError::Io(NOT_SUPPORTED.to_string()))
+}
+
+#[cfg(any(target_os = "android", target_os = "ios"))]
+pub async fn ledger_ble_connection_events(
+    _sink: StreamSink<RustLedgerBleEvent>,
+) -> Result<(), RustLedgerError> {
+    Err(RustLedgerError::Io(NOT_SUPPORTED.to_string()))
+}
+
+#[cfg(any(target_os = "android", target_os = "ios"))]
+pub async fn ledger_ble_exchange(
+    _connection_id: String,
+    _apdu: Vec<u8>,
+) -> Result<Vec<u8>, RustLedgerError> {
+    Err(RustLedgerError::Io(NOT_SUPPORTED.to_string()))
 }
 
 #[cfg(any(target_os = "android", target_os = "ios"))]
-pub fn ledger_hid_open(_device_id: String) -> Result<String, String> {
-    Err(NOT_SUPPORTED.to_string())
+pub async fn ledger_ble_close(_connection_id: String) -> Result<(), RustLedgerError> {
+    Err(RustLedgerError::Io(NOT_SUPPORTED.to_string()))
 }
 
 #[cfg(any(target_os = "android", target_os = "ios"))]
-pub fn ledger_hid_exchange(_connection_id: String, _apdu: Vec<u8>) -> Result<Vec<u8>, String> {
-    Err(NOT_SUPPORTED.to_string())
+pub fn ledger_speculos_open(_params: SpeculosConnectParams) -> Result<String, RustLedgerError> {
+    Err(RustLedgerError::Io(NOT_SUPPORTED.to_string()))
 }
 
 #[cfg(any(target_os = "android", target_os = "ios"))]
-pub fn ledger_hid_close(_connection_id: String) -> Result<(), String> {
-    Err(NOT_SUPPORTED.to_string())
+pub async fn ledger_speculos_exchange(
+    _connection_id: String,
+    _apdu: Vec<u8>,
+) -> Result<Vec<u8>, RustLedgerError> {
+    Err(RustLedgerError::Io(NOT_SUPPORTED.to_string()))
 }
 
 #[cfg(any(target_os = "android", target_os = "ios"))]
-pub async fn ledger_ble_scan() -> Result<Vec<RustLedgerBleDevice>, String> {
-    Err(NOT_SUPPORTED.to_string())
+pub async fn ledger_speculos_close(_connection_id: String) -> Result<(), RustLedgerError> {
+    Err(RustLedgerError::Io(NOT_SUPPORTED.to_string()))
 }
 
 #[cfg(any(target_os = "android", target_os = "ios"))]
-pub async fn ledger_ble_open(_device_id: String) -> Result<String, String> {
-    Err(NOT_SUPPORTED.to_string())
+pub async fn ledger_record_start(
+    _connection_id: String,
+    _path: String,
+) -> Result<(), RustLedgerError> {
+    Err(RustLedgerError::Io(NOT_SUPPORTED.to_string()))
 }
 
 #[cfg(any(target_os = "android", target_os = "ios"))]
-pub async fn ledger_ble_exchange(_connection_id: String, _apdu: Vec<u8>) -> Result<Vec<u8>, String> {
-    Err(NOT_SUPPORTED.to_string())
+pub async fn ledger_record_stop(_connection_id: String) -> Result<(), RustLedgerError> {
+    Err(RustLedgerError::Io(NOT_SUPPORTED.to_string()))
 }
 
 #[cfg(any(target_os = "android", target_os = "ios"))]
-pub async fn ledger_ble_close(_connection_id: String) -> Result<(), String> {
-    Err(NOT_SUPPORTED.to_string())
+pub fn ledger_replay_open(_path: String) -> Result<String, RustLedgerError> {
+    Err(RustLedgerError::Io(NOT_SUPPORTED.to_string()))
 }
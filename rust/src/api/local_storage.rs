@@ -39,3 +39,25 @@ impl KeyValueStorage for LocalStorageImpl {
         Ok(())
     }
 }
+
+/// In-memory storage for tests.
+#[cfg(test)]
+#[derive(Default)]
+pub(crate) struct MemoryStorage(std::sync::Mutex<std::collections::HashMap<String, String>>);
+
+#[cfg(test)]
+impl KeyValueStorage for MemoryStorage {
+    fn set(&self, key: String, value: String) -> Result<(), String> {
+        self.0.lock().unwrap().insert(key, value);
+        Ok(())
+    }
+
+    fn get(&self, key: String) -> Option<String> {
+        self.0.lock().unwrap().get(&key).cloned()
+    }
+
+    fn rm(&self, key: String) -> Result<(), String> {
+        self.0.lock().unwrap().remove(&key);
+        Ok(())
+    }
+}
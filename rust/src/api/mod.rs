@@ -1,18 +1,34 @@
 pub mod auth;
 pub mod backend;
 pub mod book;
+pub mod btc;
+pub mod btc_coin_control;
+pub mod btc_discovery;
+pub mod btc_electrum;
+pub mod btc_fees;
+pub mod btc_labels;
 pub mod btc_ledger;
+pub mod btc_message;
+pub mod btc_multisig;
+pub mod btc_psbt;
+pub mod btc_silent_payments;
+pub mod btc_watch_only;
 pub mod cache;
 pub mod connections;
+pub mod eth_ledger;
 pub mod ledger;
+pub mod ledger_dashboard;
 pub mod ledger_transport;
 pub mod local_storage;
 pub mod methods;
 pub mod provider;
 pub mod qrcode;
 pub mod settings;
+pub mod sol_ledger;
 pub mod stake;
 pub mod token;
 pub mod transaction;
+pub mod tron_ledger;
 pub mod utils;
 pub mod wallet;
+pub mod zil_ledger;
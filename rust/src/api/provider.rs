@@ -1,5 +1,7 @@
 use crate::{
-    models::provider::NetworkConfigInfo,
+    models::provider::{
+        init_chain_btc_backend, set_chain_btc_backend, ChainConfig, NetworkConfigInfo,
+    },
     service::service::BACKGROUND_SERVICE,
     utils::{errors::ServiceError, utils::with_service},
 };
@@ -22,15 +24,13 @@ pub async fn get_networks(
     let providers_testnet = get_chains_providers_from_json(testnet_json)?;
 
     with_service(|core| {
-        let mut configs: Vec<_> = providers_mainnet
-            .iter()
-            .map(|n| n.clone().try_into())
-            .collect::<Result<Vec<_>, _>>()?;
-        let testnet_configs: Vec<_> = providers_testnet
-            .iter()
-            .map(|n| n.clone().try_into())
-            .collect::<Result<Vec<_>, _>>()?;
-        configs.extend(testnet_configs);
+        let mut configs: Vec<ChainConfig> = Vec::new();
+        for info in providers_mainnet.iter().chain(&providers_testnet) {
+            let config: ChainConfig = info.clone().try_into()?;
+            init_chain_btc_backend(config.hash(), info.btc_backend)
+                .map_err(ServiceError::AppStorage)?;
+            configs.push(config);
+        }
 
         core.add_batch_providers(configs)?;
 
@@ -83,8 +83,10 @@ pub async fn provider_req_proxy(payload: String, chain_hash: u64) -> Result<Stri
 
 pub async fn add_provider(provider_config: NetworkConfigInfo) -> Result<u64, String> {
     with_service(|core| {
+        let btc_backend = provider_config.btc_backend;
         let config = provider_config.try_into()?;
         let hash = core.add_provider(config)?;
+        set_chain_btc_backend(hash, btc_backend).map_err(ServiceError::AppStorage)?;
 
         Ok(hash)
     })
@@ -111,7 +113,10 @@ pub async fn add_providers_list(provider_config: Vec<NetworkConfigInfo>) -> Resu
         }
 
         for new_conf in provider_config {
+            let btc_backend = new_conf.btc_backend;
             let new_provider = NetworkProvider::new(new_conf.try_into()?);
+            set_chain_btc_backend(new_provider.config.hash(), btc_backend)
+                .map_err(ServiceError::AppStorage)?;
 
             providers.push(new_provider);
         }
@@ -137,6 +142,9 @@ pub async fn create_or_update_chain(provider_config: NetworkConfigInfo) -> Resul
             .iter()
             .position(|p| p.config.hash() == new_chain.chain_hash);
 
+        set_chain_btc_backend(new_chain.chain_hash, new_chain.btc_backend)
+            .map_err(ServiceError::AppStorage)?;
+
         match existing_provider_index {
             Some(index) => {
                 providers[index].config = new_chain.try_into()?;
@@ -0,0 +1,132 @@
+use crate::api::ledger_transport::{RustLedgerAccount, RustLedgerError};
+use crate::models::transactions::request::TransactionRequestInfo;
+
+// ---- Desktop implementation ----
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+use crate::api::transaction::encode_tx_rlp;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+use crate::ledger::registry;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+use crate::ledger::solana;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+use crate::ledger::transport::LedgerError;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+use crate::utils::utils::ensure_wallet_can_sign;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+use zilpay::crypto::slip44;
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub async fn sol_ledger_get_app_version(connection_id: String) -> Result<String, RustLedgerError> {
+    let mut session = registry::session(&connection_id).await?;
+    solana::get_app_version(&mut session)
+        .await
+        .map_err(Into::into)
+}
+
+/// Solana accounts live at `m/44'/501'/index'/0'`, the address is the
+/// base58 encoded public key.
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub async fn sol_ledger_get_address(
+    connection_id: String,
+    index: u32,
+    display: bool,
+) -> Result<RustLedgerAccount, RustLedgerError> {
+    let mut session = registry::session(&connection_id).await?;
+    get_account(&mut session, index, display)
+        .await
+        .map_err(Into::into)
+}
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+async fn get_account(
+    session: &mut registry::Session,
+    index: u32,
+    display: bool,
+) -> Result<RustLedgerAccount, LedgerError> {
+    let public_key = solana::get_pubkey(session, &solana::solana_path(index), display).await?;
+
+    Ok(RustLedgerAccount {
+        index,
+        address: bitcoin::base58::encode(&public_key),
+        public_key: hex::encode(public_key),
+    })
+}
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub async fn sol_ledger_get_accounts(
+    connection_id: String,
+    indices: Vec<u32>,
+) -> Result<Vec<RustLedgerAccount>, RustLedgerError> {
+    let mut session = registry::session(&connection_id).await?;
+    let mut accounts = Vec::with_capacity(indices.len());
+    for index in indices {
+        accounts.push(get_account(&mut session, index, false).await?);
+    }
+    Ok(accounts)
+}
+
+/// Sign `tx` with the device key at `ledger_index`. Returns the 64-byte
+/// ed25519 signature over the serialized message.
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub async fn sol_ledger_sign_transaction(
+    connection_id: String,
+    wallet_index: usize,
+    account_index: usize,
+    ledger_index: u32,
+    tx: TransactionRequestInfo,
+) -> Result<Vec<u8>, RustLedgerError> {
+    ensure_wallet_can_sign(wallet_index)
+        .await
+        .map_err(|e| RustLedgerError::Service(e.to_string()))?;
+    let encoded = encode_tx_rlp(wallet_index, account_index, tx, slip44::SOLANA)
+        .await
+        .map_err(RustLedgerError::Service)?;
+
+    let mut session = registry::session(&connection_id).await?;
+    solana::sign_transaction(
+        &mut session,
+        &solana::solana_path(ledger_index),
+        &encoded.bytes,
+    )
+    .await
+    .map_err(Into::into)
+}
+
+// ---- Mobile stubs ----
+
+#[cfg(any(target_os = "android", target_os = "ios"))]
+const NOT_SUPPORTED: &str = "Ledger Solana app not supported on mobile";
+
+#[cfg(any(target_os = "android", target_os = "ios"))]
+pub async fn sol_ledger_get_app_version(_connection_id: String) -> Result<String, RustLedgerError> {
+    Err(RustLedgerError::Io(NOT_SUPPORTED.to_string()))
+}
+
+#[cfg(any(target_os = "android", target_os = "ios"))]
+pub async fn sol_ledger_get_address(
+    _connection_id: String,
+    _index: u32,
+    _display: bool,
+) -> Result<RustLedgerAccount, RustLedgerError> {
+    Err(RustLedgerError::Io(NOT_SUPPORTED.to_string()))
+}
+
+#[cfg(any(target_os = "android", target_os = "ios"))]
+pub async fn sol_ledger_get_accounts(
+    _connection_id: String,
+    _indices: Vec<u32>,
+) -> Result<Vec<RustLedgerAccount>, RustLedgerError> {
+    Err(RustLedgerError::Io(NOT_SUPPORTED.to_string()))
+}
+
+#[cfg(any(target_os = "android", target_os = "ios"))]
+pub async fn sol_ledger_sign_transaction(
+    _connection_id: String,
+    _wallet_index: usize,
+    _account_index: usize,
+    _ledger_index: u32,
+    _tx: TransactionRequestInfo,
+) -> Result<Vec<u8>, RustLedgerError> {
+    Err(RustLedgerError::Io(NOT_SUPPORTED.to_string()))
+}
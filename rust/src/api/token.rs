@@ -1,4 +1,5 @@
 use crate::{
+    api::btc_electrum::{btc_electrum_sync_balances, uses_electrum},
     models::ftoken::FTokenInfo,
     service::service::BACKGROUND_SERVICE,
     utils::{
@@ -142,6 +143,10 @@ struct SolanaTokenListResponse {
 }
 
 pub async fn sync_balances(wallet_index: usize) -> Result<(), String> {
+    if uses_electrum(wallet_index).await? {
+        return btc_electrum_sync_balances(wallet_index).await.map(|_| ());
+    }
+
     if let Some(service) = BACKGROUND_SERVICE.read().await.as_ref() {
         let core = Arc::clone(&service.core);
 
@@ -1,13 +1,20 @@
 use std::sync::Arc;
 
+use crate::api::btc::{create_transaction, record_replacement};
+use crate::api::btc_fees::btc_required_tx_params;
+use crate::api::local_storage::LocalStorageImpl;
+use crate::btc::message::{self as btc_message, key_address};
 use crate::frb_generated::StreamSink;
+use crate::models::btc::{BtcCoinSelectionInfo, BtcMessageFormat, BtcTxParamsInfo};
 use crate::models::ftoken::FTokenInfo;
 use crate::models::gas::RequiredTxParamsInfo;
 use crate::models::transactions::history::HistoricalTransactionInfo;
 use crate::models::transactions::request::TransactionRequestInfo;
+use crate::models::transactions::transaction_metadata::TransactionMetadataInfo;
 use crate::service::service::BACKGROUND_SERVICE;
 use crate::utils::errors::ServiceError;
-use crate::utils::utils::{parse_address, with_service};
+use crate::utils::utils::{ensure_can_sign, parse_address, with_service};
+use bitcoin::secp256k1::{Secp256k1, SecretKey};
 use secrecy::zeroize::Zeroize;
 use secrecy::SecretString;
 use tokio::sync::mpsc;
@@ -23,6 +30,7 @@ pub use zilpay::errors::wallet::WalletErrors;
 use zilpay::history::transaction::HistoricalTransaction;
 use zilpay::network::evm::RequiredTxParams;
 pub use zilpay::proto::address::Address;
+use zilpay::proto::keypair::KeyPair;
 use zilpay::proto::pubkey::PubKey;
 use zilpay::proto::signature::Signature;
 pub use zilpay::proto::tx::TransactionReceipt;
@@ -32,6 +40,7 @@ pub use zilpay::proto::U256;
 use zilpay::token::ft::FToken;
 pub use zilpay::wallet::wallet_storage::StorageOperations;
 pub use zilpay::wallet::wallet_transaction::WalletTransaction;
+use zilpay::wallet::Wallet;
 
 pub async fn send_signed_transactions(
     wallet_index: u8,
@@ -65,12 +74,12 @@ pub async fn send_signed_transactions(
         .map_err(ServiceError::BackgroundError)?
         .into_iter()
         .next()
-        .map(|v| v.into())
         .ok_or(ServiceError::TransactionErrors(
             zilpay::errors::tx::TransactionErrors::InvalidTxHash,
         ))?;
+    record_btc_replacement(wallet, wallet_index, &tx);
 
-    Ok(tx)
+    Ok(tx.into())
 }
 
 pub async fn sign_send_transactions(
@@ -87,6 +96,7 @@ pub async fn sign_send_transactions(
     let wallet = core
         .get_wallet_by_index(wallet_index)
         .map_err(ServiceError::BackgroundError)?;
+    ensure_can_sign(wallet, wallet_index)?;
 
     let signed_tx = {
         let seed_bytes = if let Some(mut pass) = password {
@@ -136,15 +146,17 @@ pub async fn sign_send_transactions(
     let is_broadcast = signed_tx.get_metadata().broadcast;
 
     let tx = if is_broadcast {
-        core.broadcast_signed_transactions(wallet_index, vec![signed_tx])
+        let tx = core
+            .broadcast_signed_transactions(wallet_index, vec![signed_tx])
             .await
             .map_err(ServiceError::BackgroundError)?
             .into_iter()
             .next()
-            .map(|v| v.into())
             .ok_or(ServiceError::TransactionErrors(
                 zilpay::errors::tx::TransactionErrors::InvalidTransaction,
-            ))?
+            ))?;
+        record_btc_replacement(wallet, wallet_index, &tx);
+        tx.into()
     } else {
         let history = HistoricalTransaction::from_transaction_receipt(signed_tx)
             .map_err(ServiceError::TransactionErrors)?;
@@ -157,6 +169,47 @@ pub async fn sign_send_transactions(
     Ok(tx)
 }
 
+/// A broadcast BTC send spending the inputs of pending entries replaced
+/// them, record that on the originals. The send is already out, so a
+/// failure here is logged rather than returned.
+fn record_btc_replacement(wallet: &Wallet, wallet_index: usize, sent: &HistoricalTransaction) {
+    if sent.btc.is_none() {
+        return;
+    }
+    if let Err(e) = mark_replaced(wallet, wallet_index, sent) {
+        eprintln!(
+            "Failed to mark replaced BTC transactions in wallet {}: {}",
+            wallet_index, e
+        );
+    }
+}
+
+/// History can only be cleared and appended to, so if writing the updated
+/// entries fails the previous ones are put back instead of leaving the
+/// history empty.
+fn mark_replaced(
+    wallet: &Wallet,
+    wallet_index: usize,
+    sent: &HistoricalTransaction,
+) -> Result<(), ServiceError> {
+    let previous = wallet
+        .get_history()
+        .map_err(|e| ServiceError::WalletError(wallet_index, e))?;
+    let mut history = previous.clone();
+    if !record_replacement(&mut history, sent) {
+        return Ok(());
+    }
+
+    wallet
+        .clear_history()
+        .map_err(|e| ServiceError::WalletError(wallet_index, e))?;
+    if let Err(e) = wallet.add_history(&history) {
+        wallet.add_history(&previous).ok();
+        return Err(ServiceError::WalletError(wallet_index, e));
+    }
+    Ok(())
+}
+
 pub struct EncodedRLPTx {
     pub bytes: Vec<u8>,
     pub chunks_bytes: Vec<Vec<u8>>,
@@ -267,6 +320,10 @@ pub async fn sign_message(
     let guard = BACKGROUND_SERVICE.read().await;
     let service = guard.as_ref().ok_or(ServiceError::NotRunning)?;
     let core = Arc::clone(&service.core);
+    let wallet = core
+        .get_wallet_by_index(wallet_index)
+        .map_err(ServiceError::BackgroundError)?;
+    ensure_can_sign(wallet, wallet_index)?;
     let password = password.map(|p| SecretString::new(p.into()));
 
     let signed: (PubKey, Signature) = {
@@ -301,6 +358,94 @@ pub async fn sign_message(
     Ok((pubkey, sig))
 }
 
+/// Private key, network and address type of a software Bitcoin account.
+async fn reveal_btc_key(
+    wallet_index: usize,
+    account_index: usize,
+    password: Option<String>,
+    passphrase: Option<String>,
+) -> Result<(SecretKey, bitcoin::Network, bitcoin::AddressType), String> {
+    let guard = BACKGROUND_SERVICE.read().await;
+    let service = guard.as_ref().ok_or(ServiceError::NotRunning)?;
+    let core = Arc::clone(&service.core);
+    let wallet = core
+        .get_wallet_by_index(wallet_index)
+        .map_err(ServiceError::BackgroundError)?;
+    ensure_can_sign(wallet, wallet_index)?;
+    let password = password.map(|p| SecretString::new(p.into()));
+
+    let keypair = {
+        let seed_bytes = if let Some(mut pass) = password {
+            let key = core
+                .unlock_wallet_with_password(&pass, None, wallet_index)
+                .await;
+            pass.zeroize();
+            key
+        } else {
+            core.unlock_wallet_with_session(wallet_index).await
+        }
+        .map_err(ServiceError::BackgroundError)?;
+
+        wallet
+            .reveal_keypair(account_index, &seed_bytes, passphrase.as_deref())
+            .map_err(|e| ServiceError::WalletError(wallet_index, e))?
+    };
+    let KeyPair::Secp256k1Bitcoin((_, sk, network, addr_type)) = keypair else {
+        return Err(ServiceError::AccountTypeNotValid.into());
+    };
+    let sk = SecretKey::from_slice(&sk).map_err(|e| e.to_string())?;
+
+    Ok((sk, network, addr_type))
+}
+
+/// Sign with a software Bitcoin account: legacy `signmessage` for P2PKH,
+/// BIP-322 for P2WPKH and P2TR. Returns the account address and the
+/// base64 signature.
+pub async fn btc_sign_message(
+    wallet_index: usize,
+    account_index: usize,
+    password: Option<String>,
+    passphrase: Option<String>,
+    message: String,
+    format: BtcMessageFormat,
+) -> Result<(String, String), String> {
+    let (sk, network, addr_type) =
+        reveal_btc_key(wallet_index, account_index, password, passphrase).await?;
+
+    let secp = Secp256k1::new();
+    let address = key_address(&secp, &sk, network, addr_type).map_err(|e| e.to_string())?;
+    let signature = btc_message::sign(&secp, &sk, &address, &message, format.into())
+        .map_err(|e| e.to_string())?;
+
+    Ok((address.to_string(), signature))
+}
+
+/// `btc_create_transaction` for recipients that include `sp1...` silent
+/// payment addresses, whose outputs are derived from the keys of the
+/// selected inputs. Every UTXO must belong to the account.
+#[allow(clippy::too_many_arguments)]
+pub async fn btc_create_silent_payment_transaction(
+    storage: &LocalStorageImpl,
+    wallet_address: String,
+    wallet_index: usize,
+    account_index: usize,
+    password: Option<String>,
+    passphrase: Option<String>,
+    params: BtcTxParamsInfo,
+    metadata: TransactionMetadataInfo,
+) -> Result<BtcCoinSelectionInfo, String> {
+    let (sk, _, addr_type) =
+        reveal_btc_key(wallet_index, account_index, password, passphrase).await?;
+
+    create_transaction(
+        storage,
+        &wallet_address,
+        params,
+        metadata,
+        Some((sk, addr_type)),
+    )
+}
+
 pub async fn sign_typed_data_eip712(
     wallet_index: usize,
     account_index: usize,
@@ -313,6 +458,10 @@ pub async fn sign_typed_data_eip712(
     let guard = BACKGROUND_SERVICE.read().await;
     let service = guard.as_ref().ok_or(ServiceError::NotRunning)?;
     let core = Arc::clone(&service.core);
+    let wallet = core
+        .get_wallet_by_index(wallet_index)
+        .map_err(ServiceError::BackgroundError)?;
+    ensure_can_sign(wallet, wallet_index)?;
     let password = password.map(|p| SecretString::new(p.into()));
     let signed: (PubKey, Signature) = {
         let seed_bytes = if let Some(mut pass) = password {
@@ -437,6 +586,10 @@ pub async fn cacl_gas_fee(
     account_index: usize,
     params: TransactionRequestInfo,
 ) -> Result<RequiredTxParamsInfo, String> {
+    if params.btc.is_some() {
+        return btc_required_tx_params(&params).await;
+    }
+
     let chain_hash = params.metadata.chain_hash;
     let gas = {
         let guard = BACKGROUND_SERVICE.read().await;
@@ -0,0 +1,146 @@
+use crate::api::ledger_transport::{RustLedgerAccount, RustLedgerError};
+use crate::models::transactions::request::TransactionRequestInfo;
+
+pub struct RustTronAppConfiguration {
+    pub version: String,
+    pub allow_data: bool,
+    pub allow_contract: bool,
+    pub truncate_address: bool,
+    pub sign_by_hash: bool,
+}
+
+// ---- Desktop implementation ----
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+use crate::api::transaction::encode_tx_rlp;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+use crate::ledger::registry;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+use crate::ledger::transport::{bip44_path, LedgerError};
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+use crate::ledger::tron;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+use crate::utils::utils::ensure_wallet_can_sign;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+use zilpay::crypto::slip44;
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub async fn tron_ledger_get_app_configuration(
+    connection_id: String,
+) -> Result<RustTronAppConfiguration, RustLedgerError> {
+    let mut session = registry::session(&connection_id).await?;
+    let config = tron::get_app_configuration(&mut session).await?;
+
+    Ok(RustTronAppConfiguration {
+        version: config.version,
+        allow_data: config.allow_data,
+        allow_contract: config.allow_contract,
+        truncate_address: config.truncate_address,
+        sign_by_hash: config.sign_by_hash,
+    })
+}
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub async fn tron_ledger_get_address(
+    connection_id: String,
+    index: u32,
+    display: bool,
+) -> Result<RustLedgerAccount, RustLedgerError> {
+    let mut session = registry::session(&connection_id).await?;
+    get_account(&mut session, index, display)
+        .await
+        .map_err(Into::into)
+}
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+async fn get_account(
+    session: &mut registry::Session,
+    index: u32,
+    display: bool,
+) -> Result<RustLedgerAccount, LedgerError> {
+    let path = bip44_path(slip44::TRON, 0, 0, index);
+    let addr = tron::get_address(session, &path, display).await?;
+
+    Ok(RustLedgerAccount {
+        index,
+        public_key: hex::encode(addr.public_key),
+        address: addr.address,
+    })
+}
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub async fn tron_ledger_get_accounts(
+    connection_id: String,
+    indices: Vec<u32>,
+) -> Result<Vec<RustLedgerAccount>, RustLedgerError> {
+    let mut session = registry::session(&connection_id).await?;
+    let mut accounts = Vec::with_capacity(indices.len());
+    for index in indices {
+        accounts.push(get_account(&mut session, index, false).await?);
+    }
+    Ok(accounts)
+}
+
+/// Sign `tx` with the device key at `ledger_index`. Returns the 65-byte
+/// `r || s || v` signature.
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub async fn tron_ledger_sign_transaction(
+    connection_id: String,
+    wallet_index: usize,
+    account_index: usize,
+    ledger_index: u32,
+    tx: TransactionRequestInfo,
+) -> Result<Vec<u8>, RustLedgerError> {
+    ensure_wallet_can_sign(wallet_index)
+        .await
+        .map_err(|e| RustLedgerError::Service(e.to_string()))?;
+    let encoded = encode_tx_rlp(wallet_index, account_index, tx, slip44::TRON)
+        .await
+        .map_err(RustLedgerError::Service)?;
+    let path = bip44_path(slip44::TRON, 0, 0, ledger_index);
+
+    let mut session = registry::session(&connection_id).await?;
+    tron::sign_transaction(&mut session, &path, &encoded.bytes)
+        .await
+        .map_err(Into::into)
+}
+
+// ---- Mobile stubs ----
+
+#[cfg(any(target_os = "android", target_os = "ios"))]
+const NOT_SUPPORTED: &str = "Ledger Tron app not supported on mobile";
+
+#[cfg(any(target_os = "android", target_os = "ios"))]
+pub async fn tron_ledger_get_app_configuration(
+    _connection_id: String,
+) -> Result<RustTronAppConfiguration, RustLedgerError> {
+    Err(RustLedgerError::Io(NOT_SUPPORTED.to_string()))
+}
+
+#[cfg(any(target_os = "android", target_os = "ios"))]
+pub async fn tron_ledger_get_address(
+    _connection_id: String,
+    _index: u32,
+    _display: bool,
+) -> Result<RustLedgerAccount, RustLedgerError> {
+    Err(RustLedgerError::Io(NOT_SUPPORTED.to_string()))
+}
+
+#[cfg(any(target_os = "android", target_os = "ios"))]
+pub async fn tron_ledger_get_accounts(
+    _connection_id: String,
+    _indices: Vec<u32>,
+) -> Result<Vec<RustLedgerAccount>, RustLedgerError> {
+    Err(RustLedgerError::Io(NOT_SUPPORTED.to_string()))
+}
+
+#[cfg(any(target_os = "android", target_os = "ios"))]
+pub async fn tron_ledger_sign_transaction(
+    _connection_id: String,
+    _wallet_index: usize,
+    _account_index: usize,
+    _ledger_index: u32,
+    _tx: TransactionRequestInfo,
+) -> Result<Vec<u8>, RustLedgerError> {
+    Err(RustLedgerError::Io(NOT_SUPPORTED.to_string()))
+}
@@ -1,9 +1,11 @@
 use std::sync::Arc;
 
+use bitcoin::secp256k1::Secp256k1;
 use secrecy::zeroize::Zeroize;
 use secrecy::{ExposeSecret, SecretString};
 use zilpay::background::bg_provider::ProvidersManagement;
 use zilpay::background::bg_storage::StorageManagement;
+use zilpay::crypto::slip44;
 use zilpay::errors::background::BackgroundError;
 use zilpay::errors::token::TokenError;
 use zilpay::errors::wallet::WalletErrors;
@@ -21,11 +23,16 @@ pub use zilpay::{
     proto::{pubkey::PubKey, secret_key::SecretKey},
 };
 
+use crate::api::btc_discovery::remove_discovered_addresses;
+use crate::btc::silent_payments::SilentPaymentKeys;
+use crate::models::btc::BtcSilentPaymentKeysInfo;
 use crate::models::ftoken::FTokenInfo;
 use crate::models::keypair::KeyPairInfo;
 use crate::models::settings::WalletSettingsInfo;
 use crate::service::service::BACKGROUND_SERVICE;
-use crate::utils::utils::{secretkey_from_provider, with_wallet};
+use crate::utils::utils::{
+    bitcoin_network, remove_keyless_wallet, secretkey_from_provider, with_wallet,
+};
 use crate::{
     models::wallet::WalletInfo,
     utils::{errors::ServiceError, utils::with_service},
@@ -57,6 +64,10 @@ pub struct Bip39AddWalletParams {
     pub chain_hash: u64,
 }
 
+/// Adds the accounts in `params.accounts` only. BTC wallets restored from
+/// a seed used elsewhere find the rest with `btc_bip39_discover`. BTC
+/// chains must be mainnet, testnet, regtest or signet, so the accounts get
+/// the network's address prefix and coin type.
 pub async fn add_bip39_wallet(
     params: Bip39AddWalletParams,
     wallet_settings: WalletSettingsInfo,
@@ -75,6 +86,13 @@ pub async fn add_bip39_wallet(
     let password = SecretString::new(params.password.into());
     let secret_mnemonic_str = SecretString::new(params.mnemonic_str.into());
     let secret_passphrase = SecretString::new(params.passphrase.into());
+    let chain = service
+        .core
+        .get_provider(params.chain_hash)
+        .map_err(ServiceError::BackgroundError)?;
+    if chain.config.slip_44 == slip44::BITCOIN && bitcoin_network(&chain.config).is_none() {
+        return Err(ServiceError::AccountTypeNotValid.into());
+    }
 
     Arc::get_mut(&mut service.core)
         .ok_or(ServiceError::CoreAccess)?
@@ -194,7 +212,7 @@ pub async fn add_next_bip39_account(params: AddNextBip39AccountParams) -> Result
         .core
         .get_provider(wallet_data.chain_hash)
         .map_err(ServiceError::BackgroundError)?;
-    let network = chain.config.bitcoin_network();
+    let network = bitcoin_network(&chain.config);
 
     wallet
         .add_next_bip39_account(
@@ -273,6 +291,7 @@ pub async fn delete_wallet(wallet_index: usize, password: Option<String>) -> Res
     let wallet_data = wallet
         .get_wallet_data()
         .map_err(|e| ServiceError::WalletError(wallet_index, e))?;
+    let wallet_address = hex::encode(wallet.wallet_address);
 
     if !matches!(wallet_data.wallet_type, WalletTypes::Ledger(_)) {
         let password = password.map(|p| SecretString::new(p.into()));
@@ -297,6 +316,19 @@ pub async fn delete_wallet(wallet_index: usize, password: Option<String>) -> Res
         .delete_wallet(wallet_index)
         .map_err(ServiceError::BackgroundError)?;
 
+    if let Err(e) = remove_keyless_wallet(&wallet_address) {
+        eprintln!(
+            "Failed to remove key-less record of wallet {}: {}",
+            wallet_address, e
+        );
+    }
+    if let Err(e) = remove_discovered_addresses(&wallet_address) {
+        eprintln!(
+            "Failed to remove discovered addresses of wallet {}: {}",
+            wallet_address, e
+        );
+    }
+
     Ok(())
 }
 
@@ -387,6 +419,57 @@ pub async fn reveal_bip39_phrase(
     Ok(m.to_phrase().expose_secret().to_string())
 }
 
+/// BIP-352 silent payment keys of a seed phrase wallet. The scan secret
+/// stays with the app for scanning, the spend key never leaves the wallet.
+pub async fn btc_silent_payment_keys(
+    wallet_index: usize,
+    account_index: usize,
+    password: String,
+    passphrase: Option<String>,
+) -> Result<BtcSilentPaymentKeysInfo, String> {
+    let guard = BACKGROUND_SERVICE.read().await;
+    let service = guard.as_ref().ok_or(ServiceError::NotRunning)?;
+
+    let mut password = SecretString::new(password.into());
+    let seed = service
+        .core
+        .unlock_wallet_with_password(&password, None, wallet_index)
+        .await
+        .map_err(ServiceError::BackgroundError)?;
+    password.zeroize();
+
+    let wallet = service
+        .core
+        .get_wallet_by_index(wallet_index)
+        .map_err(ServiceError::BackgroundError)?;
+    let wallet_data = wallet
+        .get_wallet_data()
+        .map_err(|e| ServiceError::WalletError(wallet_index, e))?;
+    let chain = service
+        .core
+        .get_provider(wallet_data.chain_hash)
+        .map_err(ServiceError::BackgroundError)?;
+    let network = bitcoin_network(&chain.config).ok_or(ServiceError::AccountTypeNotValid)?;
+    let mnemonic = wallet
+        .reveal_mnemonic(&seed)
+        .map_err(|e| ServiceError::WalletError(wallet_index, e))?;
+
+    let mut bip39_seed = mnemonic
+        .to_seed(passphrase.as_deref().unwrap_or_default())
+        .map_err(|e| e.to_string())?;
+    let secp = Secp256k1::new();
+    let keys =
+        SilentPaymentKeys::from_seed(&secp, &bip39_seed, network.into(), account_index as u32);
+    bip39_seed.zeroize();
+    let keys = keys.map_err(|e| e.to_string())?;
+
+    Ok(BtcSilentPaymentKeysInfo {
+        address: keys.address(&secp).to_string(),
+        scan_secret: hex::encode(keys.scan.secret_bytes()),
+        spend_pubkey: keys.spend.public_key(&secp).to_string(),
+    })
+}
+
 pub async fn zilliqa_swap_chain(wallet_index: usize, account_index: usize) -> Result<(), String> {
     with_service(|core| {
         core.swap_zilliqa_chain(wallet_index, account_index)?;
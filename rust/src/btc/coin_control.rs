@@ -0,0 +1,170 @@
+use std::collections::HashSet;
+use std::str::FromStr;
+
+use bitcoin::{Amount, FeeRate, OutPoint, ScriptBuf, TxOut};
+use thiserror::Error;
+
+use crate::btc::coin_selection::{estimate_weight, fee, CoinSelectionError, Selection, Utxo};
+use crate::btc::labels::{Label, LabelType};
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum CoinControlError {
+    #[error("UTXO {0} is frozen")]
+    Frozen(OutPoint),
+
+    #[error("Nothing to consolidate, need at least two UTXOs worth spending")]
+    NothingToConsolidate,
+
+    #[error(transparent)]
+    Selection(#[from] CoinSelectionError),
+}
+
+/// Outputs marked `spendable: false` in BIP-329 labels.
+pub fn frozen_outpoints(labels: &[Label]) -> HashSet<OutPoint> {
+    labels
+        .iter()
+        .filter(|l| l.label_type == LabelType::Output && l.spendable == Some(false))
+        .filter_map(|l| OutPoint::from_str(&l.reference).ok())
+        .collect()
+}
+
+/// Drop frozen UTXOs before automatic selection. Inputs picked by hand are
+/// spent as given, so a frozen one among them is an error instead.
+pub fn unfrozen(
+    utxos: Vec<Utxo>,
+    frozen: &HashSet<OutPoint>,
+    manual: bool,
+) -> Result<Vec<Utxo>, CoinControlError> {
+    if manual {
+        if let Some(utxo) = utxos.iter().find(|u| frozen.contains(&u.outpoint)) {
+            return Err(CoinControlError::Frozen(utxo.outpoint));
+        }
+        return Ok(utxos);
+    }
+    Ok(utxos
+        .into_iter()
+        .filter(|u| !frozen.contains(&u.outpoint))
+        .collect())
+}
+
+/// Merge the UTXOs worth at most `max_value` into one output paying
+/// `destination`. Those that cost more than they are worth at `fee_rate`
+/// are left alone.
+pub fn consolidate(
+    utxos: &[Utxo],
+    destination: ScriptBuf,
+    fee_rate: FeeRate,
+    max_value: Option<Amount>,
+) -> Result<Selection, CoinControlError> {
+    let selected: Vec<Utxo> = utxos
+        .iter()
+        .filter(|u| max_value.is_none_or(|max| u.txout.value <= max))
+        .filter(|u| u.effective_value(fee_rate) > 0)
+        .cloned()
+        .collect();
+    if selected.len() < 2 {
+        return Err(CoinControlError::NothingToConsolidate);
+    }
+
+    let mut output = TxOut {
+        value: Amount::ZERO,
+        script_pubkey: destination,
+    };
+    let weight = estimate_weight(&selected, std::slice::from_ref(&output));
+    let fee = Amount::from_sat(fee(fee_rate, weight));
+    let available: Amount = selected.iter().map(|u| u.txout.value).sum();
+    let needed = fee + output.script_pubkey.minimal_non_dust();
+    if available < needed {
+        return Err(CoinSelectionError::InsufficientFunds { needed, available }.into());
+    }
+    output.value = available - fee;
+
+    Ok(Selection {
+        selected,
+        recipients: vec![output],
+        change: None,
+        fee,
+        weight,
+    })
+}
+
+/// Confirmations of an output mined at `height` (0 or below while in the
+/// mempool) with the chain tip at `tip`.
+pub fn confirmations(height: i64, tip: u32) -> u32 {
+    if height <= 0 {
+        return 0;
+    }
+    (tip as i64 - height + 1).max(0) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btc::testing::{p2wpkh, utxo};
+
+    #[test]
+    fn test_frozen() {
+        let utxos = vec![utxo(0, 10_000), utxo(1, 20_000)];
+        let mut frozen_label = Label::new(LabelType::Output, utxos[1].outpoint.to_string(), None);
+        frozen_label.spendable = Some(false);
+        let mut spendable_label =
+            Label::new(LabelType::Output, utxos[0].outpoint.to_string(), None);
+        spendable_label.spendable = Some(true);
+        let frozen = frozen_outpoints(&[frozen_label, spendable_label]);
+        assert_eq!(frozen.len(), 1);
+
+        let left = unfrozen(utxos.clone(), &frozen, false).unwrap();
+        assert_eq!(left, vec![utxos[0].clone()]);
+        assert_eq!(
+            unfrozen(utxos.clone(), &frozen, true),
+            Err(CoinControlError::Frozen(utxos[1].outpoint))
+        );
+    }
+
+    #[test]
+    fn test_consolidate() {
+        let fee_rate = FeeRate::from_sat_per_vb(2).unwrap();
+        let utxos = vec![
+            utxo(0, 5_000),
+            utxo(1, 8_000),
+            // Not small
+            utxo(2, 1_000_000),
+            // Costs more than it is worth
+            utxo(3, 100),
+        ];
+
+        let selection = consolidate(
+            &utxos,
+            p2wpkh(0xcc),
+            fee_rate,
+            Some(Amount::from_sat(10_000)),
+        )
+        .unwrap();
+        let vouts: Vec<u32> = selection.selected.iter().map(|u| u.outpoint.vout).collect();
+        assert_eq!(vouts, vec![0, 1]);
+        assert!(selection.change.is_none());
+        // 2-in 1-out p2wpkh
+        assert_eq!(selection.vsize(), 178);
+        assert_eq!(selection.fee, Amount::from_sat(356));
+        assert_eq!(
+            selection.recipients[0].value,
+            Amount::from_sat(13_000 - 356)
+        );
+
+        let tx = selection.to_unsigned_tx(bitcoin::Sequence::ENABLE_RBF_NO_LOCKTIME);
+        assert_eq!(tx.output.len(), 1);
+
+        assert_eq!(
+            consolidate(&utxos[..1], p2wpkh(0xcc), fee_rate, None),
+            Err(CoinControlError::NothingToConsolidate)
+        );
+    }
+
+    #[test]
+    fn test_confirmations() {
+        assert_eq!(confirmations(0, 800_000), 0);
+        assert_eq!(confirmations(-1, 800_000), 0);
+        assert_eq!(confirmations(800_000, 800_000), 1);
+        assert_eq!(confirmations(799_990, 800_000), 11);
+    }
+}
@@ -0,0 +1,807 @@
+use bitcoin::absolute::LockTime;
+use bitcoin::transaction::Version;
+use bitcoin::{
+    Amount, FeeRate, OutPoint, Script, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Weight,
+    Witness,
+};
+use thiserror::Error;
+
+/// Outpoint, sequence and the scriptSig length byte.
+const TXIN_BASE_WEIGHT: u64 = (36 + 4 + 1) * 4;
+/// Version and locktime.
+const TX_BASE_WEIGHT: u64 = (4 + 4) * 4;
+/// Segwit marker and flag.
+const SEGWIT_HEADER_WEIGHT: u64 = 2;
+
+const BNB_MAX_TRIES: usize = 100_000;
+const KNAPSACK_ITERATIONS: usize = 1_000;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum CoinSelectionError {
+    #[error("No recipients")]
+    NoRecipients,
+
+    #[error("Output {0} of {1} is below the dust threshold")]
+    DustOutput(usize, Amount),
+
+    #[error("Unknown spending weight for {0}")]
+    UnknownScript(OutPoint),
+
+    #[error("Insufficient funds: needed {needed}, available {available}")]
+    InsufficientFunds { needed: Amount, available: Amount },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoinSelectionStrategy {
+    /// Look for an input set that needs no change, fall back to knapsack.
+    BranchAndBound,
+    Knapsack,
+    LargestFirst,
+    /// Spend every given input, for inputs the user picked.
+    Manual,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Utxo {
+    pub outpoint: OutPoint,
+    pub txout: TxOut,
+    /// Weight of the scriptSig and witness needed to spend the output.
+    pub satisfaction_weight: Weight,
+}
+
+impl Utxo {
+    /// For single-sig outputs whose spending weight is known from the script.
+    pub fn new(outpoint: OutPoint, txout: TxOut) -> Result<Self, CoinSelectionError> {
+        let satisfaction_weight = satisfaction_weight(&txout.script_pubkey)
+            .ok_or(CoinSelectionError::UnknownScript(outpoint))?;
+
+        Ok(Utxo {
+            outpoint,
+            txout,
+            satisfaction_weight,
+        })
+    }
+
+    pub fn input_weight(&self) -> Weight {
+        Weight::from_wu(TXIN_BASE_WEIGHT) + self.satisfaction_weight
+    }
+
+    /// Value left after paying for the input at `fee_rate`.
+    pub fn effective_value(&self, fee_rate: FeeRate) -> i64 {
+        self.txout.value.to_sat() as i64 - fee(fee_rate, self.input_weight()) as i64
+    }
+
+    fn is_segwit(&self) -> bool {
+        // p2sh is assumed to wrap a witness program (BIP-49)
+        self.txout.script_pubkey.is_witness_program() || self.txout.script_pubkey.is_p2sh()
+    }
+}
+
+/// Spending weight of a single-sig output, assuming a 72-byte DER
+/// signature and a compressed key. scriptSig bytes weigh 4, witness bytes 1.
+pub fn satisfaction_weight(script_pubkey: &Script) -> Option<Weight> {
+    let wu = if script_pubkey.is_p2pkh() {
+        (1 + 72 + 1 + 33) * 4
+    } else if script_pubkey.is_p2wpkh() {
+        1 + 1 + 72 + 1 + 33
+    } else if script_pubkey.is_p2sh() {
+        // scriptSig pushes the 22-byte p2wpkh program
+        23 * 4 + 1 + 1 + 72 + 1 + 33
+    } else if script_pubkey.is_p2tr() {
+        // key path, default sighash
+        1 + 1 + 64
+    } else {
+        return None;
+    };
+
+    Some(Weight::from_wu(wu))
+}
+
+fn varint_len(n: u64) -> u64 {
+    match n {
+        0..=0xfc => 1,
+        0xfd..=0xffff => 3,
+        0x10000..=0xffff_ffff => 5,
+        _ => 9,
+    }
+}
+
+pub fn txout_weight(script_pubkey: &Script) -> Weight {
+    let len = script_pubkey.len() as u64;
+    Weight::from_wu((8 + varint_len(len) + len) * 4)
+}
+
+/// Version, locktime, the input/output counts and, when any input is
+/// segwit, the marker and flag.
+fn overhead_weight(inputs: usize, outputs: usize, segwit: bool) -> Weight {
+    let counts = varint_len(inputs as u64) + varint_len(outputs as u64);
+    let mut weight = Weight::from_wu(TX_BASE_WEIGHT + counts * 4);
+    if segwit {
+        weight += Weight::from_wu(SEGWIT_HEADER_WEIGHT);
+    }
+    weight
+}
+
+/// Estimated weight of a signed transaction spending `inputs`.
+pub fn estimate_weight(inputs: &[Utxo], outputs: &[TxOut]) -> Weight {
+    let weight = overhead_weight(
+        inputs.len(),
+        outputs.len(),
+        inputs.iter().any(|u| u.is_segwit()),
+    );
+    let weight = inputs.iter().fold(weight, |w, u| w + u.input_weight());
+    outputs
+        .iter()
+        .fold(weight, |w, out| w + txout_weight(&out.script_pubkey))
+}
+
+/// Charged on whole vbytes, so the sum over parts of a transaction never
+/// undercuts the rate on its rounded up vsize.
+pub(crate) fn fee(fee_rate: FeeRate, weight: Weight) -> u64 {
+    fee_rate
+        .fee_vb(weight.to_vbytes_ceil())
+        .map(|a| a.to_sat())
+        .unwrap_or(u64::MAX / 2)
+}
+
+/// Where the leftover goes. Change worth less than `min_value` is added to
+/// the fee instead of creating an output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangePolicy {
+    pub script_pubkey: ScriptBuf,
+    pub min_value: Amount,
+}
+
+impl ChangePolicy {
+    /// Change to `script_pubkey`, dropped when below its dust threshold.
+    pub fn new(script_pubkey: ScriptBuf) -> Self {
+        let min_value = script_pubkey.minimal_non_dust();
+        ChangePolicy {
+            script_pubkey,
+            min_value,
+        }
+    }
+
+    fn output_fee(&self, fee_rate: FeeRate) -> u64 {
+        fee(fee_rate, txout_weight(&self.script_pubkey))
+    }
+
+    /// Creating the change output now plus spending it later.
+    fn cost_of_change(&self, fee_rate: FeeRate) -> u64 {
+        let spend_weight = satisfaction_weight(&self.script_pubkey)
+            .unwrap_or(Weight::from_wu(1 + 1 + 72 + 1 + 33))
+            + Weight::from_wu(TXIN_BASE_WEIGHT);
+        self.output_fee(fee_rate) + fee(fee_rate, spend_weight)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selection {
+    pub selected: Vec<Utxo>,
+    pub recipients: Vec<TxOut>,
+    /// Change output, if the leftover was worth keeping.
+    pub change: Option<TxOut>,
+    pub fee: Amount,
+    /// Estimated weight of the signed transaction.
+    pub weight: Weight,
+}
+
+impl Selection {
+    pub fn vsize(&self) -> u64 {
+        self.weight.to_vbytes_ceil()
+    }
+
+    pub fn input_value(&self) -> Amount {
+        self.selected.iter().map(|u| u.txout.value).sum()
+    }
+
+    /// Unsigned transaction spending the selected inputs, change last.
+    pub fn to_unsigned_tx(&self, sequence: Sequence) -> Transaction {
+        let input = self
+            .selected
+            .iter()
+            .map(|u| TxIn {
+                previous_output: u.outpoint,
+                script_sig: ScriptBuf::new(),
+                sequence,
+                witness: Witness::new(),
+            })
+            .collect();
+        let output = self
+            .recipients
+            .iter()
+            .chain(self.change.as_ref())
+            .cloned()
+            .collect();
+
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input,
+            output,
+        }
+    }
+
+    /// Spent outputs in input order, as PSBT building and signing expect.
+    pub fn witness_utxos(&self) -> Vec<TxOut> {
+        self.selected.iter().map(|u| u.txout.clone()).collect()
+    }
+}
+
+/// Pick inputs from `utxos` paying `recipients` at `fee_rate`. Inputs that
+/// cost more to spend than they are worth are never selected, unless the
+/// strategy is `Manual`.
+pub fn select_coins(
+    utxos: &[Utxo],
+    recipients: &[TxOut],
+    fee_rate: FeeRate,
+    change: &ChangePolicy,
+    strategy: CoinSelectionStrategy,
+) -> Result<Selection, CoinSelectionError> {
+    if recipients.is_empty() {
+        return Err(CoinSelectionError::NoRecipients);
+    }
+    for (i, out) in recipients.iter().enumerate() {
+        if out.value < out.script_pubkey.minimal_non_dust() {
+            return Err(CoinSelectionError::DustOutput(i, out.value));
+        }
+    }
+
+    let manual = strategy == CoinSelectionStrategy::Manual;
+    let mut candidates: Vec<&Utxo> = utxos
+        .iter()
+        .filter(|u| manual || u.effective_value(fee_rate) > 0)
+        .collect();
+    candidates.sort_by_key(|u| std::cmp::Reverse(u.effective_value(fee_rate)));
+    let values: Vec<i64> = candidates
+        .iter()
+        .map(|u| u.effective_value(fee_rate))
+        .collect();
+
+    // Selection runs before the input set is known, so assume the segwit
+    // header whenever a candidate could need it. The fee is settled on the
+    // actual selection below.
+    let base_weight = recipients.iter().fold(
+        overhead_weight(
+            1,
+            recipients.len() + 1,
+            candidates.iter().any(|u| u.is_segwit()),
+        ),
+        |w, out| w + txout_weight(&out.script_pubkey),
+    );
+    let send_value: Amount = recipients.iter().map(|out| out.value).sum();
+    let target = (send_value.to_sat() + fee(fee_rate, base_weight)) as i64;
+
+    let available: i64 = values.iter().sum();
+    if available < target {
+        return Err(CoinSelectionError::InsufficientFunds {
+            needed: Amount::from_sat(target as u64),
+            available: Amount::from_sat(available.max(0) as u64),
+        });
+    }
+
+    let min_change = (change.output_fee(fee_rate) + change.min_value.to_sat()) as i64;
+    let picked = match strategy {
+        CoinSelectionStrategy::BranchAndBound => {
+            branch_and_bound(&values, target, change.cost_of_change(fee_rate) as i64)
+                .or_else(|| knapsack(&values, target, min_change))
+        }
+        CoinSelectionStrategy::Knapsack => knapsack(&values, target, min_change),
+        CoinSelectionStrategy::LargestFirst => largest_first(&values, target),
+        CoinSelectionStrategy::Manual => Some((0..values.len()).collect()),
+    }
+    .ok_or(CoinSelectionError::InsufficientFunds {
+        needed: Amount::from_sat(target as u64),
+        available: Amount::from_sat(available as u64),
+    })?;
+
+    let selected: Vec<Utxo> = picked.iter().map(|&i| candidates[i].clone()).collect();
+    let input_value: Amount = selected.iter().map(|u| u.txout.value).sum();
+
+    // Fees on the real transaction: the segwit header only when a selected
+    // input needs it, the input count varint from the selection size.
+    let weight = estimate_weight(&selected, recipients);
+    let needed = send_value + Amount::from_sat(fee(fee_rate, weight));
+    if input_value < needed {
+        return Err(CoinSelectionError::InsufficientFunds {
+            needed,
+            available: input_value,
+        });
+    }
+
+    let mut outputs = recipients.to_vec();
+    outputs.push(TxOut {
+        value: Amount::ZERO,
+        script_pubkey: change.script_pubkey.clone(),
+    });
+    let change_weight = estimate_weight(&selected, &outputs);
+    let change_fee = Amount::from_sat(fee(fee_rate, change_weight));
+    let (change_out, weight) = match input_value
+        .checked_sub(send_value + change_fee)
+        .filter(|value| *value >= change.min_value)
+    {
+        Some(value) => {
+            let mut out = outputs.pop().expect("change output");
+            out.value = value;
+            (Some(out), change_weight)
+        }
+        None => (None, weight),
+    };
+    let change_value = change_out.as_ref().map(|o| o.value).unwrap_or(Amount::ZERO);
+
+    Ok(Selection {
+        selected,
+        recipients: recipients.to_vec(),
+        change: change_out,
+        fee: input_value - send_value - change_value,
+        weight,
+    })
+}
+
+/// Depth-first search for an input set whose effective value lands in
+/// `[target, target + cost_of_change]`, so no change output is needed.
+/// Keeps the set with the least excess. `values` must be sorted descending.
+fn branch_and_bound(values: &[i64], target: i64, cost_of_change: i64) -> Option<Vec<usize>> {
+    let mut available: i64 = values.iter().sum();
+    let mut current = Vec::new();
+    let mut current_value = 0i64;
+    let mut best: Option<(Vec<usize>, i64)> = None;
+    let mut index = 0;
+
+    for _ in 0..BNB_MAX_TRIES {
+        let mut backtrack = false;
+        if current_value + available < target || current_value > target + cost_of_change {
+            backtrack = true;
+        } else if current_value >= target {
+            let excess = current_value - target;
+            if best.as_ref().is_none_or(|(_, e)| excess < *e) {
+                best = Some((current.clone(), excess));
+                if excess == 0 {
+                    break;
+                }
+            }
+            backtrack = true;
+        }
+
+        if backtrack {
+            let Some(&last) = current.last() else {
+                break;
+            };
+            // Give back everything skipped after the last included input,
+            // then try the branch without it.
+            while index > last + 1 {
+                index -= 1;
+                available += values[index];
+            }
+            index = last;
+            current.pop();
+            current_value -= values[last];
+        } else {
+            available -= values[index];
+            // Excluding an input and including an identical one next is a
+            // branch already explored.
+            if index == 0
+                || current.last() == Some(&(index - 1))
+                || values[index] != values[index - 1]
+            {
+                current.push(index);
+                current_value += values[index];
+            }
+        }
+        index += 1;
+    }
+
+    best.map(|(selection, _)| selection)
+}
+
+/// Bitcoin Core's knapsack: an exact match if one exists, otherwise the
+/// smallest set that leaves at least `min_change`, or the single smallest
+/// input that covers it. `values` must be sorted descending.
+fn knapsack(values: &[i64], target: i64, min_change: i64) -> Option<Vec<usize>> {
+    if let Some(i) = values.iter().position(|v| *v == target) {
+        return Some(vec![i]);
+    }
+
+    let lowest_larger = values.iter().rposition(|v| *v >= target + min_change);
+    let smaller: Vec<usize> = (0..values.len())
+        .filter(|&i| values[i] < target + min_change)
+        .collect();
+    let smaller_total: i64 = smaller.iter().map(|&i| values[i]).sum();
+
+    if smaller_total == target {
+        return Some(smaller);
+    }
+    if smaller_total < target {
+        return lowest_larger.map(|i| vec![i]);
+    }
+
+    let smaller_values: Vec<i64> = smaller.iter().map(|&i| values[i]).collect();
+    let mut rng = XorShift::new(target as u64 ^ smaller_total as u64);
+    let (mut best, mut best_value) =
+        approximate_best_subset(&smaller_values, smaller_total, target, &mut rng);
+    if best_value != target && smaller_total >= target + min_change {
+        (best, best_value) = approximate_best_subset(
+            &smaller_values,
+            smaller_total,
+            target + min_change,
+            &mut rng,
+        );
+    }
+
+    // A single larger input beats a subset that leaves less than min_change
+    if let Some(i) = lowest_larger {
+        if (best_value != target && best_value < target + min_change) || values[i] <= best_value {
+            return Some(vec![i]);
+        }
+    }
+
+    Some(
+        best.iter()
+            .enumerate()
+            .filter(|(_, included)| **included)
+            .map(|(i, _)| smaller[i])
+            .collect(),
+    )
+}
+
+fn approximate_best_subset(
+    values: &[i64],
+    total: i64,
+    target: i64,
+    rng: &mut XorShift,
+) -> (Vec<bool>, i64) {
+    let mut best = vec![true; values.len()];
+    let mut best_value = total;
+
+    for _ in 0..KNAPSACK_ITERATIONS {
+        if best_value == target {
+            break;
+        }
+        let mut included = vec![false; values.len()];
+        let mut sum = 0;
+        let mut reached = false;
+
+        // First pass picks at random, the second fills in the rest
+        for pass in 0..2 {
+            if reached {
+                break;
+            }
+            for i in 0..values.len() {
+                let take = if pass == 0 {
+                    rng.next_bool()
+                } else {
+                    !included[i]
+                };
+                if !take {
+                    continue;
+                }
+                sum += values[i];
+                included[i] = true;
+                if sum >= target {
+                    reached = true;
+                    if sum < best_value {
+                        best_value = sum;
+                        best = included.clone();
+                    }
+                    sum -= values[i];
+                    included[i] = false;
+                }
+            }
+        }
+    }
+
+    (best, best_value)
+}
+
+/// Largest inputs first until the target is covered. `values` must be
+/// sorted descending.
+fn largest_first(values: &[i64], target: i64) -> Option<Vec<usize>> {
+    let mut sum = 0;
+    for (i, value) in values.iter().enumerate() {
+        sum += value;
+        if sum >= target {
+            return Some((0..=i).collect());
+        }
+    }
+    None
+}
+
+/// Seeded from the request, so the same wallet state selects the same
+/// inputs.
+struct XorShift(u64);
+
+impl XorShift {
+    fn new(seed: u64) -> Self {
+        XorShift(seed | 1)
+    }
+
+    fn next_bool(&mut self) -> bool {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 & 1 == 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::hashes::Hash;
+    use bitcoin::{PubkeyHash, Txid, WPubkeyHash};
+
+    fn p2wpkh(tag: u8) -> ScriptBuf {
+        ScriptBuf::new_p2wpkh(&WPubkeyHash::from_byte_array([tag; 20]))
+    }
+
+    fn utxo(vout: u32, sat: u64) -> Utxo {
+        Utxo::new(
+            OutPoint::new(Txid::from_byte_array([0x11; 32]), vout),
+            TxOut {
+                value: Amount::from_sat(sat),
+                script_pubkey: p2wpkh(0xaa),
+            },
+        )
+        .unwrap()
+    }
+
+    fn pay(sat: u64) -> Vec<TxOut> {
+        vec![TxOut {
+            value: Amount::from_sat(sat),
+            script_pubkey: p2wpkh(0xbb),
+        }]
+    }
+
+    fn change() -> ChangePolicy {
+        ChangePolicy::new(p2wpkh(0xcc))
+    }
+
+    fn assert_balanced(selection: &Selection) {
+        let out: Amount = selection
+            .recipients
+            .iter()
+            .chain(selection.change.as_ref())
+            .map(|o| o.value)
+            .sum();
+        assert_eq!(selection.input_value(), out + selection.fee);
+    }
+
+    #[test]
+    fn test_weights() {
+        // 1-in 2-out p2wpkh is the well known 141 vbytes
+        let weight = overhead_weight(1, 2, true)
+            + utxo(0, 10_000).input_weight()
+            + txout_weight(&p2wpkh(1)) * 2;
+        assert_eq!(weight.to_vbytes_ceil(), 141);
+        assert_eq!(p2wpkh(1).minimal_non_dust(), Amount::from_sat(294));
+    }
+
+    #[test]
+    fn test_bnb_exact_match_without_change() {
+        let fee_rate = FeeRate::from_sat_per_vb(2).unwrap();
+        let utxos = vec![utxo(0, 50_000), utxo(1, 30_000), utxo(2, 20_000)];
+
+        // Inputs 1 + 2 cover the payment and fees exactly
+        let selection = select_coins(
+            &utxos,
+            &pay(50_000 - 356),
+            fee_rate,
+            &change(),
+            CoinSelectionStrategy::BranchAndBound,
+        )
+        .unwrap();
+
+        let vouts: Vec<u32> = selection.selected.iter().map(|u| u.outpoint.vout).collect();
+        assert_eq!(vouts, vec![1, 2]);
+        assert!(selection.change.is_none());
+        assert_eq!(selection.fee, Amount::from_sat(356));
+        assert_balanced(&selection);
+    }
+
+    #[test]
+    fn test_bnb_falls_back_with_change() {
+        let fee_rate = FeeRate::from_sat_per_vb(1).unwrap();
+        let utxos = vec![utxo(0, 100_000), utxo(1, 7_000)];
+
+        let selection = select_coins(
+            &utxos,
+            &pay(40_000),
+            fee_rate,
+            &change(),
+            CoinSelectionStrategy::BranchAndBound,
+        )
+        .unwrap();
+
+        assert_eq!(selection.selected.len(), 1);
+        assert_eq!(selection.selected[0].outpoint.vout, 0);
+        let change_out = selection.change.as_ref().unwrap();
+        assert_eq!(change_out.script_pubkey, p2wpkh(0xcc));
+        assert_eq!(selection.fee, Amount::from_sat(selection.vsize()));
+        assert_balanced(&selection);
+
+        let tx = selection.to_unsigned_tx(Sequence::ENABLE_RBF_NO_LOCKTIME);
+        assert_eq!(tx.input.len(), 1);
+        assert_eq!(tx.output.len(), 2);
+        assert_eq!(tx.output[1], *change_out);
+    }
+
+    #[test]
+    fn test_knapsack_prefers_small_inputs() {
+        let fee_rate = FeeRate::from_sat_per_vb(1).unwrap();
+        let utxos = vec![
+            utxo(0, 1_000_000),
+            utxo(1, 20_000),
+            utxo(2, 15_000),
+            utxo(3, 12_000),
+        ];
+
+        let selection = select_coins(
+            &utxos,
+            &pay(25_000),
+            fee_rate,
+            &change(),
+            CoinSelectionStrategy::Knapsack,
+        )
+        .unwrap();
+
+        assert!(selection.selected.iter().all(|u| u.outpoint.vout != 0));
+        assert!(selection.change.is_some());
+        assert_balanced(&selection);
+    }
+
+    #[test]
+    fn test_largest_first() {
+        let fee_rate = FeeRate::from_sat_per_vb(1).unwrap();
+        let utxos = vec![utxo(0, 5_000), utxo(1, 60_000), utxo(2, 50_000)];
+
+        let selection = select_coins(
+            &utxos,
+            &pay(100_000),
+            fee_rate,
+            &change(),
+            CoinSelectionStrategy::LargestFirst,
+        )
+        .unwrap();
+
+        let vouts: Vec<u32> = selection.selected.iter().map(|u| u.outpoint.vout).collect();
+        assert_eq!(vouts, vec![1, 2]);
+        assert_balanced(&selection);
+    }
+
+    #[test]
+    fn test_manual_spends_every_input() {
+        let fee_rate = FeeRate::from_sat_per_vb(10).unwrap();
+        // 600 sat costs more than it is worth at 10 sat/vB
+        let utxos = vec![utxo(0, 600), utxo(1, 100_000), utxo(2, 50_000)];
+
+        let selection = select_coins(
+            &utxos,
+            &pay(10_000),
+            fee_rate,
+            &change(),
+            CoinSelectionStrategy::Manual,
+        )
+        .unwrap();
+
+        assert_eq!(selection.selected.len(), 3);
+        assert!(selection.change.is_some());
+        assert_balanced(&selection);
+
+        assert!(matches!(
+            select_coins(
+                &utxos[..1],
+                &pay(10_000),
+                fee_rate,
+                &change(),
+                CoinSelectionStrategy::Manual
+            ),
+            Err(CoinSelectionError::InsufficientFunds { .. })
+        ));
+    }
+
+    #[test]
+    fn test_dust_change_goes_to_fee() {
+        let fee_rate = FeeRate::from_sat_per_vb(1).unwrap();
+        // 110 vB without change, 200 sat left over is below change dust + its fee
+        let selection = select_coins(
+            &[utxo(0, 50_310)],
+            &pay(50_000),
+            fee_rate,
+            &change(),
+            CoinSelectionStrategy::LargestFirst,
+        )
+        .unwrap();
+
+        assert!(selection.change.is_none());
+        assert_eq!(selection.fee, Amount::from_sat(310));
+    }
+
+    #[test]
+    fn test_weight_follows_selection() {
+        let fee_rate = FeeRate::from_sat_per_vb(1).unwrap();
+        let legacy = Utxo::new(
+            OutPoint::new(Txid::from_byte_array([0x22; 32]), 0),
+            TxOut {
+                value: Amount::from_sat(200_000),
+                script_pubkey: ScriptBuf::new_p2pkh(&PubkeyHash::from_byte_array([0xdd; 20])),
+            },
+        )
+        .unwrap();
+
+        // Only the legacy input is picked, so no marker and flag
+        let selection = select_coins(
+            &[legacy.clone(), utxo(0, 10_000)],
+            &pay(100_000),
+            fee_rate,
+            &change(),
+            CoinSelectionStrategy::LargestFirst,
+        )
+        .unwrap();
+        assert_eq!(selection.selected, vec![legacy]);
+        let tx = selection.to_unsigned_tx(Sequence::ENABLE_RBF_NO_LOCKTIME);
+        assert_eq!(
+            selection.weight,
+            tx.weight() + selection.selected[0].satisfaction_weight
+        );
+        assert_eq!(selection.fee, Amount::from_sat(selection.vsize()));
+        assert_balanced(&selection);
+
+        // 253 inputs need a 3-byte input count
+        let utxos: Vec<Utxo> = (0..253).map(|vout| utxo(vout, 1_000)).collect();
+        let selection = select_coins(
+            &utxos,
+            &pay(100_000),
+            fee_rate,
+            &change(),
+            CoinSelectionStrategy::Manual,
+        )
+        .unwrap();
+        let tx = selection.to_unsigned_tx(Sequence::ENABLE_RBF_NO_LOCKTIME);
+        let satisfaction = utxos
+            .iter()
+            .fold(Weight::ZERO, |w, u| w + u.satisfaction_weight);
+        assert_eq!(
+            selection.weight,
+            tx.weight() + satisfaction + Weight::from_wu(SEGWIT_HEADER_WEIGHT)
+        );
+        assert_eq!(selection.fee, Amount::from_sat(selection.vsize()));
+        assert_balanced(&selection);
+    }
+
+    #[test]
+    fn test_errors() {
+        let fee_rate = FeeRate::from_sat_per_vb(10).unwrap();
+        let strategy = CoinSelectionStrategy::BranchAndBound;
+
+        assert_eq!(
+            select_coins(&[utxo(0, 1_000)], &[], fee_rate, &change(), strategy),
+            Err(CoinSelectionError::NoRecipients)
+        );
+        assert_eq!(
+            select_coins(&[utxo(0, 1_000)], &pay(100), fee_rate, &change(), strategy),
+            Err(CoinSelectionError::DustOutput(0, Amount::from_sat(100)))
+        );
+        assert!(matches!(
+            select_coins(&[utxo(0, 1_000)], &pay(900), fee_rate, &change(), strategy),
+            Err(CoinSelectionError::InsufficientFunds { .. })
+        ));
+
+        // Costs more than it is worth to spend, never selected
+        assert!(select_coins(
+            &[utxo(0, 600), utxo(1, 20_000)],
+            &pay(10_000),
+            fee_rate,
+            &change(),
+            strategy
+        )
+        .unwrap()
+        .selected
+        .iter()
+        .all(|u| u.outpoint.vout == 1));
+
+        let bare = TxOut {
+            value: Amount::from_sat(1_000),
+            script_pubkey: ScriptBuf::from_bytes(vec![0x51]),
+        };
+        assert!(Utxo::new(OutPoint::null(), bare).is_err());
+    }
+}
@@ -0,0 +1,208 @@
+use bitcoin::absolute::LockTime;
+use bitcoin::transaction::Version;
+use bitcoin::{
+    Amount, FeeRate, OutPoint, Script, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Weight,
+    Witness,
+};
+use thiserror::Error;
+
+use crate::btc::coin_selection::{estimate_weight, fee, CoinSelectionError, Utxo};
+use crate::btc::rbf::INCREMENTAL_RELAY_FEE;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum CpfpError {
+    #[error("Nothing to spend from the parent")]
+    NoOutputs,
+
+    #[error(transparent)]
+    Utxo(#[from] CoinSelectionError),
+
+    #[error("Parent already pays {0} sat/vB")]
+    ParentAboveTarget(u64),
+
+    #[error("Spent outputs cannot cover the child fee: needed {needed}, available {available}")]
+    InsufficientValue { needed: Amount, available: Amount },
+}
+
+/// The unconfirmed transaction being pulled in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Parent {
+    pub txid: Txid,
+    pub fee: Amount,
+    pub weight: Weight,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cpfp {
+    /// Unsigned child sweeping `spent` to a single output.
+    pub tx: Transaction,
+    pub spent: Vec<Utxo>,
+    pub fee: Amount,
+    pub weight: Weight,
+}
+
+impl Cpfp {
+    pub fn witness_utxos(&self) -> Vec<TxOut> {
+        self.spent.iter().map(|u| u.txout.clone()).collect()
+    }
+
+    /// Fee rate miners see for parent and child together, sat/vB.
+    pub fn package_fee_rate(&self, parent: &Parent) -> f64 {
+        let fee = (parent.fee + self.fee).to_sat() as f64;
+        let vsize = parent.weight.to_vbytes_ceil() + self.weight.to_vbytes_ceil();
+        fee / vsize as f64
+    }
+}
+
+/// Build a child spending the wallet's `outputs` of `parent` (vout and the
+/// output itself) into `destination`, paying enough that parent and child
+/// together reach `target`.
+pub fn build_cpfp(
+    parent: &Parent,
+    outputs: &[(u32, TxOut)],
+    destination: &Script,
+    target: FeeRate,
+) -> Result<Cpfp, CpfpError> {
+    if outputs.is_empty() {
+        return Err(CpfpError::NoOutputs);
+    }
+    let spent = outputs
+        .iter()
+        .map(|(vout, txout)| {
+            let outpoint = OutPoint::new(parent.txid, *vout);
+            Ok(Utxo::new(outpoint, txout.clone())?)
+        })
+        .collect::<Result<Vec<_>, CpfpError>>()?;
+
+    let parent_vsize = parent.weight.to_vbytes_ceil();
+    if fee(target, parent.weight) <= parent.fee.to_sat() {
+        return Err(CpfpError::ParentAboveTarget(
+            parent.fee.to_sat() / parent_vsize.max(1),
+        ));
+    }
+
+    let input_value: Amount = spent.iter().map(|u| u.txout.value).sum();
+    let output = TxOut {
+        value: Amount::ZERO,
+        script_pubkey: destination.to_owned(),
+    };
+    let weight = estimate_weight(&spent, std::slice::from_ref(&output));
+
+    // The child also has to clear the relay minimum on its own
+    let child_fee = (fee(target, parent.weight + weight) - parent.fee.to_sat())
+        .max(fee(INCREMENTAL_RELAY_FEE, weight));
+    let needed = child_fee + destination.minimal_non_dust().to_sat();
+    if input_value.to_sat() < needed {
+        return Err(CpfpError::InsufficientValue {
+            needed: Amount::from_sat(needed),
+            available: input_value,
+        });
+    }
+
+    let tx = Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: spent
+            .iter()
+            .map(|u| TxIn {
+                previous_output: u.outpoint,
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: Witness::new(),
+            })
+            .collect(),
+        output: vec![TxOut {
+            value: input_value - Amount::from_sat(child_fee),
+            ..output
+        }],
+    };
+
+    Ok(Cpfp {
+        tx,
+        spent,
+        fee: Amount::from_sat(child_fee),
+        weight,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::hashes::Hash;
+    use bitcoin::WPubkeyHash;
+
+    fn p2wpkh(tag: u8) -> ScriptBuf {
+        ScriptBuf::new_p2wpkh(&WPubkeyHash::from_byte_array([tag; 20]))
+    }
+
+    /// 1 sat/vB parent of 141 vB, the wallet owns output 1.
+    fn parent() -> (Parent, Vec<(u32, TxOut)>) {
+        let parent = Parent {
+            txid: Txid::from_byte_array([0x22; 32]),
+            fee: Amount::from_sat(141),
+            weight: Weight::from_vb_unchecked(141),
+        };
+        let owned = TxOut {
+            value: Amount::from_sat(20_000),
+            script_pubkey: p2wpkh(0xaa),
+        };
+        (parent, vec![(1, owned)])
+    }
+
+    #[test]
+    fn test_build_cpfp() {
+        let (parent, outputs) = parent();
+        let cpfp = build_cpfp(
+            &parent,
+            &outputs,
+            &p2wpkh(0xcc),
+            FeeRate::from_sat_per_vb(10).unwrap(),
+        )
+        .unwrap();
+
+        // 1-in 1-out p2wpkh child is 110 vB, package 251 vB at 10 sat/vB
+        assert_eq!(cpfp.weight.to_vbytes_ceil(), 110);
+        assert_eq!(cpfp.fee, Amount::from_sat(2510 - 141));
+        assert!(cpfp.package_fee_rate(&parent) >= 10.0);
+
+        assert_eq!(
+            cpfp.tx.input[0].previous_output,
+            OutPoint::new(parent.txid, 1)
+        );
+        assert_eq!(cpfp.tx.output.len(), 1);
+        assert_eq!(cpfp.tx.output[0].script_pubkey, p2wpkh(0xcc));
+        assert_eq!(
+            cpfp.tx.output[0].value,
+            Amount::from_sat(20_000 - 2510 + 141)
+        );
+    }
+
+    #[test]
+    fn test_build_cpfp_errors() {
+        let (parent, outputs) = parent();
+        let dest = p2wpkh(0xcc);
+
+        assert_eq!(
+            build_cpfp(&parent, &[], &dest, FeeRate::from_sat_per_vb(10).unwrap()),
+            Err(CpfpError::NoOutputs)
+        );
+        assert_eq!(
+            build_cpfp(
+                &parent,
+                &outputs,
+                &dest,
+                FeeRate::from_sat_per_vb(1).unwrap()
+            ),
+            Err(CpfpError::ParentAboveTarget(1))
+        );
+        assert!(matches!(
+            build_cpfp(
+                &parent,
+                &outputs,
+                &dest,
+                FeeRate::from_sat_per_vb(100).unwrap()
+            ),
+            Err(CpfpError::InsufficientValue { .. })
+        ));
+    }
+}
@@ -0,0 +1,327 @@
+use bitcoin::bip32::{self, ChildNumber, Xpriv, Xpub};
+use bitcoin::secp256k1::{Secp256k1, Signing, Verification};
+use bitcoin::{Address, Network, ScriptBuf};
+use thiserror::Error;
+
+use crate::btc::network::coin_type;
+use crate::btc::watch_only::{scan_target, SingleSigType};
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum DiscoveryError {
+    #[error("Key derivation failed: {0}")]
+    Derivation(#[from] bip32::Error),
+
+    #[error("Address lookup failed: {0}")]
+    Lookup(String),
+}
+
+/// `m/purpose'/coin'/account'` key of a BIP-44 style account.
+pub fn account_xpub<C: Signing>(
+    secp: &Secp256k1<C>,
+    seed: &[u8],
+    network: Network,
+    script_type: SingleSigType,
+    account: u32,
+) -> Result<Xpub, DiscoveryError> {
+    let master = Xpriv::new_master(network, seed)?;
+    let path = [
+        ChildNumber::from_hardened_idx(script_type.bip_purpose())?,
+        ChildNumber::from_hardened_idx(coin_type(network))?,
+        ChildNumber::from_hardened_idx(account)?,
+    ];
+    Ok(Xpub::from_priv(secp, &master.derive_priv(secp, &path)?))
+}
+
+/// Receive and change chains of one account.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AccountChains {
+    pub xpub: Xpub,
+    pub script_type: SingleSigType,
+}
+
+impl AccountChains {
+    pub fn script_pubkey<C: Verification>(
+        &self,
+        secp: &Secp256k1<C>,
+        change: bool,
+        index: u32,
+    ) -> Result<ScriptBuf, DiscoveryError> {
+        let path = [
+            ChildNumber::from_normal_idx(change as u32)?,
+            ChildNumber::from_normal_idx(index)?,
+        ];
+        let child = self.xpub.derive_pub(secp, &path)?;
+        Ok(self.script_type.script_pubkey(secp, child.public_key))
+    }
+
+    pub fn address<C: Verification>(
+        &self,
+        secp: &Secp256k1<C>,
+        network: Network,
+        change: bool,
+        index: u32,
+    ) -> Result<Address, DiscoveryError> {
+        let script = self.script_pubkey(secp, change, index)?;
+        Address::from_script(&script, network).map_err(|e| DiscoveryError::Lookup(e.to_string()))
+    }
+}
+
+/// All discovery needs from a backend: whether each script has any
+/// history. Closures work too, which keeps tests backend-free.
+pub trait HistoryLookup {
+    fn has_history(&mut self, scripts: &[ScriptBuf]) -> Result<Vec<bool>, DiscoveryError>;
+}
+
+impl<F> HistoryLookup for F
+where
+    F: FnMut(&[ScriptBuf]) -> Result<Vec<bool>, DiscoveryError>,
+{
+    fn has_history(&mut self, scripts: &[ScriptBuf]) -> Result<Vec<bool>, DiscoveryError> {
+        self(scripts)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChainScan {
+    /// Indices with history, ascending
+    pub used: Vec<u32>,
+    /// Addresses `0..scanned` were looked up
+    pub scanned: u32,
+}
+
+impl ChainScan {
+    pub fn last_used(&self) -> Option<u32> {
+        self.used.last().copied()
+    }
+
+    /// First index after the last used one.
+    pub fn next_index(&self) -> u32 {
+        self.last_used().map_or(0, |i| i + 1)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccountScan {
+    pub account: u32,
+    pub chains: AccountChains,
+    pub receive: ChainScan,
+    pub change: ChainScan,
+}
+
+impl AccountScan {
+    pub fn is_used(&self) -> bool {
+        !self.receive.used.is_empty() || !self.change.used.is_empty()
+    }
+}
+
+/// Look up one chain in batches until `gap_limit` addresses after the last
+/// used one have no history.
+pub fn scan_chain<C, L>(
+    secp: &Secp256k1<C>,
+    chains: &AccountChains,
+    change: bool,
+    gap_limit: u32,
+    lookup: &mut L,
+) -> Result<ChainScan, DiscoveryError>
+where
+    C: Verification,
+    L: HistoryLookup,
+{
+    let mut scan = ChainScan::default();
+    loop {
+        let target = scan_target(scan.last_used(), gap_limit);
+        if target <= scan.scanned {
+            return Ok(scan);
+        }
+
+        let scripts = (scan.scanned..target)
+            .map(|index| chains.script_pubkey(secp, change, index))
+            .collect::<Result<Vec<_>, _>>()?;
+        let used = lookup.has_history(&scripts)?;
+        if used.len() != scripts.len() {
+            return Err(DiscoveryError::Lookup(format!(
+                "expected {} answers, got {}",
+                scripts.len(),
+                used.len()
+            )));
+        }
+
+        scan.used.extend(
+            (scan.scanned..target)
+                .zip(used)
+                .filter(|(_, used)| *used)
+                .map(|(index, _)| index),
+        );
+        scan.scanned = target;
+    }
+}
+
+/// BIP-44 account discovery: scan accounts in order and stop at the first
+/// one without history, which is not returned unless it is account 0.
+pub fn discover_accounts<C, A, L>(
+    secp: &Secp256k1<C>,
+    gap_limit: u32,
+    max_accounts: u32,
+    mut account_chains: A,
+    mut lookup: L,
+) -> Result<Vec<AccountScan>, DiscoveryError>
+where
+    C: Verification,
+    A: FnMut(u32) -> Result<AccountChains, DiscoveryError>,
+    L: HistoryLookup,
+{
+    let mut accounts = Vec::new();
+    for account in 0..max_accounts {
+        let chains = account_chains(account)?;
+        let scan = AccountScan {
+            account,
+            chains,
+            receive: scan_chain(secp, &chains, false, gap_limit, &mut lookup)?,
+            change: scan_chain(secp, &chains, true, gap_limit, &mut lookup)?,
+        };
+        let used = scan.is_used();
+        if used || account == 0 {
+            accounts.push(scan);
+        }
+        if !used {
+            break;
+        }
+    }
+
+    Ok(accounts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    /// BIP-39 seed of "abandon abandon ... about" without a passphrase.
+    const SEED: &str = "5eb00bbddcf069084889a8ab9155568165f5c453ccb85e70811aaed6f6da5fc19a5ac40b389cd370d086206dec8aa6c43daea6690f20ad3d8d48b2d2ce9e38e4";
+
+    fn seed() -> Vec<u8> {
+        hex::decode(SEED).unwrap()
+    }
+
+    fn chains(account: u32) -> Result<AccountChains, DiscoveryError> {
+        let secp = Secp256k1::new();
+        let seed = seed();
+        Ok(AccountChains {
+            xpub: account_xpub(&secp, &seed, Network::Bitcoin, SingleSigType::Wpkh, account)?,
+            script_type: SingleSigType::Wpkh,
+        })
+    }
+
+    /// Lookup answering from a fixed set of used scripts, counting calls.
+    fn lookup<'a>(
+        used: &'a HashSet<ScriptBuf>,
+        calls: &'a mut u32,
+    ) -> impl FnMut(&[ScriptBuf]) -> Result<Vec<bool>, DiscoveryError> + 'a {
+        move |scripts| {
+            *calls += 1;
+            Ok(scripts.iter().map(|s| used.contains(s)).collect())
+        }
+    }
+
+    #[test]
+    fn test_account_xpub() {
+        let secp = Secp256k1::verification_only();
+        let account = chains(0).unwrap();
+        // BIP-84 test vectors
+        assert_eq!(
+            account
+                .address(&secp, Network::Bitcoin, false, 0)
+                .unwrap()
+                .to_string(),
+            "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu"
+        );
+        assert_eq!(
+            account
+                .address(&secp, Network::Bitcoin, true, 0)
+                .unwrap()
+                .to_string(),
+            "bc1q8c6fshw2dlwun7ekn9qwf37cu2rn755upcp6el"
+        );
+    }
+
+    #[test]
+    fn test_account_xpub_test_networks() {
+        let secp = Secp256k1::new();
+        let seed = seed();
+        let xpub = account_xpub(&secp, &seed, Network::Regtest, SingleSigType::Wpkh, 0).unwrap();
+        let account = AccountChains {
+            xpub,
+            script_type: SingleSigType::Wpkh,
+        };
+        // m/84'/1'/0'/0/0
+        assert_eq!(
+            account
+                .address(&secp, Network::Testnet, false, 0)
+                .unwrap()
+                .to_string(),
+            "tb1q6rz28mcfaxtmd6v789l9rrlrusdprr9pqcpvkl"
+        );
+        assert!(account
+            .address(&secp, Network::Regtest, false, 0)
+            .unwrap()
+            .to_string()
+            .starts_with("bcrt1q"));
+    }
+
+    #[test]
+    fn test_scan_chain_gap() {
+        let secp = Secp256k1::verification_only();
+        let account = chains(0).unwrap();
+        let used: HashSet<ScriptBuf> = [3, 7, 11, 20]
+            .into_iter()
+            .map(|i| account.script_pubkey(&secp, false, i).unwrap())
+            .collect();
+
+        let mut calls = 0;
+        let scan = scan_chain(&secp, &account, false, 5, &mut lookup(&used, &mut calls)).unwrap();
+        // 20 is past the gap after 11
+        assert_eq!(scan.used, vec![3, 7, 11]);
+        assert_eq!(scan.scanned, 17);
+        assert_eq!(scan.next_index(), 12);
+        assert_eq!(calls, 4);
+
+        let mut calls = 0;
+        let scan = scan_chain(&secp, &account, false, 3, &mut lookup(&used, &mut calls)).unwrap();
+        assert!(scan.used.is_empty());
+        assert_eq!(scan.scanned, 3);
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn test_discover_accounts() {
+        let secp = Secp256k1::verification_only();
+        let mut used = HashSet::new();
+        used.insert(chains(0).unwrap().script_pubkey(&secp, true, 4).unwrap());
+        used.insert(chains(1).unwrap().script_pubkey(&secp, false, 0).unwrap());
+        // Unreachable, account 2 is empty
+        used.insert(chains(3).unwrap().script_pubkey(&secp, false, 0).unwrap());
+
+        let mut calls = 0;
+        let accounts = discover_accounts(&secp, 20, 10, chains, lookup(&used, &mut calls)).unwrap();
+        assert_eq!(accounts.len(), 2);
+        assert!(accounts[0].receive.used.is_empty());
+        assert_eq!(accounts[0].change.used, vec![4]);
+        assert_eq!(accounts[0].change.scanned, 25);
+        assert_eq!(accounts[1].receive.used, vec![0]);
+
+        let mut calls = 0;
+        let empty = HashSet::new();
+        let accounts =
+            discover_accounts(&secp, 20, 10, chains, lookup(&empty, &mut calls)).unwrap();
+        assert_eq!(accounts.len(), 1);
+        assert!(!accounts[0].is_used());
+        assert_eq!(calls, 2);
+    }
+
+    #[test]
+    fn test_lookup_mismatch() {
+        let secp = Secp256k1::verification_only();
+        let result = discover_accounts(&secp, 5, 1, chains, |_: &[ScriptBuf]| Ok(vec![true]));
+        assert!(matches!(result, Err(DiscoveryError::Lookup(_))));
+    }
+}
@@ -0,0 +1,711 @@
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use bitcoin::consensus::encode;
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::{Script, ScriptBuf, Transaction, Txid};
+use rustls::crypto::ring;
+use rustls::pki_types::ServerName;
+use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use thiserror::Error;
+
+use crate::btc::discovery::{DiscoveryError, HistoryLookup};
+
+const CONNECT_TIMEOUT_SECS: u64 = 10;
+const READ_TIMEOUT_SECS: u64 = 30;
+const SCRIPTHASH_SUBSCRIBE: &str = "blockchain.scripthash.subscribe";
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ElectrumError {
+    #[error("Invalid Electrum server url {0}")]
+    Url(String),
+
+    #[error("Electrum connection failed: {0}")]
+    Io(String),
+
+    #[error("Electrum server error: {0}")]
+    Server(String),
+
+    #[error("Invalid Electrum response: {0}")]
+    Response(String),
+
+    #[error("Electrum request timed out")]
+    Timeout,
+}
+
+/// `tcp://host:port` or `ssl://host:port`, as found in the chain configs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerUrl {
+    pub host: String,
+    pub port: u16,
+    pub tls: bool,
+}
+
+impl ServerUrl {
+    pub fn parse(url: &str) -> Result<Self, ElectrumError> {
+        let invalid = || ElectrumError::Url(url.to_string());
+        let (tls, rest) = match url.trim().split_once("://") {
+            Some(("ssl" | "tls", rest)) => (true, rest),
+            Some(("tcp", rest)) => (false, rest),
+            _ => return Err(invalid()),
+        };
+        let (host, port) = rest
+            .trim_end_matches('/')
+            .rsplit_once(':')
+            .ok_or_else(invalid)?;
+        if host.is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            host: host.to_string(),
+            port: port.parse().map_err(|_| invalid())?,
+            tls,
+        })
+    }
+}
+
+/// The Electrum servers among a chain's `rpc` urls.
+pub fn electrum_servers(rpc: &[String]) -> Vec<String> {
+    rpc.iter()
+        .filter(|url| ServerUrl::parse(url).is_ok())
+        .cloned()
+        .collect()
+}
+
+/// Electrum script hash: SHA-256 of the script, byte-reversed, hex encoded.
+pub fn script_hash(script: &Script) -> String {
+    let mut hash = sha256::Hash::hash(script.as_bytes()).to_byte_array();
+    hash.reverse();
+    hex::encode(hash)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct HistoryItem {
+    pub tx_hash: String,
+    /// 0 or -1 while in the mempool
+    pub height: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct UnspentItem {
+    pub tx_hash: String,
+    pub tx_pos: u32,
+    /// 0 while in the mempool
+    pub height: i64,
+    /// Satoshis
+    pub value: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct Balance {
+    /// Satoshis
+    pub confirmed: u64,
+    /// Mempool change in satoshis, negative while spending
+    pub unconfirmed: i64,
+}
+
+/// A `blockchain.scripthash.subscribe` notification.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptStatus {
+    pub script_hash: String,
+    /// Hash of the script's history, `None` while it has none
+    pub status: Option<String>,
+}
+
+trait Stream: Read + Write + Send {}
+impl<T: Read + Write + Send> Stream for T {}
+
+/// Blocking Electrum JSON-RPC client, one request per line.
+pub struct ElectrumClient {
+    stream: BufReader<Box<dyn Stream>>,
+    next_id: u64,
+    /// Start of a line cut short by the read timeout
+    partial: Vec<u8>,
+    notifications: VecDeque<ScriptStatus>,
+}
+
+impl ElectrumClient {
+    pub fn connect(url: &str) -> Result<Self, ElectrumError> {
+        let url = ServerUrl::parse(url)?;
+        let io = |e: std::io::Error| ElectrumError::Io(e.to_string());
+        let addr = (url.host.as_str(), url.port)
+            .to_socket_addrs()
+            .map_err(io)?
+            .next()
+            .ok_or_else(|| ElectrumError::Io(format!("Cannot resolve {}", url.host)))?;
+        let tcp = TcpStream::connect_timeout(&addr, Duration::from_secs(CONNECT_TIMEOUT_SECS))
+            .map_err(io)?;
+        tcp.set_read_timeout(Some(Duration::from_secs(READ_TIMEOUT_SECS)))
+            .map_err(io)?;
+        tcp.set_nodelay(true).map_err(io)?;
+
+        if !url.tls {
+            return Ok(Self::from_stream(tcp));
+        }
+
+        let roots = RootCertStore {
+            roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+        };
+        let config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(|e| ElectrumError::Io(e.to_string()))?
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let server_name = ServerName::try_from(url.host.clone())
+            .map_err(|_| ElectrumError::Url(url.host.clone()))?;
+        let conn = ClientConnection::new(Arc::new(config), server_name)
+            .map_err(|e| ElectrumError::Io(e.to_string()))?;
+
+        Ok(Self::from_stream(StreamOwned::new(conn, tcp)))
+    }
+
+    /// Connect to the first server in `urls` that answers.
+    pub fn connect_any(urls: &[String]) -> Result<Self, ElectrumError> {
+        let mut last_error = ElectrumError::Url("no servers".to_string());
+        for url in urls {
+            match Self::connect(url) {
+                Ok(client) => return Ok(client),
+                Err(e) => last_error = e,
+            }
+        }
+        Err(last_error)
+    }
+
+    fn from_stream<S: Read + Write + Send + 'static>(stream: S) -> Self {
+        Self {
+            stream: BufReader::new(Box::new(stream)),
+            next_id: 0,
+            partial: Vec::new(),
+            notifications: VecDeque::new(),
+        }
+    }
+
+    /// Send `request` and wait for the response carrying an id in `ids`.
+    /// Responses to earlier requests that timed out are dropped.
+    fn send(&mut self, request: &Value, ids: RangeInclusive<u64>) -> Result<Value, ElectrumError> {
+        let io = |e: std::io::Error| ElectrumError::Io(e.to_string());
+        let mut line = request.to_string();
+        line.push('\n');
+        let stream = self.stream.get_mut();
+        stream.write_all(line.as_bytes()).map_err(io)?;
+        stream.flush().map_err(io)?;
+
+        loop {
+            let message = self.read_message()?.ok_or(ElectrumError::Timeout)?;
+            if self.take_notification(&message) {
+                continue;
+            }
+            if response_id(&message).is_some_and(|id| ids.contains(&id)) {
+                return Ok(message);
+            }
+        }
+    }
+
+    /// Next line from the server, `None` when the read timed out first.
+    fn read_message(&mut self) -> Result<Option<Value>, ElectrumError> {
+        match self.stream.read_until(b'\n', &mut self.partial) {
+            Ok(_) if self.partial.ends_with(b"\n") => {
+                let line = std::mem::take(&mut self.partial);
+                serde_json::from_slice(&line)
+                    .map(Some)
+                    .map_err(|e| ElectrumError::Response(e.to_string()))
+            }
+            Ok(_) => Err(ElectrumError::Io("connection closed".to_string())),
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => Ok(None),
+            Err(e) => Err(ElectrumError::Io(e.to_string())),
+        }
+    }
+
+    /// Queues script status notifications for `next_notification`, header
+    /// notifications are dropped. False for responses.
+    fn take_notification(&mut self, message: &Value) -> bool {
+        let Some(method) = message.get("method").and_then(Value::as_str) else {
+            return false;
+        };
+        if method == SCRIPTHASH_SUBSCRIBE {
+            let params = &message["params"];
+            if let Some(script_hash) = params[0].as_str() {
+                self.notifications.push_back(ScriptStatus {
+                    script_hash: script_hash.to_string(),
+                    status: params[1].as_str().map(str::to_string),
+                });
+            }
+        }
+        true
+    }
+
+    fn request(&mut self, method: &str, params: Value) -> Value {
+        self.next_id += 1;
+        json!({
+            "jsonrpc": "2.0",
+            "id": self.next_id,
+            "method": method,
+            "params": params,
+        })
+    }
+
+    pub fn call(&mut self, method: &str, params: Value) -> Result<Value, ElectrumError> {
+        let request = self.request(method, params);
+        result(self.send(&request, self.next_id..=self.next_id)?)
+    }
+
+    /// Several calls in one JSON-RPC batch, results in call order.
+    pub fn batch_call(&mut self, calls: &[(&str, Value)]) -> Result<Vec<Value>, ElectrumError> {
+        if calls.is_empty() {
+            return Ok(Vec::new());
+        }
+        let first_id = self.next_id + 1;
+        let requests: Vec<Value> = calls
+            .iter()
+            .map(|(method, params)| self.request(method, params.clone()))
+            .collect();
+
+        let ids = first_id..=self.next_id;
+        let Value::Array(responses) = self.send(&Value::Array(requests), ids)? else {
+            return Err(ElectrumError::Response("expected a batch".to_string()));
+        };
+        let mut results = vec![Value::Null; calls.len()];
+        for response in responses {
+            let position = response
+                .get("id")
+                .and_then(Value::as_u64)
+                .and_then(|id| id.checked_sub(first_id))
+                .filter(|&p| (p as usize) < calls.len())
+                .ok_or_else(|| ElectrumError::Response("unexpected id".to_string()))?;
+            results[position as usize] = result(response)?;
+        }
+
+        Ok(results)
+    }
+
+    /// `blockchain.scripthash.get_history` for each script.
+    pub fn scripts_history(
+        &mut self,
+        scripts: &[&Script],
+    ) -> Result<Vec<Vec<HistoryItem>>, ElectrumError> {
+        self.scripts_call("blockchain.scripthash.get_history", scripts)
+    }
+
+    /// `blockchain.scripthash.listunspent` for each script.
+    pub fn scripts_unspent(
+        &mut self,
+        scripts: &[&Script],
+    ) -> Result<Vec<Vec<UnspentItem>>, ElectrumError> {
+        self.scripts_call("blockchain.scripthash.listunspent", scripts)
+    }
+
+    fn scripts_call<T: DeserializeOwned>(
+        &mut self,
+        method: &str,
+        scripts: &[&Script],
+    ) -> Result<Vec<T>, ElectrumError> {
+        let calls: Vec<(&str, Value)> = scripts
+            .iter()
+            .map(|s| (method, json!([script_hash(s)])))
+            .collect();
+        self.batch_call(&calls)?
+            .into_iter()
+            .map(|v| serde_json::from_value(v).map_err(|e| ElectrumError::Response(e.to_string())))
+            .collect()
+    }
+
+    /// `blockchain.scripthash.get_balance` for each script.
+    pub fn scripts_balance(&mut self, scripts: &[&Script]) -> Result<Vec<Balance>, ElectrumError> {
+        self.scripts_call("blockchain.scripthash.get_balance", scripts)
+    }
+
+    /// `blockchain.scripthash.subscribe` for each script. Returns the
+    /// current statuses, changes then come from `next_notification`.
+    pub fn scripts_subscribe(
+        &mut self,
+        scripts: &[&Script],
+    ) -> Result<Vec<Option<String>>, ElectrumError> {
+        self.scripts_call(SCRIPTHASH_SUBSCRIBE, scripts)
+    }
+
+    /// Next status change of a subscribed script, or `None` when nothing
+    /// came within the read timeout.
+    pub fn next_notification(&mut self) -> Result<Option<ScriptStatus>, ElectrumError> {
+        loop {
+            if let Some(status) = self.notifications.pop_front() {
+                return Ok(Some(status));
+            }
+            match self.read_message()? {
+                Some(message) => {
+                    self.take_notification(&message);
+                }
+                None => return Ok(None),
+            }
+        }
+    }
+
+    /// Keeps an idle connection open, servers drop silent clients.
+    pub fn ping(&mut self) -> Result<(), ElectrumError> {
+        self.call("server.ping", json!([])).map(|_| ())
+    }
+
+    /// `blockchain.transaction.get` for each txid.
+    pub fn transactions(&mut self, txids: &[Txid]) -> Result<Vec<Transaction>, ElectrumError> {
+        let calls: Vec<(&str, Value)> = txids
+            .iter()
+            .map(|txid| ("blockchain.transaction.get", json!([txid.to_string()])))
+            .collect();
+        self.batch_call(&calls)?
+            .iter()
+            .map(|v| {
+                let bytes = v
+                    .as_str()
+                    .and_then(|raw| hex::decode(raw).ok())
+                    .ok_or_else(|| ElectrumError::Response(format!("invalid transaction {}", v)))?;
+                encode::deserialize(&bytes).map_err(|e| ElectrumError::Response(e.to_string()))
+            })
+            .collect()
+    }
+
+    /// `blockchain.transaction.broadcast`, returns the txid the server saw.
+    pub fn broadcast(&mut self, tx: &Transaction) -> Result<Txid, ElectrumError> {
+        let txid = self.call(
+            "blockchain.transaction.broadcast",
+            json!([encode::serialize_hex(tx)]),
+        )?;
+        txid.as_str()
+            .and_then(|txid| Txid::from_str(txid).ok())
+            .ok_or_else(|| ElectrumError::Response(format!("invalid txid {}", txid)))
+    }
+
+    /// `blockchain.estimatefee` for each confirmation target, then
+    /// `blockchain.relayfee`, in BTC/kvB. Targets without an estimate
+    /// come back as -1.
+    pub fn fee_rates(&mut self, targets: &[u32]) -> Result<Vec<f64>, ElectrumError> {
+        let mut calls: Vec<(&str, Value)> = targets
+            .iter()
+            .map(|target| ("blockchain.estimatefee", json!([target])))
+            .collect();
+        calls.push(("blockchain.relayfee", json!([])));
+        self.batch_call(&calls)?
+            .iter()
+            .map(|v| {
+                v.as_f64()
+                    .ok_or_else(|| ElectrumError::Response(format!("invalid fee rate {}", v)))
+            })
+            .collect()
+    }
+
+    /// Height of the chain tip.
+    pub fn tip_height(&mut self) -> Result<u32, ElectrumError> {
+        self.call("blockchain.headers.subscribe", json!([]))?
+            .get("height")
+            .and_then(Value::as_u64)
+            .map(|h| h as u32)
+            .ok_or_else(|| ElectrumError::Response("missing tip height".to_string()))
+    }
+}
+
+impl HistoryLookup for ElectrumClient {
+    fn has_history(&mut self, scripts: &[ScriptBuf]) -> Result<Vec<bool>, DiscoveryError> {
+        let scripts: Vec<_> = scripts.iter().map(ScriptBuf::as_script).collect();
+        self.scripts_history(&scripts)
+            .map(|history| history.iter().map(|h| !h.is_empty()).collect())
+            .map_err(|e| DiscoveryError::Lookup(e.to_string()))
+    }
+}
+
+/// Id of a response, of its first entry for a batch.
+fn response_id(message: &Value) -> Option<u64> {
+    match message {
+        Value::Array(responses) => responses.first()?.get("id")?.as_u64(),
+        response => response.get("id")?.as_u64(),
+    }
+}
+
+fn result(mut response: Value) -> Result<Value, ElectrumError> {
+    match response.get("error") {
+        Some(error) if !error.is_null() => {
+            let message = error
+                .get("message")
+                .and_then(Value::as_str)
+                .map(str::to_string)
+                .unwrap_or_else(|| error.to_string());
+            Err(ElectrumError::Server(message))
+        }
+        _ => Ok(response
+            .get_mut("result")
+            .map(Value::take)
+            .unwrap_or_default()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::str::FromStr;
+    use std::thread;
+
+    use bitcoin::Address;
+
+    /// Answers each line with `respond(request)` until the client hangs up.
+    fn serve(respond: fn(Value) -> Value) -> ElectrumClient {
+        serve_lines(move |request| vec![respond(request)])
+    }
+
+    /// Like `serve`, with notifications pushed ahead of the response.
+    fn serve_lines(respond: impl Fn(Value) -> Vec<Value> + Send + 'static) -> ElectrumClient {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut writer = stream;
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap_or(0) > 0 {
+                for message in respond(serde_json::from_str(&line).unwrap()) {
+                    writer
+                        .write_all(format!("{}\n", message).as_bytes())
+                        .unwrap();
+                }
+                line.clear();
+            }
+        });
+        ElectrumClient::connect(&format!("tcp://127.0.0.1:{}", port)).unwrap()
+    }
+
+    fn batch(request: &Value, result: impl Fn(&Value) -> Value) -> Value {
+        Value::Array(
+            request
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|r| json!({"id": r["id"], "result": result(r)}))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_parse_url() {
+        assert_eq!(
+            ServerUrl::parse("ssl://electrum.blockstream.info:50002").unwrap(),
+            ServerUrl {
+                host: "electrum.blockstream.info".to_string(),
+                port: 50002,
+                tls: true,
+            }
+        );
+        assert!(!ServerUrl::parse("tcp://127.0.0.1:50001").unwrap().tls);
+        assert!(ServerUrl::parse("https://example.com:443").is_err());
+        assert!(ServerUrl::parse("ssl://example.com").is_err());
+
+        let rpc = vec![
+            "https://mempool.space/api".to_string(),
+            "tcp://127.0.0.1:50001".to_string(),
+        ];
+        assert_eq!(electrum_servers(&rpc), vec!["tcp://127.0.0.1:50001"]);
+    }
+
+    #[test]
+    fn test_script_hash() {
+        // Example from the Electrum protocol docs
+        let address = Address::from_str("1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa")
+            .unwrap()
+            .assume_checked();
+        assert_eq!(
+            script_hash(&address.script_pubkey()),
+            "8b01df4e368ea28f8dc0423bcf7a4923e3a12d307c875e47a0cfbf90b5c39161"
+        );
+    }
+
+    #[test]
+    fn test_batch_history() {
+        let mut client = serve(|request| {
+            // Answer in reverse order, ids must put them back
+            let mut responses: Vec<Value> = request
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|r| {
+                    let history = if r["params"][0] == script_hash(Script::new()) {
+                        json!([{"tx_hash": "aa", "height": 100}])
+                    } else {
+                        json!([])
+                    };
+                    json!({"jsonrpc": "2.0", "id": r["id"], "result": history})
+                })
+                .collect();
+            responses.reverse();
+            Value::Array(responses)
+        });
+
+        let used = bitcoin::ScriptBuf::new();
+        let unused = bitcoin::ScriptBuf::from_bytes(vec![0x51]);
+        let history = client
+            .scripts_history(&[unused.as_script(), used.as_script()])
+            .unwrap();
+        assert!(history[0].is_empty());
+        assert_eq!(history[1][0].height, 100);
+    }
+
+    #[test]
+    fn test_unspent_and_tip() {
+        let mut client = serve(|request| match request {
+            Value::Array(batch) => Value::Array(
+                batch
+                    .iter()
+                    .map(|r| {
+                        json!({"id": r["id"], "result": [
+                            {"tx_hash": "bb", "tx_pos": 1, "height": 0, "value": 5000}
+                        ]})
+                    })
+                    .collect(),
+            ),
+            r => json!({"id": r["id"], "result": {"height": 850000, "hex": "00"}}),
+        });
+
+        assert_eq!(client.tip_height().unwrap(), 850_000);
+        let unspent = client.scripts_unspent(&[Script::new()]).unwrap();
+        assert_eq!(
+            unspent[0],
+            vec![UnspentItem {
+                tx_hash: "bb".to_string(),
+                tx_pos: 1,
+                height: 0,
+                value: 5000,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_fee_rates() {
+        let mut client = serve(|request| {
+            let Value::Array(batch) = request else {
+                panic!("expected a batch");
+            };
+            Value::Array(
+                batch
+                    .iter()
+                    .map(|r| {
+                        let rate = match (r["method"].as_str(), r["params"][0].as_u64()) {
+                            (Some("blockchain.relayfee"), _) => 0.00001,
+                            (_, Some(2)) => -1.0,
+                            (_, Some(target)) => 0.0006 / target as f64,
+                            _ => unreachable!(),
+                        };
+                        json!({"id": r["id"], "result": rate})
+                    })
+                    .collect(),
+            )
+        });
+
+        assert_eq!(
+            client.fee_rates(&[2, 6, 24]).unwrap(),
+            vec![-1.0, 0.0001, 0.000025, 0.00001]
+        );
+    }
+
+    #[test]
+    fn test_subscribe_notifications() {
+        let mut client = serve_lines(|request| {
+            let notify = |status: &str| {
+                json!({
+                    "jsonrpc": "2.0",
+                    "method": SCRIPTHASH_SUBSCRIBE,
+                    "params": [script_hash(Script::new()), status],
+                })
+            };
+            if request.is_array() {
+                vec![
+                    json!({"method": "blockchain.headers.subscribe", "params": [{"height": 1}]}),
+                    notify("aa"),
+                    batch(&request, |_| Value::Null),
+                ]
+            } else {
+                vec![notify("bb"), json!({"id": request["id"], "result": null})]
+            }
+        });
+
+        let statuses = client.scripts_subscribe(&[Script::new()]).unwrap();
+        assert_eq!(statuses, vec![None]);
+        client.ping().unwrap();
+
+        for status in ["aa", "bb"] {
+            assert_eq!(
+                client.next_notification().unwrap(),
+                Some(ScriptStatus {
+                    script_hash: script_hash(Script::new()),
+                    status: Some(status.to_string()),
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn test_balance_transactions_and_broadcast() {
+        let tx = Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![],
+            output: vec![bitcoin::TxOut {
+                value: bitcoin::Amount::from_sat(1000),
+                script_pubkey: bitcoin::ScriptBuf::new(),
+            }],
+        };
+        let raw = encode::serialize_hex(&tx);
+        let txid = tx.compute_txid();
+        let mut client = serve_lines(move |request| {
+            let response = match request["method"].as_str() {
+                Some("blockchain.transaction.broadcast") => {
+                    assert_eq!(request["params"][0], raw);
+                    json!({"id": request["id"], "result": txid.to_string()})
+                }
+                _ => batch(&request, |r| match r["method"].as_str() {
+                    Some("blockchain.transaction.get") => json!(raw),
+                    _ => json!({"confirmed": 5000, "unconfirmed": -2000}),
+                }),
+            };
+            vec![response]
+        });
+
+        assert_eq!(
+            client.scripts_balance(&[Script::new()]).unwrap(),
+            vec![Balance {
+                confirmed: 5000,
+                unconfirmed: -2000,
+            }]
+        );
+        assert_eq!(client.transactions(&[txid]).unwrap(), vec![tx.clone()]);
+        assert_eq!(client.broadcast(&tx).unwrap(), txid);
+    }
+
+    #[test]
+    fn test_stale_responses_are_dropped() {
+        let mut client = serve_lines(|request| {
+            let id = request["id"].as_u64().unwrap();
+            vec![
+                json!({"id": id - 1, "result": "stale"}),
+                json!({"id": id, "result": {"height": id}}),
+            ]
+        });
+
+        assert_eq!(client.tip_height().unwrap(), 1);
+        assert_eq!(client.tip_height().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_server_error() {
+        let mut client = serve(
+            |request| json!({"id": request["id"], "error": {"code": 1, "message": "unknown method"}}),
+        );
+        assert_eq!(
+            client.call("server.nope", json!([])).unwrap_err(),
+            ElectrumError::Server("unknown method".to_string())
+        );
+    }
+}
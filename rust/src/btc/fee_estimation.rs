@@ -0,0 +1,208 @@
+use bitcoin::{OutPoint, Transaction, TxOut};
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::btc::coin_selection::{estimate_weight, CoinSelectionError, Utxo};
+
+/// Confirmation targets in blocks, as asked from `estimatesmartfee`.
+pub const FAST_TARGET: u32 = 2;
+pub const MARKET_TARGET: u32 = 6;
+pub const SLOW_TARGET: u32 = 24;
+
+/// Bitcoin Core's default `minrelaytxfee`, in sat/vB.
+pub const MIN_RELAY_FEE: f64 = 1.0;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum FeeEstimationError {
+    #[error("Transaction has {inputs} inputs but {prevouts} previous outputs")]
+    PrevoutCount { inputs: usize, prevouts: usize },
+
+    #[error(transparent)]
+    Selection(#[from] CoinSelectionError),
+}
+
+/// Fee rates in sat/vB, `slow <= market <= fast`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FeeTiers {
+    pub slow: f64,
+    pub market: f64,
+    pub fast: f64,
+}
+
+impl FeeTiers {
+    /// Tiers from per-target estimates. Targets the node has no estimate
+    /// for (a fresh node, regtest) take the next slower known rate, or
+    /// `min_rate` when there is none.
+    pub fn new(slow: Option<f64>, market: Option<f64>, fast: Option<f64>, min_rate: f64) -> Self {
+        let min_rate = min_rate.max(MIN_RELAY_FEE);
+        let slow = slow.unwrap_or(min_rate).max(min_rate);
+        let market = market.unwrap_or(slow).max(slow);
+        let fast = fast.unwrap_or(market).max(market);
+
+        Self { slow, market, fast }
+    }
+
+    /// Electrum `blockchain.estimatefee` results for `SLOW_TARGET`,
+    /// `MARKET_TARGET` and `FAST_TARGET`, and `blockchain.relayfee`, all in
+    /// BTC/kvB.
+    pub fn from_electrum(slow: f64, market: f64, fast: f64, relay_fee: f64) -> Self {
+        Self::new(
+            btc_per_kvb(slow),
+            btc_per_kvb(market),
+            btc_per_kvb(fast),
+            btc_per_kvb(relay_fee).unwrap_or(MIN_RELAY_FEE),
+        )
+    }
+
+    /// A mempool.space style `/api/v1/fees/recommended` response.
+    pub fn from_mempool(recommended: &Value) -> Option<Self> {
+        let rate = |key: &str| recommended.get(key).and_then(Value::as_f64);
+        let fast = rate("fastestFee")?;
+
+        Some(Self::new(
+            rate("hourFee"),
+            rate("halfHourFee"),
+            Some(fast),
+            rate("minimumFee").unwrap_or(MIN_RELAY_FEE),
+        ))
+    }
+}
+
+/// BTC/kvB to sat/vB. Nodes answer -1 when they have no estimate.
+fn btc_per_kvb(rate: f64) -> Option<f64> {
+    (rate.is_finite() && rate > 0.0).then_some(rate * 100_000.0)
+}
+
+/// Virtual size of `tx` once signed, from the script type of each output
+/// it spends. Exact for taproot, and at most a byte over per ECDSA input,
+/// whose signature may come out a byte shorter than the 72 assumed.
+pub fn signed_vsize(tx: &Transaction, prevouts: &[TxOut]) -> Result<u64, FeeEstimationError> {
+    if tx.input.len() != prevouts.len() {
+        return Err(FeeEstimationError::PrevoutCount {
+            inputs: tx.input.len(),
+            prevouts: prevouts.len(),
+        });
+    }
+    let inputs = tx
+        .input
+        .iter()
+        .zip(prevouts)
+        .map(|(input, prevout)| Utxo::new(input.previous_output, prevout.clone()))
+        .collect::<Result<Vec<Utxo>, _>>()?;
+
+    Ok(estimate_weight(&inputs, &tx.output).to_vbytes_ceil())
+}
+
+/// Virtual size of one more input of the type of `prevout`.
+pub fn input_vsize(prevout: &TxOut) -> Result<u64, FeeEstimationError> {
+    let utxo = Utxo::new(OutPoint::null(), prevout.clone())?;
+    Ok(utxo.input_weight().to_vbytes_ceil())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::hashes::Hash;
+    use bitcoin::{
+        absolute, transaction, Amount, PubkeyHash, ScriptBuf, ScriptHash, Sequence, TxIn, Txid,
+        WPubkeyHash, Witness, WitnessProgram, WitnessVersion,
+    };
+    use serde_json::json;
+
+    fn txout(script_pubkey: ScriptBuf) -> TxOut {
+        TxOut {
+            value: Amount::from_sat(50_000),
+            script_pubkey,
+        }
+    }
+
+    fn p2tr() -> ScriptBuf {
+        ScriptBuf::new_witness_program(
+            &WitnessProgram::new(WitnessVersion::V1, &[0x79; 32]).unwrap(),
+        )
+    }
+
+    fn tx(inputs: usize, outputs: Vec<TxOut>) -> Transaction {
+        Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: (0..inputs)
+                .map(|vout| TxIn {
+                    previous_output: OutPoint::new(Txid::from_byte_array([0x11; 32]), vout as u32),
+                    script_sig: ScriptBuf::new(),
+                    sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                    witness: Witness::new(),
+                })
+                .collect(),
+            output: outputs,
+        }
+    }
+
+    #[test]
+    fn test_tiers_from_electrum() {
+        let tiers = FeeTiers::from_electrum(0.00002, 0.00008, 0.0002, 0.00001);
+        assert_eq!(
+            tiers,
+            FeeTiers {
+                slow: 2.0,
+                market: 8.0,
+                fast: 20.0
+            }
+        );
+
+        // regtest: no estimates at all
+        let tiers = FeeTiers::from_electrum(-1.0, -1.0, -1.0, 0.00001);
+        assert_eq!((tiers.slow, tiers.market, tiers.fast), (1.0, 1.0, 1.0));
+
+        // Faster targets never come out cheaper
+        let tiers = FeeTiers::from_electrum(0.0001, 0.00005, -1.0, 0.00001);
+        assert_eq!((tiers.slow, tiers.market, tiers.fast), (10.0, 10.0, 10.0));
+    }
+
+    #[test]
+    fn test_tiers_from_mempool() {
+        let tiers = FeeTiers::from_mempool(&json!({
+            "fastestFee": 12,
+            "halfHourFee": 9,
+            "hourFee": 7,
+            "economyFee": 4,
+            "minimumFee": 2
+        }))
+        .unwrap();
+        assert_eq!((tiers.slow, tiers.market, tiers.fast), (7.0, 9.0, 12.0));
+
+        assert!(FeeTiers::from_mempool(&json!({"error": "rate limited"})).is_none());
+    }
+
+    #[test]
+    fn test_signed_vsize() {
+        let p2wpkh = ScriptBuf::new_p2wpkh(&WPubkeyHash::from_byte_array([0xaa; 20]));
+        let p2pkh = ScriptBuf::new_p2pkh(&PubkeyHash::from_byte_array([0xaa; 20]));
+        let p2sh = ScriptBuf::new_p2sh(&ScriptHash::from_byte_array([0xaa; 20]));
+        let outputs = vec![txout(p2wpkh.clone()), txout(p2wpkh.clone())];
+
+        // One input of each type paying two p2wpkh outputs
+        let one_in = tx(1, outputs.clone());
+        assert_eq!(signed_vsize(&one_in, &[txout(p2wpkh.clone())]), Ok(141));
+        assert_eq!(signed_vsize(&one_in, &[txout(p2tr())]), Ok(130));
+        assert_eq!(signed_vsize(&one_in, &[txout(p2sh.clone())]), Ok(164));
+        assert_eq!(signed_vsize(&one_in, &[txout(p2pkh.clone())]), Ok(220));
+
+        let mixed = tx(2, outputs);
+        assert_eq!(
+            signed_vsize(&mixed, &[txout(p2wpkh.clone()), txout(p2pkh)]),
+            Ok(141 + 148)
+        );
+        assert_eq!(
+            signed_vsize(&mixed, &[txout(p2wpkh.clone())]),
+            Err(FeeEstimationError::PrevoutCount {
+                inputs: 2,
+                prevouts: 1
+            })
+        );
+
+        assert_eq!(input_vsize(&txout(p2wpkh)), Ok(68));
+        assert_eq!(input_vsize(&txout(p2tr())), Ok(58));
+        assert_eq!(input_vsize(&txout(p2sh)), Ok(91));
+    }
+}
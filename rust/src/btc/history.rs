@@ -0,0 +1,145 @@
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+use bitcoin::{ScriptBuf, Transaction, Txid};
+use thiserror::Error;
+
+use crate::btc::electrum::HistoryItem;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum HistoryError {
+    #[error("Invalid txid {0}")]
+    Txid(String),
+}
+
+/// Transactions of several scripts' histories, each once with its height,
+/// mempool ones first and then newest first.
+pub fn merge_histories(histories: &[Vec<HistoryItem>]) -> Result<Vec<(Txid, i64)>, HistoryError> {
+    let mut merged: Vec<(Txid, i64)> = Vec::new();
+    let mut seen = HashSet::new();
+    for item in histories.iter().flatten() {
+        let txid =
+            Txid::from_str(&item.tx_hash).map_err(|_| HistoryError::Txid(item.tx_hash.clone()))?;
+        if seen.insert(txid) {
+            merged.push((txid, item.height));
+        }
+    }
+    merged.sort_by_key(|&(_, height)| Reverse(if height <= 0 { i64::MAX } else { height }));
+
+    Ok(merged)
+}
+
+/// Satoshis `tx` moves into the wallet owning `scripts`, negative when it
+/// spends more than comes back, fee included. Spent outputs are looked up
+/// in `wallet_txs`, the transactions of the wallet's history.
+pub fn net_amount(
+    tx: &Transaction,
+    wallet_txs: &HashMap<Txid, Transaction>,
+    scripts: &HashSet<ScriptBuf>,
+) -> i64 {
+    let received: u64 = tx
+        .output
+        .iter()
+        .filter(|o| scripts.contains(&o.script_pubkey))
+        .map(|o| o.value.to_sat())
+        .sum();
+    let spent: u64 = tx
+        .input
+        .iter()
+        .filter_map(|input| {
+            let previous = wallet_txs.get(&input.previous_output.txid)?;
+            previous.output.get(input.previous_output.vout as usize)
+        })
+        .filter(|o| scripts.contains(&o.script_pubkey))
+        .map(|o| o.value.to_sat())
+        .sum();
+
+    received as i64 - spent as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::hashes::Hash;
+    use bitcoin::{
+        absolute, transaction, Amount, OutPoint, Sequence, TxIn, TxOut, WPubkeyHash, Witness,
+    };
+
+    fn script(tag: u8) -> ScriptBuf {
+        ScriptBuf::new_p2wpkh(&WPubkeyHash::from_byte_array([tag; 20]))
+    }
+
+    fn tx(inputs: Vec<OutPoint>, outputs: Vec<(u8, u64)>) -> Transaction {
+        Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: inputs
+                .into_iter()
+                .map(|previous_output| TxIn {
+                    previous_output,
+                    script_sig: ScriptBuf::new(),
+                    sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                    witness: Witness::new(),
+                })
+                .collect(),
+            output: outputs
+                .into_iter()
+                .map(|(tag, value)| TxOut {
+                    value: Amount::from_sat(value),
+                    script_pubkey: script(tag),
+                })
+                .collect(),
+        }
+    }
+
+    fn item(tag: u8, height: i64) -> HistoryItem {
+        HistoryItem {
+            tx_hash: Txid::from_byte_array([tag; 32]).to_string(),
+            height,
+        }
+    }
+
+    #[test]
+    fn test_merge_histories() {
+        let merged = merge_histories(&[
+            vec![item(1, 100), item(2, 0)],
+            vec![item(1, 100), item(3, 250), item(4, -1)],
+        ])
+        .unwrap();
+        let order: Vec<(u8, i64)> = merged
+            .iter()
+            .map(|(txid, height)| (txid.to_byte_array()[0], *height))
+            .collect();
+        assert_eq!(order, vec![(2, 0), (4, -1), (3, 250), (1, 100)]);
+
+        let invalid = HistoryItem {
+            tx_hash: "zz".to_string(),
+            height: 1,
+        };
+        assert_eq!(
+            merge_histories(&[vec![invalid]]),
+            Err(HistoryError::Txid("zz".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_net_amount() {
+        let scripts: HashSet<ScriptBuf> = [script(0xaa), script(0xab)].into();
+        let funding = tx(vec![OutPoint::null()], vec![(0xaa, 100_000), (0xee, 5_000)]);
+        let funding_id = funding.compute_txid();
+        // Pays 60k away, 39_800 change to the second account, 200 fee
+        let payment = tx(
+            vec![OutPoint::new(funding_id, 0)],
+            vec![(0xbb, 60_000), (0xab, 39_800)],
+        );
+        let wallet_txs: HashMap<Txid, Transaction> = [
+            (funding_id, funding.clone()),
+            (payment.compute_txid(), payment.clone()),
+        ]
+        .into();
+
+        assert_eq!(net_amount(&funding, &wallet_txs, &scripts), 100_000);
+        assert_eq!(net_amount(&payment, &wallet_txs, &scripts), -60_200);
+    }
+}
@@ -0,0 +1,225 @@
+use std::str::FromStr;
+
+use bitcoin::address::NetworkUnchecked;
+use bitcoin::bip32::Xpub;
+use bitcoin::{Address, OutPoint, PublicKey, Txid};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum LabelError {
+    #[error("Line {0} is not a BIP-329 record: {1}")]
+    Parse(usize, String),
+
+    #[error("Invalid {0} reference {1}")]
+    Reference(LabelType, String),
+
+    #[error("Only outputs can be marked spendable")]
+    Spendable,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LabelType {
+    Tx,
+    Addr,
+    Pubkey,
+    Input,
+    Output,
+    Xpub,
+}
+
+impl std::fmt::Display for LabelType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            LabelType::Tx => "tx",
+            LabelType::Addr => "addr",
+            LabelType::Pubkey => "pubkey",
+            LabelType::Input => "input",
+            LabelType::Output => "output",
+            LabelType::Xpub => "xpub",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for LabelType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        serde_json::from_value(Value::String(s.to_string()))
+            .map_err(|_| format!("Unknown label type {}", s))
+    }
+}
+
+/// One BIP-329 record. Fields this wallet does not use (height, value,
+/// keypath, ...) are kept in `extra` so they survive a round trip.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Label {
+    #[serde(rename = "type")]
+    pub label_type: LabelType,
+    #[serde(rename = "ref")]
+    pub reference: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spendable: Option<bool>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl Label {
+    pub fn new(label_type: LabelType, reference: String, label: Option<String>) -> Self {
+        Self {
+            label_type,
+            reference,
+            label,
+            origin: None,
+            spendable: None,
+            extra: Map::new(),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), LabelError> {
+        let r = self.reference.as_str();
+        let valid = match self.label_type {
+            LabelType::Tx => Txid::from_str(r).is_ok(),
+            LabelType::Addr => Address::<NetworkUnchecked>::from_str(r).is_ok(),
+            LabelType::Pubkey => PublicKey::from_str(r).is_ok(),
+            LabelType::Input | LabelType::Output => OutPoint::from_str(r).is_ok(),
+            LabelType::Xpub => Xpub::from_str(r).is_ok(),
+        };
+        if !valid {
+            return Err(LabelError::Reference(
+                self.label_type,
+                self.reference.clone(),
+            ));
+        }
+        if self.spendable.is_some() && self.label_type != LabelType::Output {
+            return Err(LabelError::Spendable);
+        }
+        Ok(())
+    }
+
+    fn same_target(&self, other: &Label) -> bool {
+        self.label_type == other.label_type && self.reference == other.reference
+    }
+}
+
+/// Valid records of a BIP-329 export and the errors of the lines that were
+/// skipped. Blank lines are ignored.
+pub fn parse_jsonl(jsonl: &str) -> (Vec<Label>, Vec<LabelError>) {
+    let mut labels = Vec::new();
+    let mut errors = Vec::new();
+
+    for (i, line) in jsonl.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let parsed = serde_json::from_str::<Label>(line)
+            .map_err(|e| LabelError::Parse(i + 1, e.to_string()))
+            .and_then(|label| label.validate().map(|_| label));
+        match parsed {
+            Ok(label) => labels.push(label),
+            Err(e) => errors.push(e),
+        }
+    }
+
+    (labels, errors)
+}
+
+pub fn to_jsonl(labels: &[Label]) -> Result<String, serde_json::Error> {
+    let mut out = String::new();
+    for label in labels {
+        out.push_str(&serde_json::to_string(label)?);
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+/// Insert or replace by `(type, ref)`, later records win as in a file read
+/// top to bottom. Returns how many records were merged.
+pub fn merge(existing: &mut Vec<Label>, incoming: Vec<Label>) -> usize {
+    let count = incoming.len();
+    for label in incoming {
+        match existing.iter_mut().find(|l| l.same_target(&label)) {
+            Some(current) => *current = label,
+            None => existing.push(label),
+        }
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Example export from BIP-329
+    const EXAMPLE: &str = r#"{ "type": "tx", "ref": "f91d0a8a78462bc59398f2c5d7a84fcff491c26ba54c4833478b202796c8aafd", "label": "Transaction", "origin": "wpkh([d34db33f/84'/0'/0'])" }
+{ "type": "addr", "ref": "bc1q34aq5drpuwy3wgl9lhup9892qp6svr8ldzyy7c", "label": "Address" }
+{ "type": "pubkey", "ref": "0283409659355b6d1cc3c32decd5d561abaac86c37a353b52895a5e6c196d6f448", "label": "Public Key" }
+{ "type": "input", "ref": "f91d0a8a78462bc59398f2c5d7a84fcff491c26ba54c4833478b202796c8aafd:0", "label": "Input" }
+{ "type": "output", "ref": "f91d0a8a78462bc59398f2c5d7a84fcff491c26ba54c4833478b202796c8aafd:1", "label": "Output" , "spendable" : false }
+{ "type": "xpub", "ref": "xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8", "label": "Extended Public Key" }
+"#;
+
+    #[test]
+    fn test_parse_example() {
+        let (labels, errors) = parse_jsonl(EXAMPLE);
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(labels.len(), 6);
+        assert_eq!(
+            labels[0].origin.as_deref(),
+            Some("wpkh([d34db33f/84'/0'/0'])")
+        );
+        assert_eq!(labels[4].spendable, Some(false));
+        assert_eq!(labels[5].label_type, LabelType::Xpub);
+    }
+
+    #[test]
+    fn test_round_trip_keeps_unknown_fields() {
+        let line = r#"{"type":"output","ref":"f91d0a8a78462bc59398f2c5d7a84fcff491c26ba54c4833478b202796c8aafd:1","label":"Cold","height":800000,"keypath":"/1/123"}"#;
+        let (labels, errors) = parse_jsonl(line);
+        assert!(errors.is_empty());
+        assert_eq!(labels[0].extra["height"], 800000);
+
+        let exported = to_jsonl(&labels).unwrap();
+        assert_eq!(parse_jsonl(&exported).0, labels);
+        assert!(exported.contains(r#""keypath":"/1/123""#));
+    }
+
+    #[test]
+    fn test_invalid_lines_are_skipped() {
+        let jsonl = "not json\n\n{\"type\":\"tx\",\"ref\":\"00\",\"label\":\"x\"}\n\
+            {\"type\":\"addr\",\"ref\":\"bc1q34aq5drpuwy3wgl9lhup9892qp6svr8ldzyy7c\",\"spendable\":true}\n\
+            {\"type\":\"wallet\",\"ref\":\"x\"}\n\
+            {\"type\":\"addr\",\"ref\":\"bc1q34aq5drpuwy3wgl9lhup9892qp6svr8ldzyy7c\",\"label\":\"ok\"}";
+        let (labels, errors) = parse_jsonl(jsonl);
+        assert_eq!(labels.len(), 1);
+        assert_eq!(errors.len(), 4);
+        assert!(matches!(errors[0], LabelError::Parse(1, _)));
+        assert!(matches!(errors[1], LabelError::Reference(LabelType::Tx, _)));
+        assert_eq!(errors[2], LabelError::Spendable);
+        assert!(matches!(errors[3], LabelError::Parse(5, _)));
+    }
+
+    #[test]
+    fn test_merge() {
+        let (mut labels, _) = parse_jsonl(EXAMPLE);
+        let renamed = Label::new(
+            LabelType::Addr,
+            "bc1q34aq5drpuwy3wgl9lhup9892qp6svr8ldzyy7c".to_string(),
+            Some("Savings".to_string()),
+        );
+        let new = Label::new(LabelType::Tx, "a".repeat(64), Some("Rent".to_string()));
+
+        assert_eq!(merge(&mut labels, vec![renamed, new]), 2);
+        assert_eq!(labels.len(), 7);
+        assert_eq!(labels[1].label.as_deref(), Some("Savings"));
+        assert_eq!(labels[6].label.as_deref(), Some("Rent"));
+    }
+}
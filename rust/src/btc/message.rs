@@ -0,0 +1,557 @@
+use bitcoin::base64::engine::general_purpose::STANDARD as BASE64;
+use bitcoin::base64::Engine;
+use bitcoin::blockdata::opcodes::all::{OP_PUSHBYTES_0, OP_RETURN};
+use bitcoin::blockdata::script::Builder;
+use bitcoin::consensus::encode::{deserialize, serialize};
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use bitcoin::key::{Keypair, TapTweak};
+use bitcoin::psbt::Psbt;
+use bitcoin::secp256k1::{Message, Secp256k1, SecretKey, Signing, Verification, XOnlyPublicKey};
+use bitcoin::sighash::{EcdsaSighashType, Prevouts, SighashCache, TapSighashType};
+use bitcoin::sign_message::{signed_msg_hash, MessageSignature};
+use bitcoin::transaction::Version;
+use bitcoin::{
+    absolute, ecdsa, taproot, Address, AddressType, Amount, CompressedPublicKey, Network, OutPoint,
+    PublicKey, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness,
+};
+use thiserror::Error;
+
+use crate::btc::psbt::{finalize, PsbtError};
+
+const BIP322_TAG: &[u8] = b"BIP0322-signed-message";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureFormat {
+    /// `signmessage` of Bitcoin Core, P2PKH only
+    Legacy,
+    /// BIP-322 witness
+    Simple,
+    /// BIP-322 `to_sign` transaction
+    Full,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum MessageError {
+    #[error("Signature is not valid base64")]
+    Encoding,
+
+    #[error("{0:?} signatures are not supported for {1}")]
+    Unsupported(SignatureFormat, String),
+
+    #[error("Message signing is not supported for {0} addresses")]
+    AddressType(AddressType),
+
+    #[error("Key does not belong to {0}")]
+    KeyMismatch(String),
+
+    #[error("Signing failed: {0}")]
+    Signing(String),
+
+    #[error(transparent)]
+    Psbt(#[from] PsbtError),
+}
+
+/// Address of `sk` for the account's address type.
+pub fn key_address<C: Signing + Verification>(
+    secp: &Secp256k1<C>,
+    sk: &SecretKey,
+    network: Network,
+    address_type: AddressType,
+) -> Result<Address, MessageError> {
+    let pk = CompressedPublicKey(sk.public_key(secp));
+    match address_type {
+        AddressType::P2pkh => Ok(Address::p2pkh(pk, network)),
+        AddressType::P2wpkh => Ok(Address::p2wpkh(&pk, network)),
+        AddressType::P2tr => Ok(Address::p2tr(
+            secp,
+            pk.0.x_only_public_key().0,
+            None,
+            network,
+        )),
+        other => Err(MessageError::AddressType(other)),
+    }
+}
+
+/// Tagged hash committed to by `to_spend`.
+pub fn message_hash(message: &[u8]) -> sha256::Hash {
+    let tag = sha256::Hash::hash(BIP322_TAG);
+    let mut engine = sha256::Hash::engine();
+    engine.input(tag.as_ref());
+    engine.input(tag.as_ref());
+    engine.input(message);
+    sha256::Hash::from_engine(engine)
+}
+
+pub fn to_spend(script_pubkey: &ScriptBuf, message: &[u8]) -> Transaction {
+    Transaction {
+        version: Version(0),
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::new(Txid::all_zeros(), 0xFFFF_FFFF),
+            script_sig: Builder::new()
+                .push_opcode(OP_PUSHBYTES_0)
+                .push_slice(message_hash(message).to_byte_array())
+                .into_script(),
+            sequence: Sequence::ZERO,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut {
+            value: Amount::ZERO,
+            script_pubkey: script_pubkey.clone(),
+        }],
+    }
+}
+
+pub fn to_sign(to_spend: &Transaction) -> Transaction {
+    Transaction {
+        version: Version(0),
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::new(to_spend.compute_txid(), 0),
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::ZERO,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut {
+            value: Amount::ZERO,
+            script_pubkey: Builder::new().push_opcode(OP_RETURN).into_script(),
+        }],
+    }
+}
+
+/// `to_sign` as a PSBT, for signers that only sign PSBTs such as Ledger.
+pub fn to_sign_psbt(address: &Address, message: &str) -> Result<Psbt, MessageError> {
+    let to_spend = to_spend(&address.script_pubkey(), message.as_bytes());
+    let mut psbt = Psbt::from_unsigned_tx(to_sign(&to_spend))
+        .map_err(|e| MessageError::Signing(e.to_string()))?;
+    psbt.inputs[0].witness_utxo = Some(to_spend.output[0].clone());
+    Ok(psbt)
+}
+
+fn encode(to_sign: &mut Transaction, witness: Witness, format: SignatureFormat) -> String {
+    match format {
+        SignatureFormat::Full => {
+            to_sign.input[0].witness = witness;
+            BASE64.encode(serialize(to_sign))
+        }
+        _ => BASE64.encode(serialize(&witness)),
+    }
+}
+
+fn bip322_supported(address: &Address, format: SignatureFormat) -> Result<(), MessageError> {
+    match address.address_type() {
+        Some(AddressType::P2wpkh | AddressType::P2tr) if format != SignatureFormat::Legacy => {
+            Ok(())
+        }
+        Some(AddressType::P2pkh) if format == SignatureFormat::Legacy => Ok(()),
+        _ => Err(MessageError::Unsupported(format, address.to_string())),
+    }
+}
+
+pub fn sign<C: Signing + Verification>(
+    secp: &Secp256k1<C>,
+    sk: &SecretKey,
+    address: &Address,
+    message: &str,
+    format: SignatureFormat,
+) -> Result<String, MessageError> {
+    bip322_supported(address, format)?;
+    let pk = PublicKey::new(sk.public_key(secp));
+    let script_pubkey = address.script_pubkey();
+    let mismatch = || MessageError::KeyMismatch(address.to_string());
+
+    if format == SignatureFormat::Legacy {
+        if ScriptBuf::new_p2pkh(&pk.pubkey_hash()) != script_pubkey {
+            return Err(mismatch());
+        }
+        let msg = Message::from_digest(signed_msg_hash(message).to_byte_array());
+        let sig = secp.sign_ecdsa_recoverable(&msg, sk);
+        return Ok(MessageSignature::new(sig, true).to_base64());
+    }
+
+    let to_spend = to_spend(&script_pubkey, message.as_bytes());
+    let mut to_sign = to_sign(&to_spend);
+    let mut cache = SighashCache::new(to_sign.clone());
+
+    let witness = if script_pubkey.is_p2wpkh() {
+        let wpkh = pk.wpubkey_hash().map_err(|_| mismatch())?;
+        if ScriptBuf::new_p2wpkh(&wpkh) != script_pubkey {
+            return Err(mismatch());
+        }
+        let sighash = cache
+            .p2wpkh_signature_hash(0, &script_pubkey, Amount::ZERO, EcdsaSighashType::All)
+            .map_err(|e| MessageError::Signing(e.to_string()))?;
+        let sig = secp.sign_ecdsa(&Message::from_digest(sighash.to_byte_array()), sk);
+        Witness::p2wpkh(&ecdsa::Signature::sighash_all(sig), &pk.inner)
+    } else {
+        let keypair = Keypair::from(Keypair::from_secret_key(secp, sk).tap_tweak(secp, None));
+        let (output_key, _) = keypair.x_only_public_key();
+        if ScriptBuf::new_p2tr_tweaked(output_key.dangerous_assume_tweaked()) != script_pubkey {
+            return Err(mismatch());
+        }
+        let sighash = cache
+            .taproot_key_spend_signature_hash(
+                0,
+                &Prevouts::All(&to_spend.output),
+                TapSighashType::Default,
+            )
+            .map_err(|e| MessageError::Signing(e.to_string()))?;
+        let msg = Message::from_digest(sighash.to_byte_array());
+        let signature = secp.sign_schnorr_no_aux_rand(&msg, &keypair);
+        Witness::p2tr_key_spend(&taproot::Signature {
+            signature,
+            sighash_type: TapSighashType::Default,
+        })
+    };
+
+    Ok(encode(&mut to_sign, witness, format))
+}
+
+/// Encode the BIP-322 signature of a `to_sign_psbt` signed elsewhere and
+/// check it before handing it out.
+pub fn signature_from_psbt<C: Verification>(
+    secp: &Secp256k1<C>,
+    mut psbt: Psbt,
+    address: &Address,
+    message: &str,
+    format: SignatureFormat,
+) -> Result<String, MessageError> {
+    bip322_supported(address, format)?;
+    if format == SignatureFormat::Legacy {
+        return Err(MessageError::Unsupported(format, address.to_string()));
+    }
+    finalize(&mut psbt)?;
+    let witness = psbt.inputs[0]
+        .final_script_witness
+        .clone()
+        .ok_or(PsbtError::Unsigned(0))?;
+
+    let signature = encode(&mut psbt.unsigned_tx, witness, format);
+    if !verify(secp, address, message, &signature)? {
+        return Err(MessageError::KeyMismatch(address.to_string()));
+    }
+    Ok(signature)
+}
+
+/// Encode the compact signature of a `signmessage` made elsewhere, e.g.
+/// by the Ledger SIGN_MESSAGE command, and check it before handing it out.
+/// Legacy signatures cannot come from a PSBT, see [`signature_from_psbt`].
+pub fn signature_from_compact<C: Verification>(
+    secp: &Secp256k1<C>,
+    address: &Address,
+    message: &str,
+    compact: &[u8],
+) -> Result<String, MessageError> {
+    bip322_supported(address, SignatureFormat::Legacy)?;
+    if !verify_legacy(secp, address, message, compact) {
+        return Err(MessageError::KeyMismatch(address.to_string()));
+    }
+    Ok(BASE64.encode(compact))
+}
+
+/// Check a legacy, BIP-137 or BIP-322 simple/full signature.
+pub fn verify<C: Verification>(
+    secp: &Secp256k1<C>,
+    address: &Address,
+    message: &str,
+    signature: &str,
+) -> Result<bool, MessageError> {
+    let bytes = BASE64
+        .decode(signature.trim())
+        .map_err(|_| MessageError::Encoding)?;
+
+    if bytes.len() == 65 {
+        return Ok(verify_legacy(secp, address, message, &bytes));
+    }
+
+    let script_pubkey = address.script_pubkey();
+    let to_spend = to_spend(&script_pubkey, message.as_bytes());
+    let (to_sign, witness) = match deserialize::<Witness>(&bytes) {
+        Ok(witness) => (to_sign(&to_spend), witness),
+        Err(_) => {
+            let mut tx: Transaction = deserialize(&bytes).map_err(|_| MessageError::Encoding)?;
+            let expected = to_sign(&to_spend);
+            // Full signatures may set their own version, lock time and
+            // sequence to prove a time lock
+            if tx.input.len() != 1
+                || tx.input[0].previous_output != expected.input[0].previous_output
+                || tx.output != expected.output
+            {
+                return Ok(false);
+            }
+            let witness = std::mem::take(&mut tx.input[0].witness);
+            (tx, witness)
+        }
+    };
+
+    let mut cache = SighashCache::new(&to_sign);
+    let valid = match address.address_type() {
+        Some(AddressType::P2wpkh) => {
+            let (Some(sig), Some(pk), 2) = (witness.nth(0), witness.nth(1), witness.len()) else {
+                return Ok(false);
+            };
+            let (Ok(sig), Ok(pk)) = (ecdsa::Signature::from_slice(sig), PublicKey::from_slice(pk))
+            else {
+                return Ok(false);
+            };
+            if pk
+                .wpubkey_hash()
+                .map_or(true, |h| ScriptBuf::new_p2wpkh(&h) != script_pubkey)
+            {
+                return Ok(false);
+            }
+            cache
+                .p2wpkh_signature_hash(0, &script_pubkey, Amount::ZERO, sig.sighash_type)
+                .is_ok_and(|sighash| {
+                    let msg = Message::from_digest(sighash.to_byte_array());
+                    secp.verify_ecdsa(&msg, &sig.signature, &pk.inner).is_ok()
+                })
+        }
+        Some(AddressType::P2tr) => {
+            let (Some(sig), 1) = (witness.nth(0), witness.len()) else {
+                return Ok(false);
+            };
+            let (Ok(sig), Ok(output_key)) = (
+                taproot::Signature::from_slice(sig),
+                XOnlyPublicKey::from_slice(&script_pubkey.as_bytes()[2..]),
+            ) else {
+                return Ok(false);
+            };
+            cache
+                .taproot_key_spend_signature_hash(
+                    0,
+                    &Prevouts::All(&to_spend.output),
+                    sig.sighash_type,
+                )
+                .is_ok_and(|sighash| {
+                    let msg = Message::from_digest(sighash.to_byte_array());
+                    secp.verify_schnorr(&sig.signature, &msg, &output_key)
+                        .is_ok()
+                })
+        }
+        _ => {
+            return Err(MessageError::Unsupported(
+                SignatureFormat::Simple,
+                address.to_string(),
+            ))
+        }
+    };
+
+    Ok(valid)
+}
+
+/// Bitcoin Core `signmessage`, also accepting the BIP-137 headers other
+/// wallets use for segwit addresses.
+fn verify_legacy<C: Verification>(
+    secp: &Secp256k1<C>,
+    address: &Address,
+    message: &str,
+    bytes: &[u8],
+) -> bool {
+    let Ok(mut sig) = MessageSignature::from_slice(bytes) else {
+        return false;
+    };
+    // BIP-137 headers above 34 only mark the address type
+    sig.compressed |= bytes[0] > 34;
+    let Ok(pk) = sig.recover_pubkey(secp, signed_msg_hash(message)) else {
+        return false;
+    };
+
+    let script_pubkey = address.script_pubkey();
+    if ScriptBuf::new_p2pkh(&pk.pubkey_hash()) == script_pubkey {
+        return true;
+    }
+    pk.wpubkey_hash().is_ok_and(|hash| {
+        let p2wpkh = ScriptBuf::new_p2wpkh(&hash);
+        p2wpkh == script_pubkey || ScriptBuf::new_p2sh(&p2wpkh.script_hash()) == script_pubkey
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    // Test vectors from BIP-322
+    const P2WPKH: &str = "bc1q9vza2e8x573nczrlzms0wvx3gsqjx7vavgkx0l";
+    const P2TR: &str = "bc1ppv609nr0vr25u07u95waq5lucwfm6tde4nydujnu8npg4q75mr5sxq8lt3";
+
+    fn address(s: &str) -> Address {
+        Address::from_str(s)
+            .unwrap()
+            .require_network(Network::Bitcoin)
+            .unwrap()
+    }
+
+    fn key() -> SecretKey {
+        SecretKey::from_slice(&[7; 32]).unwrap()
+    }
+
+    fn key_addresses(secp: &Secp256k1<bitcoin::secp256k1::All>) -> [Address; 3] {
+        [AddressType::P2pkh, AddressType::P2wpkh, AddressType::P2tr]
+            .map(|t| key_address(secp, &key(), Network::Bitcoin, t).unwrap())
+    }
+
+    #[test]
+    fn test_message_hash() {
+        assert_eq!(
+            message_hash(b"").to_string(),
+            "c90c269c4f8fcbe6880f72a721ddfbf1914268a794cbb21cfafee13770ae19f1"
+        );
+        assert_eq!(
+            message_hash(b"Hello World").to_string(),
+            "f0eb03b1a75ac6d9847f55c624a99169b5dccba2a31f5b23bea77ba270de0a7a"
+        );
+    }
+
+    #[test]
+    fn test_transactions() {
+        let script_pubkey = address(P2WPKH).script_pubkey();
+        for (message, spend, sign) in [
+            (
+                "",
+                "c5680aa69bb8d860bf82d4e9cd3504b55dde018de765a91bb566283c545a99a7",
+                "1e9654e951a5ba44c8604c4de6c67fd78a27e81dcadcfe1edf638ba3aaebaed6",
+            ),
+            (
+                "Hello World",
+                "b79d196740ad5217771c1098fc4a4b51e0535c32236c71f1ea4d61a2d603352b",
+                "88737ae86f2077145f93cc4b153ae9a1cb8d56afa511988c149c5c8c9d93bddf",
+            ),
+        ] {
+            let to_spend = to_spend(&script_pubkey, message.as_bytes());
+            assert_eq!(to_spend.compute_txid().to_string(), spend);
+            assert_eq!(to_sign(&to_spend).compute_txid().to_string(), sign);
+        }
+    }
+
+    #[test]
+    fn test_verify_vectors() {
+        let secp = Secp256k1::verification_only();
+        let p2wpkh = address(P2WPKH);
+
+        let empty = "AkcwRAIgM2gBAQqvZX15ZiysmKmQpDrG83avLIT492QBzLnQIxYCIBaTpOaD20qRlEylyxFSeEA2ba9YOixpX8z46TSDtS40ASECx/EgAxlkQpQ9hYjgGu6EBCPMVPwVIVJqO4XCsMvViHI=";
+        let hello = "AkcwRAIgZRfIY3p7/DoVTty6YZbWS71bc5Vct9p9Fia83eRmw2QCICK/ENGfwLtptFluMGs2KsqoNSk89pO7F29zJLUx9a/sASECx/EgAxlkQpQ9hYjgGu6EBCPMVPwVIVJqO4XCsMvViHI=";
+        assert!(verify(&secp, &p2wpkh, "", empty).unwrap());
+        assert!(verify(&secp, &p2wpkh, "Hello World", hello).unwrap());
+        assert!(!verify(&secp, &p2wpkh, "Hello World", empty).unwrap());
+
+        let taproot = "AUHd69PrJQEv+oKTfZ8l+WROBHuy9HKrbFCJu7U1iK2iiEy1vMU5EfMtjc+VSHM7aU0SDbak5IUZRVno2P5mjSafAQ==";
+        assert!(verify(&secp, &address(P2TR), "Hello World", taproot).unwrap());
+        assert!(!verify(&secp, &address(P2TR), "Hello", taproot).unwrap());
+
+        assert_eq!(
+            verify(&secp, &p2wpkh, "", "not base64!"),
+            Err(MessageError::Encoding)
+        );
+    }
+
+    #[test]
+    fn test_sign_round_trip() {
+        let secp = Secp256k1::new();
+        let sk = key();
+        let [p2pkh, p2wpkh, p2tr] = key_addresses(&secp);
+        assert!(p2wpkh.to_string().starts_with("bc1q"));
+        assert!(p2tr.to_string().starts_with("bc1p"));
+
+        for (address, format) in [
+            (&p2wpkh, SignatureFormat::Simple),
+            (&p2wpkh, SignatureFormat::Full),
+            (&p2tr, SignatureFormat::Simple),
+            (&p2tr, SignatureFormat::Full),
+            (&p2pkh, SignatureFormat::Legacy),
+        ] {
+            let sig = sign(&secp, &sk, address, "Hello World", format).unwrap();
+            assert!(verify(&secp, address, "Hello World", &sig).unwrap());
+            assert!(!verify(&secp, address, "Hello", &sig).unwrap());
+        }
+
+        let other = SecretKey::from_slice(&[1; 32]).unwrap();
+        assert_eq!(
+            sign(&secp, &other, &p2wpkh, "", SignatureFormat::Simple),
+            Err(MessageError::KeyMismatch(p2wpkh.to_string()))
+        );
+        assert!(matches!(
+            sign(&secp, &sk, &p2pkh, "", SignatureFormat::Simple),
+            Err(MessageError::Unsupported(..))
+        ));
+        assert!(matches!(
+            sign(&secp, &sk, &p2tr, "", SignatureFormat::Legacy),
+            Err(MessageError::Unsupported(..))
+        ));
+    }
+
+    #[test]
+    fn test_signature_from_psbt() {
+        let secp = Secp256k1::new();
+        let sk = key();
+        let [_, address, _] = key_addresses(&secp);
+        let mut psbt = to_sign_psbt(&address, "Hello World").unwrap();
+
+        let err = signature_from_psbt(
+            &secp,
+            psbt.clone(),
+            &address,
+            "Hello World",
+            SignatureFormat::Simple,
+        );
+        assert_eq!(err, Err(MessageError::Psbt(PsbtError::Unsigned(0))));
+
+        // Sign the PSBT the way an external signer would
+        let pk = PublicKey::new(sk.public_key(&secp));
+        let sighash = SighashCache::new(&psbt.unsigned_tx)
+            .p2wpkh_signature_hash(
+                0,
+                &address.script_pubkey(),
+                Amount::ZERO,
+                EcdsaSighashType::All,
+            )
+            .unwrap();
+        let sig = secp.sign_ecdsa(&Message::from_digest(sighash.to_byte_array()), &sk);
+        psbt.inputs[0]
+            .partial_sigs
+            .insert(pk, ecdsa::Signature::sighash_all(sig));
+
+        let signature = signature_from_psbt(
+            &secp,
+            psbt.clone(),
+            &address,
+            "Hello World",
+            SignatureFormat::Simple,
+        )
+        .unwrap();
+        assert!(verify(&secp, &address, "Hello World", &signature).unwrap());
+        assert!(matches!(
+            signature_from_psbt(&secp, psbt, &address, "Hello", SignatureFormat::Full),
+            Err(MessageError::KeyMismatch(_))
+        ));
+    }
+
+    #[test]
+    fn test_signature_from_compact() {
+        let secp = Secp256k1::new();
+        let [p2pkh, p2wpkh, _] = key_addresses(&secp);
+        let msg = Message::from_digest(signed_msg_hash("Hello World").to_byte_array());
+        let compact =
+            MessageSignature::new(secp.sign_ecdsa_recoverable(&msg, &key()), true).serialize();
+
+        assert_eq!(
+            signature_from_compact(&secp, &p2pkh, "Hello World", &compact),
+            sign(
+                &secp,
+                &key(),
+                &p2pkh,
+                "Hello World",
+                SignatureFormat::Legacy
+            )
+        );
+        assert_eq!(
+            signature_from_compact(&secp, &p2pkh, "Hello", &compact),
+            Err(MessageError::KeyMismatch(p2pkh.to_string()))
+        );
+        assert!(matches!(
+            signature_from_compact(&secp, &p2wpkh, "Hello World", &compact),
+            Err(MessageError::Unsupported(..))
+        ));
+    }
+}
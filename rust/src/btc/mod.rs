@@ -0,0 +1,17 @@
+pub mod coin_control;
+pub mod coin_selection;
+pub mod cpfp;
+pub mod discovery;
+pub mod electrum;
+pub mod fee_estimation;
+pub mod history;
+pub mod labels;
+pub mod message;
+pub mod multisig;
+pub mod network;
+pub mod psbt;
+pub mod rbf;
+pub mod silent_payments;
+#[cfg(test)]
+pub(crate) mod testing;
+pub mod watch_only;
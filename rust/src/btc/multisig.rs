@@ -0,0 +1,828 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use bitcoin::bip32::{ChildNumber, DerivationPath, Fingerprint, KeySource, Xpub};
+use bitcoin::blockdata::opcodes::all::OP_CHECKMULTISIG;
+use bitcoin::blockdata::script::{Builder, Instruction, PushBytesBuf};
+use bitcoin::psbt::{self, Psbt};
+use bitcoin::secp256k1::{PublicKey, Secp256k1, Verification};
+use bitcoin::{Address, Network, NetworkKind, Script, ScriptBuf, Weight, Witness};
+use thiserror::Error;
+
+/// Consensus limit of OP_CHECKMULTISIG.
+pub const MAX_MULTISIG_KEYS: usize = 20;
+
+const INPUT_CHARSET: &str =
+    "0123456789()[],'/*abcdefgh@:$%{}IJKLMNOPQRSTUVWXYZ&+-.;<=>?!^_|~ijklmnopqrstuvwxyzABCDEFGH`#\"\\ ";
+const CHECKSUM_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum MultisigError {
+    #[error("Invalid descriptor: {0}")]
+    Descriptor(String),
+
+    #[error("Descriptor checksum mismatch: expected {expected}, got {actual}")]
+    Checksum { expected: String, actual: String },
+
+    #[error("Threshold {0} of {1} keys is not allowed")]
+    Threshold(usize, usize),
+
+    #[error("Invalid key {0}")]
+    Key(String),
+
+    #[error("Key {0} is not for {1}")]
+    Network(String, Network),
+
+    #[error("Key derivation failed: {0}")]
+    Derivation(String),
+
+    #[error("Input {0} does not belong to the wallet")]
+    ForeignInput(usize),
+
+    #[error("Input {input} has {have} of {need} signatures")]
+    MissingSignatures {
+        input: usize,
+        have: usize,
+        need: usize,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MultisigScriptType {
+    /// `wsh(...)`
+    Wsh,
+    /// `sh(wsh(...))`
+    ShWsh,
+}
+
+/// One `[fingerprint/path]xpub/<0;1>/*` key expression. The receive and
+/// change steps are the unhardened steps after the xpub, without the final
+/// wildcard. Single-chain keys (`xpub/0/*`) use the same steps for both.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DescriptorKey {
+    pub origin: Option<(Fingerprint, DerivationPath)>,
+    pub xpub: Xpub,
+    pub receive: Vec<ChildNumber>,
+    pub change: Vec<ChildNumber>,
+}
+
+impl DescriptorKey {
+    fn steps(&self, change: bool) -> &[ChildNumber] {
+        if change {
+            &self.change
+        } else {
+            &self.receive
+        }
+    }
+
+    fn is_multipath(&self) -> bool {
+        self.receive != self.change
+    }
+
+    /// Key origin as written in descriptors and Ledger key info.
+    pub fn origin_string(&self) -> String {
+        match &self.origin {
+            Some((fp, path)) if path.is_empty() => format!("[{}]{}", fp, self.xpub),
+            Some((fp, path)) => format!("[{}/{}]{}", fp, path, self.xpub),
+            None => self.xpub.to_string(),
+        }
+    }
+
+    pub(crate) fn derive<C: Verification>(
+        &self,
+        secp: &Secp256k1<C>,
+        change: bool,
+        index: u32,
+    ) -> Result<(PublicKey, KeySource), MultisigError> {
+        let index = ChildNumber::from_normal_idx(index)
+            .map_err(|e| MultisigError::Derivation(e.to_string()))?;
+        let mut steps = self.steps(change).to_vec();
+        steps.push(index);
+
+        let pubkey = self
+            .xpub
+            .derive_pub(secp, &steps)
+            .map_err(|e| MultisigError::Derivation(e.to_string()))?
+            .public_key;
+        let source = match &self.origin {
+            Some((fp, path)) => (*fp, path.extend(&steps)),
+            None => (self.xpub.fingerprint(), DerivationPath::from(steps)),
+        };
+
+        Ok((pubkey, source))
+    }
+
+    pub(crate) fn parse(expr: &str) -> Result<Self, MultisigError> {
+        let invalid = || MultisigError::Key(expr.to_string());
+
+        let (origin, rest) = match expr.strip_prefix('[') {
+            Some(rest) => {
+                let (origin, rest) = rest.split_once(']').ok_or_else(invalid)?;
+                let (fp, path) = origin.split_once('/').unwrap_or((origin, ""));
+                let fp = Fingerprint::from_str(fp).map_err(|_| invalid())?;
+                let path = DerivationPath::from_str(path).map_err(|_| invalid())?;
+                (Some((fp, path)), rest)
+            }
+            None => (None, expr),
+        };
+
+        let mut parts = rest.split('/');
+        let xpub = parts
+            .next()
+            .and_then(|x| Xpub::from_str(x).ok())
+            .ok_or_else(invalid)?;
+
+        let mut receive = Vec::new();
+        let mut change = Vec::new();
+        let mut multipath = false;
+        let mut ranged = false;
+        for step in parts {
+            if ranged {
+                // The wildcard has to be the last step
+                return Err(invalid());
+            }
+            if step == "*" {
+                ranged = true;
+            } else if let Some(pair) = step.strip_prefix('<').and_then(|s| s.strip_suffix('>')) {
+                let (a, b) = pair.split_once(';').ok_or_else(invalid)?;
+                if multipath {
+                    return Err(invalid());
+                }
+                multipath = true;
+                receive.push(parse_step(a).ok_or_else(invalid)?);
+                change.push(parse_step(b).ok_or_else(invalid)?);
+            } else {
+                let child = parse_step(step).ok_or_else(invalid)?;
+                receive.push(child);
+                change.push(child);
+            }
+        }
+        if !ranged || (multipath && receive == change) {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            origin,
+            xpub,
+            receive,
+            change,
+        })
+    }
+}
+
+impl fmt::Display for DescriptorKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.origin_string())?;
+        for (r, c) in self.receive.iter().zip(&self.change) {
+            if r == c {
+                write!(f, "/{}", r)?;
+            } else {
+                write!(f, "/<{};{}>", r, c)?;
+            }
+        }
+        write!(f, "/*")
+    }
+}
+
+fn parse_step(step: &str) -> Option<ChildNumber> {
+    // Hardened steps cannot be derived from an xpub
+    step.parse::<u32>()
+        .ok()
+        .and_then(|i| ChildNumber::from_normal_idx(i).ok())
+}
+
+/// Scripts and key origins of one derived multisig address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DerivedMultisig {
+    pub witness_script: ScriptBuf,
+    pub redeem_script: Option<ScriptBuf>,
+    pub script_pubkey: ScriptBuf,
+    pub keys: BTreeMap<PublicKey, KeySource>,
+}
+
+/// A k-of-n multisig wallet defined by an output descriptor such as
+/// `wsh(sortedmulti(2,[d34db33f/48'/0'/0'/2']xpub.../<0;1>/*,...))`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MultisigDescriptor {
+    pub script_type: MultisigScriptType,
+    pub threshold: usize,
+    pub sorted: bool,
+    pub keys: Vec<DescriptorKey>,
+}
+
+impl MultisigDescriptor {
+    /// Every key must be for the network the wallet runs on.
+    pub fn check_network(&self, network: Network) -> Result<(), MultisigError> {
+        let kind = NetworkKind::from(network);
+        match self.keys.iter().find(|k| k.xpub.network != kind) {
+            Some(key) => Err(MultisigError::Network(key.xpub.to_string(), network)),
+            None => Ok(()),
+        }
+    }
+
+    pub fn derive<C: Verification>(
+        &self,
+        secp: &Secp256k1<C>,
+        change: bool,
+        index: u32,
+    ) -> Result<DerivedMultisig, MultisigError> {
+        let mut derived = self
+            .keys
+            .iter()
+            .map(|k| k.derive(secp, change, index))
+            .collect::<Result<Vec<_>, _>>()?;
+        if self.sorted {
+            derived.sort_by_key(|(pk, _)| pk.serialize());
+        }
+
+        let witness_script = derived
+            .iter()
+            .fold(
+                Builder::new().push_int(self.threshold as i64),
+                |b, (pk, _)| b.push_key(&bitcoin::PublicKey::new(*pk)),
+            )
+            .push_int(derived.len() as i64)
+            .push_opcode(OP_CHECKMULTISIG)
+            .into_script();
+        let p2wsh = ScriptBuf::new_p2wsh(&witness_script.wscript_hash());
+        let (script_pubkey, redeem_script) = match self.script_type {
+            MultisigScriptType::Wsh => (p2wsh, None),
+            MultisigScriptType::ShWsh => (ScriptBuf::new_p2sh(&p2wsh.script_hash()), Some(p2wsh)),
+        };
+
+        Ok(DerivedMultisig {
+            witness_script,
+            redeem_script,
+            script_pubkey,
+            keys: derived.into_iter().collect(),
+        })
+    }
+
+    pub fn address(
+        &self,
+        network: Network,
+        change: bool,
+        index: u32,
+    ) -> Result<Address, MultisigError> {
+        let derived = self.derive(&Secp256k1::verification_only(), change, index)?;
+        Address::from_script(&derived.script_pubkey, network)
+            .map_err(|e| MultisigError::Derivation(e.to_string()))
+    }
+
+    /// Spending weight of one input, for coin selection.
+    pub fn satisfaction_weight(&self) -> Weight {
+        let script_len = 3 + 34 * self.keys.len() as u64;
+        let script_len_varint = if script_len < 0xfd { 1 } else { 3 };
+        // item count, the CHECKMULTISIG dummy, signatures, witness script
+        let witness = 1 + 1 + 73 * self.threshold as u64 + script_len_varint + script_len;
+        let script_sig = match self.script_type {
+            MultisigScriptType::Wsh => 0,
+            // scriptSig pushes the 34-byte p2wsh program
+            MultisigScriptType::ShWsh => 35 * 4,
+        };
+        Weight::from_wu(witness + script_sig)
+    }
+
+    /// Fill in witness scripts and the key origins of every cosigner for
+    /// inputs and change outputs, scanning the first `lookahead` addresses
+    /// of both chains. All inputs must belong to the wallet.
+    pub fn update_psbt(&self, psbt: &mut Psbt, lookahead: u32) -> Result<(), MultisigError> {
+        let secp = Secp256k1::verification_only();
+        let mut derived = Vec::new();
+        for change in [false, true] {
+            for index in 0..lookahead {
+                derived.push(self.derive(&secp, change, index)?);
+            }
+        }
+        let find = |script: &Script| derived.iter().find(|d| d.script_pubkey == *script);
+
+        for (i, input) in psbt.inputs.iter_mut().enumerate() {
+            let d = input
+                .witness_utxo
+                .as_ref()
+                .and_then(|utxo| find(&utxo.script_pubkey))
+                .ok_or(MultisigError::ForeignInput(i))?;
+            input.witness_script = Some(d.witness_script.clone());
+            input.redeem_script = d.redeem_script.clone();
+            input.bip32_derivation.extend(d.keys.clone());
+        }
+
+        for (txout, output) in psbt.unsigned_tx.output.iter().zip(psbt.outputs.iter_mut()) {
+            if let Some(d) = find(&txout.script_pubkey) {
+                output.witness_script = Some(d.witness_script.clone());
+                output.redeem_script = d.redeem_script.clone();
+                output.bip32_derivation.extend(d.keys.clone());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn descriptor_body(&self) -> String {
+        let keys: Vec<String> = self.keys.iter().map(|k| k.to_string()).collect();
+        let multi = format!(
+            "{}({},{})",
+            if self.sorted { "sortedmulti" } else { "multi" },
+            self.threshold,
+            keys.join(",")
+        );
+        match self.script_type {
+            MultisigScriptType::Wsh => format!("wsh({})", multi),
+            MultisigScriptType::ShWsh => format!("sh(wsh({}))", multi),
+        }
+    }
+
+    /// Descriptor template and key info for a Ledger wallet policy. The
+    /// device only accepts `/**` and `/<M;N>/*` after the key.
+    pub fn ledger_policy(&self) -> Result<(String, Vec<String>), MultisigError> {
+        let mut placeholders = Vec::with_capacity(self.keys.len());
+        for (i, key) in self.keys.iter().enumerate() {
+            let (r, c) = match (key.receive.as_slice(), key.change.as_slice()) {
+                ([r], [c]) if key.is_multipath() => (u32::from(*r), u32::from(*c)),
+                _ => return Err(MultisigError::Key(key.to_string())),
+            };
+            placeholders.push(if (r, c) == (0, 1) {
+                format!("@{}/**", i)
+            } else {
+                format!("@{}/<{};{}>/*", i, r, c)
+            });
+        }
+
+        let multi = format!(
+            "{}({},{})",
+            if self.sorted { "sortedmulti" } else { "multi" },
+            self.threshold,
+            placeholders.join(",")
+        );
+        let template = match self.script_type {
+            MultisigScriptType::Wsh => format!("wsh({})", multi),
+            MultisigScriptType::ShWsh => format!("sh(wsh({}))", multi),
+        };
+        let keys_info = self.keys.iter().map(|k| k.origin_string()).collect();
+
+        Ok((template, keys_info))
+    }
+}
+
+impl FromStr for MultisigDescriptor {
+    type Err = MultisigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let body = strip_checksum(s)?;
+        let unsupported = || MultisigError::Descriptor(format!("unsupported script {}", body));
+
+        let (script_type, inner) = if let Some(inner) = unwrap_fragment(body, "sh(wsh(", "))") {
+            (MultisigScriptType::ShWsh, inner)
+        } else if let Some(inner) = unwrap_fragment(body, "wsh(", ")") {
+            (MultisigScriptType::Wsh, inner)
+        } else {
+            return Err(unsupported());
+        };
+        let (sorted, args) = if let Some(args) = unwrap_fragment(inner, "sortedmulti(", ")") {
+            (true, args)
+        } else if let Some(args) = unwrap_fragment(inner, "multi(", ")") {
+            (false, args)
+        } else {
+            return Err(unsupported());
+        };
+
+        let mut args = args.split(',');
+        let threshold = args
+            .next()
+            .and_then(|k| k.parse::<usize>().ok())
+            .ok_or_else(|| MultisigError::Descriptor("missing threshold".to_string()))?;
+        let keys = args
+            .map(DescriptorKey::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if threshold == 0 || threshold > keys.len() || keys.len() > MAX_MULTISIG_KEYS {
+            return Err(MultisigError::Threshold(threshold, keys.len()));
+        }
+        if let Some(key) = keys
+            .iter()
+            .enumerate()
+            .find(|(i, k)| keys[..*i].iter().any(|other| other.xpub == k.xpub))
+            .map(|(_, k)| k)
+        {
+            return Err(MultisigError::Descriptor(format!(
+                "key {} is used twice",
+                key.xpub
+            )));
+        }
+
+        Ok(Self {
+            script_type,
+            threshold,
+            sorted,
+            keys,
+        })
+    }
+}
+
+impl fmt::Display for MultisigDescriptor {
+    /// Canonical descriptor with checksum, apostrophes for hardened steps.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let body = self.descriptor_body();
+        let checksum = descriptor_checksum(&body).map_err(|_| fmt::Error)?;
+        write!(f, "{}#{}", body, checksum)
+    }
+}
+
+pub(crate) fn unwrap_fragment<'a>(s: &'a str, prefix: &str, suffix: &str) -> Option<&'a str> {
+    s.strip_prefix(prefix)?.strip_suffix(suffix)
+}
+
+/// Descriptor without its `#checksum`, which is verified when present.
+pub(crate) fn strip_checksum(descriptor: &str) -> Result<&str, MultisigError> {
+    match descriptor.split_once('#') {
+        Some((body, actual)) => {
+            let expected = descriptor_checksum(body)?;
+            if expected != actual {
+                return Err(MultisigError::Checksum {
+                    expected,
+                    actual: actual.to_string(),
+                });
+            }
+            Ok(body)
+        }
+        None => Ok(descriptor),
+    }
+}
+
+fn polymod(c: u64, val: u64) -> u64 {
+    const GENERATOR: [u64; 5] = [
+        0xf5dee51989,
+        0xa9fdca3312,
+        0x1bab10e32d,
+        0x3706b1677a,
+        0x644d626ffd,
+    ];
+    let c0 = c >> 35;
+    let mut c = ((c & 0x7ffffffff) << 5) ^ val;
+    for (i, g) in GENERATOR.iter().enumerate() {
+        if c0 & (1 << i) != 0 {
+            c ^= g;
+        }
+    }
+    c
+}
+
+/// BIP-380 descriptor checksum.
+pub fn descriptor_checksum(descriptor: &str) -> Result<String, MultisigError> {
+    let mut c = 1u64;
+    let mut class = 0u64;
+    let mut class_count = 0;
+    for ch in descriptor.chars() {
+        let pos = INPUT_CHARSET
+            .find(ch)
+            .ok_or_else(|| MultisigError::Descriptor(format!("invalid character {:?}", ch)))?
+            as u64;
+        c = polymod(c, pos & 31);
+        class = class * 3 + (pos >> 5);
+        class_count += 1;
+        if class_count == 3 {
+            c = polymod(c, class);
+            class = 0;
+            class_count = 0;
+        }
+    }
+    if class_count > 0 {
+        c = polymod(c, class);
+    }
+    for _ in 0..8 {
+        c = polymod(c, 0);
+    }
+    c ^= 1;
+
+    Ok((0..8)
+        .map(|j| CHECKSUM_CHARSET[((c >> (5 * (7 - j))) & 31) as usize] as char)
+        .collect())
+}
+
+/// Threshold and keys, in script order, of a bare multisig script.
+pub(crate) fn parse_multisig_script(script: &Script) -> Option<(usize, Vec<bitcoin::PublicKey>)> {
+    let instructions = script.instructions().collect::<Result<Vec<_>, _>>().ok()?;
+    let (last, rest) = instructions.split_last()?;
+    if *last != Instruction::Op(OP_CHECKMULTISIG) {
+        return None;
+    }
+    let (first, rest) = rest.split_first()?;
+    let (n, keys) = rest.split_last()?;
+
+    let threshold = usize::try_from(first.script_num()?).ok()?;
+    let keys = keys
+        .iter()
+        .map(|i| bitcoin::PublicKey::from_slice(i.push_bytes()?.as_bytes()).ok())
+        .collect::<Option<Vec<_>>>()?;
+    if n.script_num()? != keys.len() as i64 {
+        return None;
+    }
+
+    Some((threshold, keys))
+}
+
+/// Combine the witness of every multisig input from its partial signatures,
+/// taking the first `threshold` signatures in script order. Inputs must
+/// carry their witness script, see [`MultisigDescriptor::update_psbt`].
+pub fn finalize_psbt(psbt: &mut Psbt) -> Result<(), MultisigError> {
+    for (i, input) in psbt.inputs.iter_mut().enumerate() {
+        finalize_input(i, input)?;
+    }
+
+    Ok(())
+}
+
+/// Finalize input `i` of a PSBT that spends a multisig witness script.
+pub(crate) fn finalize_input(i: usize, input: &mut psbt::Input) -> Result<(), MultisigError> {
+    let witness_script = input
+        .witness_script
+        .as_ref()
+        .ok_or(MultisigError::ForeignInput(i))?;
+    let (threshold, keys) =
+        parse_multisig_script(witness_script).ok_or(MultisigError::ForeignInput(i))?;
+
+    let sigs: Vec<_> = keys
+        .iter()
+        .filter_map(|pk| input.partial_sigs.get(pk))
+        .take(threshold)
+        .collect();
+    // Leave the input untouched so more signatures can still be added
+    if sigs.len() < threshold {
+        return Err(MultisigError::MissingSignatures {
+            input: i,
+            have: sigs.len(),
+            need: threshold,
+        });
+    }
+
+    let mut witness = Witness::new();
+    // OP_CHECKMULTISIG pops one extra item
+    witness.push(Vec::<u8>::new());
+    for sig in sigs {
+        witness.push(sig.serialize());
+    }
+    witness.push(witness_script.as_bytes());
+    input.witness_script = None;
+    input.final_script_witness = Some(witness);
+
+    if let Some(redeem_script) = input.redeem_script.take() {
+        let push = PushBytesBuf::try_from(redeem_script.into_bytes())
+            .map_err(|e| MultisigError::Derivation(e.to_string()))?;
+        input.final_script_sig = Some(Builder::new().push_slice(push).into_script());
+    }
+
+    input.partial_sigs.clear();
+    input.sighash_type = None;
+    input.bip32_derivation.clear();
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::bip32::Xpriv;
+    use bitcoin::hashes::Hash;
+    use bitcoin::secp256k1::Message;
+    use bitcoin::sighash::{EcdsaSighashType, SighashCache};
+    use bitcoin::transaction::Version;
+    use bitcoin::{absolute, Amount, OutPoint, Sequence, Transaction, TxIn, TxOut, Txid};
+
+    fn xprivs() -> Vec<Xpriv> {
+        (1..=3u8)
+            .map(|i| Xpriv::new_master(NetworkKind::Test, &[i; 32]).unwrap())
+            .collect()
+    }
+
+    /// 2-of-3 with the keys as account xpubs at m/48'/1'/0'/2'.
+    fn descriptor(sorted: bool) -> (MultisigDescriptor, Vec<Xpriv>) {
+        let secp = Secp256k1::new();
+        let path = DerivationPath::from_str("48'/1'/0'/2'").unwrap();
+        let keys: Vec<String> = xprivs()
+            .iter()
+            .map(|master| {
+                let account = master.derive_priv(&secp, &path).unwrap();
+                format!(
+                    "[{}/{}]{}/<0;1>/*",
+                    master.fingerprint(&secp),
+                    path,
+                    Xpub::from_priv(&secp, &account)
+                )
+            })
+            .collect();
+        let desc = format!(
+            "wsh({}(2,{}))",
+            if sorted { "sortedmulti" } else { "multi" },
+            keys.join(",")
+        );
+        (MultisigDescriptor::from_str(&desc).unwrap(), xprivs())
+    }
+
+    #[test]
+    fn test_descriptor_checksum() {
+        assert_eq!(descriptor_checksum("raw(deadbeef)").unwrap(), "89f8spxm");
+        assert!(MultisigDescriptor::from_str("raw(deadbeef)#89f8spxm").is_err());
+
+        let (desc, _) = descriptor(true);
+        let canonical = desc.to_string();
+        assert_eq!(MultisigDescriptor::from_str(&canonical).unwrap(), desc);
+
+        let mut corrupted = canonical.clone();
+        corrupted.pop();
+        corrupted.push('q');
+        assert!(matches!(
+            MultisigDescriptor::from_str(&corrupted),
+            Err(MultisigError::Checksum { .. }) | Err(MultisigError::Descriptor(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_descriptor_errors() {
+        let (desc, _) = descriptor(true);
+        let keys: Vec<String> = desc.keys.iter().map(|k| k.to_string()).collect();
+
+        let three_of_two = format!("wsh(sortedmulti(3,{},{}))", keys[0], keys[1]);
+        assert_eq!(
+            MultisigDescriptor::from_str(&three_of_two),
+            Err(MultisigError::Threshold(3, 2))
+        );
+        let unranged = format!(
+            "wsh(sortedmulti(1,{}))",
+            keys[0].trim_end_matches("/<0;1>/*")
+        );
+        assert!(matches!(
+            MultisigDescriptor::from_str(&unranged),
+            Err(MultisigError::Key(_))
+        ));
+        let hardened = keys[0].replace("/<0;1>/*", "/0'/*");
+        assert!(matches!(
+            MultisigDescriptor::from_str(&format!("wsh(multi(1,{}))", hardened)),
+            Err(MultisigError::Key(_))
+        ));
+        let duplicate = format!("wsh(sortedmulti(1,{},{}))", keys[0], keys[0]);
+        assert!(MultisigDescriptor::from_str(&duplicate).is_err());
+        assert!(MultisigDescriptor::from_str(&format!("tr({})", keys[0])).is_err());
+
+        assert!(desc.check_network(Network::Testnet).is_ok());
+        assert!(matches!(
+            desc.check_network(Network::Bitcoin),
+            Err(MultisigError::Network(..))
+        ));
+    }
+
+    #[test]
+    fn test_derive_addresses() {
+        let secp = Secp256k1::verification_only();
+        let (sorted, _) = descriptor(true);
+        let (unsorted, _) = descriptor(false);
+
+        let d = sorted.derive(&secp, false, 0).unwrap();
+        assert!(d.script_pubkey.is_p2wsh());
+        assert_eq!(d.keys.len(), 3);
+        let (threshold, keys) = parse_multisig_script(&d.witness_script).unwrap();
+        assert_eq!(threshold, 2);
+        assert!(keys.windows(2).all(|w| w[0].to_bytes() < w[1].to_bytes()));
+
+        // Every key path ends in the chain and index
+        for (_, path) in d.keys.values() {
+            assert_eq!(path.to_string(), "48'/1'/0'/2'/0/0");
+        }
+
+        assert_ne!(
+            sorted.address(Network::Testnet, false, 0).unwrap(),
+            sorted.address(Network::Testnet, true, 0).unwrap()
+        );
+        assert_ne!(
+            sorted.address(Network::Testnet, false, 0).unwrap(),
+            sorted.address(Network::Testnet, false, 1).unwrap()
+        );
+
+        let mut nested = sorted.clone();
+        nested.script_type = MultisigScriptType::ShWsh;
+        let n = nested.derive(&secp, false, 0).unwrap();
+        assert!(n.script_pubkey.is_p2sh());
+        assert_eq!(n.witness_script, d.witness_script);
+        assert_eq!(n.redeem_script, Some(d.script_pubkey.clone()));
+        assert!(nested.to_string().starts_with("sh(wsh(sortedmulti(2,"));
+
+        // Unsorted multi keeps descriptor order, so it only matches by chance
+        let (_, unsorted_keys) =
+            parse_multisig_script(&unsorted.derive(&secp, false, 0).unwrap().witness_script)
+                .unwrap();
+        let expected: Vec<_> = unsorted
+            .keys
+            .iter()
+            .map(|k| bitcoin::PublicKey::new(k.derive(&secp, false, 0).unwrap().0))
+            .collect();
+        assert_eq!(unsorted_keys, expected);
+    }
+
+    #[test]
+    fn test_sign_combine_finalize() {
+        let secp = Secp256k1::new();
+        let (desc, masters) = descriptor(true);
+        let funding = desc.derive(&secp, false, 3).unwrap();
+        let change = desc.derive(&secp, true, 0).unwrap();
+
+        let utxo = TxOut {
+            value: Amount::from_sat(100_000),
+            script_pubkey: funding.script_pubkey.clone(),
+        };
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::from_byte_array([0x11; 32]), 0),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                ..Default::default()
+            }],
+            output: vec![
+                TxOut {
+                    value: Amount::from_sat(60_000),
+                    script_pubkey: ScriptBuf::new_p2wsh(&bitcoin::WScriptHash::all_zeros()),
+                },
+                TxOut {
+                    value: Amount::from_sat(39_000),
+                    script_pubkey: change.script_pubkey.clone(),
+                },
+            ],
+        };
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        psbt.inputs[0].witness_utxo = Some(utxo.clone());
+        desc.update_psbt(&mut psbt, 5).unwrap();
+
+        assert_eq!(
+            psbt.inputs[0].witness_script,
+            Some(funding.witness_script.clone())
+        );
+        assert_eq!(psbt.inputs[0].bip32_derivation, funding.keys);
+        assert_eq!(psbt.outputs[1].bip32_derivation, change.keys);
+        assert!(psbt.outputs[0].bip32_derivation.is_empty());
+
+        let sighash = SighashCache::new(&psbt.unsigned_tx)
+            .p2wsh_signature_hash(
+                0,
+                &funding.witness_script,
+                utxo.value,
+                EcdsaSighashType::All,
+            )
+            .unwrap();
+        let msg = Message::from_digest(sighash.to_byte_array());
+        let sign = |master: &Xpriv| {
+            let mut copy = psbt.clone();
+            let (pubkey, (_, path)) = funding
+                .keys
+                .iter()
+                .find(|(_, (fp, _))| *fp == master.fingerprint(&secp))
+                .unwrap();
+            let key = master.derive_priv(&secp, path).unwrap().private_key;
+            let sig = bitcoin::ecdsa::Signature::sighash_all(secp.sign_ecdsa(&msg, &key));
+            copy.inputs[0]
+                .partial_sigs
+                .insert(bitcoin::PublicKey::new(*pubkey), sig);
+            copy
+        };
+
+        let mut single = sign(&masters[0]);
+        assert_eq!(
+            finalize_psbt(&mut single),
+            Err(MultisigError::MissingSignatures {
+                input: 0,
+                have: 1,
+                need: 2
+            })
+        );
+        assert_eq!(
+            single.inputs[0].witness_script,
+            Some(funding.witness_script.clone())
+        );
+
+        single.combine(sign(&masters[2])).unwrap();
+        finalize_psbt(&mut single).unwrap();
+
+        let witness = single.inputs[0].final_script_witness.clone().unwrap();
+        assert_eq!(witness.len(), 4);
+        assert!(witness.nth(0).unwrap().is_empty());
+        assert_eq!(witness.last().unwrap(), funding.witness_script.as_bytes());
+        assert!(single.inputs[0].final_script_sig.is_none());
+        assert_eq!(crate::btc::psbt::verify(&single), Ok(()));
+
+        let signed = single.extract_tx_unchecked_fee_rate();
+        assert_eq!(signed.input[0].witness, witness);
+    }
+
+    #[test]
+    fn test_ledger_policy() {
+        let (desc, _) = descriptor(true);
+        let (template, keys_info) = desc.ledger_policy().unwrap();
+        assert_eq!(template, "wsh(sortedmulti(2,@0/**,@1/**,@2/**))");
+        assert_eq!(keys_info.len(), 3);
+        assert!(keys_info[0].starts_with('['));
+        assert!(keys_info[0].contains("48'/1'/0'/2']tpub"));
+
+        let mut single_chain = desc.clone();
+        single_chain.keys[1].change = single_chain.keys[1].receive.clone();
+        assert!(single_chain.ledger_policy().is_err());
+    }
+}
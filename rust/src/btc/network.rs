@@ -0,0 +1,65 @@
+use bitcoin::{Network, NetworkKind};
+
+/// Bitcoin network of a chain config, from the first of its `chainIds`.
+pub fn network_from_chain_id(chain_id: u64) -> Option<Network> {
+    match chain_id {
+        0 => Some(Network::Bitcoin),
+        1 => Some(Network::Testnet),
+        2 => Some(Network::Regtest),
+        3 => Some(Network::Signet),
+        _ => None,
+    }
+}
+
+pub fn chain_id(network: Network) -> Option<u64> {
+    match network {
+        Network::Bitcoin => Some(0),
+        Network::Testnet => Some(1),
+        Network::Regtest => Some(2),
+        Network::Signet => Some(3),
+        _ => None,
+    }
+}
+
+/// BIP-44 coin type: 0 on mainnet, 1 on every test network.
+pub fn coin_type(network: impl Into<NetworkKind>) -> u32 {
+    match network.into() {
+        NetworkKind::Main => 0,
+        NetworkKind::Test => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::hashes::Hash;
+    use bitcoin::{Address, ScriptBuf, WPubkeyHash};
+
+    #[test]
+    fn test_chain_ids() {
+        for id in 0..4 {
+            assert_eq!(chain_id(network_from_chain_id(id).unwrap()), Some(id));
+        }
+        assert_eq!(network_from_chain_id(4), None);
+    }
+
+    #[test]
+    fn test_hrp_and_coin_type() {
+        let script = ScriptBuf::new_p2wpkh(&WPubkeyHash::from_byte_array([0; 20]));
+        let address = |id: u64| {
+            Address::from_script(&script, network_from_chain_id(id).unwrap())
+                .unwrap()
+                .to_string()
+        };
+        assert!(address(0).starts_with("bc1q"));
+        assert!(address(1).starts_with("tb1q"));
+        assert!(address(2).starts_with("bcrt1q"));
+        assert!(address(3).starts_with("tb1q"));
+
+        assert_eq!(coin_type(Network::Bitcoin), 0);
+        for network in [Network::Testnet, Network::Regtest, Network::Signet] {
+            assert_eq!(coin_type(network), 1);
+        }
+        assert_eq!(coin_type(NetworkKind::Test), 1);
+    }
+}
@@ -0,0 +1,512 @@
+use std::str::FromStr;
+
+use bitcoin::blockdata::script::{Builder, Instruction, PushBytesBuf};
+use bitcoin::hashes::Hash;
+use bitcoin::psbt::{self, Psbt};
+use bitcoin::secp256k1::{Message, Secp256k1, Verification, XOnlyPublicKey};
+use bitcoin::sighash::{Prevouts, SighashCache};
+use bitcoin::{ecdsa, taproot, PublicKey, Script, ScriptBuf, Transaction, TxOut, Txid, Witness};
+use thiserror::Error;
+
+use crate::btc::multisig::{self, MultisigError};
+
+const PSBT_MAGIC: &[u8] = b"psbt\xff";
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum PsbtError {
+    #[error("Failed to parse PSBT: {0}")]
+    Decode(String),
+
+    #[error("PSBT is for transaction {actual}, expected {expected}")]
+    Mismatch { expected: Txid, actual: Txid },
+
+    #[error("Input {0} has no UTXO information")]
+    MissingUtxo(usize),
+
+    #[error("Input {0} is not signed")]
+    Unsigned(usize),
+
+    #[error("Input {0} spends an unsupported script")]
+    Unsupported(usize),
+
+    #[error("Input {0} UTXO does not match the outpoint it spends")]
+    UtxoMismatch(usize),
+
+    #[error("Input {0} has an invalid or incomplete signature")]
+    InvalidSignature(usize),
+
+    #[error(transparent)]
+    Multisig(#[from] MultisigError),
+}
+
+/// Parse a PSBT given as binary, base64 or hex, the encodings wallets and
+/// signing devices use for files and QR codes.
+pub fn decode(data: &[u8]) -> Result<Psbt, PsbtError> {
+    if data.starts_with(PSBT_MAGIC) {
+        return Psbt::deserialize(data).map_err(|e| PsbtError::Decode(e.to_string()));
+    }
+
+    let text = std::str::from_utf8(data)
+        .map_err(|_| PsbtError::Decode("not binary, base64 or hex".to_string()))?
+        .trim();
+    match hex::decode(text) {
+        Ok(bytes) if bytes.starts_with(PSBT_MAGIC) => {
+            Psbt::deserialize(&bytes).map_err(|e| PsbtError::Decode(e.to_string()))
+        }
+        _ => Psbt::from_str(text).map_err(|e| PsbtError::Decode(e.to_string())),
+    }
+}
+
+pub fn encode_base64(psbt: &Psbt) -> String {
+    psbt.to_string()
+}
+
+/// Make sure a PSBT coming back from a signer spends and pays exactly what
+/// was sent out.
+pub fn check_same_tx(original: &Psbt, signed: &Psbt) -> Result<(), PsbtError> {
+    let expected = original.unsigned_tx.compute_txid();
+    let actual = signed.unsigned_tx.compute_txid();
+    if expected != actual {
+        return Err(PsbtError::Mismatch { expected, actual });
+    }
+    Ok(())
+}
+
+fn is_final(input: &psbt::Input) -> bool {
+    input.final_script_sig.is_some() || input.final_script_witness.is_some()
+}
+
+/// Finalize every input the signer left unfinalized. Inputs some signers
+/// finalize themselves are kept as they are.
+pub fn finalize(psbt: &mut Psbt) -> Result<(), PsbtError> {
+    for i in 0..psbt.inputs.len() {
+        if is_final(&psbt.inputs[i]) {
+            continue;
+        }
+        let spent = psbt
+            .spend_utxo(i)
+            .map_err(|_| PsbtError::MissingUtxo(i))?
+            .script_pubkey
+            .clone();
+        finalize_input(i, &mut psbt.inputs[i], &spent)?;
+    }
+    Ok(())
+}
+
+fn finalize_input(i: usize, input: &mut psbt::Input, spent: &Script) -> Result<(), PsbtError> {
+    let script = match &input.redeem_script {
+        Some(redeem_script) if spent.is_p2sh() => redeem_script.clone(),
+        _ => spent.to_owned(),
+    };
+
+    if script.is_p2wsh() {
+        multisig::finalize_input(i, input)?;
+    } else if script.is_p2tr() {
+        let sig = input.tap_key_sig.ok_or(PsbtError::Unsigned(i))?;
+        input.final_script_witness = Some(Witness::p2tr_key_spend(&sig));
+    } else if script.is_p2wpkh() || script.is_p2pkh() {
+        let (pubkey, sig) = input
+            .partial_sigs
+            .iter()
+            .find(|(pk, _)| {
+                ScriptBuf::new_p2pkh(&pk.pubkey_hash()) == script
+                    || pk
+                        .wpubkey_hash()
+                        .is_ok_and(|hash| ScriptBuf::new_p2wpkh(&hash) == script)
+            })
+            .map(|(pk, sig)| (*pk, *sig))
+            .ok_or(PsbtError::Unsigned(i))?;
+
+        if script.is_p2pkh() {
+            input.final_script_sig = Some(
+                Builder::new()
+                    .push_slice(sig.serialize())
+                    .push_key(&pubkey)
+                    .into_script(),
+            );
+        } else {
+            input.final_script_witness = Some(Witness::p2wpkh(&sig, &pubkey.inner));
+            if spent.is_p2sh() {
+                let push = PushBytesBuf::try_from(script.into_bytes())
+                    .map_err(|_| PsbtError::Unsupported(i))?;
+                input.final_script_sig = Some(Builder::new().push_slice(push).into_script());
+            }
+        }
+    } else {
+        return Err(PsbtError::Unsupported(i));
+    }
+
+    // BIP-174 finalizer drops everything but the UTXO and final scripts
+    input.partial_sigs.clear();
+    input.sighash_type = None;
+    input.redeem_script = None;
+    input.witness_script = None;
+    input.bip32_derivation.clear();
+    input.tap_key_sig = None;
+    input.tap_key_origins.clear();
+    input.tap_internal_key = None;
+    input.tap_merkle_root = None;
+
+    Ok(())
+}
+
+/// The output input `i` spends. A full previous transaction must be the
+/// one the outpoint refers to.
+fn spent_utxo(psbt: &Psbt, i: usize) -> Result<&TxOut, PsbtError> {
+    let input = &psbt.inputs[i];
+    let outpoint = psbt.unsigned_tx.input[i].previous_output;
+    if let Some(prev_tx) = &input.non_witness_utxo {
+        if prev_tx.compute_txid() != outpoint.txid {
+            return Err(PsbtError::UtxoMismatch(i));
+        }
+        let output = prev_tx
+            .output
+            .get(outpoint.vout as usize)
+            .ok_or(PsbtError::UtxoMismatch(i))?;
+        if input
+            .witness_utxo
+            .as_ref()
+            .is_some_and(|utxo| utxo != output)
+        {
+            return Err(PsbtError::UtxoMismatch(i));
+        }
+        return Ok(output);
+    }
+    input.witness_utxo.as_ref().ok_or(PsbtError::MissingUtxo(i))
+}
+
+/// Check every final input against the output it spends: the scripts must
+/// match and every signature must commit to this transaction. Run after
+/// [`finalize`], so inputs a signer finalized itself are checked as well.
+pub fn verify(psbt: &Psbt) -> Result<(), PsbtError> {
+    let secp = Secp256k1::verification_only();
+    let mut cache = SighashCache::new(&psbt.unsigned_tx);
+    for i in 0..psbt.inputs.len() {
+        verify_input(&secp, &mut cache, psbt, i)?;
+    }
+    Ok(())
+}
+
+fn pushes(script: &Script) -> Option<Vec<&[u8]>> {
+    script
+        .instructions()
+        .map(|instruction| match instruction.ok()? {
+            Instruction::PushBytes(push) => Some(push.as_bytes()),
+            Instruction::Op(_) => None,
+        })
+        .collect()
+}
+
+fn verify_ecdsa<C: Verification>(
+    secp: &Secp256k1<C>,
+    digest: [u8; 32],
+    sig: &ecdsa::Signature,
+    pubkey: &PublicKey,
+) -> bool {
+    let mut signature = sig.signature;
+    signature.normalize_s();
+    secp.verify_ecdsa(&Message::from_digest(digest), &signature, &pubkey.inner)
+        .is_ok()
+}
+
+fn verify_input<C: Verification>(
+    secp: &Secp256k1<C>,
+    cache: &mut SighashCache<&Transaction>,
+    psbt: &Psbt,
+    i: usize,
+) -> Result<(), PsbtError> {
+    let invalid = || PsbtError::InvalidSignature(i);
+    let input = &psbt.inputs[i];
+    let utxo = spent_utxo(psbt, i)?;
+    let script_sig = input.final_script_sig.clone().unwrap_or_default();
+    let witness = input.final_script_witness.clone().unwrap_or_default();
+
+    let script = if utxo.script_pubkey.is_p2sh() {
+        let pushed = pushes(&script_sig).unwrap_or_default();
+        let [redeem_script] = pushed.as_slice() else {
+            return Err(invalid());
+        };
+        let redeem_script = ScriptBuf::from_bytes(redeem_script.to_vec());
+        if ScriptBuf::new_p2sh(&redeem_script.script_hash()) != utxo.script_pubkey {
+            return Err(invalid());
+        }
+        redeem_script
+    } else {
+        if !script_sig.is_empty() && !utxo.script_pubkey.is_p2pkh() {
+            return Err(invalid());
+        }
+        utxo.script_pubkey.clone()
+    };
+
+    if script.is_p2pkh() {
+        let pushed = pushes(&script_sig).unwrap_or_default();
+        let [sig, pubkey] = pushed.as_slice() else {
+            return Err(invalid());
+        };
+        let sig = ecdsa::Signature::from_slice(sig).map_err(|_| invalid())?;
+        let pubkey = PublicKey::from_slice(pubkey).map_err(|_| invalid())?;
+        if ScriptBuf::new_p2pkh(&pubkey.pubkey_hash()) != script || !witness.is_empty() {
+            return Err(invalid());
+        }
+        let sighash = cache
+            .legacy_signature_hash(i, &script, sig.sighash_type.to_u32())
+            .map_err(|_| invalid())?;
+        if !verify_ecdsa(secp, sighash.to_byte_array(), &sig, &pubkey) {
+            return Err(invalid());
+        }
+    } else if script.is_p2wpkh() {
+        if witness.len() != 2 {
+            return Err(invalid());
+        }
+        let sig = ecdsa::Signature::from_slice(&witness[0]).map_err(|_| invalid())?;
+        let pubkey = PublicKey::from_slice(&witness[1]).map_err(|_| invalid())?;
+        let matches = pubkey
+            .wpubkey_hash()
+            .is_ok_and(|hash| ScriptBuf::new_p2wpkh(&hash) == script);
+        if !matches {
+            return Err(invalid());
+        }
+        let sighash = cache
+            .p2wpkh_signature_hash(i, &script, utxo.value, sig.sighash_type)
+            .map_err(|_| invalid())?;
+        if !verify_ecdsa(secp, sighash.to_byte_array(), &sig, &pubkey) {
+            return Err(invalid());
+        }
+    } else if script.is_p2wsh() {
+        let witness_script = ScriptBuf::from_bytes(witness.last().ok_or_else(invalid)?.to_vec());
+        if ScriptBuf::new_p2wsh(&witness_script.wscript_hash()) != script {
+            return Err(invalid());
+        }
+        let (threshold, keys) =
+            multisig::parse_multisig_script(&witness_script).ok_or(PsbtError::Unsupported(i))?;
+        // Dummy element, `threshold` signatures, witness script
+        let items: Vec<&[u8]> = witness.iter().collect();
+        if items.len() != threshold + 2 || !items[0].is_empty() {
+            return Err(invalid());
+        }
+
+        // OP_CHECKMULTISIG matches signatures to keys in script order
+        let mut keys = keys.iter();
+        for sig in &items[1..=threshold] {
+            let sig = ecdsa::Signature::from_slice(sig).map_err(|_| invalid())?;
+            let sighash = cache
+                .p2wsh_signature_hash(i, &witness_script, utxo.value, sig.sighash_type)
+                .map_err(|_| invalid())?;
+            let digest = sighash.to_byte_array();
+            if !keys.any(|pubkey| verify_ecdsa(secp, digest, &sig, pubkey)) {
+                return Err(invalid());
+            }
+        }
+    } else if script.is_p2tr() {
+        if witness.len() != 1 {
+            return Err(invalid());
+        }
+        let sig = taproot::Signature::from_slice(&witness[0]).map_err(|_| invalid())?;
+        let output_key =
+            XOnlyPublicKey::from_slice(&script.as_bytes()[2..]).map_err(|_| invalid())?;
+        let prevouts = (0..psbt.inputs.len())
+            .map(|j| spent_utxo(psbt, j).cloned())
+            .collect::<Result<Vec<_>, _>>()?;
+        let sighash = cache
+            .taproot_key_spend_signature_hash(i, &Prevouts::All(&prevouts), sig.sighash_type)
+            .map_err(|_| invalid())?;
+        let msg = Message::from_digest(sighash.to_byte_array());
+        if secp
+            .verify_schnorr(&sig.signature, &msg, &output_key)
+            .is_err()
+        {
+            return Err(invalid());
+        }
+    } else {
+        return Err(PsbtError::Unsupported(i));
+    }
+
+    Ok(())
+}
+
+/// The signed transaction, once every input is final.
+pub fn extract(psbt: Psbt) -> Result<Transaction, PsbtError> {
+    if let Some(i) = psbt.inputs.iter().position(|input| !is_final(input)) {
+        return Err(PsbtError::Unsigned(i));
+    }
+    Ok(psbt.extract_tx_unchecked_fee_rate())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::key::TapTweak;
+    use bitcoin::secp256k1::SecretKey;
+    use bitcoin::sighash::{EcdsaSighashType, TapSighashType};
+    use bitcoin::transaction::Version;
+    use bitcoin::{absolute, Amount, OutPoint, Sequence, TxIn, WPubkeyHash};
+
+    fn key() -> (SecretKey, PublicKey) {
+        let secp = Secp256k1::new();
+        let sk = SecretKey::from_slice(&[7u8; 32]).unwrap();
+        (sk, PublicKey::new(sk.public_key(&secp)))
+    }
+
+    fn unsigned(script_pubkey: ScriptBuf) -> Psbt {
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::from_byte_array([1; 32]), 0),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(90_000),
+                script_pubkey: ScriptBuf::new_p2wpkh(&WPubkeyHash::from_byte_array([2; 20])),
+            }],
+        };
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        psbt.inputs[0].witness_utxo = Some(TxOut {
+            value: Amount::from_sat(100_000),
+            script_pubkey,
+        });
+        psbt
+    }
+
+    fn sign(psbt: &mut Psbt) {
+        let secp = Secp256k1::new();
+        let (sk, pk) = key();
+        let sig = secp.sign_ecdsa(&Message::from_digest([3; 32]), &sk);
+        psbt.inputs[0]
+            .partial_sigs
+            .insert(pk, ecdsa::Signature::sighash_all(sig));
+    }
+
+    #[test]
+    fn test_decode_encodings() {
+        let (_, pk) = key();
+        let psbt = unsigned(ScriptBuf::new_p2wpkh(&pk.wpubkey_hash().unwrap()));
+        let binary = psbt.serialize();
+        let base64 = encode_base64(&psbt);
+        assert!(base64.starts_with("cHNidP"));
+
+        assert_eq!(decode(&binary).unwrap(), psbt);
+        assert_eq!(decode(format!("{}\n", base64).as_bytes()).unwrap(), psbt);
+        assert_eq!(decode(hex::encode(&binary).as_bytes()).unwrap(), psbt);
+        assert!(matches!(decode(b"psbt"), Err(PsbtError::Decode(_))));
+        assert!(matches!(decode(&[0xff, 0xfe]), Err(PsbtError::Decode(_))));
+    }
+
+    #[test]
+    fn test_check_same_tx() {
+        let (_, pk) = key();
+        let original = unsigned(ScriptBuf::new_p2wpkh(&pk.wpubkey_hash().unwrap()));
+        let mut signed = original.clone();
+        sign(&mut signed);
+        assert!(check_same_tx(&original, &signed).is_ok());
+
+        signed.unsigned_tx.output[0].value = Amount::from_sat(99_000);
+        assert!(matches!(
+            check_same_tx(&original, &signed),
+            Err(PsbtError::Mismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_finalize_single_key() {
+        let (_, pk) = key();
+        let p2wpkh = ScriptBuf::new_p2wpkh(&pk.wpubkey_hash().unwrap());
+
+        let mut psbt = unsigned(p2wpkh.clone());
+        assert_eq!(finalize(&mut psbt), Err(PsbtError::Unsigned(0)));
+        sign(&mut psbt);
+        finalize(&mut psbt).unwrap();
+        assert!(psbt.inputs[0].partial_sigs.is_empty());
+        let tx = extract(psbt).unwrap();
+        assert_eq!(tx.input[0].witness.len(), 2);
+        assert!(tx.input[0].script_sig.is_empty());
+
+        let mut psbt = unsigned(ScriptBuf::new_p2sh(&p2wpkh.script_hash()));
+        psbt.inputs[0].redeem_script = Some(p2wpkh.clone());
+        sign(&mut psbt);
+        finalize(&mut psbt).unwrap();
+        let tx = extract(psbt).unwrap();
+        assert_eq!(tx.input[0].witness.len(), 2);
+        assert_eq!(&tx.input[0].script_sig.as_bytes()[1..], p2wpkh.as_bytes());
+
+        let mut psbt = unsigned(ScriptBuf::new_p2pkh(&pk.pubkey_hash()));
+        sign(&mut psbt);
+        finalize(&mut psbt).unwrap();
+        let tx = extract(psbt).unwrap();
+        assert!(tx.input[0].witness.is_empty());
+        assert_eq!(tx.input[0].script_sig.instructions().count(), 2);
+    }
+
+    #[test]
+    fn test_finalized_inputs_are_kept() {
+        let mut psbt = unsigned(ScriptBuf::new_op_return([0u8; 4]));
+        assert_eq!(finalize(&mut psbt), Err(PsbtError::Unsupported(0)));
+        assert_eq!(extract(psbt.clone()), Err(PsbtError::Unsigned(0)));
+
+        let witness = Witness::from_slice(&[vec![1u8; 64]]);
+        psbt.inputs[0].final_script_witness = Some(witness.clone());
+        finalize(&mut psbt).unwrap();
+        assert_eq!(extract(psbt).unwrap().input[0].witness, witness);
+    }
+
+    #[test]
+    fn test_verify_signatures() {
+        let secp = Secp256k1::new();
+        let (sk, pk) = key();
+        let p2wpkh = ScriptBuf::new_p2wpkh(&pk.wpubkey_hash().unwrap());
+
+        let mut psbt = unsigned(p2wpkh.clone());
+        sign(&mut psbt);
+        finalize(&mut psbt).unwrap();
+        assert_eq!(verify(&psbt), Err(PsbtError::InvalidSignature(0)));
+
+        let mut psbt = unsigned(p2wpkh.clone());
+        let sighash = SighashCache::new(&psbt.unsigned_tx)
+            .p2wpkh_signature_hash(0, &p2wpkh, Amount::from_sat(100_000), EcdsaSighashType::All)
+            .unwrap();
+        let sig = secp.sign_ecdsa(&Message::from_digest(sighash.to_byte_array()), &sk);
+        psbt.inputs[0]
+            .partial_sigs
+            .insert(pk, ecdsa::Signature::sighash_all(sig));
+        let mut wrong_amount = psbt.clone();
+        finalize(&mut psbt).unwrap();
+        assert_eq!(verify(&psbt), Ok(()));
+
+        // The signature commits to the amount the signer was shown
+        wrong_amount.inputs[0].witness_utxo.as_mut().unwrap().value = Amount::from_sat(90_000);
+        finalize(&mut wrong_amount).unwrap();
+        assert_eq!(verify(&wrong_amount), Err(PsbtError::InvalidSignature(0)));
+
+        let mut psbt = unsigned(ScriptBuf::new_p2pkh(&pk.pubkey_hash()));
+        psbt.inputs[0].non_witness_utxo = Some(Transaction {
+            version: Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![],
+            output: vec![],
+        });
+        assert_eq!(verify(&psbt), Err(PsbtError::UtxoMismatch(0)));
+
+        let keypair = bitcoin::key::Keypair::from_secret_key(&secp, &sk);
+        let (internal_key, _) = keypair.x_only_public_key();
+        let p2tr = ScriptBuf::new_p2tr(&secp, internal_key, None);
+        let mut psbt = unsigned(p2tr.clone());
+        let prevouts = [psbt.inputs[0].witness_utxo.clone().unwrap()];
+        let sighash = SighashCache::new(&psbt.unsigned_tx)
+            .taproot_key_spend_signature_hash(0, &Prevouts::All(&prevouts), TapSighashType::Default)
+            .unwrap();
+        let tweaked = keypair.tap_tweak(&secp, None).to_keypair();
+        let signature =
+            secp.sign_schnorr_no_aux_rand(&Message::from_digest(sighash.to_byte_array()), &tweaked);
+        psbt.inputs[0].tap_key_sig = Some(taproot::Signature {
+            signature,
+            sighash_type: TapSighashType::Default,
+        });
+        finalize(&mut psbt).unwrap();
+        assert_eq!(verify(&psbt), Ok(()));
+
+        // An input the signer finalized itself is checked too
+        psbt.inputs[0].final_script_witness = Some(Witness::from_slice(&[vec![1u8; 64]]));
+        assert_eq!(verify(&psbt), Err(PsbtError::InvalidSignature(0)));
+    }
+}
@@ -0,0 +1,450 @@
+use std::str::FromStr;
+
+use bitcoin::absolute::LockTime;
+use bitcoin::transaction::Version;
+use bitcoin::{
+    Amount, FeeRate, OutPoint, Script, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Weight,
+    Witness,
+};
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::btc::coin_selection::{estimate_weight, fee, Utxo};
+
+/// Bitcoin Core's default `-incrementalrelayfee`, the minimum a replacement
+/// has to add on top of the fee it replaces (BIP-125 rule 4).
+pub const INCREMENTAL_RELAY_FEE: FeeRate = FeeRate::from_sat_per_kwu(250);
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum RbfError {
+    #[error("Cannot parse pending transaction: {0}")]
+    Parse(String),
+
+    #[error("Expected {expected} spent outputs, got {got}")]
+    SpentOutputsMismatch { expected: usize, got: usize },
+
+    #[error("Fee rate must be above the current {0} sat/vB")]
+    FeeRateTooLow(u64),
+
+    #[error("Transaction has no change output to take the fee from")]
+    NoChangeOutput,
+
+    #[error("Change cannot cover the fee bump: needed {needed}, available {available}")]
+    InsufficientChange { needed: Amount, available: Amount },
+}
+
+/// A broadcast transaction waiting for confirmation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingTx {
+    pub txid: Option<Txid>,
+    pub inputs: Vec<(OutPoint, Sequence)>,
+    pub outputs: Vec<TxOut>,
+    /// Known only when the decoded JSON carries it
+    pub fee: Option<Amount>,
+    /// Exact for raw transactions, from `weight` or `vsize` in JSON. Gives
+    /// the fee rate being replaced, estimated from the inputs when unknown.
+    pub weight: Option<Weight>,
+}
+
+impl PendingTx {
+    /// BIP-125 opt-in: any input with a sequence below 0xfffffffe.
+    pub fn signals_rbf(&self) -> bool {
+        self.inputs.iter().any(|(_, sequence)| sequence.is_rbf())
+    }
+
+    /// `other` is a different transaction spending one of our inputs, so
+    /// once it is accepted this one can no longer confirm.
+    pub fn is_replaced_by(&self, other: &PendingTx) -> bool {
+        let different = match (self.txid, other.txid) {
+            (Some(a), Some(b)) => a != b,
+            _ => self.outputs != other.outputs,
+        };
+        different
+            && self
+                .inputs
+                .iter()
+                .any(|(outpoint, _)| other.inputs.iter().any(|(o, _)| o == outpoint))
+    }
+
+    /// Accepts a raw transaction hex or the decoded JSON kept in history
+    /// (`vin`/`vout` as returned by `getrawtransaction`).
+    pub fn parse(btc: &str) -> Result<Self, RbfError> {
+        if let Ok(bytes) = hex::decode(btc.trim()) {
+            let tx: Transaction = bitcoin::consensus::encode::deserialize(&bytes)
+                .map_err(|e| RbfError::Parse(e.to_string()))?;
+            return Ok(PendingTx {
+                txid: Some(tx.compute_txid()),
+                fee: None,
+                weight: Some(tx.weight()),
+                inputs: tx
+                    .input
+                    .iter()
+                    .map(|i| (i.previous_output, i.sequence))
+                    .collect(),
+                outputs: tx.output,
+            });
+        }
+
+        let json: Value = serde_json::from_str(btc).map_err(|e| RbfError::Parse(e.to_string()))?;
+        let invalid = |field: &str| RbfError::Parse(format!("missing or invalid {}", field));
+
+        let txid = json["txid"].as_str().and_then(|t| Txid::from_str(t).ok());
+        let inputs = json["vin"]
+            .as_array()
+            .ok_or_else(|| invalid("vin"))?
+            .iter()
+            .map(|vin| {
+                let txid = vin["txid"]
+                    .as_str()
+                    .and_then(|t| Txid::from_str(t).ok())
+                    .ok_or_else(|| invalid("vin.txid"))?;
+                let vout = vin["vout"].as_u64().ok_or_else(|| invalid("vin.vout"))?;
+                let sequence = vin["sequence"].as_u64().unwrap_or(u32::MAX as u64);
+                Ok((OutPoint::new(txid, vout as u32), Sequence(sequence as u32)))
+            })
+            .collect::<Result<Vec<_>, RbfError>>()?;
+        let outputs = json["vout"]
+            .as_array()
+            .ok_or_else(|| invalid("vout"))?
+            .iter()
+            .map(|vout| {
+                let script_pubkey = vout["scriptPubKey"]["hex"]
+                    .as_str()
+                    .and_then(|h| ScriptBuf::from_hex(h).ok())
+                    .ok_or_else(|| invalid("vout.scriptPubKey"))?;
+                let value = parse_value(&vout["value"]).ok_or_else(|| invalid("vout.value"))?;
+                Ok(TxOut {
+                    value,
+                    script_pubkey,
+                })
+            })
+            .collect::<Result<Vec<_>, RbfError>>()?;
+
+        let weight = json["weight"]
+            .as_u64()
+            .map(Weight::from_wu)
+            .or_else(|| json["vsize"].as_u64().map(Weight::from_vb_unchecked));
+
+        Ok(PendingTx {
+            txid,
+            inputs,
+            outputs,
+            fee: parse_value(&json["fee"]),
+            weight,
+        })
+    }
+}
+
+/// Integers are satoshis, decimals are BTC, as the history view reads them.
+pub(crate) fn parse_value(value: &Value) -> Option<Amount> {
+    match value {
+        Value::Number(n) if n.is_u64() => n.as_u64().map(Amount::from_sat),
+        Value::Number(n) => n.as_f64().and_then(|btc| Amount::from_btc(btc).ok()),
+        Value::String(s) => s
+            .parse::<u64>()
+            .ok()
+            .map(Amount::from_sat)
+            .or_else(|| s.parse::<f64>().ok().and_then(|b| Amount::from_btc(b).ok())),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeeBump {
+    /// Unsigned replacement, same inputs in the same order.
+    pub tx: Transaction,
+    pub spent: Vec<Utxo>,
+    pub fee: Amount,
+    pub weight: Weight,
+}
+
+impl FeeBump {
+    pub fn witness_utxos(&self) -> Vec<TxOut> {
+        self.spent.iter().map(|u| u.txout.clone()).collect()
+    }
+}
+
+/// Build a BIP-125 replacement of `pending` paying at least `fee_rate`.
+/// `witness_utxos` are the outputs spent by `pending`, in input order. The
+/// extra fee comes out of the output paying `change_script`, which is
+/// dropped when what is left would be dust.
+pub fn bump_fee(
+    pending: &PendingTx,
+    witness_utxos: &[TxOut],
+    change_script: &Script,
+    fee_rate: FeeRate,
+) -> Result<FeeBump, RbfError> {
+    if pending.inputs.len() != witness_utxos.len() {
+        return Err(RbfError::SpentOutputsMismatch {
+            expected: pending.inputs.len(),
+            got: witness_utxos.len(),
+        });
+    }
+    let spent = pending
+        .inputs
+        .iter()
+        .zip(witness_utxos)
+        .map(|((outpoint, _), txout)| {
+            Utxo::new(*outpoint, txout.clone()).map_err(|e| RbfError::Parse(e.to_string()))
+        })
+        .collect::<Result<Vec<_>, RbfError>>()?;
+
+    let input_value: Amount = witness_utxos.iter().map(|o| o.value).sum();
+    let output_value: Amount = pending.outputs.iter().map(|o| o.value).sum();
+    let original_fee = input_value
+        .checked_sub(output_value)
+        .ok_or_else(|| RbfError::Parse("outputs exceed inputs".to_string()))?
+        .to_sat();
+
+    let original_weight = estimate_weight(&spent, &pending.outputs);
+    let current_weight = pending.weight.unwrap_or(original_weight);
+    if fee(fee_rate, current_weight) <= original_fee {
+        return Err(RbfError::FeeRateTooLow(
+            original_fee / current_weight.to_vbytes_ceil().max(1),
+        ));
+    }
+    let required = |weight: Weight| {
+        fee(fee_rate, weight).max(original_fee + fee(INCREMENTAL_RELAY_FEE, weight))
+    };
+
+    let change_index = pending
+        .outputs
+        .iter()
+        .position(|o| o.script_pubkey.as_script() == change_script)
+        .ok_or(RbfError::NoChangeOutput)?;
+    let change_value = pending.outputs[change_index].value.to_sat();
+
+    let mut outputs = pending.outputs.clone();
+    let mut weight = original_weight;
+    let needed = required(weight) - original_fee;
+    let remaining = change_value.saturating_sub(needed);
+
+    if change_value > needed && remaining >= change_script.minimal_non_dust().to_sat() {
+        outputs[change_index].value = Amount::from_sat(remaining);
+    } else {
+        outputs.remove(change_index);
+        if outputs.is_empty() {
+            return Err(RbfError::NoChangeOutput);
+        }
+        weight = estimate_weight(&spent, &outputs);
+        let needed = required(weight) - original_fee;
+        if change_value < needed {
+            return Err(RbfError::InsufficientChange {
+                needed: Amount::from_sat(needed),
+                available: Amount::from_sat(change_value),
+            });
+        }
+    }
+
+    let new_output_value: Amount = outputs.iter().map(|o| o.value).sum();
+    let tx = Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: pending
+            .inputs
+            .iter()
+            .map(|(outpoint, _)| TxIn {
+                previous_output: *outpoint,
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: Witness::new(),
+            })
+            .collect(),
+        output: outputs,
+    };
+
+    Ok(FeeBump {
+        tx,
+        spent,
+        fee: input_value - new_output_value,
+        weight,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::hashes::Hash;
+    use bitcoin::WPubkeyHash;
+
+    fn p2wpkh(tag: u8) -> ScriptBuf {
+        ScriptBuf::new_p2wpkh(&WPubkeyHash::from_byte_array([tag; 20]))
+    }
+
+    fn txout(sat: u64, tag: u8) -> TxOut {
+        TxOut {
+            value: Amount::from_sat(sat),
+            script_pubkey: p2wpkh(tag),
+        }
+    }
+
+    /// 2 inputs of 50k, pays 60k and 39_791 change: 209 sat at 1 sat/vB.
+    fn pending() -> (PendingTx, Vec<TxOut>) {
+        let txid = Txid::from_byte_array([0x11; 32]);
+        let pending = PendingTx {
+            txid: None,
+            inputs: vec![
+                (OutPoint::new(txid, 0), Sequence::ENABLE_RBF_NO_LOCKTIME),
+                (OutPoint::new(txid, 1), Sequence::ENABLE_RBF_NO_LOCKTIME),
+            ],
+            outputs: vec![txout(60_000, 0xbb), txout(39_791, 0xcc)],
+            fee: None,
+            weight: None,
+        };
+        (pending, vec![txout(50_000, 0xaa), txout(50_000, 0xaa)])
+    }
+
+    #[test]
+    fn test_parse_history_json() {
+        let json = r#"{
+            "txid": "1111111111111111111111111111111111111111111111111111111111111111",
+            "vsize": 141,
+            "fee": 0.00000209,
+            "vin": [{"txid": "2222222222222222222222222222222222222222222222222222222222222222", "vout": 3, "sequence": 4294967293}],
+            "vout": [
+                {"n": 0, "value": 0.0006, "scriptPubKey": {"hex": "0014bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb"}},
+                {"n": 1, "value": 39791, "scriptPubKey": {"hex": "0014cccccccccccccccccccccccccccccccccccccccc"}}
+            ]
+        }"#;
+        let pending = PendingTx::parse(json).unwrap();
+
+        assert!(pending.txid.is_some());
+        assert_eq!(pending.fee, Some(Amount::from_sat(209)));
+        assert_eq!(pending.weight, Some(Weight::from_wu(564)));
+        assert_eq!(pending.inputs[0].0.vout, 3);
+        assert!(pending.signals_rbf());
+        assert_eq!(pending.outputs[0], txout(60_000, 0xbb));
+        assert_eq!(pending.outputs[1], txout(39_791, 0xcc));
+
+        let tx = bump_fee(
+            &pending,
+            &[txout(100_000, 0xaa)],
+            &p2wpkh(0xcc),
+            FeeRate::from_sat_per_vb(10).unwrap(),
+        )
+        .unwrap()
+        .tx;
+        let raw = hex::encode(bitcoin::consensus::encode::serialize(&tx));
+        assert_eq!(PendingTx::parse(&raw).unwrap().outputs, tx.output);
+
+        assert!(PendingTx::parse(r#"{"vin": []}"#).is_err());
+    }
+
+    #[test]
+    fn test_bump_fee_reduces_change() {
+        let (pending, spent) = pending();
+        let bump = bump_fee(
+            &pending,
+            &spent,
+            &p2wpkh(0xcc),
+            FeeRate::from_sat_per_vb(5).unwrap(),
+        )
+        .unwrap();
+
+        assert_eq!(bump.weight.to_vbytes_ceil(), 209);
+        assert_eq!(bump.fee, Amount::from_sat(5 * 209));
+        assert_eq!(bump.tx.input.len(), 2);
+        assert_eq!(bump.tx.input[1].previous_output, pending.inputs[1].0);
+        assert!(bump.tx.input.iter().all(|i| i.sequence.is_rbf()));
+        assert_eq!(bump.tx.output[0], txout(60_000, 0xbb));
+        assert_eq!(
+            bump.tx.output[1].value,
+            Amount::from_sat(100_000 - 60_000 - 5 * 209)
+        );
+    }
+
+    #[test]
+    fn test_bump_fee_pays_incremental_relay_fee() {
+        let (pending, spent) = pending();
+        // 1.5 sat/vB is above the current rate but below current + 1 sat/vB
+        let bump = bump_fee(
+            &pending,
+            &spent,
+            &p2wpkh(0xcc),
+            FeeRate::from_sat_per_kwu(375),
+        )
+        .unwrap();
+        assert_eq!(bump.fee, Amount::from_sat(209 + 209));
+    }
+
+    #[test]
+    fn test_bump_fee_drops_dust_change() {
+        let (mut pending, spent) = pending();
+        pending.outputs = vec![txout(99_400, 0xbb), txout(391, 0xcc)];
+
+        let bump = bump_fee(
+            &pending,
+            &spent,
+            &p2wpkh(0xcc),
+            FeeRate::from_sat_per_vb(2).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(bump.tx.output, vec![txout(99_400, 0xbb)]);
+        assert_eq!(bump.fee, Amount::from_sat(600));
+    }
+
+    #[test]
+    fn test_bump_fee_errors() {
+        let (pending, spent) = pending();
+        let rate = FeeRate::from_sat_per_vb(5).unwrap();
+
+        assert_eq!(
+            bump_fee(
+                &pending,
+                &spent,
+                &p2wpkh(0xcc),
+                FeeRate::from_sat_per_vb(1).unwrap()
+            ),
+            Err(RbfError::FeeRateTooLow(1))
+        );
+        assert_eq!(
+            bump_fee(&pending, &spent, &p2wpkh(0xdd), rate),
+            Err(RbfError::NoChangeOutput)
+        );
+        assert!(matches!(
+            bump_fee(&pending, &spent[..1], &p2wpkh(0xcc), rate),
+            Err(RbfError::SpentOutputsMismatch { .. })
+        ));
+        assert!(matches!(
+            bump_fee(
+                &pending,
+                &spent,
+                &p2wpkh(0xcc),
+                FeeRate::from_sat_per_vb(500).unwrap()
+            ),
+            Err(RbfError::InsufficientChange { .. })
+        ));
+
+        // The known weight gives the rate actually paid, 209 sat over 100 vB
+        let two = FeeRate::from_sat_per_vb(2).unwrap();
+        assert!(bump_fee(&pending, &spent, &p2wpkh(0xcc), two).is_ok());
+        let mut measured = pending.clone();
+        measured.weight = Some(Weight::from_vb_unchecked(100));
+        assert_eq!(
+            bump_fee(&measured, &spent, &p2wpkh(0xcc), two),
+            Err(RbfError::FeeRateTooLow(2))
+        );
+    }
+
+    #[test]
+    fn test_is_replaced_by() {
+        let (pending, spent) = pending();
+        let bump = bump_fee(
+            &pending,
+            &spent,
+            &p2wpkh(0xcc),
+            FeeRate::from_sat_per_vb(5).unwrap(),
+        )
+        .unwrap();
+        let raw = hex::encode(bitcoin::consensus::encode::serialize(&bump.tx));
+        let replacement = PendingTx::parse(&raw).unwrap();
+
+        assert!(pending.is_replaced_by(&replacement));
+        assert!(!pending.is_replaced_by(&pending));
+
+        let mut unrelated = replacement.clone();
+        unrelated.inputs[0].0.vout = 7;
+        unrelated.inputs[1].0.vout = 8;
+        assert!(!pending.is_replaced_by(&unrelated));
+    }
+}
@@ -0,0 +1,583 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use bitcoin::bech32::primitives::decode::CheckedHrpstring;
+use bitcoin::bech32::{Bech32m, ByteIterExt, Fe32, Fe32IterExt, Hrp};
+use bitcoin::bip32::{self, ChildNumber, Xpriv};
+use bitcoin::blockdata::script::Instruction;
+use bitcoin::consensus::encode::serialize;
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use bitcoin::key::{Keypair, TapTweak, TweakedPublicKey};
+use bitcoin::secp256k1::{
+    self, Parity, PublicKey, Scalar, Secp256k1, SecretKey, Signing, Verification, XOnlyPublicKey,
+};
+use bitcoin::{NetworkKind, OutPoint, Script, ScriptBuf, Transaction, TxIn, TxOut};
+use thiserror::Error;
+
+use crate::btc::network::coin_type;
+
+const INPUTS_TAG: &[u8] = b"BIP0352/Inputs";
+const SHARED_SECRET_TAG: &[u8] = b"BIP0352/SharedSecret";
+
+/// BIP-341 NUMS point, the internal key of script-only taproot outputs.
+const NUMS_H: [u8; 32] = [
+    0x50, 0x92, 0x9b, 0x74, 0xc1, 0xa0, 0x49, 0x54, 0xb7, 0x8b, 0x4b, 0x60, 0x35, 0xe9, 0x7a, 0x5e,
+    0x07, 0x8a, 0x5a, 0x0f, 0x28, 0xec, 0x96, 0xd5, 0x47, 0xbf, 0xee, 0x9a, 0xce, 0x80, 0x3a, 0xc0,
+];
+
+const PAYLOAD_LEN: usize = 66;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum SilentPaymentError {
+    #[error("Invalid silent payment address: {0}")]
+    Address(String),
+
+    #[error("Silent payment address is for {0:?} networks")]
+    Network(NetworkKind),
+
+    #[error("Transaction has no inputs eligible for silent payments")]
+    NoEligibleInputs,
+
+    #[error("Expected {expected} prevouts, got {actual}")]
+    Prevouts { expected: usize, actual: usize },
+
+    #[error("Invalid key: {0}")]
+    Key(#[from] secp256k1::Error),
+
+    #[error("Key derivation failed: {0}")]
+    Derivation(#[from] bip32::Error),
+}
+
+/// `sp1...` address: the receiver's scan and spend public keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SilentPaymentAddress {
+    pub scan: PublicKey,
+    pub spend: PublicKey,
+    pub network: NetworkKind,
+}
+
+impl SilentPaymentAddress {
+    pub fn require_network(self, network: NetworkKind) -> Result<Self, SilentPaymentError> {
+        if self.network != network {
+            return Err(SilentPaymentError::Network(self.network));
+        }
+        Ok(self)
+    }
+}
+
+fn hrp(network: NetworkKind) -> Hrp {
+    match network {
+        NetworkKind::Main => Hrp::parse_unchecked("sp"),
+        NetworkKind::Test => Hrp::parse_unchecked("tsp"),
+    }
+}
+
+impl fmt::Display for SilentPaymentAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hrp = hrp(self.network);
+        let payload = self
+            .scan
+            .serialize()
+            .into_iter()
+            .chain(self.spend.serialize());
+        for c in payload
+            .bytes_to_fes()
+            .with_checksum::<Bech32m>(&hrp)
+            .with_witness_version(Fe32::Q)
+            .chars()
+        {
+            write!(f, "{}", c)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for SilentPaymentAddress {
+    type Err = SilentPaymentError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: &str| SilentPaymentError::Address(reason.to_string());
+        let checked = CheckedHrpstring::new::<Bech32m>(s).map_err(|e| invalid(&e.to_string()))?;
+
+        let network = if checked.hrp() == hrp(NetworkKind::Main) {
+            NetworkKind::Main
+        } else if checked.hrp() == hrp(NetworkKind::Test) {
+            NetworkKind::Test
+        } else {
+            return Err(invalid("unknown prefix"));
+        };
+
+        let (&version, data) = checked
+            .data_part_ascii_no_checksum()
+            .split_first()
+            .ok_or_else(|| invalid("empty data"))?;
+        let version = Fe32::from_char(version.into())
+            .map_err(|e| invalid(&e.to_string()))?
+            .to_u8();
+        let payload: Vec<u8> = data
+            .iter()
+            .map(|&c| Fe32::from_char_unchecked(c))
+            .fes_to_bytes()
+            .collect();
+
+        // Later versions only append data, so read the keys and ignore the rest
+        let payload = match version {
+            0 if payload.len() == PAYLOAD_LEN => &payload[..],
+            1..=30 if payload.len() >= PAYLOAD_LEN => &payload[..PAYLOAD_LEN],
+            31 => return Err(invalid("version 31 is reserved")),
+            _ => return Err(invalid("wrong payload length")),
+        };
+
+        Ok(Self {
+            scan: PublicKey::from_slice(&payload[..33])?,
+            spend: PublicKey::from_slice(&payload[33..])?,
+            network,
+        })
+    }
+}
+
+/// Receiving keys of a wallet account.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SilentPaymentKeys {
+    pub scan: SecretKey,
+    pub spend: SecretKey,
+    pub network: NetworkKind,
+}
+
+impl SilentPaymentKeys {
+    /// Derive the scan key at `m/352'/coin'/account'/1'/0` and the spend key
+    /// at `m/352'/coin'/account'/0'/0` from a BIP-39 seed.
+    pub fn from_seed<C: Signing>(
+        secp: &Secp256k1<C>,
+        seed: &[u8],
+        network: NetworkKind,
+        account: u32,
+    ) -> Result<Self, SilentPaymentError> {
+        let coin = coin_type(network);
+        let master = Xpriv::new_master(network, seed)?;
+        let derive = |branch: u32| -> Result<SecretKey, SilentPaymentError> {
+            let path = [
+                ChildNumber::from_hardened_idx(352)?,
+                ChildNumber::from_hardened_idx(coin)?,
+                ChildNumber::from_hardened_idx(account)?,
+                ChildNumber::from_hardened_idx(branch)?,
+                ChildNumber::from_normal_idx(0)?,
+            ];
+            Ok(master.derive_priv(secp, &path)?.private_key)
+        };
+
+        Ok(Self {
+            scan: derive(1)?,
+            spend: derive(0)?,
+            network,
+        })
+    }
+
+    pub fn address<C: Signing>(&self, secp: &Secp256k1<C>) -> SilentPaymentAddress {
+        SilentPaymentAddress {
+            scan: self.scan.public_key(secp),
+            spend: self.spend.public_key(secp),
+            network: self.network,
+        }
+    }
+}
+
+fn tagged_hash(tag: &[u8], data: &[&[u8]]) -> [u8; 32] {
+    let tag = sha256::Hash::hash(tag);
+    let mut engine = sha256::Hash::engine();
+    engine.input(tag.as_ref());
+    engine.input(tag.as_ref());
+    for chunk in data {
+        engine.input(chunk);
+    }
+    sha256::Hash::from_engine(engine).to_byte_array()
+}
+
+fn input_hash(outpoints: &[OutPoint], sum: &PublicKey) -> Result<Scalar, SilentPaymentError> {
+    let smallest = outpoints
+        .iter()
+        .map(serialize)
+        .min()
+        .ok_or(SilentPaymentError::NoEligibleInputs)?;
+    let hash = tagged_hash(INPUTS_TAG, &[&smallest, &sum.serialize()]);
+    Ok(Scalar::from(SecretKey::from_slice(&hash)?))
+}
+
+fn shared_secret_tweak(shared: &PublicKey, k: u32) -> Result<SecretKey, SilentPaymentError> {
+    let hash = tagged_hash(SHARED_SECRET_TAG, &[&shared.serialize(), &k.to_be_bytes()]);
+    Ok(SecretKey::from_slice(&hash)?)
+}
+
+fn output_key<C: Verification>(
+    secp: &Secp256k1<C>,
+    spend: &PublicKey,
+    tweak: &SecretKey,
+) -> Result<XOnlyPublicKey, SilentPaymentError> {
+    let key = spend.add_exp_tweak(secp, &Scalar::from(*tweak))?;
+    Ok(key.x_only_public_key().0)
+}
+
+fn p2tr_script(key: XOnlyPublicKey) -> ScriptBuf {
+    ScriptBuf::new_p2tr_tweaked(TweakedPublicKey::dangerous_assume_tweaked(key))
+}
+
+/// Output script with the size of a silent payment output, to select coins
+/// before the inputs, and so the real outputs, are known.
+pub fn placeholder_script(address: &SilentPaymentAddress) -> ScriptBuf {
+    p2tr_script(address.spend.x_only_public_key().0)
+}
+
+/// Private key of an input the sender spends.
+#[derive(Debug, Clone, Copy)]
+pub enum InputKey {
+    /// P2PKH, P2WPKH or P2SH-P2WPKH key
+    Ecdsa(SecretKey),
+    /// BIP-86 internal key of a key path spend
+    Taproot(SecretKey),
+}
+
+/// Output scripts paying `recipients`, in the same order. `outpoints` are
+/// all the transaction inputs, `keys` those of the eligible ones.
+pub fn sender_outputs<C: Signing + Verification>(
+    secp: &Secp256k1<C>,
+    outpoints: &[OutPoint],
+    keys: &[InputKey],
+    recipients: &[SilentPaymentAddress],
+) -> Result<Vec<ScriptBuf>, SilentPaymentError> {
+    let mut sum: Option<SecretKey> = None;
+    for key in keys {
+        let sk = match key {
+            InputKey::Ecdsa(sk) => *sk,
+            InputKey::Taproot(sk) => {
+                let tweaked =
+                    Keypair::from(Keypair::from_secret_key(secp, sk).tap_tweak(secp, None));
+                match tweaked.x_only_public_key().1 {
+                    Parity::Even => tweaked.secret_key(),
+                    Parity::Odd => tweaked.secret_key().negate(),
+                }
+            }
+        };
+        sum = Some(match sum {
+            Some(acc) => acc.add_tweak(&Scalar::from(sk))?,
+            None => sk,
+        });
+    }
+    let a = sum.ok_or(SilentPaymentError::NoEligibleInputs)?;
+    let input_hash = input_hash(outpoints, &a.public_key(secp))?;
+    let a = a.mul_tweak(&input_hash)?;
+
+    let mut counters: BTreeMap<[u8; 33], (PublicKey, u32)> = BTreeMap::new();
+    recipients
+        .iter()
+        .map(|recipient| {
+            let (shared, k) = match counters.entry(recipient.scan.serialize()) {
+                std::collections::btree_map::Entry::Occupied(entry) => entry.into_mut(),
+                std::collections::btree_map::Entry::Vacant(entry) => {
+                    entry.insert((recipient.scan.mul_tweak(secp, &Scalar::from(a))?, 0))
+                }
+            };
+            let tweak = shared_secret_tweak(shared, *k)?;
+            *k += 1;
+            Ok(p2tr_script(output_key(secp, &recipient.spend, &tweak)?))
+        })
+        .collect()
+}
+
+fn compressed_key(bytes: &[u8]) -> Option<PublicKey> {
+    if bytes.len() != 33 {
+        return None;
+    }
+    PublicKey::from_slice(bytes).ok()
+}
+
+/// Public key of an input, if it counts for silent payments.
+fn input_public_key(txin: &TxIn, prevout: &Script) -> Option<PublicKey> {
+    if prevout.is_p2pkh() {
+        // The key is pushed last, but anything may be pushed around it
+        let pushes: Vec<_> = txin
+            .script_sig
+            .instructions()
+            .filter_map(|i| match i {
+                Ok(Instruction::PushBytes(push)) => Some(push.as_bytes()),
+                _ => None,
+            })
+            .collect();
+        return pushes.into_iter().rev().find_map(|push| {
+            let key = compressed_key(push)?;
+            (ScriptBuf::new_p2pkh(&bitcoin::PublicKey::new(key).pubkey_hash()) == *prevout)
+                .then_some(key)
+        });
+    }
+
+    if prevout.is_p2sh() {
+        let mut instructions = txin.script_sig.instructions();
+        let redeem_script = match (instructions.next(), instructions.next()) {
+            (Some(Ok(Instruction::PushBytes(push))), None) => Script::from_bytes(push.as_bytes()),
+            _ => return None,
+        };
+        if !redeem_script.is_p2wpkh() || txin.witness.len() != 2 {
+            return None;
+        }
+        return compressed_key(txin.witness.nth(1)?);
+    }
+
+    if prevout.is_p2wpkh() {
+        if txin.witness.len() != 2 {
+            return None;
+        }
+        return compressed_key(txin.witness.nth(1)?);
+    }
+
+    if prevout.is_p2tr() {
+        let mut stack: Vec<&[u8]> = txin.witness.iter().collect();
+        if stack.len() > 1 && stack.last().is_some_and(|e| e.first() == Some(&0x50)) {
+            stack.pop();
+        }
+        // Script path spends of outputs without a key path don't count
+        if stack.len() > 1 {
+            let control_block = stack.last()?;
+            if control_block.get(1..33) == Some(&NUMS_H[..]) {
+                return None;
+            }
+        }
+        let key = XOnlyPublicKey::from_slice(&prevout.as_bytes()[2..]).ok()?;
+        return Some(key.public_key(Parity::Even));
+    }
+
+    None
+}
+
+/// `input_hash·A` of a transaction, what a receiver needs besides the
+/// outputs to scan it. `prevouts` are the outputs the inputs spend, in
+/// order. `None` when no input is eligible.
+pub fn public_tweak<C: Verification>(
+    secp: &Secp256k1<C>,
+    tx: &Transaction,
+    prevouts: &[TxOut],
+) -> Result<Option<PublicKey>, SilentPaymentError> {
+    if prevouts.len() != tx.input.len() {
+        return Err(SilentPaymentError::Prevouts {
+            expected: tx.input.len(),
+            actual: prevouts.len(),
+        });
+    }
+    if tx.is_coinbase() {
+        return Ok(None);
+    }
+    // Spending a future segwit version makes the whole transaction ineligible
+    if prevouts.iter().any(|prevout| {
+        prevout
+            .script_pubkey
+            .witness_version()
+            .is_some_and(|v| v.to_num() > 1)
+    }) {
+        return Ok(None);
+    }
+
+    let keys: Vec<PublicKey> = tx
+        .input
+        .iter()
+        .zip(prevouts)
+        .filter_map(|(txin, prevout)| input_public_key(txin, &prevout.script_pubkey))
+        .collect();
+    if keys.is_empty() {
+        return Ok(None);
+    }
+    // Keys summing to the point at infinity pay nobody
+    let Ok(sum) = PublicKey::combine_keys(&keys.iter().collect::<Vec<_>>()) else {
+        return Ok(None);
+    };
+
+    let outpoints: Vec<OutPoint> = tx.input.iter().map(|txin| txin.previous_output).collect();
+    let input_hash = input_hash(&outpoints, &sum)?;
+    Ok(Some(sum.mul_tweak(secp, &input_hash)?))
+}
+
+/// Output found by `scan`. Its private key is `spend + tweak`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FoundOutput {
+    /// Index into the scanned keys
+    pub index: usize,
+    pub tweak: SecretKey,
+}
+
+/// Find the outputs of one transaction paying the receiver. `outputs` are
+/// the x-only keys of its taproot outputs.
+pub fn scan<C: Verification>(
+    secp: &Secp256k1<C>,
+    scan_key: &SecretKey,
+    spend: &PublicKey,
+    tweak: &PublicKey,
+    outputs: &[XOnlyPublicKey],
+) -> Result<Vec<FoundOutput>, SilentPaymentError> {
+    let shared = tweak.mul_tweak(secp, &Scalar::from(*scan_key))?;
+    let mut found = Vec::new();
+    for k in 0..outputs.len() as u32 {
+        let tweak = shared_secret_tweak(&shared, k)?;
+        let key = output_key(secp, spend, &tweak)?;
+        let Some(index) = outputs.iter().enumerate().position(|(i, output)| {
+            *output == key && found.iter().all(|f: &FoundOutput| f.index != i)
+        }) else {
+            break;
+        };
+        found.push(FoundOutput { index, tweak });
+    }
+    Ok(found)
+}
+
+/// Private key spending a found output.
+pub fn spend_key(spend: &SecretKey, tweak: &SecretKey) -> Result<SecretKey, SilentPaymentError> {
+    Ok(spend.add_tweak(&Scalar::from(*tweak))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::hashes::Hash;
+    use bitcoin::transaction::Version;
+    use bitcoin::{absolute, Amount, Txid, WPubkeyHash, Witness};
+
+    fn keys() -> SilentPaymentKeys {
+        SilentPaymentKeys {
+            scan: SecretKey::from_slice(
+                &hex::decode("0f694e068028a717f8af6b9411f9a133dd3565258714cc226594b34db90c1f2c")
+                    .unwrap(),
+            )
+            .unwrap(),
+            spend: SecretKey::from_slice(
+                &hex::decode("9d6ad855ce3417ef84e836892e5a56392bfba05fa5d97ccea30e266f540e08b3")
+                    .unwrap(),
+            )
+            .unwrap(),
+            network: NetworkKind::Main,
+        }
+    }
+
+    #[test]
+    fn test_address_encoding() {
+        let secp = Secp256k1::new();
+        let address = keys().address(&secp);
+        let encoded = address.to_string();
+        assert_eq!(
+            encoded,
+            "sp1qqgste7k9hx0qftg6qmwlkqtwuy6cycyavzmzj85c6qdfhjdpdjtdgqjuexzk6murw56suy3e0rd2cgqvycxttddwsvgxe2usfpxumr70xc9pkqwv"
+        );
+        assert_eq!(SilentPaymentAddress::from_str(&encoded).unwrap(), address);
+
+        let testnet = SilentPaymentAddress {
+            network: NetworkKind::Test,
+            ..address
+        };
+        assert!(testnet.to_string().starts_with("tsp1q"));
+        assert_eq!(
+            SilentPaymentAddress::from_str(&testnet.to_string())
+                .unwrap()
+                .require_network(NetworkKind::Main),
+            Err(SilentPaymentError::Network(NetworkKind::Test))
+        );
+
+        // A segwit address is not a silent payment address
+        assert!(SilentPaymentAddress::from_str(
+            "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_send_and_scan() {
+        let secp = Secp256k1::new();
+        let receiver = keys();
+        let address = receiver.address(&secp);
+        let other = SilentPaymentKeys::from_seed(&secp, &[5; 64], NetworkKind::Main, 0)
+            .unwrap()
+            .address(&secp);
+
+        let wpkh_key = SecretKey::from_slice(&[3; 32]).unwrap();
+        let tr_key = SecretKey::from_slice(&[4; 32]).unwrap();
+        let wpkh_pk = bitcoin::CompressedPublicKey(wpkh_key.public_key(&secp));
+        let prevouts = vec![
+            TxOut {
+                value: Amount::from_sat(50_000),
+                script_pubkey: ScriptBuf::new_p2wpkh(&wpkh_pk.wpubkey_hash()),
+            },
+            TxOut {
+                value: Amount::from_sat(50_000),
+                script_pubkey: ScriptBuf::new_p2tr(&secp, tr_key.x_only_public_key(&secp).0, None),
+            },
+        ];
+        let outpoints = [
+            OutPoint::new(Txid::from_byte_array([9; 32]), 1),
+            OutPoint::new(Txid::from_byte_array([2; 32]), 0),
+        ];
+
+        let scripts = sender_outputs(
+            &secp,
+            &outpoints,
+            &[InputKey::Ecdsa(wpkh_key), InputKey::Taproot(tr_key)],
+            &[address, other, address],
+        )
+        .unwrap();
+        assert_eq!(scripts.len(), 3);
+        assert!(scripts.iter().all(|s| s.is_p2tr()));
+        assert_ne!(scripts[0], scripts[2]);
+
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: outpoints
+                .iter()
+                .zip([
+                    Witness::from_slice(&[vec![1u8; 72], wpkh_pk.to_bytes().to_vec()]),
+                    Witness::from_slice(&[vec![1u8; 64]]),
+                ])
+                .map(|(outpoint, witness)| TxIn {
+                    previous_output: *outpoint,
+                    witness,
+                    ..Default::default()
+                })
+                .collect(),
+            output: scripts
+                .iter()
+                .chain([&ScriptBuf::new_p2wpkh(&WPubkeyHash::from_byte_array(
+                    [7; 20],
+                ))])
+                .map(|script| TxOut {
+                    value: Amount::from_sat(10_000),
+                    script_pubkey: script.clone(),
+                })
+                .collect(),
+        };
+
+        let tweak = public_tweak(&secp, &tx, &prevouts).unwrap().unwrap();
+        let outputs: Vec<XOnlyPublicKey> = tx
+            .output
+            .iter()
+            .filter(|o| o.script_pubkey.is_p2tr())
+            .map(|o| XOnlyPublicKey::from_slice(&o.script_pubkey.as_bytes()[2..]).unwrap())
+            .collect();
+        let found = scan(&secp, &receiver.scan, &address.spend, &tweak, &outputs).unwrap();
+        assert_eq!(
+            found.iter().map(|f| f.index).collect::<Vec<_>>(),
+            vec![0, 2]
+        );
+
+        let sk = spend_key(&receiver.spend, &found[1].tweak).unwrap();
+        assert_eq!(sk.x_only_public_key(&secp).0, outputs[2]);
+
+        let not_ours = scan(&secp, &receiver.scan, &other.spend, &tweak, &outputs).unwrap();
+        assert!(not_ours.is_empty());
+
+        // Inputs without a public key leave nothing to scan
+        let mut no_keys = tx.clone();
+        no_keys.input.iter_mut().for_each(|i| i.witness.clear());
+        let p2wsh = vec![
+            TxOut {
+                value: Amount::from_sat(50_000),
+                script_pubkey: ScriptBuf::new_p2wsh(&bitcoin::WScriptHash::all_zeros()),
+            };
+            2
+        ];
+        assert_eq!(public_tweak(&secp, &no_keys, &p2wsh), Ok(None));
+    }
+}
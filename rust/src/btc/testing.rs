@@ -0,0 +1,44 @@
+use bitcoin::hashes::Hash;
+use bitcoin::{Address, Amount, Network, OutPoint, ScriptBuf, TxOut, Txid, WPubkeyHash};
+
+use crate::btc::coin_selection::Utxo;
+use crate::models::transactions::transaction_metadata::TransactionMetadataInfo;
+
+/// P2WPKH script of a made-up key hash, `tag` repeated.
+pub fn p2wpkh(tag: u8) -> ScriptBuf {
+    ScriptBuf::new_p2wpkh(&WPubkeyHash::from_byte_array([tag; 20]))
+}
+
+/// Mainnet address of `p2wpkh(tag)`.
+pub fn address(tag: u8) -> String {
+    Address::from_script(&p2wpkh(tag), Network::Bitcoin)
+        .unwrap()
+        .to_string()
+}
+
+/// Output `vout` of a 0x11.. transaction, paying `sat` to `p2wpkh(0xaa)`.
+pub fn utxo(vout: u32, sat: u64) -> Utxo {
+    Utxo::new(
+        OutPoint::new(Txid::from_byte_array([0x11; 32]), vout),
+        TxOut {
+            value: Amount::from_sat(sat),
+            script_pubkey: p2wpkh(0xaa),
+        },
+    )
+    .unwrap()
+}
+
+/// Bare metadata of a transaction to broadcast.
+pub fn metadata() -> TransactionMetadataInfo {
+    TransactionMetadataInfo {
+        chain_hash: 0,
+        hash: None,
+        info: None,
+        icon: None,
+        title: None,
+        signer: None,
+        token_info: None,
+        btc_witness_utxos: None,
+        broadcast: true,
+    }
+}
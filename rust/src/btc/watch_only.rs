@@ -0,0 +1,502 @@
+use std::str::FromStr;
+
+use bitcoin::address::NetworkUnchecked;
+use bitcoin::base58;
+use bitcoin::bip32::{KeySource, Xpub};
+use bitcoin::psbt::Psbt;
+use bitcoin::secp256k1::{PublicKey, Secp256k1, Verification};
+use bitcoin::{Address, CompressedPublicKey, Network, NetworkKind, ScriptBuf};
+use thiserror::Error;
+
+use crate::btc::multisig::{
+    strip_checksum, unwrap_fragment, DescriptorKey, MultisigDescriptor, MultisigError,
+};
+
+/// SLIP-132 versions of account-level extended public keys.
+const SLIP132_VERSIONS: [([u8; 4], NetworkKind, SingleSigType); 6] = [
+    (
+        [0x04, 0x88, 0xb2, 0x1e],
+        NetworkKind::Main,
+        SingleSigType::Pkh,
+    ), // xpub
+    (
+        [0x04, 0x9d, 0x7c, 0xb2],
+        NetworkKind::Main,
+        SingleSigType::ShWpkh,
+    ), // ypub
+    (
+        [0x04, 0xb2, 0x47, 0x46],
+        NetworkKind::Main,
+        SingleSigType::Wpkh,
+    ), // zpub
+    (
+        [0x04, 0x35, 0x87, 0xcf],
+        NetworkKind::Test,
+        SingleSigType::Pkh,
+    ), // tpub
+    (
+        [0x04, 0x4a, 0x52, 0x62],
+        NetworkKind::Test,
+        SingleSigType::ShWpkh,
+    ), // upub
+    (
+        [0x04, 0x5f, 0x1c, 0xf6],
+        NetworkKind::Test,
+        SingleSigType::Wpkh,
+    ), // vpub
+];
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum WatchOnlyError {
+    #[error(transparent)]
+    Descriptor(#[from] MultisigError),
+
+    #[error("Invalid extended key {0}")]
+    ExtendedKey(String),
+
+    #[error("Invalid address {0}")]
+    Address(String),
+
+    #[error("Address {0} is not for {1}")]
+    Network(String, Network),
+
+    #[error("Nothing to watch")]
+    Empty,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SingleSigType {
+    /// `pkh(...)`
+    Pkh,
+    /// `sh(wpkh(...))`
+    ShWpkh,
+    /// `wpkh(...)`
+    Wpkh,
+    /// `tr(...)`, key path only
+    Tr,
+}
+
+impl SingleSigType {
+    pub fn bip_purpose(self) -> u32 {
+        match self {
+            SingleSigType::Pkh => 44,
+            SingleSigType::ShWpkh => 49,
+            SingleSigType::Wpkh => 84,
+            SingleSigType::Tr => 86,
+        }
+    }
+
+    pub fn from_bip_purpose(purpose: u32) -> Option<Self> {
+        match purpose {
+            44 => Some(SingleSigType::Pkh),
+            49 => Some(SingleSigType::ShWpkh),
+            84 => Some(SingleSigType::Wpkh),
+            86 => Some(SingleSigType::Tr),
+            _ => None,
+        }
+    }
+
+    pub fn script_pubkey<C: Verification>(
+        self,
+        secp: &Secp256k1<C>,
+        pubkey: PublicKey,
+    ) -> ScriptBuf {
+        let wpkh = || ScriptBuf::new_p2wpkh(&CompressedPublicKey(pubkey).wpubkey_hash());
+        match self {
+            SingleSigType::Pkh => {
+                ScriptBuf::new_p2pkh(&bitcoin::PublicKey::new(pubkey).pubkey_hash())
+            }
+            SingleSigType::ShWpkh => ScriptBuf::new_p2sh(&wpkh().script_hash()),
+            SingleSigType::Wpkh => wpkh(),
+            SingleSigType::Tr => ScriptBuf::new_p2tr(secp, pubkey.x_only_public_key().0, None),
+        }
+    }
+}
+
+/// What a watch-only wallet follows: an extended key or single-key
+/// descriptor, a multisig descriptor, or a fixed list of addresses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WatchSource {
+    Single {
+        script_type: SingleSigType,
+        key: DescriptorKey,
+    },
+    Multisig(MultisigDescriptor),
+    Addresses(Vec<Address<NetworkUnchecked>>),
+}
+
+impl WatchSource {
+    /// Whether new addresses can be derived, false for address lists.
+    pub fn is_ranged(&self) -> bool {
+        !matches!(self, WatchSource::Addresses(_))
+    }
+
+    pub fn bip_purpose(&self) -> u32 {
+        match self {
+            WatchSource::Single { script_type, .. } => script_type.bip_purpose(),
+            WatchSource::Multisig(_) => SingleSigType::Wpkh.bip_purpose(),
+            WatchSource::Addresses(addresses) => {
+                let first = addresses
+                    .first()
+                    .map(|a| a.assume_checked_ref().script_pubkey());
+                match first {
+                    Some(s) if s.is_p2pkh() => SingleSigType::Pkh.bip_purpose(),
+                    Some(s) if s.is_p2sh() => SingleSigType::ShWpkh.bip_purpose(),
+                    Some(s) if s.is_p2tr() => SingleSigType::Tr.bip_purpose(),
+                    _ => SingleSigType::Wpkh.bip_purpose(),
+                }
+            }
+        }
+    }
+
+    pub fn check_network(&self, network: Network) -> Result<(), WatchOnlyError> {
+        match self {
+            WatchSource::Single { key, .. } if key.xpub.network != NetworkKind::from(network) => {
+                Err(WatchOnlyError::Network(key.xpub.to_string(), network))
+            }
+            WatchSource::Single { .. } => Ok(()),
+            WatchSource::Multisig(descriptor) => Ok(descriptor.check_network(network)?),
+            WatchSource::Addresses(addresses) => {
+                match addresses.iter().find(|a| !a.is_valid_for_network(network)) {
+                    Some(a) => Err(WatchOnlyError::Network(
+                        a.assume_checked_ref().to_string(),
+                        network,
+                    )),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+
+    /// Address `index` of the receive or change chain. Address lists only
+    /// have a receive chain and end with the list.
+    pub fn address(
+        &self,
+        network: Network,
+        change: bool,
+        index: u32,
+    ) -> Result<Option<Address>, WatchOnlyError> {
+        let secp = Secp256k1::verification_only();
+        let script_pubkey = match self {
+            WatchSource::Single { script_type, key } => {
+                let (pubkey, _) = key.derive(&secp, change, index)?;
+                script_type.script_pubkey(&secp, pubkey)
+            }
+            WatchSource::Multisig(descriptor) => {
+                descriptor.derive(&secp, change, index)?.script_pubkey
+            }
+            WatchSource::Addresses(addresses) => {
+                return addresses
+                    .get(index as usize)
+                    .filter(|_| !change)
+                    .map(|a| a.clone().require_network(network))
+                    .transpose()
+                    .map_err(|e| WatchOnlyError::Address(e.to_string()));
+            }
+        };
+
+        Address::from_script(&script_pubkey, network)
+            .map(Some)
+            .map_err(|e| WatchOnlyError::Address(e.to_string()))
+    }
+
+    /// Key origins for inputs and change outputs of a PSBT, so an external
+    /// signer holding the keys can sign the export. Address lists carry no
+    /// keys and are left as they are.
+    pub fn update_psbt(&self, psbt: &mut Psbt, lookahead: u32) -> Result<(), WatchOnlyError> {
+        let (script_type, key) = match self {
+            WatchSource::Single { script_type, key } => (*script_type, key),
+            WatchSource::Multisig(descriptor) => {
+                return Ok(descriptor.update_psbt(psbt, lookahead)?)
+            }
+            WatchSource::Addresses(_) => return Ok(()),
+        };
+
+        let secp = Secp256k1::verification_only();
+        let mut derived: Vec<(ScriptBuf, PublicKey, KeySource)> = Vec::new();
+        for change in [false, true] {
+            for index in 0..lookahead {
+                let (pubkey, source) = key.derive(&secp, change, index)?;
+                derived.push((script_type.script_pubkey(&secp, pubkey), pubkey, source));
+            }
+        }
+
+        for input in psbt.inputs.iter_mut() {
+            let script = input.witness_utxo.as_ref().map(|u| &u.script_pubkey);
+            let Some((_, pubkey, source)) = derived.iter().find(|(s, _, _)| Some(s) == script)
+            else {
+                continue;
+            };
+            match script_type {
+                SingleSigType::Tr => {
+                    let xonly = pubkey.x_only_public_key().0;
+                    input.tap_internal_key = Some(xonly);
+                    input
+                        .tap_key_origins
+                        .insert(xonly, (vec![], source.clone()));
+                }
+                _ => {
+                    input.bip32_derivation.insert(*pubkey, source.clone());
+                    if script_type == SingleSigType::ShWpkh {
+                        input.redeem_script = Some(ScriptBuf::new_p2wpkh(
+                            &CompressedPublicKey(*pubkey).wpubkey_hash(),
+                        ));
+                    }
+                }
+            }
+        }
+
+        for (txout, output) in psbt.unsigned_tx.output.iter().zip(psbt.outputs.iter_mut()) {
+            let Some((_, pubkey, source)) =
+                derived.iter().find(|(s, _, _)| *s == txout.script_pubkey)
+            else {
+                continue;
+            };
+            match script_type {
+                SingleSigType::Tr => {
+                    let xonly = pubkey.x_only_public_key().0;
+                    output.tap_internal_key = Some(xonly);
+                    output
+                        .tap_key_origins
+                        .insert(xonly, (vec![], source.clone()));
+                }
+                _ => {
+                    output.bip32_derivation.insert(*pubkey, source.clone());
+                    if script_type == SingleSigType::ShWpkh {
+                        output.redeem_script = Some(ScriptBuf::new_p2wpkh(
+                            &CompressedPublicKey(*pubkey).wpubkey_hash(),
+                        ));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Account xpub from an xpub/ypub/zpub (or testnet tpub/upub/vpub), with
+/// the script type its version implies.
+fn parse_extended_key(s: &str) -> Result<(Xpub, SingleSigType), WatchOnlyError> {
+    let invalid = || WatchOnlyError::ExtendedKey(s.to_string());
+    let mut data = base58::decode_check(s).map_err(|_| invalid())?;
+    if data.len() != 78 {
+        return Err(invalid());
+    }
+    let (network, script_type) = SLIP132_VERSIONS
+        .iter()
+        .find(|(version, _, _)| data[..4] == version[..])
+        .map(|(_, network, script_type)| (*network, *script_type))
+        .ok_or_else(invalid)?;
+
+    // Xpub only knows the BIP-32 versions
+    let bip32_version = match network {
+        NetworkKind::Main => SLIP132_VERSIONS[0].0,
+        NetworkKind::Test => SLIP132_VERSIONS[3].0,
+    };
+    data[..4].copy_from_slice(&bip32_version);
+    let xpub = Xpub::decode(&data).map_err(|_| invalid())?;
+
+    Ok((xpub, script_type))
+}
+
+impl FromStr for WatchSource {
+    type Err = WatchOnlyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+
+        if s.contains('(') {
+            let body = strip_checksum(s)?;
+            if body.starts_with("wsh(") || body.starts_with("sh(wsh(") {
+                return Ok(WatchSource::Multisig(MultisigDescriptor::from_str(s)?));
+            }
+
+            let (script_type, key) = if let Some(key) = unwrap_fragment(body, "sh(wpkh(", "))") {
+                (SingleSigType::ShWpkh, key)
+            } else if let Some(key) = unwrap_fragment(body, "wpkh(", ")") {
+                (SingleSigType::Wpkh, key)
+            } else if let Some(key) = unwrap_fragment(body, "pkh(", ")") {
+                (SingleSigType::Pkh, key)
+            } else if let Some(key) = unwrap_fragment(body, "tr(", ")").filter(|k| !k.contains(','))
+            {
+                (SingleSigType::Tr, key)
+            } else {
+                return Err(
+                    MultisigError::Descriptor(format!("unsupported script {}", body)).into(),
+                );
+            };
+
+            return Ok(WatchSource::Single {
+                script_type,
+                key: DescriptorKey::parse(key)?,
+            });
+        }
+
+        if s.get(1..4) == Some("pub") && !s.contains(char::is_whitespace) {
+            let (xpub, script_type) = parse_extended_key(s)?;
+            return Ok(WatchSource::Single {
+                script_type,
+                key: DescriptorKey::parse(&format!("{}/<0;1>/*", xpub))?,
+            });
+        }
+
+        let addresses = s
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|a| !a.is_empty())
+            .map(|a| Address::from_str(a).map_err(|_| WatchOnlyError::Address(a.to_string())))
+            .collect::<Result<Vec<_>, _>>()?;
+        if addresses.is_empty() {
+            return Err(WatchOnlyError::Empty);
+        }
+
+        Ok(WatchSource::Addresses(addresses))
+    }
+}
+
+/// How many addresses of a chain to watch so that `gap_limit` unused ones
+/// follow the last used.
+pub fn scan_target(last_used: Option<u32>, gap_limit: u32) -> u32 {
+    last_used.map_or(0, |i| i + 1).saturating_add(gap_limit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::hashes::Hash;
+    use bitcoin::transaction::Version;
+    use bitcoin::{absolute, Amount, OutPoint, Transaction, TxIn, TxOut, Txid};
+
+    // Account keys of the "abandon ... about" mnemonic from BIP-44/49/84/86
+    const XPUB: &str = "xpub6BosfCnifzxcFwrSzQiqu2DBVTshkCXacvNsWGYJVVhhawA7d4R5WSWGFNbi8Aw6ZRc1brxMyWMzG3DSSSSoekkudhUd9yLb6qx39T9nMdj";
+    const YPUB: &str = "ypub6Ww3ibxVfGzLrAH1PNcjyAWenMTbbAosGNB6VvmSEgytSER9azLDWCxoJwW7Ke7icmizBMXrzBx9979FfaHxHcrArf3zbeJJJUZPf663zsP";
+    const ZPUB: &str = "zpub6rFR7y4Q2AijBEqTUquhVz398htDFrtymD9xYYfG1m4wAcvPhXNfE3EfH1r1ADqtfSdVCToUG868RvUUkgDKf31mGDtKsAYz2oz2AGutZYs";
+    const TR_XPUB: &str = "xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ";
+
+    fn first_address(source: &str, change: bool) -> String {
+        WatchSource::from_str(source)
+            .unwrap()
+            .address(Network::Bitcoin, change, 0)
+            .unwrap()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn test_extended_keys() {
+        assert_eq!(
+            first_address(XPUB, false),
+            "1LqBGSKuX5yYUonjxT5qGfpUsXKYYWeabA"
+        );
+        assert_eq!(
+            first_address(YPUB, false),
+            "37VucYSaXLCAsxYyAPfbSi9eh4iEcbShgf"
+        );
+        assert_eq!(
+            first_address(ZPUB, false),
+            "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu"
+        );
+        assert_eq!(
+            first_address(ZPUB, true),
+            "bc1q8c6fshw2dlwun7ekn9qwf37cu2rn755upcp6el"
+        );
+
+        let zpub = WatchSource::from_str(ZPUB).unwrap();
+        assert_eq!(zpub.bip_purpose(), 84);
+        assert!(zpub.check_network(Network::Bitcoin).is_ok());
+        assert!(zpub.check_network(Network::Testnet).is_err());
+
+        let mut corrupted = ZPUB.to_string();
+        corrupted.pop();
+        assert!(matches!(
+            WatchSource::from_str(&corrupted),
+            Err(WatchOnlyError::ExtendedKey(_))
+        ));
+    }
+
+    #[test]
+    fn test_descriptors() {
+        let wpkh = format!("wpkh([73c5da0a/84'/0'/0']{}/<0;1>/*)", XPUB);
+        let tr = format!("tr([73c5da0a/86'/0'/0']{}/<0;1>/*)", TR_XPUB);
+        assert_eq!(
+            first_address(&tr, false),
+            "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr"
+        );
+        assert!(first_address(&wpkh, false).starts_with("bc1q"));
+        assert!(first_address(&format!("sh({})", wpkh), false).starts_with('3'));
+        assert!(first_address(&format!("pkh({}/0/*)", XPUB), false).starts_with('1'));
+
+        assert!(WatchSource::from_str(&format!("tr({}/0/*,pk({}/1/*))", XPUB, XPUB)).is_err());
+        assert!(WatchSource::from_str(&format!("wpkh({})", XPUB)).is_err());
+        assert!(WatchSource::from_str(&format!("{}#00000000", wpkh)).is_err());
+    }
+
+    #[test]
+    fn test_address_list() {
+        let list =
+            "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu,\n 37VucYSaXLCAsxYyAPfbSi9eh4iEcbShgf";
+        let source = WatchSource::from_str(list).unwrap();
+        assert!(!source.is_ranged());
+        assert_eq!(source.bip_purpose(), 84);
+        assert!(source.check_network(Network::Testnet).is_err());
+
+        let second = source.address(Network::Bitcoin, false, 1).unwrap().unwrap();
+        assert_eq!(second.to_string(), "37VucYSaXLCAsxYyAPfbSi9eh4iEcbShgf");
+        assert_eq!(source.address(Network::Bitcoin, false, 2).unwrap(), None);
+        assert_eq!(source.address(Network::Bitcoin, true, 0).unwrap(), None);
+
+        assert_eq!(WatchSource::from_str(" \n"), Err(WatchOnlyError::Empty));
+        assert!(matches!(
+            WatchSource::from_str("bc1qnotanaddress"),
+            Err(WatchOnlyError::Address(_))
+        ));
+    }
+
+    #[test]
+    fn test_update_psbt() {
+        let source = WatchSource::from_str(YPUB).unwrap();
+        let receive = source.address(Network::Bitcoin, false, 2).unwrap().unwrap();
+        let change = source.address(Network::Bitcoin, true, 0).unwrap().unwrap();
+
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::from_byte_array([0x11; 32]), 0),
+                ..Default::default()
+            }],
+            output: vec![
+                TxOut {
+                    value: Amount::from_sat(5_000),
+                    script_pubkey: ScriptBuf::new_p2wpkh(&bitcoin::WPubkeyHash::from_byte_array(
+                        [0x33; 20],
+                    )),
+                },
+                TxOut {
+                    value: Amount::from_sat(4_000),
+                    script_pubkey: change.script_pubkey(),
+                },
+            ],
+        };
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        psbt.inputs[0].witness_utxo = Some(TxOut {
+            value: Amount::from_sat(10_000),
+            script_pubkey: receive.script_pubkey(),
+        });
+        source.update_psbt(&mut psbt, 5).unwrap();
+
+        let (_, path) = psbt.inputs[0].bip32_derivation.values().next().unwrap();
+        assert_eq!(path.to_string(), "0/2");
+        assert!(psbt.inputs[0].redeem_script.as_ref().unwrap().is_p2wpkh());
+        assert!(psbt.outputs[0].bip32_derivation.is_empty());
+        let (_, path) = psbt.outputs[1].bip32_derivation.values().next().unwrap();
+        assert_eq!(path.to_string(), "1/0");
+    }
+
+    #[test]
+    fn test_scan_target() {
+        assert_eq!(scan_target(None, 20), 20);
+        assert_eq!(scan_target(Some(0), 20), 21);
+        assert_eq!(scan_target(Some(14), 20), 35);
+    }
+}
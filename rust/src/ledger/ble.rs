@@ -1,20 +1,70 @@
+use std::collections::HashSet;
+use std::sync::Mutex;
 use std::time::Duration;
 
-use btleplug::api::{Central, Manager as _, Peripheral as _, ScanFilter, WriteType};
+use btleplug::api::{Central, CentralEvent, Manager as _, Peripheral as _, ScanFilter, WriteType};
 use btleplug::platform::{Adapter, Manager, Peripheral};
-use futures::StreamExt;
-use tokio::sync::OnceCell;
+use futures::future::BoxFuture;
+use futures::{Stream, StreamExt};
+use lazy_static::lazy_static;
+use tokio::sync::{broadcast, OnceCell};
 use uuid::Uuid;
 
 use crate::ledger::device::{identify_ble_service_uuid, BLE_DEVICES};
 use crate::ledger::framing::{unwrap_ble_chunk, wrap_ble_apdu, BleReceiveState};
-use crate::ledger::transport::LedgerError;
+use crate::ledger::transport::{is_idempotent, LedgerApp, LedgerError, LedgerTransport};
 
 const DEFAULT_MTU: usize = 156;
 const MTU_NEGOTIATE_CMD: u8 = 0x08;
 
+const DEFAULT_EXCHANGE_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_RECONNECT_ATTEMPTS: u32 = 3;
+const RECONNECT_BACKOFF: Duration = Duration::from_secs(1);
+const STATE_CHANNEL_CAPACITY: usize = 32;
+
 static ADAPTER: OnceCell<Adapter> = OnceCell::const_new();
 
+lazy_static! {
+    static ref STATE_EVENTS: broadcast::Sender<BleConnectionEvent> =
+        broadcast::channel(STATE_CHANNEL_CAPACITY).0;
+    /// Devices with an open transport, used to tell our link drops apart
+    /// from any other BLE device the adapter reports.
+    static ref OPEN_DEVICES: Mutex<HashSet<String>> = Mutex::new(HashSet::new());
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BleConnectionState {
+    Connecting,
+    Connected,
+    MtuNegotiated(usize),
+    Reconnecting(u32),
+    Disconnected,
+}
+
+#[derive(Debug, Clone)]
+pub struct BleConnectionEvent {
+    pub device_id: String,
+    pub state: BleConnectionState,
+}
+
+#[derive(Debug, Clone)]
+pub struct BleConfig {
+    /// How long to wait for the device to answer one APDU. Signing waits on
+    /// the user, so this must leave room for reading the screen.
+    pub exchange_timeout: Duration,
+    /// Reconnect attempts before a dropped link is reported as an error.
+    pub reconnect_attempts: u32,
+}
+
+impl Default for BleConfig {
+    fn default() -> Self {
+        BleConfig {
+            exchange_timeout: DEFAULT_EXCHANGE_TIMEOUT,
+            reconnect_attempts: DEFAULT_RECONNECT_ATTEMPTS,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct BleDeviceInfo {
     pub device_id: String,
@@ -96,16 +146,78 @@ pub async fn scan_devices() -> Result<Vec<BleDeviceInfo>, LedgerError> {
     Ok(devices)
 }
 
-pub struct BleLedgerTransport {
-    peripheral: Peripheral,
+fn emit(device_id: &str, state: BleConnectionState) {
+    // Nobody listening is fine
+    STATE_EVENTS
+        .send(BleConnectionEvent {
+            device_id: device_id.to_string(),
+            state,
+        })
+        .ok();
+}
+
+fn is_open(device_id: &str) -> bool {
+    OPEN_DEVICES
+        .lock()
+        .map(|open| open.contains(device_id))
+        .unwrap_or(false)
+}
+
+/// Connection state changes of every BLE Ledger opened by this process.
+/// Link drops reported by the adapter show up as `Disconnected` even while
+/// the connection is idle.
+pub async fn connection_events() -> Result<impl Stream<Item = BleConnectionEvent>, LedgerError> {
+    let adapter = get_adapter().await?;
+    let dropped = adapter
+        .events()
+        .await
+        .map_err(|e| LedgerError::Io(format!("Failed to get adapter events: {}", e)))?
+        .filter_map(|event| async move {
+            match event {
+                CentralEvent::DeviceDisconnected(id) if is_open(&id.to_string()) => {
+                    Some(BleConnectionEvent {
+                        device_id: id.to_string(),
+                        state: BleConnectionState::Disconnected,
+                    })
+                }
+                _ => None,
+            }
+        });
+    let own = futures::stream::unfold(STATE_EVENTS.subscribe(), |mut rx| async move {
+        loop {
+            match rx.recv().await {
+                Ok(event) => return Some((event, rx)),
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    Ok(futures::stream::select(own, dropped))
+}
+
+struct BleLink {
     write_char: btleplug::api::Characteristic,
     write_cmd_char: Option<btleplug::api::Characteristic>,
     notify_char: btleplug::api::Characteristic,
     mtu: usize,
 }
 
+pub struct BleLedgerTransport {
+    peripheral: Peripheral,
+    device_id: String,
+    link: BleLink,
+    config: BleConfig,
+    /// Decides which APDUs are retried after a dropped link
+    app: LedgerApp,
+}
+
 impl BleLedgerTransport {
     pub async fn open(device_id: &str) -> Result<Self, LedgerError> {
+        Self::open_with_config(device_id, BleConfig::default()).await
+    }
+
+    pub async fn open_with_config(device_id: &str, config: BleConfig) -> Result<Self, LedgerError> {
         let adapter = get_adapter().await?;
 
         let peripherals = adapter
@@ -118,11 +230,28 @@ impl BleLedgerTransport {
             .find(|p| p.id().to_string() == device_id)
             .ok_or_else(|| LedgerError::Io(format!("Device {} not found", device_id)))?;
 
-        // Connect
+        let link = Self::connect(&peripheral, device_id).await?;
+        if let Ok(mut open) = OPEN_DEVICES.lock() {
+            open.insert(device_id.to_string());
+        }
+
+        Ok(BleLedgerTransport {
+            peripheral,
+            device_id: device_id.to_string(),
+            link,
+            config,
+            app: LedgerApp::Unknown,
+        })
+    }
+
+    async fn connect(peripheral: &Peripheral, device_id: &str) -> Result<BleLink, LedgerError> {
+        emit(device_id, BleConnectionState::Connecting);
+
         peripheral
             .connect()
             .await
             .map_err(|e| LedgerError::Io(format!("Connection failed: {}", e)))?;
+        emit(device_id, BleConnectionState::Connected);
 
         // Discover services
         peripheral
@@ -174,10 +303,10 @@ impl BleLedgerTransport {
             .map_err(|e| LedgerError::Io(format!("Subscribe failed: {}", e)))?;
 
         // Negotiate MTU
-        let mtu = Self::negotiate_mtu(&peripheral, &write_char, &notify_char).await;
+        let mtu = Self::negotiate_mtu(peripheral, &write_char, &notify_char).await;
+        emit(device_id, BleConnectionState::MtuNegotiated(mtu));
 
-        Ok(BleLedgerTransport {
-            peripheral,
+        Ok(BleLink {
             write_char,
             write_cmd_char,
             notify_char,
@@ -185,6 +314,25 @@ impl BleLedgerTransport {
         })
     }
 
+    async fn reconnect(&mut self) -> Result<(), LedgerError> {
+        for attempt in 1..=self.config.reconnect_attempts {
+            emit(&self.device_id, BleConnectionState::Reconnecting(attempt));
+            match Self::connect(&self.peripheral, &self.device_id).await {
+                Ok(link) => {
+                    self.link = link;
+                    return Ok(());
+                }
+                Err(_) => tokio::time::sleep(RECONNECT_BACKOFF * attempt).await,
+            }
+        }
+        emit(&self.device_id, BleConnectionState::Disconnected);
+        Err(LedgerError::Disconnected)
+    }
+
+    async fn is_connected(&self) -> bool {
+        self.peripheral.is_connected().await.unwrap_or(false)
+    }
+
     async fn negotiate_mtu(
         peripheral: &Peripheral,
         write_char: &btleplug::api::Characteristic,
@@ -216,14 +364,44 @@ impl BleLedgerTransport {
         DEFAULT_MTU
     }
 
-    pub async fn exchange(&self, apdu: &[u8]) -> Result<Vec<u8>, LedgerError> {
-        let chunks = wrap_ble_apdu(apdu, self.mtu);
+    /// Send `apdu`, reconnecting first if the link dropped while idle. When
+    /// the link drops mid-exchange only APDUs idempotent for the running app
+    /// are sent again, any other command fails with `Disconnected` after the
+    /// reconnect.
+    pub async fn exchange(&mut self, apdu: &[u8]) -> Result<Vec<u8>, LedgerError> {
+        if !self.is_connected().await {
+            self.reconnect().await?;
+        }
+
+        let mut result = self.exchange_once(apdu).await;
+        let link_error = matches!(
+            result,
+            Err(LedgerError::Timeout | LedgerError::Disconnected | LedgerError::Io(_))
+        );
+        if link_error && !self.is_connected().await {
+            self.reconnect().await?;
+            result = if is_idempotent(self.app, apdu) {
+                self.exchange_once(apdu).await
+            } else {
+                Err(LedgerError::Disconnected)
+            };
+        }
+
+        if let Ok(response) = &result {
+            self.app = self.app.after_exchange(apdu, response);
+        }
+        result
+    }
+
+    async fn exchange_once(&self, apdu: &[u8]) -> Result<Vec<u8>, LedgerError> {
+        let link = &self.link;
+        let chunks = wrap_ble_apdu(apdu, link.mtu);
 
         // Prefer write-without-response for faster throughput (matches Ledger Live)
-        let (char_to_use, write_type) = if let Some(ref cmd_char) = self.write_cmd_char {
+        let (char_to_use, write_type) = if let Some(ref cmd_char) = link.write_cmd_char {
             (cmd_char, WriteType::WithoutResponse)
         } else {
-            (&self.write_char, WriteType::WithResponse)
+            (&link.write_char, WriteType::WithResponse)
         };
 
         // Write all chunks
@@ -231,9 +409,7 @@ impl BleLedgerTransport {
             self.peripheral
                 .write(char_to_use, chunk, write_type)
                 .await
-                .map_err(|e| {
-                    LedgerError::Io(format!("BLE write failed: {}", e))
-                })?;
+                .map_err(|e| LedgerError::Io(format!("BLE write failed: {}", e)))?;
 
             // Small delay between chunks (matching Android's 20ms)
             tokio::time::sleep(Duration::from_millis(20)).await;
@@ -247,20 +423,16 @@ impl BleLedgerTransport {
             .map_err(|e| LedgerError::Io(format!("Failed to get notifications: {}", e)))?;
 
         let mut state = BleReceiveState::new();
-        let timeout_duration = Duration::from_secs(30);
+        let timeout_duration = self.config.exchange_timeout;
 
         loop {
             let notif = tokio::time::timeout(timeout_duration, notifs.next())
                 .await
-                .map_err(|_| {
-                    LedgerError::Timeout
-                })?
-                .ok_or_else(|| {
-                    LedgerError::Disconnected
-                })?;
+                .map_err(|_| LedgerError::Timeout)?
+                .ok_or(LedgerError::Disconnected)?;
 
             // Skip non-data notifications (MTU responses, etc.)
-            if notif.uuid != self.notify_char.uuid {
+            if notif.uuid != link.notify_char.uuid {
                 continue;
             }
 
@@ -279,14 +451,28 @@ impl BleLedgerTransport {
     }
 
     pub async fn close(&self) -> Result<(), LedgerError> {
+        if let Ok(mut open) = OPEN_DEVICES.lock() {
+            open.remove(&self.device_id);
+        }
         self.peripheral
-            .unsubscribe(&self.notify_char)
+            .unsubscribe(&self.link.notify_char)
             .await
             .ok();
         self.peripheral
             .disconnect()
             .await
             .map_err(|e| LedgerError::Io(format!("Disconnect failed: {}", e)))?;
+        emit(&self.device_id, BleConnectionState::Disconnected);
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+impl LedgerTransport for BleLedgerTransport {
+    fn exchange<'a>(&'a mut self, apdu: &'a [u8]) -> BoxFuture<'a, Result<Vec<u8>, LedgerError>> {
+        Box::pin(BleLedgerTransport::exchange(self, apdu))
+    }
+
+    fn close(&mut self) -> BoxFuture<'_, Result<(), LedgerError>> {
+        Box::pin(BleLedgerTransport::close(self))
+    }
+}
@@ -0,0 +1,742 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::api::btc_ledger::{
+    btc_ledger_compute_merkle_root, btc_ledger_get_merkle_proof, btc_ledger_hash_leaf,
+    btc_ledger_sha256, decode_varint, encode_varint, MerkelizedPsbt, WalletPolicy,
+};
+use crate::ledger::dashboard::get_app_and_version;
+use crate::ledger::registry::Session;
+use crate::ledger::transport::{build_apdu, check_status_word, status_word, LedgerError, SW_OK};
+
+const CLA_BTC: u8 = 0xe1;
+const CLA_FRAMEWORK: u8 = 0xf8;
+
+const INS_GET_EXTENDED_PUBKEY: u8 = 0x00;
+const INS_REGISTER_WALLET: u8 = 0x02;
+const INS_GET_WALLET_ADDRESS: u8 = 0x03;
+const INS_SIGN_PSBT: u8 = 0x04;
+const INS_GET_MASTER_FINGERPRINT: u8 = 0x05;
+const INS_SIGN_MESSAGE: u8 = 0x10;
+const INS_CONTINUE_INTERRUPTED: u8 = 0x01;
+
+const CC_YIELD: u8 = 0x10;
+const CC_GET_PREIMAGE: u8 = 0x40;
+const CC_GET_MERKLE_LEAF_PROOF: u8 = 0x41;
+const CC_GET_MERKLE_LEAF_INDEX: u8 = 0x42;
+const CC_GET_MORE_ELEMENTS: u8 = 0xa0;
+
+pub const SW_INTERRUPTED_EXECUTION: u16 = 0xe000;
+
+/// First app version whose SIGN_PSBT yields carry the signing pubkey.
+const YIELD_PUBKEY_VERSION: (u32, u32, u32) = (2, 1, 0);
+
+const MAX_RESPONSE_LEN: usize = 255;
+const MESSAGE_CHUNK_LEN: usize = 64;
+const MESSAGE_SIGNATURE_LEN: usize = 65;
+const HASH_LEN: usize = 32;
+
+/// Answers the client commands the BTC app sends while a command is
+/// interrupted (SW 0xE000). Everything the device may ask for has to be
+/// registered up front as a preimage or a merkle tree.
+#[derive(Default)]
+pub struct ClientCommandInterpreter {
+    preimages: HashMap<Vec<u8>, Vec<u8>>,
+    trees: HashMap<Vec<u8>, Vec<Vec<u8>>>,
+    queue: VecDeque<Vec<u8>>,
+    yielded: Vec<Vec<u8>>,
+}
+
+impl ClientCommandInterpreter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Make `data` available to GET_PREIMAGE under `SHA256(data)`.
+    pub fn add_known_preimage(&mut self, data: Vec<u8>) {
+        self.preimages.insert(btc_ledger_sha256(data.clone()), data);
+    }
+
+    /// Make a merkle tree available to GET_MERKLE_LEAF_PROOF and
+    /// GET_MERKLE_LEAF_INDEX.
+    pub fn add_known_tree(&mut self, leaf_hashes: Vec<Vec<u8>>) {
+        let root = btc_ledger_compute_merkle_root(leaf_hashes.clone());
+        self.trees.insert(root, leaf_hashes);
+    }
+
+    /// Register a list of elements: the merkle tree of their leaf hashes and
+    /// each `0x00 || element` as a preimage.
+    pub fn add_known_list(&mut self, elements: &[Vec<u8>]) {
+        let mut leaves = Vec::with_capacity(elements.len());
+        for element in elements {
+            let mut preimage = vec![0x00];
+            preimage.extend_from_slice(element);
+            self.add_known_preimage(preimage);
+            leaves.push(btc_ledger_hash_leaf(element.clone()));
+        }
+        self.add_known_tree(leaves);
+    }
+
+    /// Register the serialized policy, its descriptor (v2 policies only
+    /// commit to its hash) and its keys.
+    pub fn add_wallet_policy(&mut self, policy: &WalletPolicy) {
+        self.add_known_preimage(policy.serialized.clone());
+        self.add_known_preimage(policy.descriptor_template.as_bytes().to_vec());
+        let keys: Vec<Vec<u8>> = policy
+            .keys_info
+            .iter()
+            .map(|k| k.as_bytes().to_vec())
+            .collect();
+        self.add_known_list(&keys);
+    }
+
+    /// Register every preimage and tree of a merkelized PSBT.
+    pub fn add_psbt(&mut self, psbt: &MerkelizedPsbt) {
+        for data in &psbt.preimage_data {
+            self.add_known_preimage(data.clone());
+        }
+
+        self.add_known_tree(psbt.global_map_keys_leaves.clone());
+        self.add_known_tree(psbt.global_map_values_leaves.clone());
+
+        let maps = psbt
+            .input_map_keys_leaves
+            .iter()
+            .zip(&psbt.input_map_values_leaves)
+            .chain(
+                psbt.output_map_keys_leaves
+                    .iter()
+                    .zip(&psbt.output_map_values_leaves),
+            );
+        for (keys, values) in maps {
+            self.add_known_tree(keys.clone());
+            self.add_known_tree(values.clone());
+        }
+
+        for commitments in [&psbt.input_map_commitments, &psbt.output_map_commitments] {
+            let leaves = commitments
+                .iter()
+                .map(|c| btc_ledger_hash_leaf(c.clone()))
+                .collect();
+            self.add_known_tree(leaves);
+        }
+    }
+
+    /// Everything the device sent with YIELD, in order.
+    pub fn yielded(&self) -> &[Vec<u8>] {
+        &self.yielded
+    }
+
+    /// Handle one client command (`code || data`) and return the reply.
+    pub fn execute(&mut self, request: &[u8]) -> Result<Vec<u8>, LedgerError> {
+        let (&code, data) = request
+            .split_first()
+            .ok_or_else(|| LedgerError::Framing("Empty client command".to_string()))?;
+
+        if !self.queue.is_empty() && code != CC_GET_MORE_ELEMENTS {
+            return Err(LedgerError::Framing(
+                "Queued elements were not fetched".to_string(),
+            ));
+        }
+
+        match code {
+            CC_YIELD => {
+                self.yielded.push(data.to_vec());
+                Ok(Vec::new())
+            }
+            CC_GET_PREIMAGE => self.get_preimage(data),
+            CC_GET_MERKLE_LEAF_PROOF => self.get_merkle_leaf_proof(data),
+            CC_GET_MERKLE_LEAF_INDEX => self.get_merkle_leaf_index(data),
+            CC_GET_MORE_ELEMENTS => self.get_more_elements(),
+            _ => Err(LedgerError::Framing(format!(
+                "Unknown client command: 0x{:02x}",
+                code
+            ))),
+        }
+    }
+
+    /// Request: `0x00 || hash(32)`.
+    /// Reply: `varint(len) || chunk_len || chunk`, the rest is queued as
+    /// 1-byte elements.
+    fn get_preimage(&mut self, data: &[u8]) -> Result<Vec<u8>, LedgerError> {
+        if data.len() != 1 + HASH_LEN || data[0] != 0x00 {
+            return Err(LedgerError::Framing(
+                "Invalid GET_PREIMAGE request".to_string(),
+            ));
+        }
+        let hash = &data[1..];
+        let preimage = self.preimages.get(hash).ok_or_else(|| {
+            LedgerError::Framing(format!(
+                "Preimage not found for hash: {}",
+                hex::encode(hash)
+            ))
+        })?;
+
+        let len_varint = encode_varint(preimage.len() as u64);
+        let max_payload = MAX_RESPONSE_LEN - len_varint.len() - 1;
+        let chunk_len = preimage.len().min(max_payload);
+
+        self.queue
+            .extend(preimage[chunk_len..].iter().map(|b| vec![*b]));
+
+        let mut reply = len_varint;
+        reply.push(chunk_len as u8);
+        reply.extend_from_slice(&preimage[..chunk_len]);
+        Ok(reply)
+    }
+
+    /// Request: `root(32) || varint(tree_size) || varint(leaf_index)`.
+    /// Reply: `leaf_hash || proof_len || n || proof[..n]`, the rest of the
+    /// proof is queued.
+    fn get_merkle_leaf_proof(&mut self, data: &[u8]) -> Result<Vec<u8>, LedgerError> {
+        let root = data
+            .get(..HASH_LEN)
+            .ok_or_else(|| LedgerError::Framing("Invalid GET_MERKLE_LEAF_PROOF".to_string()))?;
+        let (tree_size, read) = read_varint(&data[HASH_LEN..])?;
+        let (leaf_index, _) = read_varint(&data[HASH_LEN + read..])?;
+
+        let leaves = self.trees.get(root).ok_or_else(|| {
+            LedgerError::Framing(format!("Unknown merkle root: {}", hex::encode(root)))
+        })?;
+        if leaves.len() as u64 != tree_size {
+            return Err(LedgerError::Framing(format!(
+                "Merkle tree size mismatch: {} != {}",
+                leaves.len(),
+                tree_size
+            )));
+        }
+        let proof = btc_ledger_get_merkle_proof(leaves.clone(), leaf_index as u32)
+            .map_err(LedgerError::Framing)?;
+
+        let max_hashes = (MAX_RESPONSE_LEN - HASH_LEN - 2) / HASH_LEN;
+        let n = proof.proof_hashes.len().min(max_hashes);
+
+        let mut reply = proof.leaf_hash;
+        reply.push(proof.proof_hashes.len() as u8);
+        reply.push(n as u8);
+        for hash in &proof.proof_hashes[..n] {
+            reply.extend_from_slice(hash);
+        }
+        self.queue.extend(proof.proof_hashes[n..].iter().cloned());
+        Ok(reply)
+    }
+
+    /// Request: `root(32) || leaf_hash(32)`.
+    /// Reply: `0x01 || varint(index)` or `0x00 || 0x00` when not found.
+    fn get_merkle_leaf_index(&mut self, data: &[u8]) -> Result<Vec<u8>, LedgerError> {
+        if data.len() != 2 * HASH_LEN {
+            return Err(LedgerError::Framing(
+                "Invalid GET_MERKLE_LEAF_INDEX request".to_string(),
+            ));
+        }
+        let (root, leaf_hash) = data.split_at(HASH_LEN);
+        let index = self
+            .trees
+            .get(root)
+            .and_then(|leaves| leaves.iter().position(|l| l == leaf_hash));
+
+        Ok(match index {
+            Some(index) => {
+                let mut reply = vec![0x01];
+                reply.extend_from_slice(&encode_varint(index as u64));
+                reply
+            }
+            None => vec![0x00, 0x00],
+        })
+    }
+
+    /// Reply: `n || element_len || elements`.
+    fn get_more_elements(&mut self) -> Result<Vec<u8>, LedgerError> {
+        let element_len = self
+            .queue
+            .front()
+            .map(|e| e.len())
+            .ok_or_else(|| LedgerError::Framing("No queued elements".to_string()))?;
+
+        let max_elements = (MAX_RESPONSE_LEN - 2) / element_len.max(1);
+        let mut reply = vec![0x00, element_len as u8];
+        let mut n = 0;
+        while n < max_elements {
+            match self.queue.front() {
+                Some(e) if e.len() == element_len => {
+                    reply.extend_from_slice(&self.queue.pop_front().unwrap_or_default());
+                    n += 1;
+                }
+                _ => break,
+            }
+        }
+        reply[0] = n as u8;
+        Ok(reply)
+    }
+}
+
+fn read_varint(data: &[u8]) -> Result<(u64, usize), LedgerError> {
+    let needed = match data.first() {
+        None => 0,
+        Some(0xfd) => 3,
+        Some(0xfe) => 5,
+        Some(0xff) => 9,
+        Some(_) => 1,
+    };
+    if needed == 0 || data.len() < needed {
+        return Err(LedgerError::Framing("Truncated varint".to_string()));
+    }
+    Ok(decode_varint(data))
+}
+
+/// Send a BTC app command and answer client commands until the device
+/// returns 0x9000. Returns the final response data.
+pub async fn run_interactive(
+    session: &mut Session,
+    ins: u8,
+    data: &[u8],
+    interpreter: &mut ClientCommandInterpreter,
+) -> Result<Vec<u8>, LedgerError> {
+    let mut apdu = build_apdu(CLA_BTC, ins, 0x00, 0x00, data)?;
+
+    loop {
+        let mut response = check_status_word(session.exchange(&apdu).await?)?;
+        let sw = status_word(&response)?;
+        response.truncate(response.len() - 2);
+
+        match sw {
+            SW_OK => return Ok(response),
+            SW_INTERRUPTED_EXECUTION => {
+                let reply = interpreter.execute(&response)?;
+                apdu = build_apdu(CLA_FRAMEWORK, INS_CONTINUE_INTERRUPTED, 0x00, 0x00, &reply)?;
+            }
+            _ => return Err(LedgerError::UnexpectedStatus(sw)),
+        }
+    }
+}
+
+pub async fn get_master_fingerprint(session: &mut Session) -> Result<Vec<u8>, LedgerError> {
+    let data = session
+        .send(CLA_BTC, INS_GET_MASTER_FINGERPRINT, 0x00, 0x00, &[])
+        .await?;
+    data.get(..4)
+        .map(|fp| fp.to_vec())
+        .ok_or_else(|| LedgerError::Framing("Fingerprint truncated".to_string()))
+}
+
+/// `encoded_path` as produced by `btc_ledger_encode_path`.
+pub async fn get_extended_pubkey(
+    session: &mut Session,
+    encoded_path: &[u8],
+    display: bool,
+) -> Result<String, LedgerError> {
+    let mut payload = vec![display as u8];
+    payload.extend_from_slice(encoded_path);
+    let data = session
+        .send(CLA_BTC, INS_GET_EXTENDED_PUBKEY, 0x00, 0x00, &payload)
+        .await?;
+    String::from_utf8(data)
+        .map(|s| s.trim_end_matches('\0').to_string())
+        .map_err(|e| LedgerError::Framing(e.to_string()))
+}
+
+/// Run REGISTER_WALLET and return the 32-byte HMAC the device uses to
+/// recognise the policy later. The user has to approve every key.
+pub async fn register_wallet(
+    session: &mut Session,
+    policy: &WalletPolicy,
+) -> Result<Vec<u8>, LedgerError> {
+    let mut payload = encode_varint(policy.serialized.len() as u64);
+    payload.extend_from_slice(&policy.serialized);
+
+    let mut interpreter = ClientCommandInterpreter::new();
+    interpreter.add_wallet_policy(policy);
+
+    let data = run_interactive(session, INS_REGISTER_WALLET, &payload, &mut interpreter).await?;
+    if data.len() != 64 {
+        return Err(LedgerError::Framing(format!(
+            "Unexpected REGISTER_WALLET response length {}",
+            data.len()
+        )));
+    }
+    if data[..32] != policy.policy_id[..] {
+        return Err(LedgerError::Framing(
+            "Device registered a different policy id".to_string(),
+        ));
+    }
+    Ok(data[32..].to_vec())
+}
+
+pub async fn get_wallet_address(
+    session: &mut Session,
+    policy: &WalletPolicy,
+    change: bool,
+    address_index: u32,
+    display: bool,
+) -> Result<String, LedgerError> {
+    let mut payload = vec![display as u8];
+    payload.extend_from_slice(&policy.policy_id);
+    payload.extend_from_slice(&policy.policy_hmac);
+    payload.push(change as u8);
+    payload.extend_from_slice(&address_index.to_be_bytes());
+
+    let mut interpreter = ClientCommandInterpreter::new();
+    interpreter.add_wallet_policy(policy);
+
+    let data = run_interactive(session, INS_GET_WALLET_ADDRESS, &payload, &mut interpreter).await?;
+    String::from_utf8(data).map_err(|e| LedgerError::Framing(e.to_string()))
+}
+
+/// Whether SIGN_PSBT yields of app `version` include the pubkey.
+pub fn yields_pubkey(version: &str) -> bool {
+    let mut parts = version
+        .split(|c: char| !c.is_ascii_digit())
+        .map(|p| p.parse::<u32>().unwrap_or(0));
+    let version = (
+        parts.next().unwrap_or(0),
+        parts.next().unwrap_or(0),
+        parts.next().unwrap_or(0),
+    );
+    version >= YIELD_PUBKEY_VERSION
+}
+
+/// Run SIGN_PSBT and return `(input_index, pubkey, signature)` sorted by
+/// input index. The pubkey is empty on apps older than 2.1.0.
+pub async fn sign_psbt(
+    session: &mut Session,
+    psbt: &MerkelizedPsbt,
+    policy: &WalletPolicy,
+) -> Result<Vec<(u32, Vec<u8>, Vec<u8>)>, LedgerError> {
+    let mut payload = psbt.global_map_commitment.clone();
+    payload.extend_from_slice(&encode_varint(psbt.input_count as u64));
+    payload.extend_from_slice(&psbt.input_maps_root);
+    payload.extend_from_slice(&encode_varint(psbt.output_count as u64));
+    payload.extend_from_slice(&psbt.output_maps_root);
+    payload.extend_from_slice(&policy.policy_id);
+    payload.extend_from_slice(&policy.policy_hmac);
+
+    let with_pubkey = yields_pubkey(&get_app_and_version(session).await?.version);
+
+    let mut interpreter = ClientCommandInterpreter::new();
+    interpreter.add_psbt(psbt);
+    interpreter.add_wallet_policy(policy);
+
+    run_interactive(session, INS_SIGN_PSBT, &payload, &mut interpreter).await?;
+
+    let mut sigs = interpreter
+        .yielded()
+        .iter()
+        .map(|y| parse_yielded_signature(y, with_pubkey))
+        .collect::<Result<Vec<_>, _>>()?;
+    sigs.sort_by_key(|(index, _, _)| *index);
+    Ok(sigs)
+}
+
+/// Run SIGN_MESSAGE with the key at `encoded_path` (as produced by
+/// `btc_ledger_encode_path`) and return the 65-byte compact signature of
+/// Bitcoin Core `signmessage`, recovery header first. The message is sent
+/// as a merkle list of 64-byte chunks.
+pub async fn sign_message(
+    session: &mut Session,
+    encoded_path: &[u8],
+    message: &[u8],
+) -> Result<Vec<u8>, LedgerError> {
+    let chunks: Vec<Vec<u8>> = message
+        .chunks(MESSAGE_CHUNK_LEN)
+        .map(<[u8]>::to_vec)
+        .collect();
+    let leaves = chunks
+        .iter()
+        .map(|chunk| btc_ledger_hash_leaf(chunk.clone()))
+        .collect();
+
+    let mut payload = encoded_path.to_vec();
+    payload.extend_from_slice(&encode_varint(message.len() as u64));
+    payload.extend_from_slice(&btc_ledger_compute_merkle_root(leaves));
+
+    let mut interpreter = ClientCommandInterpreter::new();
+    interpreter.add_known_list(&chunks);
+
+    let data = run_interactive(session, INS_SIGN_MESSAGE, &payload, &mut interpreter).await?;
+    if data.len() != MESSAGE_SIGNATURE_LEN {
+        return Err(LedgerError::Framing(format!(
+            "Unexpected SIGN_MESSAGE response length {}",
+            data.len()
+        )));
+    }
+    Ok(data)
+}
+
+/// YIELD payload of SIGN_PSBT, `(input_index, pubkey, signature)`. With
+/// `with_pubkey` (app 2.1.0 and newer, see `yields_pubkey`) it is
+/// `varint(input_index) || pubkey_len || pubkey || signature`, otherwise
+/// the pubkey is omitted and comes back empty.
+pub fn parse_yielded_signature(
+    data: &[u8],
+    with_pubkey: bool,
+) -> Result<(u32, Vec<u8>, Vec<u8>), LedgerError> {
+    let (index, read) = read_varint(data)?;
+    let rest = &data[read..];
+    let (pubkey, signature) = if with_pubkey {
+        let len = *rest
+            .first()
+            .ok_or_else(|| LedgerError::Framing("Missing pubkey length".to_string()))?
+            as usize;
+        let pubkey = rest
+            .get(1..=len)
+            .ok_or_else(|| LedgerError::Framing("Pubkey truncated".to_string()))?;
+        (pubkey.to_vec(), &rest[1 + len..])
+    } else {
+        (Vec::new(), rest)
+    };
+    if signature.is_empty() {
+        return Err(LedgerError::Framing("Empty signature".to_string()));
+    }
+    Ok((index as u32, pubkey, signature.to_vec()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::btc_ledger::btc_ledger_build_registered_policy;
+    use crate::ledger::registry;
+    use crate::ledger::testing::{ok, scripted};
+
+    fn interrupt(command: &[u8]) -> Vec<u8> {
+        let mut response = command.to_vec();
+        response.extend_from_slice(&[0xe0, 0x00]);
+        response
+    }
+
+    #[test]
+    fn test_get_preimage_with_more_elements() {
+        let mut interpreter = ClientCommandInterpreter::new();
+        let data = vec![0x42; 300];
+        interpreter.add_known_preimage(data.clone());
+
+        let mut request = vec![CC_GET_PREIMAGE, 0x00];
+        request.extend_from_slice(&btc_ledger_sha256(data.clone()));
+        let reply = interpreter.execute(&request).unwrap();
+
+        // varint(300) is 3 bytes, so 251 bytes fit in the first reply
+        assert_eq!(&reply[..3], &[0xfd, 0x2c, 0x01]);
+        assert_eq!(reply[3], 251);
+        assert_eq!(reply.len(), 255);
+
+        // Other commands are refused until the queue is drained
+        assert!(interpreter.execute(&[CC_YIELD]).is_err());
+
+        let more = interpreter.execute(&[CC_GET_MORE_ELEMENTS]).unwrap();
+        assert_eq!(more[0], 49);
+        assert_eq!(more[1], 1);
+        assert_eq!(&more[2..], &data[251..]);
+        assert!(interpreter.execute(&[CC_GET_MORE_ELEMENTS]).is_err());
+    }
+
+    #[test]
+    fn test_merkle_leaf_proof_and_index() {
+        let mut interpreter = ClientCommandInterpreter::new();
+        let elements: Vec<Vec<u8>> = (0..200u8).map(|i| vec![i]).collect();
+        interpreter.add_known_list(&elements);
+
+        let leaves: Vec<Vec<u8>> = elements
+            .iter()
+            .map(|e| btc_ledger_hash_leaf(e.clone()))
+            .collect();
+        let root = btc_ledger_compute_merkle_root(leaves.clone());
+
+        let mut request = vec![CC_GET_MERKLE_LEAF_PROOF];
+        request.extend_from_slice(&root);
+        request.extend_from_slice(&encode_varint(200));
+        request.extend_from_slice(&encode_varint(137));
+        let reply = interpreter.execute(&request).unwrap();
+
+        let expected = btc_ledger_get_merkle_proof(leaves.clone(), 137).unwrap();
+        assert_eq!(&reply[..32], &leaves[137][..]);
+        assert_eq!(reply[32] as usize, expected.proof_hashes.len());
+        assert_eq!(reply[33], 6);
+        assert_eq!(&reply[34..66], &expected.proof_hashes[0][..]);
+
+        let more = interpreter.execute(&[CC_GET_MORE_ELEMENTS]).unwrap();
+        assert_eq!(more[0] as usize, expected.proof_hashes.len() - 6);
+        assert_eq!(more[1], 32);
+
+        let mut request = vec![CC_GET_MERKLE_LEAF_INDEX];
+        request.extend_from_slice(&root);
+        request.extend_from_slice(&leaves[137]);
+        assert_eq!(interpreter.execute(&request).unwrap(), vec![0x01, 137]);
+
+        let mut request = vec![CC_GET_MERKLE_LEAF_INDEX];
+        request.extend_from_slice(&root);
+        request.extend_from_slice(&[0u8; 32]);
+        assert_eq!(interpreter.execute(&request).unwrap(), vec![0x00, 0x00]);
+    }
+
+    #[test]
+    fn test_parse_yielded_signature() {
+        let mut data = vec![0x02, 0x30, 0x44];
+        data.extend_from_slice(&[0x01; 10]);
+        let (index, pubkey, sig) = parse_yielded_signature(&data, false).unwrap();
+        assert_eq!(index, 2);
+        assert!(pubkey.is_empty());
+        assert_eq!(sig[0], 0x30);
+
+        let mut data = vec![0x01, 33];
+        data.extend_from_slice(&[0x02; 33]);
+        data.extend_from_slice(&[0x30; 71]);
+        let (index, pubkey, sig) = parse_yielded_signature(&data, true).unwrap();
+        assert_eq!(index, 1);
+        assert_eq!(pubkey, vec![0x02; 33]);
+        assert_eq!(sig, vec![0x30; 71]);
+
+        // A short Schnorr signature after a 32-byte x-only key
+        let mut data = vec![0x00, 32];
+        data.extend_from_slice(&[0x03; 32]);
+        data.extend_from_slice(&[0x44; 64]);
+        let (_, pubkey, sig) = parse_yielded_signature(&data, true).unwrap();
+        assert_eq!(pubkey, vec![0x03; 32]);
+        assert_eq!(sig, vec![0x44; 64]);
+
+        assert!(parse_yielded_signature(&[0x01], false).is_err());
+        assert!(parse_yielded_signature(&[0x01, 33, 0x02], true).is_err());
+    }
+
+    #[test]
+    fn test_yields_pubkey() {
+        assert!(yields_pubkey("2.1.0"));
+        assert!(yields_pubkey("2.2.4"));
+        assert!(yields_pubkey("3.0.0-rc1"));
+        assert!(!yields_pubkey("2.0.6"));
+        assert!(!yields_pubkey("1.6.5"));
+    }
+
+    #[tokio::test]
+    async fn test_run_interactive_loop() {
+        let mut yield_one = vec![CC_YIELD, 0x01];
+        yield_one.extend_from_slice(&[0xbb; 64]);
+        let mut yield_zero = vec![CC_YIELD, 0x00];
+        yield_zero.extend_from_slice(&[0xaa; 64]);
+
+        let (conn_id, sent) =
+            scripted(vec![interrupt(&yield_one), interrupt(&yield_zero), ok(&[])]);
+
+        let mut interpreter = ClientCommandInterpreter::new();
+        run_interactive(
+            &mut registry::session(&conn_id).await.unwrap(),
+            INS_SIGN_PSBT,
+            &[0x01],
+            &mut interpreter,
+        )
+        .await
+        .unwrap();
+
+        let sigs = interpreter
+            .yielded()
+            .iter()
+            .map(|y| parse_yielded_signature(y, false).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(sigs[0].0, 1);
+        assert_eq!(sigs[1], (0, Vec::new(), vec![0xaa; 64]));
+
+        let sent = sent.lock().unwrap().clone();
+        assert_eq!(&sent[0][..2], &[CLA_BTC, INS_SIGN_PSBT]);
+        assert_eq!(
+            sent[1],
+            vec![CLA_FRAMEWORK, INS_CONTINUE_INTERRUPTED, 0, 0, 0]
+        );
+        registry::close(&conn_id).await.unwrap();
+    }
+
+    fn multisig_policy() -> WalletPolicy {
+        btc_ledger_build_registered_policy(
+            "Cold 2of2".to_string(),
+            "wsh(sortedmulti(2,@0/**,@1/**))".to_string(),
+            vec![
+                "[76223a6e/48'/1'/0'/2']tpubDE7NQymr4AFtewpAsWtnreyq9ghkzQBXpCZjWLFVRAvnbf7vya2eMTvT2fPapNqL8SuVvLQdbUbMfWLVDCZKnsEBqp6UK93QEzL8Ck23AwF".to_string(),
+                "[f5acc2fd/48'/1'/0'/2']tpubDFAqEGNyad35aBCKUAXbQGDjdVhNueno5ZZVEn3sQbW5ci457gLR7HyTmHBg93oourBssgUxuWz1jX5uhc1qaqFo9VsybY1J5FuedLfm4dK".to_string(),
+            ],
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_register_wallet() {
+        let policy = multisig_policy();
+        let mut response = policy.policy_id.clone();
+        response.extend_from_slice(&[0x5a; 32]);
+
+        let (conn_id, sent) = scripted(vec![ok(&response)]);
+        let hmac = register_wallet(&mut registry::session(&conn_id).await.unwrap(), &policy)
+            .await
+            .unwrap();
+        assert_eq!(hmac, vec![0x5a; 32]);
+
+        let sent = sent.lock().unwrap().clone();
+        assert_eq!(&sent[0][..2], &[CLA_BTC, INS_REGISTER_WALLET]);
+        assert_eq!(sent[0][5] as usize, policy.serialized.len());
+        assert_eq!(&sent[0][6..], &policy.serialized[..]);
+        registry::close(&conn_id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_register_wallet_id_mismatch() {
+        let policy = multisig_policy();
+        let (conn_id, _) = scripted(vec![ok(&[0x11; 64]), ok(&[0x11; 10])]);
+        assert!(matches!(
+            register_wallet(&mut registry::session(&conn_id).await.unwrap(), &policy).await,
+            Err(LedgerError::Framing(_))
+        ));
+        assert!(matches!(
+            register_wallet(&mut registry::session(&conn_id).await.unwrap(), &policy).await,
+            Err(LedgerError::Framing(_))
+        ));
+        registry::close(&conn_id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_sign_message() {
+        let message = vec![0x61; 100];
+        let path =
+            crate::api::btc_ledger::btc_ledger_encode_path("m/44'/0'/0'/0/0".to_string()).unwrap();
+        let chunk_leaves = vec![
+            btc_ledger_hash_leaf(message[..64].to_vec()),
+            btc_ledger_hash_leaf(message[64..].to_vec()),
+        ];
+        let root = btc_ledger_compute_merkle_root(chunk_leaves.clone());
+
+        // The device asks for the second chunk's leaf and preimage
+        let mut proof_request = vec![CC_GET_MERKLE_LEAF_PROOF];
+        proof_request.extend_from_slice(&root);
+        proof_request.extend_from_slice(&[0x02, 0x01]);
+        let mut preimage_request = vec![CC_GET_PREIMAGE, 0x00];
+        let mut preimage = vec![0x00];
+        preimage.extend_from_slice(&message[64..]);
+        preimage_request.extend_from_slice(&btc_ledger_sha256(preimage));
+
+        let signature = [0x1f; MESSAGE_SIGNATURE_LEN];
+        let (conn_id, sent) = scripted(vec![
+            interrupt(&proof_request),
+            interrupt(&preimage_request),
+            ok(&signature),
+            ok(&[0x00; 64]),
+        ]);
+        let mut session = registry::session(&conn_id).await.unwrap();
+        assert_eq!(
+            sign_message(&mut session, &path, &message).await.unwrap(),
+            signature.to_vec()
+        );
+
+        let sent = sent.lock().unwrap().clone();
+        assert_eq!(&sent[0][..2], &[CLA_BTC, INS_SIGN_MESSAGE]);
+        let mut payload = path.clone();
+        payload.push(100);
+        payload.extend_from_slice(&root);
+        assert_eq!(&sent[0][5..], &payload[..]);
+        // Leaf hash of chunk 1 with its proof (the leaf of chunk 0)
+        assert_eq!(&sent[1][5..37], &chunk_leaves[1][..]);
+        assert_eq!(&sent[1][37..39], &[1, 1]);
+        assert_eq!(&sent[2][5..8], &[37, 37, 0x00]);
+
+        // A truncated signature is refused
+        assert!(matches!(
+            sign_message(&mut session, &path, &message).await,
+            Err(LedgerError::Framing(_))
+        ));
+        drop(session);
+        registry::close(&conn_id).await.unwrap();
+    }
+}
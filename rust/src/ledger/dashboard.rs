@@ -0,0 +1,200 @@
+use crate::ledger::device::{identify_target_id, DeviceModelInfo};
+use crate::ledger::registry::Session;
+use crate::ledger::transport::LedgerError;
+
+const CLA_BOLOS: u8 = 0xb0;
+const CLA_DASHBOARD: u8 = 0xe0;
+
+const INS_GET_APP_AND_VERSION: u8 = 0x01;
+const INS_QUIT_APP: u8 = 0xa7;
+const INS_GET_VERSION: u8 = 0x01;
+const INS_OPEN_APP: u8 = 0xd8;
+
+/// Name of the dashboard as reported by GET_APP_AND_VERSION when no app runs.
+pub const DASHBOARD_APP_NAME: &str = "BOLOS";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AppInfo {
+    pub name: String,
+    pub version: String,
+    pub flags: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DeviceVersion {
+    pub target_id: u32,
+    pub se_version: String,
+    pub mcu_version: Option<String>,
+    pub flags: Vec<u8>,
+    pub model: Option<&'static DeviceModelInfo>,
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Reader { data, offset: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.offset >= self.data.len()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], LedgerError> {
+        let end = self.offset + len;
+        if end > self.data.len() {
+            return Err(LedgerError::Framing(format!(
+                "Response truncated: need {} bytes, have {}",
+                end,
+                self.data.len()
+            )));
+        }
+        let slice = &self.data[self.offset..end];
+        self.offset = end;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8, LedgerError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn lv(&mut self) -> Result<&'a [u8], LedgerError> {
+        let len = self.u8()? as usize;
+        self.take(len)
+    }
+
+    fn lv_string(&mut self) -> Result<String, LedgerError> {
+        let bytes = self.lv()?;
+        // MCU versions are NUL terminated on older firmwares
+        let bytes = bytes.strip_suffix(&[0]).unwrap_or(bytes);
+        String::from_utf8(bytes.to_vec()).map_err(|e| LedgerError::Framing(e.to_string()))
+    }
+}
+
+/// Response: format(1) || name_len || name || version_len || version
+/// || [flags_len || flags]
+pub fn parse_app_and_version(data: &[u8]) -> Result<AppInfo, LedgerError> {
+    let mut reader = Reader::new(data);
+    let format = reader.u8()?;
+    if format != 0x01 {
+        return Err(LedgerError::Framing(format!(
+            "Unsupported app info format: {}",
+            format
+        )));
+    }
+    let name = reader.lv_string()?;
+    let version = reader.lv_string()?;
+    let flags = if reader.is_empty() {
+        Vec::new()
+    } else {
+        reader.lv()?.to_vec()
+    };
+
+    Ok(AppInfo {
+        name,
+        version,
+        flags,
+    })
+}
+
+/// Response: target_id(4, BE) || se_version_len || se_version || flags_len
+/// || flags || [mcu_version_len || mcu_version] || ...
+pub fn parse_device_version(data: &[u8]) -> Result<DeviceVersion, LedgerError> {
+    let mut reader = Reader::new(data);
+    let target_id = u32::from_be_bytes(reader.take(4)?.try_into().unwrap());
+    let se_version = reader.lv_string()?;
+    let flags = reader.lv()?.to_vec();
+    let mcu_version = if reader.is_empty() {
+        None
+    } else {
+        Some(reader.lv_string()?)
+    };
+
+    Ok(DeviceVersion {
+        target_id,
+        se_version,
+        mcu_version,
+        flags,
+        model: identify_target_id(target_id),
+    })
+}
+
+/// Name and version of the app currently running ("BOLOS" for the dashboard).
+pub async fn get_app_and_version(session: &mut Session) -> Result<AppInfo, LedgerError> {
+    let data = session
+        .send(CLA_BOLOS, INS_GET_APP_AND_VERSION, 0x00, 0x00, &[])
+        .await?;
+    parse_app_and_version(&data)
+}
+
+/// Firmware version and model. Only answered by the dashboard; apps
+/// reuse this instruction for their own purposes.
+pub async fn get_version(session: &mut Session) -> Result<DeviceVersion, LedgerError> {
+    let data = session
+        .send(CLA_DASHBOARD, INS_GET_VERSION, 0x00, 0x00, &[])
+        .await?;
+    parse_device_version(&data)
+}
+
+/// Ask the dashboard to launch `app_name`. The user has to confirm on the
+/// device; USB devices re-enumerate afterwards so HID callers should reopen.
+pub async fn open_app(session: &mut Session, app_name: &str) -> Result<(), LedgerError> {
+    session
+        .send(CLA_DASHBOARD, INS_OPEN_APP, 0x00, 0x00, app_name.as_bytes())
+        .await?;
+    Ok(())
+}
+
+/// Exit the running app and return to the dashboard.
+pub async fn quit_app(session: &mut Session) -> Result<(), LedgerError> {
+    session
+        .send(CLA_BOLOS, INS_QUIT_APP, 0x00, 0x00, &[])
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_app_and_version() {
+        let mut data = vec![0x01, 0x07];
+        data.extend_from_slice(b"Bitcoin");
+        data.push(0x05);
+        data.extend_from_slice(b"2.1.3");
+        data.extend_from_slice(&[0x01, 0x02]);
+
+        let info = parse_app_and_version(&data).unwrap();
+        assert_eq!(info.name, "Bitcoin");
+        assert_eq!(info.version, "2.1.3");
+        assert_eq!(info.flags, vec![0x02]);
+
+        let mut dashboard = vec![0x01, 0x05];
+        dashboard.extend_from_slice(DASHBOARD_APP_NAME.as_bytes());
+        dashboard.push(0x05);
+        dashboard.extend_from_slice(b"1.0.0");
+        let info = parse_app_and_version(&dashboard).unwrap();
+        assert_eq!(info.name, DASHBOARD_APP_NAME);
+        assert!(info.flags.is_empty());
+
+        assert!(parse_app_and_version(&[0x01, 0x07, b'B']).is_err());
+    }
+
+    #[test]
+    fn test_parse_device_version() {
+        let mut data = vec![0x33, 0x10, 0x00, 0x04, 0x05];
+        data.extend_from_slice(b"1.1.1");
+        data.extend_from_slice(&[0x04, 0x00, 0x00, 0x00, 0x00, 0x05]);
+        data.extend_from_slice(b"5.24\0");
+
+        let version = parse_device_version(&data).unwrap();
+        assert_eq!(version.target_id, 0x33100004);
+        assert_eq!(version.se_version, "1.1.1");
+        assert_eq!(version.mcu_version.as_deref(), Some("5.24"));
+        assert_eq!(version.model.unwrap().id, "nanoSP");
+    }
+}
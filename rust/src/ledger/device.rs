@@ -6,6 +6,8 @@ pub struct DeviceModelInfo {
     pub product_name: &'static str,
     pub product_id_mm: u16,
     pub legacy_usb_product_id: u16,
+    /// Upper 16 bits of the target id reported by GET_VERSION
+    pub masks: &'static [u32],
 }
 
 pub const KNOWN_DEVICES: &[DeviceModelInfo] = &[
@@ -14,42 +16,49 @@ pub const KNOWN_DEVICES: &[DeviceModelInfo] = &[
         product_name: "Ledger Blue",
         product_id_mm: 0x00,
         legacy_usb_product_id: 0x0000,
+        masks: &[0x31000000, 0x31010000],
     },
     DeviceModelInfo {
         id: "nanoS",
         product_name: "Ledger Nano S",
         product_id_mm: 0x10,
         legacy_usb_product_id: 0x0001,
+        masks: &[0x31100000],
     },
     DeviceModelInfo {
         id: "nanoX",
         product_name: "Ledger Nano X",
         product_id_mm: 0x40,
         legacy_usb_product_id: 0x0004,
+        masks: &[0x33000000],
     },
     DeviceModelInfo {
         id: "nanoSP",
         product_name: "Ledger Nano S Plus",
         product_id_mm: 0x50,
         legacy_usb_product_id: 0x0005,
+        masks: &[0x33100000],
     },
     DeviceModelInfo {
         id: "stax",
         product_name: "Ledger Stax",
         product_id_mm: 0x60,
         legacy_usb_product_id: 0x0006,
+        masks: &[0x33200000],
     },
     DeviceModelInfo {
         id: "flex",
         product_name: "Ledger Flex",
         product_id_mm: 0x70,
         legacy_usb_product_id: 0x0007,
+        masks: &[0x33300000],
     },
     DeviceModelInfo {
         id: "apex",
         product_name: "Ledger Nano Gen5",
         product_id_mm: 0x80,
         legacy_usb_product_id: 0x0008,
+        masks: &[0x33400000],
     },
 ];
 
@@ -66,6 +75,11 @@ pub fn identify_usb_product_id(product_id: u16) -> Option<&'static DeviceModelIn
     KNOWN_DEVICES.iter().find(|d| d.product_id_mm == mm)
 }
 
+pub fn identify_target_id(target_id: u32) -> Option<&'static DeviceModelInfo> {
+    let mask = target_id & 0xffff0000;
+    KNOWN_DEVICES.iter().find(|d| d.masks.contains(&mask))
+}
+
 /// BLE service UUIDs for Ledger devices that support Bluetooth
 pub struct BleServiceInfo {
     pub model_id: &'static str,
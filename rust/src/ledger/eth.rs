@@ -0,0 +1,268 @@
+use crate::ledger::registry::Session;
+use crate::ledger::transport::{encode_path, LedgerError};
+
+const CLA: u8 = 0xe0;
+
+const INS_GET_ADDRESS: u8 = 0x02;
+const INS_SIGN_TX: u8 = 0x04;
+const INS_GET_APP_CONFIGURATION: u8 = 0x06;
+const INS_SIGN_PERSONAL_MESSAGE: u8 = 0x08;
+const INS_SIGN_EIP712_HASHED: u8 = 0x0c;
+
+const P1_FIRST_CHUNK: u8 = 0x00;
+const P1_MORE_CHUNKS: u8 = 0x80;
+
+const MAX_CHUNK_SIZE: usize = 150;
+
+pub const SIGNATURE_LEN: usize = 65;
+
+#[derive(Debug, Clone)]
+pub struct EthAddress {
+    pub public_key: Vec<u8>,
+    pub address: String,
+    pub chain_code: Option<Vec<u8>>,
+}
+
+/// Response: pubkey_len || pubkey || addr_len || addr(ascii hex, no 0x)
+/// || [chain_code(32)]
+pub fn parse_address(data: &[u8], with_chain_code: bool) -> Result<EthAddress, LedgerError> {
+    let truncated = || LedgerError::Framing("Address response truncated".to_string());
+
+    let pk_len = *data.first().ok_or_else(truncated)? as usize;
+    let public_key = data.get(1..1 + pk_len).ok_or_else(truncated)?.to_vec();
+    let addr_len = *data.get(1 + pk_len).ok_or_else(truncated)? as usize;
+    let addr_start = 2 + pk_len;
+    let addr = data
+        .get(addr_start..addr_start + addr_len)
+        .ok_or_else(truncated)?;
+    let address = format!(
+        "0x{}",
+        std::str::from_utf8(addr).map_err(|e| LedgerError::Framing(e.to_string()))?
+    );
+    let chain_code = if with_chain_code {
+        let start = addr_start + addr_len;
+        Some(data.get(start..start + 32).ok_or_else(truncated)?.to_vec())
+    } else {
+        None
+    };
+
+    Ok(EthAddress {
+        public_key,
+        address,
+        chain_code,
+    })
+}
+
+/// The app answers `v || r || s`, the wallet core expects `r || s || v`.
+pub fn signature_from_response(data: &[u8]) -> Result<Vec<u8>, LedgerError> {
+    if data.len() != SIGNATURE_LEN {
+        return Err(LedgerError::Framing(format!(
+            "Signature must be {} bytes, got {}",
+            SIGNATURE_LEN,
+            data.len()
+        )));
+    }
+    let mut sig = Vec::with_capacity(SIGNATURE_LEN);
+    sig.extend_from_slice(&data[1..]);
+    sig.push(data[0]);
+    Ok(sig)
+}
+
+/// Split a personal message into APDU payloads. The first one carries the
+/// path and the total message length.
+pub fn personal_message_chunks(path: &[u32], message: &[u8]) -> Vec<Vec<u8>> {
+    let header = encode_path(path);
+    let first_size = MAX_CHUNK_SIZE - header.len() - 4;
+    let split = first_size.min(message.len());
+
+    let mut first = header;
+    first.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    first.extend_from_slice(&message[..split]);
+
+    let mut chunks = vec![first];
+    chunks.extend(message[split..].chunks(MAX_CHUNK_SIZE).map(|c| c.to_vec()));
+    chunks
+}
+
+/// Ethereum app version as `major.minor.patch`.
+pub async fn get_app_version(session: &mut Session) -> Result<String, LedgerError> {
+    let data = session
+        .send(CLA, INS_GET_APP_CONFIGURATION, 0x00, 0x00, &[])
+        .await?;
+    if data.len() < 4 {
+        return Err(LedgerError::Framing(
+            "App configuration truncated".to_string(),
+        ));
+    }
+    Ok(format!("{}.{}.{}", data[1], data[2], data[3]))
+}
+
+pub async fn get_address(
+    session: &mut Session,
+    path: &[u32],
+    display: bool,
+    with_chain_code: bool,
+    chain_id: Option<u64>,
+) -> Result<EthAddress, LedgerError> {
+    let mut payload = encode_path(path);
+    if let Some(chain_id) = chain_id {
+        payload.extend_from_slice(&chain_id.to_be_bytes());
+    }
+    let data = session
+        .send(
+            CLA,
+            INS_GET_ADDRESS,
+            display as u8,
+            with_chain_code as u8,
+            &payload,
+        )
+        .await?;
+    parse_address(&data, with_chain_code)
+}
+
+/// Sign a legacy or EIP-1559 transaction. `chunks` are the APDU payloads
+/// produced by `encode_tx_rlp` (path already prepended to the first one).
+pub async fn sign_transaction(
+    session: &mut Session,
+    chunks: &[Vec<u8>],
+) -> Result<Vec<u8>, LedgerError> {
+    if chunks.is_empty() {
+        return Err(LedgerError::InvalidData);
+    }
+    let mut response = Vec::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let p1 = if i == 0 {
+            P1_FIRST_CHUNK
+        } else {
+            P1_MORE_CHUNKS
+        };
+        response = session.send(CLA, INS_SIGN_TX, p1, 0x00, chunk).await?;
+    }
+    signature_from_response(&response)
+}
+
+pub async fn sign_personal_message(
+    session: &mut Session,
+    path: &[u32],
+    message: &[u8],
+) -> Result<Vec<u8>, LedgerError> {
+    let mut response = Vec::new();
+    for (i, chunk) in personal_message_chunks(path, message).iter().enumerate() {
+        let p1 = if i == 0 {
+            P1_FIRST_CHUNK
+        } else {
+            P1_MORE_CHUNKS
+        };
+        response = session
+            .send(CLA, INS_SIGN_PERSONAL_MESSAGE, p1, 0x00, chunk)
+            .await?;
+    }
+    signature_from_response(&response)
+}
+
+/// Sign an EIP-712 message from its domain separator and struct hash.
+pub async fn sign_eip712_hashed(
+    session: &mut Session,
+    path: &[u32],
+    domain_separator: &[u8],
+    hash_struct_message: &[u8],
+) -> Result<Vec<u8>, LedgerError> {
+    if domain_separator.len() != 32 || hash_struct_message.len() != 32 {
+        return Err(LedgerError::InvalidData);
+    }
+    let mut payload = encode_path(path);
+    payload.extend_from_slice(domain_separator);
+    payload.extend_from_slice(hash_struct_message);
+
+    let data = session
+        .send(CLA, INS_SIGN_EIP712_HASHED, 0x00, 0x00, &payload)
+        .await?;
+    signature_from_response(&data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger::registry;
+    use crate::ledger::testing::{ok, scripted};
+    use crate::ledger::transport::bip44_path;
+
+    fn device_signature() -> Vec<u8> {
+        let mut data = vec![0x1b];
+        data.extend_from_slice(&[0x11; 32]);
+        data.extend_from_slice(&[0x22; 32]);
+        data
+    }
+
+    #[test]
+    fn test_parse_address() {
+        let mut data = vec![65];
+        data.extend_from_slice(&[0x04; 65]);
+        data.push(40);
+        data.extend_from_slice(b"ab5801a7d398351b8be11c439e05c5b3259aec9b");
+        data.extend_from_slice(&[0xcc; 32]);
+
+        let addr = parse_address(&data, true).unwrap();
+        assert_eq!(addr.public_key.len(), 65);
+        assert_eq!(addr.address, "0xab5801a7d398351b8be11c439e05c5b3259aec9b");
+        assert_eq!(addr.chain_code, Some(vec![0xcc; 32]));
+
+        assert!(parse_address(&data[..50], false).is_err());
+    }
+
+    #[test]
+    fn test_signature_from_response() {
+        let sig = signature_from_response(&device_signature()).unwrap();
+        assert_eq!(&sig[..32], &[0x11; 32]);
+        assert_eq!(&sig[32..64], &[0x22; 32]);
+        assert_eq!(sig[64], 0x1b);
+
+        assert!(signature_from_response(&[0x00; 64]).is_err());
+    }
+
+    #[test]
+    fn test_personal_message_chunks() {
+        let path = bip44_path(60, 0, 0, 0);
+        let message = vec![0xaa; 300];
+        let chunks = personal_message_chunks(&path, &message);
+
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[0].len(), MAX_CHUNK_SIZE);
+        assert_eq!(&chunks[0][21..25], &300u32.to_be_bytes());
+        assert_eq!(chunks[1].len(), MAX_CHUNK_SIZE);
+        let total: usize = chunks[0].len() - 25 + chunks[1].len() + chunks[2].len();
+        assert_eq!(total, message.len());
+
+        let short = personal_message_chunks(&path, b"hi");
+        assert_eq!(short.len(), 1);
+        assert_eq!(&short[0][25..], b"hi");
+    }
+
+    #[tokio::test]
+    async fn test_sign_transaction_chunks() {
+        let (conn_id, sent) = scripted(vec![ok(&[]), ok(&device_signature())]);
+        let chunks = vec![vec![0x01; 150], vec![0x02; 10]];
+
+        let sig = sign_transaction(&mut registry::session(&conn_id).await.unwrap(), &chunks)
+            .await
+            .unwrap();
+        assert_eq!(sig[64], 0x1b);
+
+        let sent = sent.lock().unwrap().clone();
+        assert_eq!(&sent[0][..5], &[0xe0, 0x04, 0x00, 0x00, 150]);
+        assert_eq!(&sent[1][..5], &[0xe0, 0x04, 0x80, 0x00, 10]);
+        registry::close(&conn_id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_sign_transaction_rejected() {
+        let (conn_id, _) = scripted(vec![vec![0x69, 0x85]]);
+        let result = sign_transaction(
+            &mut registry::session(&conn_id).await.unwrap(),
+            &[vec![0x01]],
+        )
+        .await;
+        assert!(matches!(result, Err(LedgerError::UserRejected)));
+        registry::close(&conn_id).await.unwrap();
+    }
+}
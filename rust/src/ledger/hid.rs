@@ -1,10 +1,18 @@
-use hidapi::HidApi;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use futures::future::BoxFuture;
+use hidapi::{HidApi, HidDevice};
 
 use crate::ledger::device::{identify_usb_product_id, LEDGER_VENDOR_ID};
 use crate::ledger::framing::{unwrap_hid_response, wrap_hid_apdu, HID_PACKET_SIZE, LEDGER_CHANNEL};
-use crate::ledger::transport::LedgerError;
+use crate::ledger::transport::{blocking_io, LedgerError, LedgerTransport};
 
 const READ_TIMEOUT_MS: i32 = 5000;
+const READ_TIMEOUT: Duration = Duration::from_millis(READ_TIMEOUT_MS as u64);
+const POLL_TIMEOUT_MS: i32 = 100;
+const MAX_READ_ATTEMPTS: u32 = 100;
 
 #[derive(Debug)]
 pub struct HidDeviceInfo {
@@ -49,7 +57,7 @@ pub fn list_devices() -> Result<Vec<HidDeviceInfo>, LedgerError> {
 }
 
 pub struct HidLedgerTransport {
-    device: hidapi::HidDevice,
+    device: Arc<Mutex<HidDevice>>,
 }
 
 impl HidLedgerTransport {
@@ -60,51 +68,34 @@ impl HidLedgerTransport {
         let device = api
             .open_path(&path)
             .map_err(|e| LedgerError::Io(format!("Failed to open device: {}", e)))?;
-        Ok(HidLedgerTransport { device })
+        Ok(HidLedgerTransport {
+            device: Arc::new(Mutex::new(device)),
+        })
     }
 
-    pub fn exchange(&self, apdu: &[u8]) -> Result<Vec<u8>, LedgerError> {
-        let packets = wrap_hid_apdu(LEDGER_CHANNEL, apdu, HID_PACKET_SIZE);
-
-        // Write all packets
-        for pkt in packets.iter() {
-            // hidapi write expects a report ID byte prepended on some platforms
-            let mut report = vec![0x00]; // Report ID 0
-            report.extend_from_slice(pkt);
-            self.device
-                .write(&report)
-                .map_err(|e| LedgerError::Io(format!("Write failed: {}", e)))?;
-        }
-
-        // Read response packets
-        let mut response_data = Vec::new();
-        let mut attempts = 0;
-        let max_attempts = 100;
-
-        loop {
-            let mut buf = [0u8; HID_PACKET_SIZE];
-            let n = self
-                .device
-                .read_timeout(&mut buf, READ_TIMEOUT_MS)
-                .map_err(|e| LedgerError::Io(format!("Read failed: {}", e)))?;
-
-            if n == 0 {
-                attempts += 1;
-                if attempts >= max_attempts {
-                    return Err(LedgerError::Timeout);
+    /// Runs on the blocking pool and reads in short slices, giving up
+    /// between them once the exchange is cancelled.
+    async fn exchange_async(&mut self, apdu: &[u8]) -> Result<Vec<u8>, LedgerError> {
+        let device = Arc::clone(&self.device);
+        let apdu = apdu.to_vec();
+
+        blocking_io(move |stop| {
+            let device = lock(&device)?;
+            write_apdu(&device, &apdu)?;
+
+            let deadline = Instant::now() + READ_TIMEOUT * MAX_READ_ATTEMPTS;
+            let mut response_data = Vec::new();
+            while Instant::now() < deadline {
+                if stop.load(Ordering::Relaxed) {
+                    return Err(LedgerError::Cancelled);
+                }
+                if let Some(result) = read_packet(&device, POLL_TIMEOUT_MS, &mut response_data)? {
+                    return Ok(result);
                 }
-                continue;
-            }
-
-            response_data.extend_from_slice(&buf[..n]);
-
-            // Try to unwrap the accumulated response
-            if let Some(result) =
-                unwrap_hid_response(LEDGER_CHANNEL, &response_data, HID_PACKET_SIZE)
-            {
-                return Ok(result);
             }
-        }
+            Err(LedgerError::Timeout)
+        })
+        .await
     }
 
     pub fn close(&mut self) -> Result<(), LedgerError> {
@@ -112,3 +103,49 @@ impl HidLedgerTransport {
         Ok(())
     }
 }
+
+fn lock(device: &Mutex<HidDevice>) -> Result<MutexGuard<'_, HidDevice>, LedgerError> {
+    device.lock().map_err(|e| LedgerError::Io(e.to_string()))
+}
+
+fn write_apdu(device: &HidDevice, apdu: &[u8]) -> Result<(), LedgerError> {
+    let packets = wrap_hid_apdu(LEDGER_CHANNEL, apdu, HID_PACKET_SIZE);
+
+    for pkt in packets.iter() {
+        // hidapi write expects a report ID byte prepended on some platforms
+        let mut report = vec![0x00]; // Report ID 0
+        report.extend_from_slice(pkt);
+        device
+            .write(&report)
+            .map_err(|e| LedgerError::Io(format!("Write failed: {}", e)))?;
+    }
+    Ok(())
+}
+
+/// Read one packet into `buffer`. Returns the response once all of its
+/// packets arrived, `None` while more are expected or on read timeout.
+fn read_packet(
+    device: &HidDevice,
+    timeout_ms: i32,
+    buffer: &mut Vec<u8>,
+) -> Result<Option<Vec<u8>>, LedgerError> {
+    let mut buf = [0u8; HID_PACKET_SIZE];
+    let n = device
+        .read_timeout(&mut buf, timeout_ms)
+        .map_err(|e| LedgerError::Io(format!("Read failed: {}", e)))?;
+    if n == 0 {
+        return Ok(None);
+    }
+    buffer.extend_from_slice(&buf[..n]);
+    Ok(unwrap_hid_response(LEDGER_CHANNEL, buffer, HID_PACKET_SIZE))
+}
+
+impl LedgerTransport for HidLedgerTransport {
+    fn exchange<'a>(&'a mut self, apdu: &'a [u8]) -> BoxFuture<'a, Result<Vec<u8>, LedgerError>> {
+        Box::pin(HidLedgerTransport::exchange_async(self, apdu))
+    }
+
+    fn close(&mut self) -> BoxFuture<'_, Result<(), LedgerError>> {
+        Box::pin(async move { HidLedgerTransport::close(self) })
+    }
+}
@@ -1,5 +1,16 @@
 pub mod ble;
+pub mod btc;
+pub mod dashboard;
 pub mod device;
+pub mod eth;
 pub mod framing;
 pub mod hid;
+pub mod registry;
+pub mod replay;
+pub mod solana;
+pub mod speculos;
 pub mod transport;
+pub mod tron;
+pub mod zilliqa;
+#[cfg(test)]
+mod testing;
@@ -0,0 +1,391 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+use lazy_static::lazy_static;
+use tokio::sync::{watch, Mutex, OwnedMutexGuard};
+
+use crate::ledger::transport::{build_apdu, expect_ok, Detached, LedgerError, LedgerTransport};
+
+/// A registered device. The transport stays locked for a whole `Session`,
+/// so operations on one connection run one at a time, in call order
+/// (tokio's mutex is fair), and the APDUs of two multi-step flows never
+/// interleave. Bumping `cancel` aborts every session queued or running at
+/// that moment.
+pub struct Connection {
+    transport: Arc<Mutex<Box<dyn LedgerTransport>>>,
+    cancel: watch::Sender<u64>,
+}
+
+pub type SharedConnection = Arc<Connection>;
+
+lazy_static! {
+    static ref TRANSPORT_REGISTRY: RwLock<HashMap<String, SharedConnection>> =
+        RwLock::new(HashMap::new());
+}
+
+fn next_connection_id() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(1);
+    format!("conn_{}", COUNTER.fetch_add(1, Ordering::Relaxed))
+}
+
+/// Store an opened transport and return the connection id used by every
+/// later call.
+pub fn register(transport: Box<dyn LedgerTransport>) -> Result<String, LedgerError> {
+    let conn_id = next_connection_id();
+    let mut registry = TRANSPORT_REGISTRY
+        .write()
+        .map_err(|e| LedgerError::Io(e.to_string()))?;
+    let connection = Connection {
+        transport: Arc::new(Mutex::new(transport)),
+        cancel: watch::channel(0).0,
+    };
+    registry.insert(conn_id.clone(), Arc::new(connection));
+    Ok(conn_id)
+}
+
+pub fn get(connection_id: &str) -> Result<SharedConnection, LedgerError> {
+    let registry = TRANSPORT_REGISTRY
+        .read()
+        .map_err(|e| LedgerError::Io(e.to_string()))?;
+    registry
+        .get(connection_id)
+        .cloned()
+        .ok_or_else(|| LedgerError::ConnectionNotFound(connection_id.to_string()))
+}
+
+fn remove(connection_id: &str) -> Result<Option<SharedConnection>, LedgerError> {
+    let mut registry = TRANSPORT_REGISTRY
+        .write()
+        .map_err(|e| LedgerError::Io(e.to_string()))?;
+    Ok(registry.remove(connection_id))
+}
+
+/// Exclusive use of a connection for one app-level operation. Holds the
+/// device lock until dropped, so a signing flow spanning several APDUs is
+/// not interleaved with another caller's commands.
+pub struct Session {
+    connection_id: String,
+    transport: OwnedMutexGuard<Box<dyn LedgerTransport>>,
+    cancelled: watch::Receiver<u64>,
+    /// Set once an in-flight exchange was aborted and the connection dropped
+    aborted: bool,
+}
+
+/// Wait for every session queued before this one and take the connection.
+/// Fails with `Cancelled` if `cancel` is called while waiting.
+pub async fn session(connection_id: &str) -> Result<Session, LedgerError> {
+    let entry = get(connection_id)?;
+    let mut cancelled = entry.cancel.subscribe();
+
+    let transport = tokio::select! {
+        transport = Arc::clone(&entry.transport).lock_owned() => transport,
+        _ = cancelled.changed() => return Err(LedgerError::Cancelled),
+    };
+
+    Ok(Session {
+        connection_id: connection_id.to_string(),
+        transport,
+        cancelled,
+        aborted: false,
+    })
+}
+
+impl Session {
+    /// Send `apdu` and return the raw response, status word included.
+    /// Status words are left to the caller, only transport failures are
+    /// errors. Fails with `Cancelled` if `cancel` was called since the
+    /// session started or is called before the device answers.
+    pub async fn exchange(&mut self, apdu: &[u8]) -> Result<Vec<u8>, LedgerError> {
+        if self.aborted || self.cancelled.has_changed().unwrap_or(true) {
+            return Err(LedgerError::Cancelled);
+        }
+        let response = tokio::select! {
+            response = self.transport.exchange(apdu) => Some(response),
+            _ = self.cancelled.changed() => None,
+        };
+        let Some(response) = response else {
+            // The device may still answer the aborted APDU, and that answer
+            // would be read as the reply to the next one. Drop the connection.
+            self.aborted = true;
+            self.transport.close().await.ok();
+            remove(&self.connection_id)?;
+            return Err(LedgerError::Cancelled);
+        };
+
+        response
+    }
+
+    /// Send a single command and return its response data. Anything other
+    /// than 0x9000 is an error.
+    pub async fn send(
+        &mut self,
+        cla: u8,
+        ins: u8,
+        p1: u8,
+        p2: u8,
+        data: &[u8],
+    ) -> Result<Vec<u8>, LedgerError> {
+        let apdu = build_apdu(cla, ins, p1, p2, data)?;
+        let response = self.exchange(&apdu).await?;
+        expect_ok(response)
+    }
+}
+
+/// Queue `apdu` behind any session already running on the connection and
+/// return the raw response, status word included.
+pub async fn exchange(connection_id: &str, apdu: &[u8]) -> Result<Vec<u8>, LedgerError> {
+    session(connection_id).await?.exchange(apdu).await
+}
+
+/// Abort every session queued or running on `connection_id`. An aborted
+/// in-flight exchange also closes the connection, later calls must reopen.
+pub fn cancel(connection_id: &str) -> Result<(), LedgerError> {
+    let entry = get(connection_id)?;
+    entry
+        .cancel
+        .send_modify(|generation| *generation = generation.wrapping_add(1));
+    Ok(())
+}
+
+/// Replace the transport behind `connection_id` with `f(transport)`,
+/// keeping the connection id. Used to layer recording on a live session.
+pub async fn wrap<F>(connection_id: &str, f: F) -> Result<(), LedgerError>
+where
+    F: FnOnce(Box<dyn LedgerTransport>) -> Box<dyn LedgerTransport>,
+{
+    let entry = get(connection_id)?;
+    let mut transport = entry.transport.lock().await;
+    let inner = std::mem::replace(&mut *transport, Box::new(Detached));
+    *transport = f(inner);
+    Ok(())
+}
+
+/// Undo the last `wrap` on `connection_id`: the wrapped transport goes back
+/// behind the id and the outer layer is returned to the caller.
+pub async fn unwrap(connection_id: &str) -> Result<Box<dyn LedgerTransport>, LedgerError> {
+    let entry = get(connection_id)?;
+    let mut transport = entry.transport.lock().await;
+    let inner = transport
+        .take_inner()
+        .ok_or_else(|| LedgerError::Io(format!("Connection {} is not wrapped", connection_id)))?;
+    Ok(std::mem::replace(&mut *transport, inner))
+}
+
+/// Remove the connection from the registry and close the underlying
+/// transport. Unknown ids are ignored.
+pub async fn close(connection_id: &str) -> Result<(), LedgerError> {
+    if let Some(entry) = remove(connection_id)? {
+        let mut transport = entry.transport.lock().await;
+        transport.close().await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future::BoxFuture;
+
+    struct EchoTransport;
+
+    impl LedgerTransport for EchoTransport {
+        fn exchange<'a>(
+            &'a mut self,
+            apdu: &'a [u8],
+        ) -> BoxFuture<'a, Result<Vec<u8>, LedgerError>> {
+            Box::pin(async move {
+                let mut response = apdu.to_vec();
+                response.extend_from_slice(&[0x90, 0x00]);
+                Ok(response)
+            })
+        }
+
+        fn close(&mut self) -> BoxFuture<'_, Result<(), LedgerError>> {
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_registry_exchange_and_close() {
+        let conn_id = register(Box::new(EchoTransport)).unwrap();
+
+        let response = exchange(&conn_id, &[0xe0, 0x01]).await.unwrap();
+        assert_eq!(response, vec![0xe0, 0x01, 0x90, 0x00]);
+
+        close(&conn_id).await.unwrap();
+
+        let result = exchange(&conn_id, &[0xe0, 0x01]).await;
+        assert!(matches!(result, Err(LedgerError::ConnectionNotFound(_))));
+    }
+
+    /// Logs when each exchange starts and ends, yielding in between so a
+    /// second caller gets a chance to interleave.
+    struct SlowTransport {
+        log: Arc<std::sync::Mutex<Vec<String>>>,
+    }
+
+    impl LedgerTransport for SlowTransport {
+        fn exchange<'a>(
+            &'a mut self,
+            apdu: &'a [u8],
+        ) -> BoxFuture<'a, Result<Vec<u8>, LedgerError>> {
+            Box::pin(async move {
+                self.log
+                    .lock()
+                    .unwrap()
+                    .push(format!("start {:02x}", apdu[1]));
+                tokio::task::yield_now().await;
+                self.log
+                    .lock()
+                    .unwrap()
+                    .push(format!("end {:02x}", apdu[1]));
+                Ok(vec![0x90, 0x00])
+            })
+        }
+
+        fn close(&mut self) -> BoxFuture<'_, Result<(), LedgerError>> {
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    struct HangingTransport;
+
+    impl LedgerTransport for HangingTransport {
+        fn exchange<'a>(
+            &'a mut self,
+            _apdu: &'a [u8],
+        ) -> BoxFuture<'a, Result<Vec<u8>, LedgerError>> {
+            Box::pin(futures::future::pending())
+        }
+
+        fn close(&mut self) -> BoxFuture<'_, Result<(), LedgerError>> {
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    /// Answers every APDU with the status word in its data.
+    struct StatusTransport;
+
+    impl LedgerTransport for StatusTransport {
+        fn exchange<'a>(
+            &'a mut self,
+            apdu: &'a [u8],
+        ) -> BoxFuture<'a, Result<Vec<u8>, LedgerError>> {
+            Box::pin(async move { Ok(apdu[5..].to_vec()) })
+        }
+
+        fn close(&mut self) -> BoxFuture<'_, Result<(), LedgerError>> {
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_exchange_passes_status_words_through() {
+        let conn_id = register(Box::new(StatusTransport)).unwrap();
+
+        for sw in [[0x69, 0x85], [0x6a, 0x80]] {
+            let apdu = build_apdu(0xe0, 0x04, 0x00, 0x00, &sw).unwrap();
+            let response = exchange(&conn_id, &apdu).await.unwrap();
+            assert_eq!(response, sw.to_vec());
+        }
+
+        let mut session = session(&conn_id).await.unwrap();
+        let rejected = session.send(0xe0, 0x04, 0x00, 0x00, &[0x69, 0x85]).await;
+        assert!(matches!(rejected, Err(LedgerError::UserRejected)));
+        let invalid = session.send(0xe0, 0x04, 0x00, 0x00, &[0x6a, 0x80]).await;
+        assert!(matches!(invalid, Err(LedgerError::InvalidData)));
+        drop(session);
+
+        close(&conn_id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_exchanges_are_serialized() {
+        let log = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let conn_id = register(Box::new(SlowTransport {
+            log: Arc::clone(&log),
+        }))
+        .unwrap();
+
+        let (a, b) = tokio::join!(
+            exchange(&conn_id, &[0xe0, 0x01]),
+            exchange(&conn_id, &[0xe0, 0x02])
+        );
+        assert!(a.is_ok() && b.is_ok());
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec!["start 01", "end 01", "start 02", "end 02"]
+        );
+        close(&conn_id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_sessions_do_not_interleave() {
+        let log = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let conn_id = register(Box::new(SlowTransport {
+            log: Arc::clone(&log),
+        }))
+        .unwrap();
+
+        let flow = |first: u8| {
+            let conn_id = conn_id.clone();
+            async move {
+                let mut session = session(&conn_id).await?;
+                session.exchange(&[0xe0, first]).await?;
+                tokio::task::yield_now().await;
+                session.exchange(&[0xe0, first + 1]).await
+            }
+        };
+        let (a, b) = tokio::join!(flow(0x01), flow(0x03));
+        assert!(a.is_ok() && b.is_ok());
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec![
+                "start 01", "end 01", "start 02", "end 02", "start 03", "end 03", "start 04",
+                "end 04"
+            ]
+        );
+        close(&conn_id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_cancel_between_session_exchanges() {
+        let conn_id = register(Box::new(EchoTransport)).unwrap();
+
+        let mut session = session(&conn_id).await.unwrap();
+        assert!(session.exchange(&[0xe0, 0x04]).await.is_ok());
+        cancel(&conn_id).unwrap();
+        assert!(matches!(
+            session.exchange(&[0xe0, 0x04]).await,
+            Err(LedgerError::Cancelled)
+        ));
+        drop(session);
+
+        // Nothing was in flight, the connection stays usable
+        assert!(exchange(&conn_id, &[0xe0, 0x01]).await.is_ok());
+        close(&conn_id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_cancel_aborts_in_flight_and_queued() {
+        let conn_id = register(Box::new(HangingTransport)).unwrap();
+
+        let (in_flight, queued, _) = tokio::join!(
+            exchange(&conn_id, &[0xe0, 0x04]),
+            exchange(&conn_id, &[0xe0, 0x04]),
+            async {
+                tokio::task::yield_now().await;
+                cancel(&conn_id).unwrap();
+            }
+        );
+        assert!(matches!(in_flight, Err(LedgerError::Cancelled)));
+        assert!(matches!(queued, Err(LedgerError::Cancelled)));
+
+        // Cancelling mid-exchange drops the connection
+        assert!(matches!(
+            get(&conn_id),
+            Err(LedgerError::ConnectionNotFound(_))
+        ));
+    }
+}
@@ -0,0 +1,300 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::future::Future;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+
+use crate::ledger::transport::{blocking_io, Detached, LedgerError, LedgerTransport};
+
+const APDU_PREFIX: &str = "=>";
+const RESPONSE_PREFIX: &str = "<=";
+
+/// One recorded APDU and the raw device response, status word included.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Exchange {
+    pub apdu: Vec<u8>,
+    pub response: Vec<u8>,
+}
+
+/// Session file format, one exchange per pair of lines:
+///
+/// => e001000000
+/// <= 0105424f4c4f5301059000
+///
+/// Blank lines and lines starting with `#` are ignored.
+pub fn format_exchange(exchange: &Exchange) -> String {
+    format!(
+        "{} {}\n{} {}\n",
+        APDU_PREFIX,
+        hex::encode(&exchange.apdu),
+        RESPONSE_PREFIX,
+        hex::encode(&exchange.response)
+    )
+}
+
+pub fn parse_session(content: &str) -> Result<Vec<Exchange>, LedgerError> {
+    let mut exchanges = Vec::new();
+    let mut pending: Option<Vec<u8>> = None;
+
+    for (n, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let invalid = |msg: &str| LedgerError::Framing(format!("Line {}: {}", n + 1, msg));
+        let decode =
+            |hex_str: &str| hex::decode(hex_str.trim()).map_err(|e| invalid(&e.to_string()));
+
+        if let Some(apdu) = line.strip_prefix(APDU_PREFIX) {
+            if pending.is_some() {
+                return Err(invalid("APDU without a response"));
+            }
+            pending = Some(decode(apdu)?);
+        } else if let Some(response) = line.strip_prefix(RESPONSE_PREFIX) {
+            let apdu = pending
+                .take()
+                .ok_or_else(|| invalid("response without an APDU"))?;
+            exchanges.push(Exchange {
+                apdu,
+                response: decode(response)?,
+            });
+        } else {
+            return Err(invalid("expected `=>` or `<=`"));
+        }
+    }
+    if pending.is_some() {
+        return Err(LedgerError::Framing(
+            "Session ends with an APDU without a response".to_string(),
+        ));
+    }
+
+    Ok(exchanges)
+}
+
+/// Forwards every exchange to `inner` and appends it to a session file.
+/// Each exchange is flushed right away so a crashed session is still usable.
+/// File writes run on the blocking pool, not on the exchange's worker.
+pub struct RecordingTransport {
+    inner: Box<dyn LedgerTransport>,
+    file: Arc<File>,
+}
+
+impl RecordingTransport {
+    pub fn new(inner: Box<dyn LedgerTransport>, file: File) -> Self {
+        RecordingTransport {
+            inner,
+            file: Arc::new(file),
+        }
+    }
+
+    fn record(&self, exchange: &Exchange) -> impl Future<Output = Result<(), LedgerError>> {
+        let file = Arc::clone(&self.file);
+        let line = format_exchange(exchange);
+        blocking_io(move |_| {
+            let mut file = file.as_ref();
+            file.write_all(line.as_bytes())
+                .and_then(|_| file.flush())
+                .map_err(|e| LedgerError::Io(format!("Cannot write session file: {}", e)))
+        })
+    }
+}
+
+impl LedgerTransport for RecordingTransport {
+    fn exchange<'a>(&'a mut self, apdu: &'a [u8]) -> BoxFuture<'a, Result<Vec<u8>, LedgerError>> {
+        Box::pin(async move {
+            let response = self.inner.exchange(apdu).await?;
+            self.record(&Exchange {
+                apdu: apdu.to_vec(),
+                response: response.clone(),
+            })
+            .await?;
+            Ok(response)
+        })
+    }
+
+    fn close(&mut self) -> BoxFuture<'_, Result<(), LedgerError>> {
+        Box::pin(async move { self.inner.close().await })
+    }
+
+    fn take_inner(&mut self) -> Option<Box<dyn LedgerTransport>> {
+        Some(std::mem::replace(&mut self.inner, Box::new(Detached)))
+    }
+}
+
+/// Serves a recorded session back. Every APDU must match the next recorded
+/// one byte for byte, so a flow that drifts from the capture fails loudly.
+pub struct ReplayTransport {
+    exchanges: VecDeque<Exchange>,
+}
+
+impl ReplayTransport {
+    pub fn new(exchanges: Vec<Exchange>) -> Self {
+        ReplayTransport {
+            exchanges: exchanges.into(),
+        }
+    }
+
+    pub fn open(path: &Path) -> Result<Self, LedgerError> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| LedgerError::Io(format!("Cannot read session file: {}", e)))?;
+        Ok(Self::new(parse_session(&content)?))
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.exchanges.len()
+    }
+
+    pub fn exchange(&mut self, apdu: &[u8]) -> Result<Vec<u8>, LedgerError> {
+        let expected = self
+            .exchanges
+            .pop_front()
+            .ok_or(LedgerError::Disconnected)?;
+        if expected.apdu != apdu {
+            return Err(LedgerError::Framing(format!(
+                "Replay mismatch: expected {}, got {}",
+                hex::encode(&expected.apdu),
+                hex::encode(apdu)
+            )));
+        }
+        Ok(expected.response)
+    }
+
+    /// Fails if the flow stopped before consuming the whole session.
+    pub fn close(&mut self) -> Result<(), LedgerError> {
+        match self.exchanges.len() {
+            0 => Ok(()),
+            n => Err(LedgerError::Framing(format!(
+                "{} recorded exchanges were not replayed",
+                n
+            ))),
+        }
+    }
+}
+
+impl LedgerTransport for ReplayTransport {
+    fn exchange<'a>(&'a mut self, apdu: &'a [u8]) -> BoxFuture<'a, Result<Vec<u8>, LedgerError>> {
+        Box::pin(async move { ReplayTransport::exchange(self, apdu) })
+    }
+
+    fn close(&mut self) -> BoxFuture<'_, Result<(), LedgerError>> {
+        Box::pin(async move { ReplayTransport::close(self) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger::eth;
+    use crate::ledger::registry;
+    use crate::ledger::testing::{ok, scripted};
+
+    #[test]
+    fn test_parse_session() {
+        let content = "# eth app version\n=> e006000000\n<= 00010a029000\n\n=> E00400\n<= 6985\n";
+        let exchanges = parse_session(content).unwrap();
+        assert_eq!(exchanges.len(), 2);
+        assert_eq!(exchanges[0].apdu, vec![0xe0, 0x06, 0x00, 0x00, 0x00]);
+        assert_eq!(exchanges[1].response, vec![0x69, 0x85]);
+
+        let formatted: String = exchanges.iter().map(format_exchange).collect();
+        assert_eq!(parse_session(&formatted).unwrap(), exchanges);
+
+        assert!(parse_session("=> e001\n=> e002\n").is_err());
+        assert!(parse_session("<= 9000\n").is_err());
+        assert!(parse_session("=> e001\n").is_err());
+        assert!(parse_session("=> zz\n<= 9000\n").is_err());
+    }
+
+    #[test]
+    fn test_replay_mismatch() {
+        let mut replay = ReplayTransport::new(vec![Exchange {
+            apdu: vec![0xe0, 0x01],
+            response: ok(&[]),
+        }]);
+        assert!(matches!(
+            replay.exchange(&[0xe0, 0x02]),
+            Err(LedgerError::Framing(_))
+        ));
+        assert!(matches!(
+            replay.exchange(&[0xe0, 0x01]),
+            Err(LedgerError::Disconnected)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_record_then_replay() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("eth_version.apdu");
+
+        let (conn_id, _) = scripted(vec![ok(&[0x00, 0x01, 0x0a, 0x02])]);
+        let file = File::create(&path).unwrap();
+        registry::wrap(&conn_id, |inner| {
+            Box::new(RecordingTransport::new(inner, file))
+        })
+        .await
+        .unwrap();
+        assert_eq!(
+            eth::get_app_version(&mut registry::session(&conn_id).await.unwrap())
+                .await
+                .unwrap(),
+            "1.10.2"
+        );
+        registry::close(&conn_id).await.unwrap();
+
+        let replay = ReplayTransport::open(&path).unwrap();
+        assert_eq!(replay.remaining(), 1);
+        let conn_id = registry::register(Box::new(replay)).unwrap();
+        assert_eq!(
+            eth::get_app_version(&mut registry::session(&conn_id).await.unwrap())
+                .await
+                .unwrap(),
+            "1.10.2"
+        );
+        registry::close(&conn_id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_record_stop() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("eth_version.apdu");
+
+        let (conn_id, _) = scripted(vec![
+            ok(&[0x00, 0x01, 0x0a, 0x02]),
+            ok(&[0x00, 0x01, 0x0a, 0x03]),
+        ]);
+        let file = File::create(&path).unwrap();
+        registry::wrap(&conn_id, |inner| {
+            Box::new(RecordingTransport::new(inner, file))
+        })
+        .await
+        .unwrap();
+        eth::get_app_version(&mut registry::session(&conn_id).await.unwrap())
+            .await
+            .unwrap();
+        drop(registry::unwrap(&conn_id).await.unwrap());
+
+        // Later exchanges reach the device but are no longer recorded
+        assert_eq!(
+            eth::get_app_version(&mut registry::session(&conn_id).await.unwrap())
+                .await
+                .unwrap(),
+            "1.10.3"
+        );
+        assert_eq!(ReplayTransport::open(&path).unwrap().remaining(), 1);
+        assert!(registry::unwrap(&conn_id).await.is_err());
+        registry::close(&conn_id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_replay_close_with_leftovers() {
+        let replay = ReplayTransport::new(vec![Exchange {
+            apdu: vec![0xe0, 0x01],
+            response: ok(&[]),
+        }]);
+        let conn_id = registry::register(Box::new(replay)).unwrap();
+        assert!(registry::close(&conn_id).await.is_err());
+    }
+}
@@ -0,0 +1,133 @@
+use crate::ledger::registry::Session;
+use crate::ledger::transport::{encode_path, LedgerError, HARDENED};
+
+const CLA: u8 = 0xe0;
+
+const INS_GET_APP_CONFIGURATION: u8 = 0x04;
+const INS_GET_PUBKEY: u8 = 0x05;
+const INS_SIGN_MESSAGE: u8 = 0x06;
+
+const P1_NON_CONFIRM: u8 = 0x00;
+const P1_CONFIRM: u8 = 0x01;
+
+const P2_EXTEND: u8 = 0x01;
+const P2_MORE: u8 = 0x02;
+
+const MAX_PAYLOAD: usize = 255;
+
+const PUB_KEY_LEN: usize = 32;
+const SIG_LEN: usize = 64;
+
+/// `m/44'/501'/index'/0'`
+pub fn solana_path(index: u32) -> Vec<u32> {
+    vec![44 | HARDENED, 501 | HARDENED, index | HARDENED, HARDENED]
+}
+
+/// Split a payload into `(p2, chunk)` pairs. Every chunk but the last has
+/// P2_MORE, every chunk but the first has P2_EXTEND.
+pub fn payload_chunks(payload: &[u8]) -> Vec<(u8, Vec<u8>)> {
+    let mut chunks: Vec<(u8, Vec<u8>)> = payload
+        .chunks(MAX_PAYLOAD)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let p2 = if i == 0 { 0x00 } else { P2_EXTEND };
+            (p2 | P2_MORE, chunk.to_vec())
+        })
+        .collect();
+    match chunks.last_mut() {
+        Some(last) => last.0 &= !P2_MORE,
+        None => chunks.push((0x00, Vec::new())),
+    }
+    chunks
+}
+
+/// Solana app version as `major.minor.patch`.
+pub async fn get_app_version(session: &mut Session) -> Result<String, LedgerError> {
+    let data = session
+        .send(CLA, INS_GET_APP_CONFIGURATION, 0x00, 0x00, &[])
+        .await?;
+    if data.len() < 5 {
+        return Err(LedgerError::Framing(
+            "App configuration truncated".to_string(),
+        ));
+    }
+    Ok(format!("{}.{}.{}", data[2], data[3], data[4]))
+}
+
+/// Ed25519 public key (32 bytes) at `path`.
+pub async fn get_pubkey(
+    session: &mut Session,
+    path: &[u32],
+    display: bool,
+) -> Result<Vec<u8>, LedgerError> {
+    let p1 = if display { P1_CONFIRM } else { P1_NON_CONFIRM };
+    let data = session
+        .send(CLA, INS_GET_PUBKEY, p1, 0x00, &encode_path(path))
+        .await?;
+    data.get(..PUB_KEY_LEN)
+        .map(|pk| pk.to_vec())
+        .ok_or_else(|| LedgerError::Framing("Public key truncated".to_string()))
+}
+
+/// Sign a serialized transaction message with the key at `path`.
+pub async fn sign_transaction(
+    session: &mut Session,
+    path: &[u32],
+    message: &[u8],
+) -> Result<Vec<u8>, LedgerError> {
+    // signer count || path || message
+    let mut payload = vec![0x01];
+    payload.extend_from_slice(&encode_path(path));
+    payload.extend_from_slice(message);
+
+    let mut data = Vec::new();
+    for (p2, chunk) in payload_chunks(&payload) {
+        data = session
+            .send(CLA, INS_SIGN_MESSAGE, P1_CONFIRM, p2, &chunk)
+            .await?;
+    }
+    data.get(..SIG_LEN)
+        .map(|sig| sig.to_vec())
+        .ok_or_else(|| LedgerError::Framing("Signature truncated".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger::registry;
+    use crate::ledger::testing::{ok, scripted};
+
+    #[test]
+    fn test_payload_chunks() {
+        let chunks = payload_chunks(&[0xaa; 600]);
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[0].0, P2_MORE);
+        assert_eq!(chunks[1].0, P2_EXTEND | P2_MORE);
+        assert_eq!(chunks[2].0, P2_EXTEND);
+        assert_eq!(chunks[2].1.len(), 90);
+
+        let single = payload_chunks(&[0xaa; 10]);
+        assert_eq!(single, vec![(0x00, vec![0xaa; 10])]);
+    }
+
+    #[tokio::test]
+    async fn test_sign_transaction() {
+        let (conn_id, sent) = scripted(vec![ok(&[]), ok(&[0x55; SIG_LEN])]);
+        let message = vec![0x01; 300];
+
+        let sig = sign_transaction(
+            &mut registry::session(&conn_id).await.unwrap(),
+            &solana_path(0),
+            &message,
+        )
+        .await
+        .unwrap();
+        assert_eq!(sig, vec![0x55; SIG_LEN]);
+
+        let sent = sent.lock().unwrap().clone();
+        assert_eq!(&sent[0][..6], &[0xe0, 0x06, 0x01, P2_MORE, 0xff, 0x01]);
+        assert_eq!(sent[0][6], 4);
+        assert_eq!(&sent[1][..4], &[0xe0, 0x06, 0x01, P2_EXTEND]);
+        registry::close(&conn_id).await.unwrap();
+    }
+}
@@ -0,0 +1,266 @@
+use std::io::{ErrorKind, Read, Write};
+use std::net::{Shutdown, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures::future::BoxFuture;
+
+use crate::ledger::transport::{blocking_io, LedgerError, LedgerTransport};
+
+pub const DEFAULT_SPECULOS_HOST: &str = "127.0.0.1";
+pub const DEFAULT_SPECULOS_APDU_PORT: u16 = 9999;
+
+const CONNECT_TIMEOUT_SECS: u64 = 5;
+const READ_TIMEOUT_SECS: u64 = 60;
+/// Socket reads wake up this often to notice a cancelled exchange
+const POLL_TIMEOUT: Duration = Duration::from_millis(100);
+/// Largest response data a Ledger can return (extended APDU `Ne`)
+const MAX_RESPONSE_DATA_LEN: usize = 65536;
+
+/// APDU-over-TCP transport for the Speculos emulator.
+///
+/// Request:  len(4, BE) || apdu
+/// Response: len(4, BE) || data(len) || sw(2)
+///
+/// The length prefix of the response does not include the status word.
+pub struct SpeculosLedgerTransport {
+    stream: Arc<TcpStream>,
+}
+
+impl SpeculosLedgerTransport {
+    pub fn open(host: &str, port: u16) -> Result<Self, LedgerError> {
+        let addr = (host, port)
+            .to_socket_addrs()
+            .map_err(|e| LedgerError::Io(format!("Invalid Speculos address: {}", e)))?
+            .next()
+            .ok_or_else(|| LedgerError::Io(format!("Cannot resolve {}:{}", host, port)))?;
+        let stream = TcpStream::connect_timeout(&addr, Duration::from_secs(CONNECT_TIMEOUT_SECS))
+            .map_err(|e| LedgerError::Io(format!("Speculos connection failed: {}", e)))?;
+
+        stream
+            .set_read_timeout(Some(POLL_TIMEOUT))
+            .map_err(|e| LedgerError::Io(e.to_string()))?;
+        stream
+            .set_nodelay(true)
+            .map_err(|e| LedgerError::Io(e.to_string()))?;
+
+        Ok(SpeculosLedgerTransport {
+            stream: Arc::new(stream),
+        })
+    }
+
+    pub fn exchange(&self, apdu: &[u8]) -> Result<Vec<u8>, LedgerError> {
+        exchange_until(&self.stream, apdu, &AtomicBool::new(false))
+    }
+
+    /// Same as `exchange`, but runs on the blocking pool and gives up
+    /// between socket reads once the exchange is cancelled.
+    async fn exchange_async(&mut self, apdu: &[u8]) -> Result<Vec<u8>, LedgerError> {
+        let stream = Arc::clone(&self.stream);
+        let apdu = apdu.to_vec();
+        blocking_io(move |stop| exchange_until(&stream, &apdu, stop)).await
+    }
+
+    pub fn close(&mut self) -> Result<(), LedgerError> {
+        self.stream.shutdown(Shutdown::Both).ok();
+        Ok(())
+    }
+}
+
+impl LedgerTransport for SpeculosLedgerTransport {
+    fn exchange<'a>(&'a mut self, apdu: &'a [u8]) -> BoxFuture<'a, Result<Vec<u8>, LedgerError>> {
+        Box::pin(SpeculosLedgerTransport::exchange_async(self, apdu))
+    }
+
+    fn close(&mut self) -> BoxFuture<'_, Result<(), LedgerError>> {
+        Box::pin(async move { SpeculosLedgerTransport::close(self) })
+    }
+}
+
+/// Run one exchange and shut the socket down if it is left mid-response.
+/// After a timeout or a bad length prefix the emulator may still send the
+/// rest of that answer, which would be read as the reply to the next APDU.
+fn exchange_until(
+    stream: &TcpStream,
+    apdu: &[u8],
+    stop: &AtomicBool,
+) -> Result<Vec<u8>, LedgerError> {
+    let result = exchange_once(stream, apdu, stop);
+    if matches!(
+        result,
+        Err(LedgerError::Timeout | LedgerError::Framing(_) | LedgerError::Cancelled)
+    ) {
+        stream.shutdown(Shutdown::Both).ok();
+    }
+    result
+}
+
+fn exchange_once(
+    mut stream: &TcpStream,
+    apdu: &[u8],
+    stop: &AtomicBool,
+) -> Result<Vec<u8>, LedgerError> {
+    let mut request = Vec::with_capacity(4 + apdu.len());
+    request.extend_from_slice(&(apdu.len() as u32).to_be_bytes());
+    request.extend_from_slice(apdu);
+    stream.write_all(&request).map_err(map_io_error)?;
+
+    let deadline = Instant::now() + Duration::from_secs(READ_TIMEOUT_SECS);
+    let mut len_buf = [0u8; 4];
+    read_full(stream, &mut len_buf, deadline, stop)?;
+    let data_len = u32::from_be_bytes(len_buf) as usize;
+    if data_len > MAX_RESPONSE_DATA_LEN {
+        return Err(LedgerError::Framing(format!(
+            "Response length {} exceeds {} bytes",
+            data_len, MAX_RESPONSE_DATA_LEN
+        )));
+    }
+
+    // Data is followed by the 2-byte status word
+    let mut response = vec![0u8; data_len + 2];
+    read_full(stream, &mut response, deadline, stop)?;
+
+    Ok(response)
+}
+
+/// `read_exact` that wakes up every `POLL_TIMEOUT` to check `stop` and the
+/// deadline.
+fn read_full(
+    mut stream: &TcpStream,
+    buf: &mut [u8],
+    deadline: Instant,
+    stop: &AtomicBool,
+) -> Result<(), LedgerError> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        if stop.load(Ordering::Relaxed) {
+            return Err(LedgerError::Cancelled);
+        }
+        if Instant::now() >= deadline {
+            return Err(LedgerError::Timeout);
+        }
+        match stream.read(&mut buf[filled..]) {
+            Ok(0) => return Err(LedgerError::Disconnected),
+            Ok(n) => filled += n,
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted
+                ) => {}
+            Err(e) => return Err(map_io_error(e)),
+        }
+    }
+    Ok(())
+}
+
+fn map_io_error(e: std::io::Error) -> LedgerError {
+    match e.kind() {
+        std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut => LedgerError::Timeout,
+        std::io::ErrorKind::UnexpectedEof
+        | std::io::ErrorKind::ConnectionReset
+        | std::io::ErrorKind::ConnectionAborted
+        | std::io::ErrorKind::BrokenPipe => LedgerError::Disconnected,
+        _ => LedgerError::Io(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    fn spawn_emulator(responses: Vec<Vec<u8>>) -> (u16, thread::JoinHandle<Vec<Vec<u8>>>) {
+        let listener = TcpListener::bind((DEFAULT_SPECULOS_HOST, 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let handle = thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            let mut received = Vec::new();
+
+            for response in responses {
+                let mut len_buf = [0u8; 4];
+                socket.read_exact(&mut len_buf).unwrap();
+                let mut apdu = vec![0u8; u32::from_be_bytes(len_buf) as usize];
+                socket.read_exact(&mut apdu).unwrap();
+                received.push(apdu);
+
+                let data_len = (response.len() - 2) as u32;
+                socket.write_all(&data_len.to_be_bytes()).unwrap();
+                socket.write_all(&response).unwrap();
+            }
+
+            received
+        });
+
+        (port, handle)
+    }
+
+    #[test]
+    fn test_speculos_exchange() {
+        let (port, handle) =
+            spawn_emulator(vec![vec![0x01, 0x02, 0x03, 0x90, 0x00], vec![0x69, 0x85]]);
+        let mut transport = SpeculosLedgerTransport::open(DEFAULT_SPECULOS_HOST, port).unwrap();
+
+        let response = transport.exchange(&[0xe0, 0x06, 0x00, 0x00, 0x00]).unwrap();
+        assert_eq!(response, vec![0x01, 0x02, 0x03, 0x90, 0x00]);
+
+        let response = transport.exchange(&[0xe0, 0x02, 0x00, 0x00, 0x00]).unwrap();
+        assert_eq!(response, vec![0x69, 0x85]);
+
+        transport.close().unwrap();
+
+        let received = handle.join().unwrap();
+        assert_eq!(received[0], vec![0xe0, 0x06, 0x00, 0x00, 0x00]);
+        assert_eq!(received[1], vec![0xe0, 0x02, 0x00, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn test_speculos_disconnected() {
+        let (port, handle) = spawn_emulator(vec![]);
+        let transport = SpeculosLedgerTransport::open(DEFAULT_SPECULOS_HOST, port).unwrap();
+        handle.join().unwrap();
+
+        let result = transport.exchange(&[0xe0, 0x06, 0x00, 0x00, 0x00]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_speculos_rejects_oversized_response() {
+        let listener = TcpListener::bind((DEFAULT_SPECULOS_HOST, 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            let mut request = [0u8; 9];
+            socket.read_exact(&mut request).unwrap();
+            socket.write_all(&u32::MAX.to_be_bytes()).unwrap();
+            socket
+        });
+
+        let transport = SpeculosLedgerTransport::open(DEFAULT_SPECULOS_HOST, port).unwrap();
+        let result = transport.exchange(&[0xe0, 0x06, 0x00, 0x00, 0x00]);
+        assert!(matches!(result, Err(LedgerError::Framing(_))));
+
+        // The connection is dropped rather than left mid-response
+        let result = transport.exchange(&[0xe0, 0x06, 0x00, 0x00, 0x00]);
+        assert!(matches!(result, Err(LedgerError::Disconnected)));
+        drop(handle.join().unwrap());
+    }
+
+    #[test]
+    fn test_speculos_stops_reading_when_cancelled() {
+        let listener = TcpListener::bind((DEFAULT_SPECULOS_HOST, 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        // Accepts the connection but never answers
+        let handle = thread::spawn(move || listener.accept().unwrap());
+
+        let transport = SpeculosLedgerTransport::open(DEFAULT_SPECULOS_HOST, port).unwrap();
+        let stop = AtomicBool::new(true);
+        let started = Instant::now();
+        let result = exchange_until(&transport.stream, &[0xe0, 0x06, 0x00, 0x00, 0x00], &stop);
+        assert!(matches!(result, Err(LedgerError::Cancelled)));
+        assert!(started.elapsed() < Duration::from_secs(READ_TIMEOUT_SECS));
+        drop(handle.join().unwrap());
+    }
+}
@@ -0,0 +1,45 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use futures::future::BoxFuture;
+
+use crate::ledger::registry;
+use crate::ledger::transport::{LedgerError, LedgerTransport};
+
+/// Test transport answering with pre-recorded responses and keeping every
+/// APDU it was sent.
+pub struct ScriptedTransport {
+    responses: VecDeque<Vec<u8>>,
+    sent: Arc<Mutex<Vec<Vec<u8>>>>,
+}
+
+impl LedgerTransport for ScriptedTransport {
+    fn exchange<'a>(&'a mut self, apdu: &'a [u8]) -> BoxFuture<'a, Result<Vec<u8>, LedgerError>> {
+        Box::pin(async move {
+            self.sent.lock().unwrap().push(apdu.to_vec());
+            self.responses.pop_front().ok_or(LedgerError::Disconnected)
+        })
+    }
+
+    fn close(&mut self) -> BoxFuture<'_, Result<(), LedgerError>> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// Register a scripted connection, returning its id and the sent-APDU log.
+pub fn scripted(responses: Vec<Vec<u8>>) -> (String, Arc<Mutex<Vec<Vec<u8>>>>) {
+    let sent = Arc::new(Mutex::new(Vec::new()));
+    let transport = ScriptedTransport {
+        responses: responses.into(),
+        sent: Arc::clone(&sent),
+    };
+    let conn_id = registry::register(Box::new(transport)).unwrap();
+    (conn_id, sent)
+}
+
+/// Append a 0x9000 status word.
+pub fn ok(data: &[u8]) -> Vec<u8> {
+    let mut response = data.to_vec();
+    response.extend_from_slice(&[0x90, 0x00]);
+    response
+}
@@ -1,9 +1,52 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+
+use crate::ledger::dashboard::{parse_app_and_version, DASHBOARD_APP_NAME};
+
+pub const SW_OK: u16 = 0x9000;
+pub const SW_USER_REJECTED: u16 = 0x6985;
+pub const SW_DEVICE_LOCKED: u16 = 0x5515;
+pub const SW_CLA_NOT_SUPPORTED: u16 = 0x6E00;
+pub const SW_INS_NOT_SUPPORTED: u16 = 0x6D00;
+pub const SW_INVALID_DATA: u16 = 0x6A80;
+pub const SW_APP_NOT_OPEN: u16 = 0x6511;
+
+pub const HARDENED: u32 = 0x8000_0000;
+
 #[derive(Debug)]
 pub enum LedgerError {
     Disconnected,
     Timeout,
     Io(String),
     Framing(String),
+    ConnectionNotFound(String),
+    UserRejected,
+    DeviceLocked,
+    WrongApp,
+    UnknownInstruction,
+    InvalidData,
+    AppNotOpen,
+    UnexpectedStatus(u16),
+    /// Aborted by `ledger_cancel`
+    Cancelled,
+}
+
+impl LedgerError {
+    /// Map a well-known status word to its error, `None` for everything
+    /// else (including success and app-specific codes).
+    pub fn from_status_word(sw: u16) -> Option<Self> {
+        match sw {
+            SW_USER_REJECTED => Some(LedgerError::UserRejected),
+            SW_DEVICE_LOCKED => Some(LedgerError::DeviceLocked),
+            SW_CLA_NOT_SUPPORTED => Some(LedgerError::WrongApp),
+            SW_INS_NOT_SUPPORTED => Some(LedgerError::UnknownInstruction),
+            SW_INVALID_DATA => Some(LedgerError::InvalidData),
+            SW_APP_NOT_OPEN => Some(LedgerError::AppNotOpen),
+            _ => None,
+        }
+    }
 }
 
 impl std::fmt::Display for LedgerError {
@@ -13,6 +56,413 @@ impl std::fmt::Display for LedgerError {
             LedgerError::Timeout => write!(f, "Device timeout"),
             LedgerError::Io(msg) => write!(f, "I/O error: {}", msg),
             LedgerError::Framing(msg) => write!(f, "Framing error: {}", msg),
+            LedgerError::ConnectionNotFound(id) => write!(f, "Connection {} not found", id),
+            LedgerError::UserRejected => write!(f, "Action rejected by user"),
+            LedgerError::DeviceLocked => write!(f, "Device is locked"),
+            LedgerError::WrongApp => write!(f, "Wrong app open on device"),
+            LedgerError::UnknownInstruction => write!(f, "Instruction not supported by app"),
+            LedgerError::InvalidData => write!(f, "Invalid data sent to device"),
+            LedgerError::AppNotOpen => write!(f, "App is not open on device"),
+            LedgerError::UnexpectedStatus(sw) => write!(f, "Unexpected status word: 0x{:04X}", sw),
+            LedgerError::Cancelled => write!(f, "Exchange cancelled"),
+        }
+    }
+}
+
+/// Common interface for every way of talking to a Ledger device (USB HID,
+/// Bluetooth, Speculos). `exchange` takes a raw APDU and returns the raw
+/// response including the trailing status word.
+pub trait LedgerTransport: Send {
+    fn exchange<'a>(&'a mut self, apdu: &'a [u8]) -> BoxFuture<'a, Result<Vec<u8>, LedgerError>>;
+
+    fn close(&mut self) -> BoxFuture<'_, Result<(), LedgerError>>;
+
+    /// For layers added with `registry::wrap`: hand back the wrapped
+    /// transport, leaving `Detached` in its place. Plain transports have
+    /// nothing underneath and return `None`.
+    fn take_inner(&mut self) -> Option<Box<dyn LedgerTransport>> {
+        None
+    }
+}
+
+/// Placeholder left where a transport was moved out, e.g. while it is
+/// being wrapped. Every exchange fails as disconnected.
+pub struct Detached;
+
+impl LedgerTransport for Detached {
+    fn exchange<'a>(&'a mut self, _apdu: &'a [u8]) -> BoxFuture<'a, Result<Vec<u8>, LedgerError>> {
+        Box::pin(async { Err(LedgerError::Disconnected) })
+    }
+
+    fn close(&mut self) -> BoxFuture<'_, Result<(), LedgerError>> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// Raises its flag when dropped.
+struct StopOnDrop(Arc<AtomicBool>);
+
+impl Drop for StopOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Run blocking device I/O on tokio's blocking pool instead of a worker.
+/// `f` gets a flag raised once the returned future is dropped, i.e. the
+/// exchange was cancelled, and should check it between reads so the device
+/// is released promptly.
+pub async fn blocking_io<T, F>(f: F) -> Result<T, LedgerError>
+where
+    F: FnOnce(&AtomicBool) -> Result<T, LedgerError> + Send + 'static,
+    T: Send + 'static,
+{
+    let stop = Arc::new(AtomicBool::new(false));
+    let _guard = StopOnDrop(Arc::clone(&stop));
+    tokio::task::spawn_blocking(move || f(&stop))
+        .await
+        .map_err(|e| LedgerError::Io(e.to_string()))?
+}
+
+/// Extract the trailing status word of an APDU response.
+pub fn status_word(response: &[u8]) -> Result<u16, LedgerError> {
+    if response.len() < 2 {
+        return Err(LedgerError::Framing(format!(
+            "Response too short: {} bytes",
+            response.len()
+        )));
+    }
+    let len = response.len();
+    Ok(((response[len - 2] as u16) << 8) | response[len - 1] as u16)
+}
+
+/// Turn well-known error status words into typed errors. Any other status
+/// word (0x9000, the BTC app's 0xE000 interrupt, app-specific codes) is
+/// passed through untouched for the caller to interpret.
+pub fn check_status_word(response: Vec<u8>) -> Result<Vec<u8>, LedgerError> {
+    let sw = status_word(&response)?;
+    match LedgerError::from_status_word(sw) {
+        Some(err) => Err(err),
+        None => Ok(response),
+    }
+}
+
+/// Build a short APDU: `cla || ins || p1 || p2 || lc || data`.
+pub fn build_apdu(cla: u8, ins: u8, p1: u8, p2: u8, data: &[u8]) -> Result<Vec<u8>, LedgerError> {
+    if data.len() > 255 {
+        return Err(LedgerError::InvalidData);
+    }
+    let mut apdu = Vec::with_capacity(5 + data.len());
+    apdu.extend_from_slice(&[cla, ins, p1, p2, data.len() as u8]);
+    apdu.extend_from_slice(data);
+    Ok(apdu)
+}
+
+/// `m/44'/coin'/account'/change/index` as path components.
+pub fn bip44_path(coin_type: u32, account: u32, change: u32, index: u32) -> Vec<u32> {
+    vec![
+        44 | HARDENED,
+        coin_type | HARDENED,
+        account | HARDENED,
+        change,
+        index,
+    ]
+}
+
+/// Path serialization shared by most Ledger apps: `count || u32 BE...`.
+pub fn encode_path(path: &[u32]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(1 + path.len() * 4);
+    out.push(path.len() as u8);
+    for component in path {
+        out.extend_from_slice(&component.to_be_bytes());
+    }
+    out
+}
+
+/// Require a 0x9000 status word and return the response data without it.
+pub fn expect_ok(mut response: Vec<u8>) -> Result<Vec<u8>, LedgerError> {
+    let sw = status_word(&response)?;
+    if sw != SW_OK {
+        return Err(LedgerError::from_status_word(sw).unwrap_or(LedgerError::UnexpectedStatus(sw)));
+    }
+    response.truncate(response.len() - 2);
+    Ok(response)
+}
+
+/// App running on the device as far as a transport can tell. Apps share
+/// CLA 0xE0 and reuse instruction codes (E0 05 is GET_PUBKEY in Solana and
+/// SIGN_TRANSACTION_HASH in Tron), so a command can only be judged
+/// read-only once the app is known.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LedgerApp {
+    Unknown,
+    Dashboard,
+    Bitcoin,
+    Ethereum,
+    Solana,
+    Tron,
+    Zilliqa,
+}
+
+impl LedgerApp {
+    /// Map a GET_APP_AND_VERSION name to the app.
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            DASHBOARD_APP_NAME => LedgerApp::Dashboard,
+            "Bitcoin" | "Bitcoin Test" => LedgerApp::Bitcoin,
+            "Ethereum" => LedgerApp::Ethereum,
+            "Solana" => LedgerApp::Solana,
+            "Tron" => LedgerApp::Tron,
+            "Zilliqa" => LedgerApp::Zilliqa,
+            _ => LedgerApp::Unknown,
+        }
+    }
+
+    /// The app running once `apdu` got `response`. Learned from a
+    /// GET_APP_AND_VERSION answer, forgotten when an app is opened or quit.
+    pub fn after_exchange(self, apdu: &[u8], response: &[u8]) -> Self {
+        match apdu {
+            [0xb0, 0x01, ..] => expect_ok(response.to_vec())
+                .and_then(|data| parse_app_and_version(&data))
+                .map(|info| LedgerApp::from_name(&info.name))
+                .unwrap_or(LedgerApp::Unknown),
+            [0xb0, 0xa7, ..] | [0xe0, 0xd8, ..] => LedgerApp::Unknown,
+            _ => self,
         }
     }
 }
+
+/// Whether `apdu` is a read-only, single-exchange command of `app` that can
+/// be sent again after a reconnect without side effects on the device:
+///
+/// - `B0 01` get app and version, answered by the OS whatever app runs
+/// - Dashboard: `E0 01` get device version
+/// - Ethereum and Tron: `E0 02` get address without display, `E0 06` app
+///   configuration
+/// - Solana: `E0 04` app configuration, `E0 05` pubkey without display
+/// - Zilliqa: `E0 01` get version
+/// - Bitcoin: `E1 05` master fingerprint, `E1 00` xpub without display
+///
+/// Signing commands, anything stateful across chunks and every app-specific
+/// command while the app is unknown are never retried.
+pub fn is_idempotent(app: LedgerApp, apdu: &[u8]) -> bool {
+    let (cla, ins, p1) = match apdu {
+        [cla, ins, p1, ..] => (*cla, *ins, *p1),
+        _ => return false,
+    };
+    let data = apdu.get(5..).unwrap_or_default();
+
+    if (cla, ins) == (0xb0, 0x01) {
+        return true;
+    }
+    match (app, cla, ins) {
+        (LedgerApp::Dashboard, 0xe0, 0x01) => data.is_empty(),
+        (LedgerApp::Ethereum | LedgerApp::Tron, 0xe0, 0x02) => p1 == 0x00,
+        (LedgerApp::Ethereum | LedgerApp::Tron, 0xe0, 0x06) => true,
+        (LedgerApp::Solana, 0xe0, 0x04) => true,
+        (LedgerApp::Solana, 0xe0, 0x05) => p1 == 0x00,
+        (LedgerApp::Zilliqa, 0xe0, 0x01) => data.is_empty(),
+        (LedgerApp::Bitcoin, 0xe1, 0x05) => true,
+        (LedgerApp::Bitcoin, 0xe1, 0x00) => data.first() == Some(&0x00),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_status_word_known_codes() {
+        assert!(matches!(
+            check_status_word(vec![0x69, 0x85]),
+            Err(LedgerError::UserRejected)
+        ));
+        assert!(matches!(
+            check_status_word(vec![0x55, 0x15]),
+            Err(LedgerError::DeviceLocked)
+        ));
+        assert!(matches!(
+            check_status_word(vec![0x6e, 0x00]),
+            Err(LedgerError::WrongApp)
+        ));
+        assert!(matches!(
+            check_status_word(vec![0x6d, 0x00]),
+            Err(LedgerError::UnknownInstruction)
+        ));
+        assert!(matches!(
+            check_status_word(vec![0x6a, 0x80]),
+            Err(LedgerError::InvalidData)
+        ));
+        assert!(matches!(
+            check_status_word(vec![0x65, 0x11]),
+            Err(LedgerError::AppNotOpen)
+        ));
+    }
+
+    #[test]
+    fn test_build_apdu_and_expect_ok() {
+        assert_eq!(
+            build_apdu(0xe0, 0xd8, 0x00, 0x00, b"Bitcoin").unwrap(),
+            [&[0xe0, 0xd8, 0x00, 0x00, 0x07][..], b"Bitcoin"].concat()
+        );
+        assert!(build_apdu(0xe0, 0x04, 0x00, 0x00, &[0u8; 256]).is_err());
+
+        assert_eq!(expect_ok(vec![0x01, 0x90, 0x00]).unwrap(), vec![0x01]);
+        assert!(matches!(
+            expect_ok(vec![0x69, 0x85]),
+            Err(LedgerError::UserRejected)
+        ));
+        assert!(matches!(
+            expect_ok(vec![0x6a, 0x82]),
+            Err(LedgerError::UnexpectedStatus(0x6a82))
+        ));
+    }
+
+    #[test]
+    fn test_encode_path() {
+        let path = bip44_path(60, 0, 0, 1);
+        assert_eq!(
+            encode_path(&path),
+            vec![
+                0x05, 0x80, 0x00, 0x00, 0x2c, 0x80, 0x00, 0x00, 0x3c, 0x80, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_status_word_passthrough() {
+        let ok = vec![0x01, 0x02, 0x90, 0x00];
+        assert_eq!(check_status_word(ok.clone()).unwrap(), ok);
+
+        let interrupt = vec![0x40, 0xe0, 0x00];
+        assert_eq!(check_status_word(interrupt.clone()).unwrap(), interrupt);
+
+        assert!(matches!(
+            check_status_word(vec![0x90]),
+            Err(LedgerError::Framing(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_blocking_io_stops_when_dropped() {
+        let stopped = Arc::new(AtomicBool::new(false));
+        let seen = Arc::clone(&stopped);
+        let io = blocking_io(move |stop| {
+            while !stop.load(Ordering::Relaxed) {
+                std::thread::sleep(std::time::Duration::from_millis(1));
+            }
+            seen.store(true, Ordering::Relaxed);
+            Err::<(), _>(LedgerError::Cancelled)
+        });
+
+        let timeout = tokio::time::timeout(std::time::Duration::from_millis(20), io).await;
+        assert!(timeout.is_err());
+        for _ in 0..1000 {
+            if stopped.load(Ordering::Relaxed) {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+        }
+        assert!(stopped.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_is_idempotent() {
+        use LedgerApp::*;
+
+        assert!(is_idempotent(Unknown, &[0xb0, 0x01, 0x00, 0x00, 0x00]));
+        assert!(is_idempotent(Dashboard, &[0xe0, 0x01, 0x00, 0x00, 0x00]));
+        assert!(is_idempotent(
+            Ethereum,
+            &[0xe0, 0x02, 0x00, 0x00, 0x01, 0x00]
+        ));
+        assert!(is_idempotent(
+            Bitcoin,
+            &[0xe1, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00]
+        ));
+
+        // Display on device, signing and short APDUs
+        assert!(!is_idempotent(
+            Ethereum,
+            &[0xe0, 0x02, 0x01, 0x00, 0x01, 0x00]
+        ));
+        assert!(!is_idempotent(
+            Bitcoin,
+            &[0xe1, 0x00, 0x00, 0x00, 0x02, 0x01, 0x00]
+        ));
+        assert!(!is_idempotent(
+            Ethereum,
+            &[0xe0, 0x04, 0x00, 0x00, 0x01, 0x00]
+        ));
+        assert!(!is_idempotent(Dashboard, &[0xe0, 0x01]));
+
+        // App-specific commands are not retried until the app is known
+        assert!(!is_idempotent(
+            Unknown,
+            &[0xe0, 0x02, 0x00, 0x00, 0x01, 0x00]
+        ));
+        assert!(!is_idempotent(Unknown, &[0xe1, 0x05, 0x00, 0x00, 0x00]));
+    }
+
+    #[test]
+    fn test_is_idempotent_tron() {
+        use LedgerApp::*;
+
+        // E0 05 is GET_PUBKEY in Solana but SIGN_TRANSACTION_HASH in Tron
+        let e0_05 = [0xe0, 0x05, 0x00, 0x00, 0x01, 0x00];
+        assert!(is_idempotent(Solana, &e0_05));
+        assert!(!is_idempotent(Tron, &e0_05));
+        assert!(!is_idempotent(Unknown, &e0_05));
+
+        assert!(is_idempotent(Tron, &[0xe0, 0x02, 0x00, 0x00, 0x01, 0x00]));
+        assert!(is_idempotent(Tron, &[0xe0, 0x06, 0x00, 0x00, 0x00]));
+        assert!(!is_idempotent(Tron, &[0xe0, 0x02, 0x01, 0x00, 0x01, 0x00]));
+    }
+
+    #[test]
+    fn test_signing_is_never_idempotent() {
+        use LedgerApp::*;
+
+        let signing: [(LedgerApp, &[u8]); 9] = [
+            (Ethereum, &[0xe0, 0x04, 0x00, 0x00, 0x00]),
+            (Ethereum, &[0xe0, 0x08, 0x00, 0x00, 0x00]),
+            (Ethereum, &[0xe0, 0x0c, 0x00, 0x00, 0x00]),
+            (Tron, &[0xe0, 0x04, 0x10, 0x00, 0x00]),
+            (Tron, &[0xe0, 0x05, 0x00, 0x00, 0x00]),
+            (Solana, &[0xe0, 0x06, 0x01, 0x00, 0x00]),
+            (Zilliqa, &[0xe0, 0x04, 0x00, 0x00, 0x00]),
+            (Zilliqa, &[0xe0, 0x08, 0x00, 0x00, 0x00]),
+            (Bitcoin, &[0xe1, 0x04, 0x00, 0x00, 0x00]),
+        ];
+        for (app, apdu) in signing {
+            assert!(!is_idempotent(app, apdu), "{:?} {:02x?}", app, apdu);
+        }
+    }
+
+    #[test]
+    fn test_app_after_exchange() {
+        let get_app = [0xb0, 0x01, 0x00, 0x00, 0x00];
+        let mut tron = vec![0x01, 0x04];
+        tron.extend_from_slice(b"Tron");
+        tron.extend_from_slice(&[0x05]);
+        tron.extend_from_slice(b"0.5.0");
+        tron.extend_from_slice(&[0x90, 0x00]);
+
+        let app = LedgerApp::Unknown.after_exchange(&get_app, &tron);
+        assert_eq!(app, LedgerApp::Tron);
+        assert_eq!(
+            app.after_exchange(&[0xe0, 0x02, 0x00, 0x00, 0x00], &[0x90, 0x00]),
+            LedgerApp::Tron
+        );
+        assert_eq!(
+            app.after_exchange(&[0xb0, 0xa7, 0x00, 0x00, 0x00], &[0x90, 0x00]),
+            LedgerApp::Unknown
+        );
+        assert_eq!(
+            app.after_exchange(&get_app, &[0x69, 0x85]),
+            LedgerApp::Unknown
+        );
+    }
+}
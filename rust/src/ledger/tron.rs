@@ -0,0 +1,235 @@
+use crate::ledger::registry::Session;
+use crate::ledger::transport::{encode_path, LedgerError};
+
+const CLA: u8 = 0xe0;
+
+const INS_GET_ADDRESS: u8 = 0x02;
+const INS_SIGN_TRANSACTION: u8 = 0x04;
+const INS_GET_APP_CONFIGURATION: u8 = 0x06;
+
+const P1_SINGLE: u8 = 0x10;
+const P1_FIRST: u8 = 0x00;
+const P1_MORE: u8 = 0x80;
+const P1_LAST: u8 = 0x90;
+
+const CHUNK_SIZE: usize = 250;
+const SIG_LEN: usize = 65;
+
+#[derive(Debug, Clone)]
+pub struct TronAppConfiguration {
+    pub allow_data: bool,
+    pub allow_contract: bool,
+    pub truncate_address: bool,
+    pub sign_by_hash: bool,
+    pub version: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct TronAddress {
+    pub public_key: Vec<u8>,
+    pub address: String,
+}
+
+fn read_protobuf_varint(data: &[u8], mut pos: usize) -> (u64, usize) {
+    let mut value = 0u64;
+    let mut shift = 0;
+    while pos < data.len() && shift < 64 {
+        let byte = data[pos];
+        value |= ((byte & 0x7f) as u64) << shift;
+        pos += 1;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+    }
+    (value, pos)
+}
+
+/// Length of the protobuf field at the start of `data`, so chunks can be
+/// cut on field boundaries.
+fn next_field_len(data: &[u8]) -> usize {
+    if data.is_empty() {
+        return 0;
+    }
+    let (tag, pos) = read_protobuf_varint(data, 0);
+    let end = match tag & 0x07 {
+        0 => read_protobuf_varint(data, pos).1,
+        1 => pos + 8,
+        2 => {
+            let (len, pos) = read_protobuf_varint(data, pos);
+            pos.saturating_add(len as usize)
+        }
+        5 => pos + 4,
+        _ => data.len(),
+    };
+    end.clamp(1, data.len())
+}
+
+/// Split `path || raw_tx` into APDU payloads of at most 250 bytes, keeping
+/// protobuf fields whole whenever they fit in one chunk.
+pub fn sign_chunks(path: &[u32], raw_tx: &[u8]) -> Vec<(u8, Vec<u8>)> {
+    let mut chunks: Vec<Vec<u8>> = Vec::new();
+    let mut current = encode_path(path);
+    let mut offset = 0;
+
+    while offset < raw_tx.len() {
+        let field_len = next_field_len(&raw_tx[offset..]);
+        let field = &raw_tx[offset..offset + field_len];
+        offset += field_len;
+
+        if current.len() + field_len > CHUNK_SIZE {
+            if !current.is_empty() {
+                chunks.push(std::mem::take(&mut current));
+            }
+            if field_len > CHUNK_SIZE {
+                chunks.extend(field.chunks(CHUNK_SIZE).map(|c| c.to_vec()));
+                continue;
+            }
+        }
+        current.extend_from_slice(field);
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+
+    let last = chunks.len() - 1;
+    chunks
+        .into_iter()
+        .enumerate()
+        .map(|(i, chunk)| {
+            let p1 = match i {
+                _ if last == 0 => P1_SINGLE,
+                0 => P1_FIRST,
+                i if i == last => P1_LAST,
+                _ => P1_MORE,
+            };
+            (p1, chunk)
+        })
+        .collect()
+}
+
+pub async fn get_app_configuration(
+    session: &mut Session,
+) -> Result<TronAppConfiguration, LedgerError> {
+    let data = session
+        .send(CLA, INS_GET_APP_CONFIGURATION, 0x00, 0x00, &[])
+        .await?;
+    if data.len() < 4 {
+        return Err(LedgerError::Framing(
+            "App configuration truncated".to_string(),
+        ));
+    }
+    let flags = data[0];
+
+    Ok(TronAppConfiguration {
+        allow_data: flags & 0x01 != 0,
+        allow_contract: flags & 0x02 != 0,
+        truncate_address: flags & 0x04 != 0,
+        sign_by_hash: flags & 0x08 != 0,
+        version: format!("{}.{}.{}", data[1], data[2], data[3]),
+    })
+}
+
+/// Response: pubkey_len || pubkey || addr_len || base58 address
+pub async fn get_address(
+    session: &mut Session,
+    path: &[u32],
+    display: bool,
+) -> Result<TronAddress, LedgerError> {
+    let data = session
+        .send(
+            CLA,
+            INS_GET_ADDRESS,
+            display as u8,
+            0x00,
+            &encode_path(path),
+        )
+        .await?;
+    let truncated = || LedgerError::Framing("Address response truncated".to_string());
+
+    let pk_len = *data.first().ok_or_else(truncated)? as usize;
+    let public_key = data.get(1..1 + pk_len).ok_or_else(truncated)?.to_vec();
+    let addr_len = *data.get(1 + pk_len).ok_or_else(truncated)? as usize;
+    let addr = data
+        .get(2 + pk_len..2 + pk_len + addr_len)
+        .ok_or_else(truncated)?;
+    let address =
+        String::from_utf8(addr.to_vec()).map_err(|e| LedgerError::Framing(e.to_string()))?;
+
+    Ok(TronAddress {
+        public_key,
+        address,
+    })
+}
+
+/// Sign a raw transaction (`raw_data` protobuf). Returns `r || s || v`.
+pub async fn sign_transaction(
+    session: &mut Session,
+    path: &[u32],
+    raw_tx: &[u8],
+) -> Result<Vec<u8>, LedgerError> {
+    let mut data = Vec::new();
+    for (p1, chunk) in sign_chunks(path, raw_tx) {
+        data = session
+            .send(CLA, INS_SIGN_TRANSACTION, p1, 0x00, &chunk)
+            .await?;
+    }
+    data.get(..SIG_LEN)
+        .map(|sig| sig.to_vec())
+        .ok_or_else(|| LedgerError::Framing("Signature truncated".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger::transport::bip44_path;
+
+    fn length_delimited(tag: u8, len: usize) -> Vec<u8> {
+        let mut field = vec![tag];
+        let mut n = len;
+        while n >= 0x80 {
+            field.push((n as u8 & 0x7f) | 0x80);
+            n >>= 7;
+        }
+        field.push(n as u8);
+        field.extend(std::iter::repeat_n(0x33, len));
+        field
+    }
+
+    #[test]
+    fn test_next_field_len() {
+        assert_eq!(next_field_len(&[0x08, 0x96, 0x01, 0xff]), 3);
+        assert_eq!(next_field_len(&length_delimited(0x0a, 5)), 7);
+        assert_eq!(next_field_len(&[0x0a, 0x20, 0x00]), 3);
+    }
+
+    #[test]
+    fn test_sign_chunks() {
+        let path = bip44_path(195, 0, 0, 0);
+
+        let small = length_delimited(0x0a, 10);
+        let chunks = sign_chunks(&path, &small);
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].0, P1_SINGLE);
+        assert_eq!(chunks[0].1.len(), 21 + small.len());
+
+        // Two fields that do not fit together stay whole in their own chunks
+        let mut tx = length_delimited(0x0a, 200);
+        tx.extend(length_delimited(0x12, 100));
+        let chunks = sign_chunks(&path, &tx);
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].0, P1_FIRST);
+        assert_eq!(chunks[0].1.len(), 21 + 203);
+        assert_eq!(chunks[1].0, P1_LAST);
+        assert_eq!(chunks[1].1.len(), 102);
+
+        // Oversized fields are split
+        let big = length_delimited(0x0a, 600);
+        let chunks = sign_chunks(&path, &big);
+        assert_eq!(chunks.len(), 4);
+        assert_eq!(chunks[1].0, P1_MORE);
+        assert_eq!(chunks[3].0, P1_LAST);
+        let total: usize = chunks.iter().map(|(_, c)| c.len()).sum();
+        assert_eq!(total, 21 + big.len());
+    }
+}
@@ -0,0 +1,198 @@
+use crate::ledger::registry::Session;
+use crate::ledger::transport::LedgerError;
+
+const CLA: u8 = 0xe0;
+
+const INS_GET_VERSION: u8 = 0x01;
+const INS_GET_PUBLIC_KEY: u8 = 0x02;
+const INS_SIGN_TXN: u8 = 0x04;
+const INS_SIGN_HASH: u8 = 0x08;
+
+const P2_PUBLIC_KEY: u8 = 0x00;
+const P2_PUBLIC_ADDRESS: u8 = 0x01;
+
+/// The Zilliqa app answers a rejected key reveal with a bare 0x0008.
+const SW_KEY_REVEAL_REJECTED: u16 = 0x0008;
+
+const PUB_KEY_LEN: usize = 33;
+const BECH32_ADDR_LEN: usize = 42;
+const SIG_LEN: usize = 64;
+const HASH_LEN: usize = 32;
+const STREAM_LEN: usize = 128;
+
+#[derive(Debug, Clone)]
+pub struct ZilliqaAddress {
+    pub public_key: Vec<u8>,
+    pub address: String,
+}
+
+async fn send(session: &mut Session, ins: u8, p2: u8, data: &[u8]) -> Result<Vec<u8>, LedgerError> {
+    session
+        .send(CLA, ins, 0x00, p2, data)
+        .await
+        .map_err(|e| match e {
+            LedgerError::UnexpectedStatus(SW_KEY_REVEAL_REJECTED) => LedgerError::UserRejected,
+            e => e,
+        })
+}
+
+fn take(data: &[u8], len: usize) -> Result<Vec<u8>, LedgerError> {
+    data.get(..len)
+        .map(|d| d.to_vec())
+        .ok_or_else(|| LedgerError::Framing(format!("Expected {} bytes, got {}", len, data.len())))
+}
+
+/// Split a serialized transaction into SIGN_TXN payloads:
+/// `[index] || remaining(i32 LE) || chunk_len(i32 LE) || chunk`, with the
+/// key index only in the first one.
+pub fn sign_txn_chunks(index: u32, tx: &[u8]) -> Vec<Vec<u8>> {
+    let mut chunks = Vec::new();
+    let mut offset = 0;
+
+    loop {
+        let chunk_len = (tx.len() - offset).min(STREAM_LEN);
+        let chunk = &tx[offset..offset + chunk_len];
+        offset += chunk_len;
+
+        let mut payload = Vec::with_capacity(12 + chunk_len);
+        if chunks.is_empty() {
+            payload.extend_from_slice(&index.to_le_bytes());
+        }
+        payload.extend_from_slice(&((tx.len() - offset) as i32).to_le_bytes());
+        payload.extend_from_slice(&(chunk_len as i32).to_le_bytes());
+        payload.extend_from_slice(chunk);
+        chunks.push(payload);
+
+        if offset >= tx.len() {
+            return chunks;
+        }
+    }
+}
+
+pub async fn get_version(session: &mut Session) -> Result<String, LedgerError> {
+    let data = send(session, INS_GET_VERSION, 0x00, &[]).await?;
+    if data.len() < 3 {
+        return Err(LedgerError::Framing(
+            "Version response truncated".to_string(),
+        ));
+    }
+    Ok(format!("{}.{}.{}", data[0], data[1], data[2]))
+}
+
+pub async fn get_public_key(session: &mut Session, index: u32) -> Result<Vec<u8>, LedgerError> {
+    let data = send(
+        session,
+        INS_GET_PUBLIC_KEY,
+        P2_PUBLIC_KEY,
+        &index.to_le_bytes(),
+    )
+    .await?;
+    take(&data, PUB_KEY_LEN)
+}
+
+/// Response: pubkey(33) || bech32 address(42)
+pub async fn get_public_address(
+    session: &mut Session,
+    index: u32,
+) -> Result<ZilliqaAddress, LedgerError> {
+    let data = send(
+        session,
+        INS_GET_PUBLIC_KEY,
+        P2_PUBLIC_ADDRESS,
+        &index.to_le_bytes(),
+    )
+    .await?;
+    let public_key = take(&data, PUB_KEY_LEN)?;
+    let address = take(&data[PUB_KEY_LEN..], BECH32_ADDR_LEN)?;
+    let address = String::from_utf8(address).map_err(|e| LedgerError::Framing(e.to_string()))?;
+
+    Ok(ZilliqaAddress {
+        public_key,
+        address,
+    })
+}
+
+/// Sign a 32-byte hash, longer input is truncated like the app expects.
+pub async fn sign_hash(
+    session: &mut Session,
+    index: u32,
+    hash: &[u8],
+) -> Result<Vec<u8>, LedgerError> {
+    if hash.is_empty() {
+        return Err(LedgerError::InvalidData);
+    }
+    let mut payload = index.to_le_bytes().to_vec();
+    payload.extend_from_slice(&hash[..hash.len().min(HASH_LEN)]);
+
+    let data = send(session, INS_SIGN_HASH, 0x00, &payload).await?;
+    take(&data, SIG_LEN)
+}
+
+/// Sign a protobuf-encoded transaction (`encode_tx_rlp` bytes).
+pub async fn sign_transaction(
+    session: &mut Session,
+    index: u32,
+    tx: &[u8],
+) -> Result<Vec<u8>, LedgerError> {
+    let mut data = Vec::new();
+    for chunk in sign_txn_chunks(index, tx) {
+        data = send(session, INS_SIGN_TXN, 0x00, &chunk).await?;
+    }
+    take(&data, SIG_LEN)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger::registry;
+    use crate::ledger::testing::{ok, scripted};
+
+    #[test]
+    fn test_sign_txn_chunks() {
+        let tx = vec![0x11; 300];
+        let chunks = sign_txn_chunks(7, &tx);
+
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(&chunks[0][..4], &7u32.to_le_bytes());
+        assert_eq!(&chunks[0][4..8], &172i32.to_le_bytes());
+        assert_eq!(&chunks[0][8..12], &128i32.to_le_bytes());
+        assert_eq!(chunks[0].len(), 12 + 128);
+
+        assert_eq!(&chunks[1][..4], &44i32.to_le_bytes());
+        assert_eq!(&chunks[2][..4], &0i32.to_le_bytes());
+        assert_eq!(&chunks[2][4..8], &44i32.to_le_bytes());
+        assert_eq!(chunks[2].len(), 8 + 44);
+
+        let short = sign_txn_chunks(0, &[0x01, 0x02]);
+        assert_eq!(short.len(), 1);
+        assert_eq!(&short[0][4..8], &0i32.to_le_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_get_public_address() {
+        let mut response = vec![0x02; PUB_KEY_LEN];
+        response.extend_from_slice(format!("zil1{}", "q".repeat(38)).as_bytes());
+        let (conn_id, sent) = scripted(vec![ok(&response)]);
+
+        let addr = get_public_address(&mut registry::session(&conn_id).await.unwrap(), 3)
+            .await
+            .unwrap();
+        assert_eq!(addr.public_key, vec![0x02; PUB_KEY_LEN]);
+        assert_eq!(addr.address.len(), BECH32_ADDR_LEN);
+
+        let sent = sent.lock().unwrap().clone();
+        assert_eq!(
+            sent[0],
+            vec![0xe0, 0x02, 0x00, 0x01, 0x04, 0x03, 0x00, 0x00, 0x00]
+        );
+        registry::close(&conn_id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_key_reveal_rejected() {
+        let (conn_id, _) = scripted(vec![vec![0x00, 0x08]]);
+        let result = get_public_key(&mut registry::session(&conn_id).await.unwrap(), 0).await;
+        assert!(matches!(result, Err(LedgerError::UserRejected)));
+        registry::close(&conn_id).await.unwrap();
+    }
+}
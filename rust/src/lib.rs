@@ -2,6 +2,7 @@ mod frb_generated;
 mod tests;
 
 pub mod api;
+pub mod btc;
 #[cfg(not(any(target_os = "android", target_os = "ios")))]
 pub mod ledger;
 pub mod models;
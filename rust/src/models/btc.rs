@@ -0,0 +1,321 @@
+use std::str::FromStr;
+
+use bitcoin::{Amount, OutPoint, ScriptBuf, TxOut, Txid, Weight};
+
+use crate::btc::coin_selection::{CoinSelectionStrategy, Utxo};
+use crate::btc::message::SignatureFormat;
+
+use super::transactions::request::TransactionRequestInfo;
+
+#[derive(Debug, Clone, Copy)]
+pub enum BtcCoinSelectionStrategy {
+    BranchAndBound,
+    Knapsack,
+    LargestFirst,
+    /// Spend exactly the given UTXOs
+    Manual,
+}
+
+impl From<BtcCoinSelectionStrategy> for CoinSelectionStrategy {
+    fn from(value: BtcCoinSelectionStrategy) -> Self {
+        match value {
+            BtcCoinSelectionStrategy::BranchAndBound => CoinSelectionStrategy::BranchAndBound,
+            BtcCoinSelectionStrategy::Knapsack => CoinSelectionStrategy::Knapsack,
+            BtcCoinSelectionStrategy::LargestFirst => CoinSelectionStrategy::LargestFirst,
+            BtcCoinSelectionStrategy::Manual => CoinSelectionStrategy::Manual,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum BtcMessageFormat {
+    /// Bitcoin Core `signmessage`, P2PKH only
+    Legacy,
+    /// BIP-322 simple, P2WPKH and P2TR
+    Bip322Simple,
+    /// BIP-322 full, P2WPKH and P2TR
+    Bip322Full,
+}
+
+impl From<BtcMessageFormat> for SignatureFormat {
+    fn from(value: BtcMessageFormat) -> Self {
+        match value {
+            BtcMessageFormat::Legacy => SignatureFormat::Legacy,
+            BtcMessageFormat::Bip322Simple => SignatureFormat::Simple,
+            BtcMessageFormat::Bip322Full => SignatureFormat::Full,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BtcUtxoInfo {
+    pub txid: String,
+    pub vout: u32,
+    /// Satoshis
+    pub value: u64,
+    /// Hex encoded
+    pub script_pubkey: String,
+    /// Spending weight for scripts other than single-sig, e.g. multisig
+    pub satisfaction_weight: Option<u64>,
+}
+
+impl TryFrom<BtcUtxoInfo> for Utxo {
+    type Error = String;
+
+    fn try_from(value: BtcUtxoInfo) -> Result<Self, Self::Error> {
+        let txid = Txid::from_str(&value.txid).map_err(|e| format!("Invalid txid: {}", e))?;
+        let script_pubkey = ScriptBuf::from_hex(&value.script_pubkey)
+            .map_err(|e| format!("Invalid script_pubkey: {}", e))?;
+        let outpoint = OutPoint::new(txid, value.vout);
+        let txout = TxOut {
+            value: Amount::from_sat(value.value),
+            script_pubkey,
+        };
+
+        match value.satisfaction_weight {
+            Some(wu) => Ok(Utxo {
+                outpoint,
+                txout,
+                satisfaction_weight: Weight::from_wu(wu),
+            }),
+            None => Utxo::new(outpoint, txout).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+impl From<Utxo> for BtcUtxoInfo {
+    fn from(value: Utxo) -> Self {
+        Self {
+            txid: value.outpoint.txid.to_string(),
+            vout: value.outpoint.vout,
+            value: value.txout.value.to_sat(),
+            script_pubkey: value.txout.script_pubkey.to_hex_string(),
+            satisfaction_weight: Some(value.satisfaction_weight.to_wu()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BtcRecipientInfo {
+    pub address: String,
+    /// Satoshis
+    pub value: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct BtcTxParamsInfo {
+    pub utxos: Vec<BtcUtxoInfo>,
+    pub recipients: Vec<BtcRecipientInfo>,
+    pub change_address: String,
+    /// "bitcoin", "testnet", "signet" or "regtest"
+    pub network: String,
+    /// sat/vB
+    pub fee_rate: f64,
+    pub strategy: BtcCoinSelectionStrategy,
+}
+
+#[derive(Debug, Clone)]
+pub struct BtcCoinSelectionInfo {
+    /// Unsigned transaction with `btc_witness_utxos` filled in, ready for
+    /// `sign_send_transactions`
+    pub tx: TransactionRequestInfo,
+    /// Same transaction as a PSBT, ready for `btc_ledger_prepare_psbt`
+    pub psbt: Vec<u8>,
+    pub selected: Vec<BtcUtxoInfo>,
+    pub fee: u64,
+    pub vsize: u64,
+    pub change: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct BtcFeeBumpInfo {
+    /// Unsigned replacement, signs through the same paths as
+    /// `BtcCoinSelectionInfo::tx`
+    pub tx: TransactionRequestInfo,
+    pub psbt: Vec<u8>,
+    pub original_txid: String,
+    /// Whether the original opted in to BIP-125. Nodes without full RBF
+    /// reject replacements of transactions that did not.
+    pub original_signals_rbf: bool,
+    pub fee: u64,
+    pub vsize: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct BtcCpfpParamsInfo {
+    /// Parent as kept in history (decoded JSON) or raw hex
+    pub parent: String,
+    /// Satoshis, required when `parent` does not carry it
+    pub parent_fee: Option<u64>,
+    /// Required when `parent` does not carry it
+    pub parent_vsize: Option<u64>,
+    /// Parent outputs owned by the wallet to spend
+    pub vouts: Vec<u32>,
+    pub destination_address: String,
+    pub network: String,
+    /// Target package fee rate, sat/vB
+    pub fee_rate: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct BtcCpfpInfo {
+    pub tx: TransactionRequestInfo,
+    pub psbt: Vec<u8>,
+    pub fee: u64,
+    pub vsize: u64,
+    /// Parent and child together, sat/vB
+    pub package_fee_rate: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct BtcMultisigInfo {
+    /// Canonical form with checksum
+    pub descriptor: String,
+    /// "wsh" or "sh-wsh"
+    pub script_type: String,
+    pub threshold: u32,
+    pub sorted: bool,
+    /// `[fingerprint/path]xpub` of every cosigner, in descriptor order
+    pub keys: Vec<String>,
+    /// Spending weight of one input, for `BtcUtxoInfo::satisfaction_weight`
+    pub satisfaction_weight: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct BtcLabelInfo {
+    /// BIP-329 type: "tx", "addr", "pubkey", "input", "output" or "xpub"
+    pub label_type: String,
+    /// Txid, address, pubkey, `txid:vout` or xpub depending on the type
+    pub reference: String,
+    pub label: Option<String>,
+    /// Descriptor of the wallet the record came from
+    pub origin: Option<String>,
+    /// Outputs only, `false` freezes the coin
+    pub spendable: Option<bool>,
+}
+
+#[derive(Debug, Clone)]
+pub struct BtcLabelImportInfo {
+    pub imported: u32,
+    /// One message per skipped line
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct BtcPsbtInfo {
+    /// BIP-174 binary, for files
+    pub psbt: Vec<u8>,
+    /// BIP-174 base64, for QR codes and clipboard
+    pub base64: String,
+    pub txid: String,
+    /// Satoshis, when every input carries its UTXO
+    pub fee: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct BtcSilentPaymentKeysInfo {
+    /// `sp1...` on mainnet, `tsp1...` on test networks
+    pub address: String,
+    /// Hex encoded, enough to scan for payments but not to spend them
+    pub scan_secret: String,
+    /// Hex encoded, compressed
+    pub spend_pubkey: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct BtcSilentPaymentOutputInfo {
+    pub utxo: BtcUtxoInfo,
+    /// Hex encoded, added to the spend key to spend `utxo`
+    pub tweak: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct BtcDiscoveredAccountInfo {
+    /// BIP-44 account index, the wallet account index
+    pub account: u32,
+    /// Receive addresses with history
+    pub used_receive: Vec<String>,
+    /// Change addresses with history
+    pub used_change: Vec<String>,
+    /// First receive address after the last used one
+    pub next_receive: String,
+    /// First change address after the last used one
+    pub next_change: String,
+    /// Whether discovery added the account to the wallet
+    pub added: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct BtcWalletUtxoInfo {
+    pub utxo: BtcUtxoInfo,
+    /// Position of the owning account in the wallet
+    pub account_index: usize,
+    pub address: String,
+    /// 0 while in the mempool
+    pub confirmations: u32,
+    /// Output label, or the address label when the output has none
+    pub label: Option<String>,
+    /// Never spent by automatic coin selection
+    pub frozen: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct BtcConsolidateParamsInfo {
+    pub utxos: Vec<BtcUtxoInfo>,
+    pub destination_address: String,
+    pub network: String,
+    /// sat/vB, usually the slow tier
+    pub fee_rate: f64,
+    /// Satoshis, only UTXOs worth at most this are merged
+    pub max_value: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct BtcFeeEstimateInfo {
+    /// sat/vB, confirms in about a day
+    pub slow: f64,
+    /// sat/vB, confirms in about an hour
+    pub market: f64,
+    /// sat/vB, confirms in the next block or two
+    pub fast: f64,
+    /// Virtual size of the given transaction once signed
+    pub vsize: Option<u64>,
+    /// What each of its inputs adds to `vsize`, by the script type it spends
+    pub input_vsizes: Vec<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BtcBackend {
+    /// The HTTP providers of the core
+    #[default]
+    Http,
+    /// The Electrum servers among the chain's rpc urls
+    Electrum,
+}
+
+#[derive(Debug, Clone)]
+pub struct BtcBalanceInfo {
+    /// Position of the account in the wallet
+    pub account_index: usize,
+    pub address: String,
+    /// Satoshis
+    pub confirmed: u64,
+    /// Mempool change in satoshis, negative while spending
+    pub unconfirmed: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct BtcBalanceEvent {
+    pub balances: Vec<BtcBalanceInfo>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct BtcHistoryItemInfo {
+    pub txid: String,
+    /// 0 while in the mempool
+    pub height: u32,
+    pub confirmations: u32,
+    /// Satoshis received minus spent, fee included
+    pub amount: i64,
+}
@@ -1,6 +1,7 @@
 pub mod account;
 pub mod background;
 pub mod book;
+pub mod btc;
 pub mod connection;
 pub mod ftoken;
 pub mod gas;
@@ -10,10 +10,48 @@ pub use zilpay::{
     rpc::network_config::{ChainConfig, Explorer},
 };
 
+use crate::service::app_storage::with_app_storage;
 use crate::utils::errors::ServiceError;
 
+use super::btc::BtcBackend;
 use super::ftoken::FTokenInfo;
 
+fn btc_backend_key(chain_hash: u64) -> String {
+    format!("btc_backend:{}", chain_hash)
+}
+
+fn stored_btc_backend(chain_hash: u64) -> Option<BtcBackend> {
+    let stored = with_app_storage(|storage| Ok(storage.get(btc_backend_key(chain_hash))));
+    match stored.ok().flatten()?.as_str() {
+        "electrum" => Some(BtcBackend::Electrum),
+        "http" => Some(BtcBackend::Http),
+        _ => None,
+    }
+}
+
+/// Backend the chain at `chain_hash` syncs Bitcoin through. `ChainConfig`
+/// has no field for it, so it is kept in the app storage.
+pub(crate) fn chain_btc_backend(chain_hash: u64) -> BtcBackend {
+    stored_btc_backend(chain_hash).unwrap_or_default()
+}
+
+pub(crate) fn set_chain_btc_backend(chain_hash: u64, backend: BtcBackend) -> Result<(), String> {
+    let value = match backend {
+        BtcBackend::Http => "http",
+        BtcBackend::Electrum => "electrum",
+    };
+    with_app_storage(|storage| storage.set(btc_backend_key(chain_hash), value.to_string()))
+}
+
+/// `set_chain_btc_backend` unless the chain already has one, so bundled
+/// chain lists do not override what the user picked.
+pub(crate) fn init_chain_btc_backend(chain_hash: u64, backend: BtcBackend) -> Result<(), String> {
+    if stored_btc_backend(chain_hash).is_some() {
+        return Ok(());
+    }
+    set_chain_btc_backend(chain_hash, backend)
+}
+
 #[derive(Debug, Clone)]
 pub struct ExplorerInfo {
     pub name: String,
@@ -40,6 +78,7 @@ pub struct NetworkConfigInfo {
     pub fallback_enabled: bool,
     pub testnet: Option<bool>,
     pub ftokens: Vec<FTokenInfo>,
+    pub btc_backend: BtcBackend,
 }
 
 impl From<ExplorerInfo> for Explorer {
@@ -68,6 +107,7 @@ impl From<ChainConfig> for NetworkConfigInfo {
     fn from(value: ChainConfig) -> Self {
         let chain_hash = value.hash();
         let chain_id = value.chain_id();
+        let btc_backend = chain_btc_backend(chain_hash);
         let explorers = value
             .explorers
             .into_iter()
@@ -95,6 +135,7 @@ impl From<ChainConfig> for NetworkConfigInfo {
             ens: value.ens.map(|a| a.auto_format()),
             explorers,
             fallback_enabled: value.fallback_enabled,
+            btc_backend,
         }
     }
 }
@@ -309,6 +350,10 @@ impl NetworkConfigInfo {
                     fallback_enabled: true,
                     testnet: obj.get("testnet").and_then(|v| v.as_bool()),
                     ftokens,
+                    btc_backend: match obj.get("backend").and_then(|v| v.as_str()) {
+                        Some("electrum") => BtcBackend::Electrum,
+                        _ => BtcBackend::Http,
+                    },
                 })
             }
             _ => Err(ServiceError::SerdeSerror(
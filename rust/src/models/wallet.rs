@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 
 use super::{account::AccountInfo, ftoken::FTokenInfo, settings::WalletSettingsInfo};
+use crate::utils::utils::wallet_type_name;
 pub use zilpay::wallet::Wallet;
 use zilpay::{errors::wallet::WalletErrors, wallet::wallet_storage::StorageOperations};
 
@@ -58,7 +59,7 @@ impl TryFrom<&Wallet> for WalletInfo {
             slip44: data.slip44,
             auth_type: data.biometric_type.into(),
             wallet_name: data.wallet_name,
-            wallet_type: data.wallet_type.to_str(),
+            wallet_type: wallet_type_name(w, &data.wallet_type),
             wallet_address: hex::encode(w.wallet_address),
             selected_account: data.selected_account,
             tokens: ftokens,
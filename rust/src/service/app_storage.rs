@@ -0,0 +1,41 @@
+use std::sync::RwLock;
+
+use lazy_static::lazy_static;
+
+use crate::api::local_storage::{KeyValueStorage, LocalStorageImpl};
+
+type Storage = Box<dyn KeyValueStorage + Send + Sync>;
+
+lazy_static! {
+    /// Records the app keeps about core objects that core has no field for,
+    /// e.g. what a key-less wallet watches. Opened with the service.
+    static ref APP_STORAGE: RwLock<Option<Storage>> = RwLock::new(None);
+}
+
+/// Open the app records next to the core storage at `core_path`. A store
+/// left from an earlier service run is closed first.
+pub fn open(core_path: &str) -> Result<(), String> {
+    let dir = format!("{}_app", core_path.trim_end_matches('/'));
+    let mut guard = APP_STORAGE.write().map_err(|e| e.to_string())?;
+    *guard = None;
+    *guard = Some(Box::new(LocalStorageImpl::new(dir)?));
+    Ok(())
+}
+
+/// In-memory app storage shared by the tests of a run.
+#[cfg(test)]
+pub(crate) fn open_in_memory() {
+    let mut guard = APP_STORAGE.write().unwrap();
+    if guard.is_none() {
+        *guard = Some(Box::new(crate::api::local_storage::MemoryStorage::default()));
+    }
+}
+
+pub fn with_app_storage<T, F>(f: F) -> Result<T, String>
+where
+    F: FnOnce(&dyn KeyValueStorage) -> Result<T, String>,
+{
+    let guard = APP_STORAGE.read().map_err(|e| e.to_string())?;
+    let storage = guard.as_deref().ok_or("App storage is not open")?;
+    f(storage)
+}
@@ -1 +1,2 @@
+pub mod app_storage;
 pub mod service;
@@ -1,5 +1,6 @@
 use crate::utils::errors::ServiceError;
 use lazy_static::lazy_static;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use tokio::task::JoinHandle;
@@ -9,6 +10,8 @@ pub struct ServiceBackground {
     pub running: bool,
     pub block_handle: Option<JoinHandle<()>>,
     pub history_handle: Option<JoinHandle<()>>,
+    /// Stops the Electrum balance subscription thread
+    pub electrum_stop: Option<Arc<AtomicBool>>,
     pub core: Arc<Background>,
 }
 
@@ -25,11 +28,15 @@ impl ServiceBackground {
             running: true,
             block_handle: None,
             history_handle: None,
+            electrum_stop: None,
         })
     }
 
     pub fn stop(&mut self) {
         self.running = false;
+        if let Some(stop) = self.electrum_stop.take() {
+            stop.store(true, Ordering::Relaxed);
+        }
     }
 
     pub fn get_wallet_mut(
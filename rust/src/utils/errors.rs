@@ -84,6 +84,15 @@ pub enum ServiceError {
 
     #[error("Bip329 Error: {0}")]
     Bip329Errors(Bip329Errors),
+
+    #[error("Wallet at index {0} is watch-only and cannot sign")]
+    WatchOnly(usize),
+
+    #[error("Wallet at index {0} is multisig, each cosigner signs its PSBTs")]
+    Multisig(usize),
+
+    #[error("App storage error: {0}")]
+    AppStorage(String),
 }
 
 impl From<Bip329Errors> for ServiceError {
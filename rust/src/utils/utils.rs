@@ -1,5 +1,8 @@
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use zilpay::rpc::network_config::ChainConfig;
+use zilpay::wallet::wallet_storage::StorageOperations;
+use zilpay::wallet::wallet_types::WalletTypes;
 use zilpay::{background::bg_settings::SettingsManagement, crypto::slip44};
 pub use zilpay::{
     background::Background,
@@ -14,8 +17,9 @@ pub use zilpay::{
 };
 
 use crate::{
+    btc::network::network_from_chain_id,
     models::{background::BackgroundState, wallet::WalletInfo},
-    service::service::BACKGROUND_SERVICE,
+    service::{app_storage::with_app_storage, service::BACKGROUND_SERVICE},
 };
 
 use super::errors::ServiceError;
@@ -38,6 +42,14 @@ pub fn decode_secret_key(sk: &str) -> Result<[u8; SECRET_KEY_SIZE], ServiceError
         .map_err(|_| ServiceError::InvalidSecretKeyLength)
 }
 
+/// Bitcoin network of a Bitcoin chain config, regtest and signet included.
+pub fn bitcoin_network(chain_config: &ChainConfig) -> Option<bitcoin::Network> {
+    if chain_config.slip_44 != slip44::BITCOIN {
+        return None;
+    }
+    network_from_chain_id(chain_config.chain_ids[0])
+}
+
 pub fn pubkey_from_provider(
     pub_key: &str,
     chain_config: &ChainConfig,
@@ -61,9 +73,8 @@ pub fn pubkey_from_provider(
                 (slip44::ZILLIQA, false) => PubKey::Secp256k1Keccak256(pub_key_bytes),
                 (slip44::ETHEREUM, _) => PubKey::Secp256k1Keccak256(pub_key_bytes),
                 (slip44::BITCOIN, _) => {
-                    let network = chain_config
-                        .bitcoin_network()
-                        .unwrap_or(bitcoin::Network::Bitcoin);
+                    let network =
+                        bitcoin_network(chain_config).ok_or(ServiceError::AccountTypeNotValid)?;
                     let addr_type = chain_config
                         .ftokens
                         .iter()
@@ -101,9 +112,7 @@ pub fn secretkey_from_provider(
                 .find(|t| t.native)
                 .and_then(|t| t.addr.get_bitcoin_address_type().ok())
                 .unwrap_or(bitcoin::AddressType::P2tr);
-            let network = chain_config
-                .bitcoin_network()
-                .unwrap_or(bitcoin::Network::Bitcoin);
+            let network = bitcoin_network(chain_config).ok_or(ServiceError::AccountTypeNotValid)?;
 
             if let Ok(sk_from_wif) = SecretKey::from_wif(trimmed, addr_type) {
                 sk_from_wif
@@ -156,6 +165,67 @@ pub fn get_background_state(service: &Background) -> Result<BackgroundState, Ser
     })
 }
 
+/// What a key-less Bitcoin wallet is. Core keeps these as Ledger wallets
+/// with no device behind them; this record, in the app storage under the
+/// wallet address, says what they watch.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum KeylessWallet {
+    /// xpub, output descriptor or address list
+    WatchOnly { source: String },
+    /// Output descriptor, cosigners sign its PSBTs
+    Multisig { descriptor: String },
+}
+
+fn keyless_wallet_key(wallet_address: &str) -> String {
+    format!("keyless_wallet:{}", wallet_address)
+}
+
+pub fn save_keyless_wallet(wallet_address: &str, record: &KeylessWallet) -> Result<(), String> {
+    let json = serde_json::to_string(record).map_err(|e| e.to_string())?;
+    with_app_storage(|storage| storage.set(keyless_wallet_key(wallet_address), json))
+}
+
+pub fn remove_keyless_wallet(wallet_address: &str) -> Result<(), String> {
+    with_app_storage(|storage| storage.rm(keyless_wallet_key(wallet_address)))
+}
+
+/// The key-less record of `wallet`, `None` for wallets with keys.
+pub fn keyless_wallet(wallet: &Wallet) -> Result<Option<KeylessWallet>, String> {
+    let key = keyless_wallet_key(&hex::encode(wallet.wallet_address));
+    with_app_storage(|storage| {
+        storage
+            .get(key)
+            .map(|json| serde_json::from_str(&json).map_err(|e| e.to_string()))
+            .transpose()
+    })
+}
+
+/// Wallet type as the app reads it: `WalletTypes::to_str`, except that
+/// key-less wallets report "watchOnly" or "multisig" rather than pass for
+/// Ledger wallets.
+pub fn wallet_type_name(wallet: &Wallet, wallet_type: &WalletTypes) -> String {
+    match keyless_wallet(wallet) {
+        Ok(Some(KeylessWallet::WatchOnly { .. })) => "watchOnly".to_string(),
+        Ok(Some(KeylessWallet::Multisig { .. })) => "multisig".to_string(),
+        _ => wallet_type.to_str(),
+    }
+}
+
+pub fn ensure_can_sign(wallet: &Wallet, wallet_index: usize) -> Result<(), ServiceError> {
+    match keyless_wallet(wallet).map_err(ServiceError::AppStorage)? {
+        Some(KeylessWallet::WatchOnly { .. }) => Err(ServiceError::WatchOnly(wallet_index)),
+        Some(KeylessWallet::Multisig { .. }) => Err(ServiceError::Multisig(wallet_index)),
+        None => Ok(()),
+    }
+}
+
+/// `ensure_can_sign` for the wallet at `wallet_index`, for signing paths
+/// that only get the index, such as Ledger ones.
+pub async fn ensure_wallet_can_sign(wallet_index: usize) -> Result<(), ServiceError> {
+    with_wallet(wallet_index, |wallet| ensure_can_sign(wallet, wallet_index)).await
+}
+
 pub fn get_last_wallet(service: &Background) -> Result<&Wallet, ServiceError> {
     service.wallets.last().ok_or(ServiceError::FailToSaveWallet)
 }